@@ -134,6 +134,43 @@ fn prometheus_client(bencher: Bencher) {
         });
 }
 
+/// A hot loop that updates the same series, looking up the label group every time
+#[divan::bench]
+fn measured_same_labels(bencher: Bencher) {
+    let error_set = ErrorsSet {
+        kind: StaticLabelSet::new(),
+        route: Rodeo::from_iter(routes()).into_reader(),
+    };
+    let counter_vec = measured::CounterVec::dense_with_label_set(error_set);
+
+    bencher.bench(|| {
+        counter_vec.inc(black_box(Error {
+            kind: ErrorKind::User,
+            route: "/api/v1/users",
+        }));
+    });
+}
+
+/// A hot loop that updates the same series, with the dense index computed once up front
+#[divan::bench]
+fn measured_dense_index(bencher: Bencher) {
+    let error_set = ErrorsSet {
+        kind: StaticLabelSet::new(),
+        route: Rodeo::from_iter(routes()).into_reader(),
+    };
+    let counter_vec = measured::CounterVec::dense_with_label_set(error_set);
+    let index = counter_vec
+        .dense_index(counter_vec.with_labels(Error {
+            kind: ErrorKind::User,
+            route: "/api/v1/users",
+        }))
+        .unwrap();
+
+    bencher.bench(|| {
+        counter_vec.get_metric_at(black_box(index), |m| m.inc());
+    });
+}
+
 fn thread_rng() -> SmallRng {
     SmallRng::seed_from_u64(
         BuildHasherDefault::<FxHasher>::default().hash_one(std::thread::current().id()),
@@ -284,3 +284,54 @@ mod no_cardinality {
         });
     }
 }
+
+#[divan::bench_group(sample_size = 100000, sample_count = 500)]
+mod bucket_search {
+    use std::cell::RefCell;
+
+    use divan::{black_box, Bencher};
+    use measured::metric::histogram::Thresholds;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    thread_local! {
+        static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+    }
+
+    fn thresholds<const N: usize>() -> Thresholds<N> {
+        Thresholds::<N>::exponential_buckets(0.001, 1.2)
+    }
+
+    fn sample<const N: usize>() -> f64 {
+        // spread the samples over all buckets
+        let max = thresholds::<N>().get()[N - 1] * 1.2;
+        RNG.with(|rng| rng.borrow_mut().gen_range(0.0..max))
+    }
+
+    #[divan::bench(consts = [8, 16, 64])]
+    fn binary_search<const N: usize>(bencher: Bencher) {
+        let t = thresholds::<N>();
+        bencher
+            .with_inputs(sample::<N>)
+            .bench_values(|x| black_box(&t).get().partition_point(|le| x > *le));
+    }
+
+    #[divan::bench(consts = [8, 16, 64])]
+    fn linear_count<const N: usize>(bencher: Bencher) {
+        let t = thresholds::<N>();
+        bencher.with_inputs(sample::<N>).bench_values(|x| {
+            black_box(&t)
+                .get()
+                .iter()
+                .map(|le| (x > *le) as usize)
+                .sum::<usize>()
+        });
+    }
+
+    #[divan::bench(consts = [8, 16, 64])]
+    fn measured<const N: usize>(bencher: Bencher) {
+        let t = thresholds::<N>();
+        bencher
+            .with_inputs(sample::<N>)
+            .bench_values(|x| black_box(&t).bucket(x));
+    }
+}
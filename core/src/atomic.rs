@@ -0,0 +1,306 @@
+//! Atomic primitives used by the metrics. See [`AtomicF64`] and [`MemoryOrdering`]
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// An atomic float, stored as the bits of an [`AtomicU64`].
+///
+/// The read-modify-write operations are implemented with a compare-and-swap loop,
+/// so they may retry under heavy contention. They take the same orderings as the
+/// corresponding operations on [`AtomicU64`].
+#[derive(Default)]
+pub struct AtomicF64 {
+    inner: AtomicU64,
+}
+
+/// Prints the current value, like the integer atomics
+impl core::fmt::Debug for AtomicF64 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+/// The ordering for the load of a compare-and-swap loop, given the ordering of the store
+fn load_ordering(ordering: Ordering) -> Ordering {
+    match ordering {
+        Ordering::Release => Ordering::Relaxed,
+        Ordering::AcqRel => Ordering::Acquire,
+        o => o,
+    }
+}
+
+impl AtomicF64 {
+    /// An atomic float with the value `0.0`
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const ZERO: Self = Self {
+        inner: AtomicU64::new(0),
+    };
+
+    /// Create a new atomic float
+    pub fn new(val: f64) -> AtomicF64 {
+        AtomicF64 {
+            inner: AtomicU64::new(val.to_bits()),
+        }
+    }
+
+    /// Store the value with relaxed ordering
+    #[inline]
+    pub fn set(&self, val: f64) {
+        self.store(val, Ordering::Relaxed);
+    }
+
+    /// Read the value with relaxed ordering
+    #[inline]
+    pub fn get(&self) -> f64 {
+        self.load(Ordering::Relaxed)
+    }
+
+    /// Read the value with the given memory ordering
+    #[inline]
+    pub fn load(&self, ordering: Ordering) -> f64 {
+        f64::from_bits(self.inner.load(ordering))
+    }
+
+    /// Store the value with the given memory ordering
+    #[inline]
+    pub fn store(&self, val: f64, ordering: Ordering) {
+        self.inner.store(val.to_bits(), ordering);
+    }
+
+    /// Read the value through a unique reference
+    #[inline]
+    pub fn get_ex(&mut self) -> f64 {
+        f64::from_bits(*self.inner.get_mut())
+    }
+
+    /// Set the value through a unique reference
+    #[inline]
+    pub fn set_mut(&mut self, f: f64) {
+        *self.inner.get_mut() = f.to_bits();
+    }
+
+    /// Consume the atomic and return the value
+    #[inline]
+    pub fn into_inner(self) -> f64 {
+        f64::from_bits(self.inner.into_inner())
+    }
+
+    /// Apply `f` to the value until it is stored without a concurrent modification.
+    ///
+    /// If `f` returns `None`, nothing is stored. Returns the previous value.
+    #[inline]
+    fn fetch_update(&self, ordering: Ordering, mut f: impl FnMut(f64) -> Option<f64>) -> f64 {
+        let prev = self
+            .inner
+            .fetch_update(ordering, load_ordering(ordering), |current| {
+                f(f64::from_bits(current)).map(f64::to_bits)
+            });
+        match prev {
+            Ok(bits) | Err(bits) => f64::from_bits(bits),
+        }
+    }
+
+    /// Store `new` if the value is `current`, like [`AtomicU64::compare_exchange`].
+    ///
+    /// The values are compared by their bits, so `0.0` and `-0.0` are different, and a NaN is only equal
+    /// to a NaN with the same bits. Returns the previous value, as `Ok` if `new` was stored.
+    #[inline]
+    pub fn compare_exchange(
+        &self,
+        current: f64,
+        new: f64,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<f64, f64> {
+        self.inner
+            .compare_exchange(current.to_bits(), new.to_bits(), success, failure)
+            .map(f64::from_bits)
+            .map_err(f64::from_bits)
+    }
+
+    /// Add to the value, returning the previous value
+    #[inline]
+    pub fn fetch_add(&self, val: f64, ordering: Ordering) -> f64 {
+        self.fetch_update(ordering, |current| Some(current + val))
+    }
+
+    /// Subtract from the value, returning the previous value
+    #[inline]
+    pub fn fetch_sub(&self, val: f64, ordering: Ordering) -> f64 {
+        self.fetch_update(ordering, |current| Some(current - val))
+    }
+
+    /// Set the value to the maximum of the current value and `val`, returning the previous value.
+    ///
+    /// Like [`f64::max`], NaN is ignored if the other value is not NaN.
+    #[inline]
+    pub fn fetch_max(&self, val: f64, ordering: Ordering) -> f64 {
+        self.fetch_update(ordering, |current| {
+            let new = current.max(val);
+            (new.to_bits() != current.to_bits()).then_some(new)
+        })
+    }
+
+    /// Set the value to the minimum of the current value and `val`, returning the previous value.
+    ///
+    /// Like [`f64::min`], NaN is ignored if the other value is not NaN.
+    #[inline]
+    pub fn fetch_min(&self, val: f64, ordering: Ordering) -> f64 {
+        self.fetch_update(ordering, |current| {
+            let new = current.min(val);
+            (new.to_bits() != current.to_bits()).then_some(new)
+        })
+    }
+
+    /// Add to the value
+    #[inline]
+    pub fn inc_by(&self, delta: f64) {
+        self.fetch_add(delta, Ordering::AcqRel);
+    }
+
+    /// Subtract from the value
+    #[inline]
+    pub fn dec_by(&self, delta: f64) {
+        self.inc_by(-delta);
+    }
+}
+
+/// The memory ordering of the updates and collections of the [`Counter`](crate::Counter),
+/// [`Gauge`](crate::Gauge) and [`FloatGauge`](crate::FloatGauge) metrics, and their vecs. This is their metric
+/// metadata, so it is selected when the metric is created with `with_metadata`.
+///
+/// The ordering only applies to the updates through these metrics. Metrics that are built from a
+/// [`CounterState`](crate::metric::counter::CounterState), like [`RequestMetrics`](crate::metric::request::RequestMetrics)
+/// or [`SloHistogram`](crate::metric::slo::SloHistogram), update it directly, which is always relaxed.
+/// The compare-and-set, max and min updates of a [`FloatGauge`](crate::FloatGauge) are always acquire-release.
+///
+/// By default, all accesses are [`Relaxed`](Ordering::Relaxed). Every update is atomic, and a collection always
+/// reads a value that some update actually wrote, never a torn or made up value. A relaxed read might not see
+/// the most recent updates from other threads, but it does not go back in time: every collection sees a value
+/// at least as recent as the previous collection on that thread. No ordering is established with any other memory.
+///
+/// With [`AcquireRelease`](Self::AcquireRelease), updates are [`Release`](Ordering::Release) and collections are
+/// [`Acquire`](Ordering::Acquire). If a collection reads the value written by an update, then everything the
+/// updating thread did before that update is visible to the collecting thread afterwards, for example
+/// a record that was written before its counter was incremented. It does not make collections see updates sooner,
+/// and it is more expensive on weakly ordered architectures, like aarch64.
+///
+/// ```
+/// use measured::Counter;
+/// use measured::atomic::MemoryOrdering;
+///
+/// let records = Counter::with_metadata(MemoryOrdering::AcquireRelease);
+/// records.inc();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MemoryOrdering {
+    /// No ordering with other memory
+    #[default]
+    Relaxed,
+    /// Updates release, and collections acquire
+    AcquireRelease,
+}
+
+impl MemoryOrdering {
+    /// The ordering used to read the value on collection
+    #[inline]
+    pub const fn load(self) -> Ordering {
+        match self {
+            MemoryOrdering::Relaxed => Ordering::Relaxed,
+            MemoryOrdering::AcquireRelease => Ordering::Acquire,
+        }
+    }
+
+    /// The ordering used to update the value
+    #[inline]
+    pub const fn store(self) -> Ordering {
+        match self {
+            MemoryOrdering::Relaxed => Ordering::Relaxed,
+            MemoryOrdering::AcquireRelease => Ordering::Release,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::AtomicF64;
+
+    fn contend(f: impl Fn(u32) + Sync) {
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let f = &f;
+                s.spawn(move || {
+                    for i in 0..10000 {
+                        f(t * 10000 + i);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn fetch_add_under_contention() {
+        let x = AtomicF64::ZERO;
+        contend(|_| {
+            x.fetch_add(0.5, Ordering::Relaxed);
+        });
+        assert_eq!(x.into_inner(), 20000.0);
+
+        let x = AtomicF64::new(100.0);
+        contend(|_| {
+            x.fetch_sub(1.0, Ordering::Relaxed);
+        });
+        assert_eq!(x.into_inner(), -39900.0);
+    }
+
+    #[test]
+    fn fetch_max_min_under_contention() {
+        let max = AtomicF64::new(f64::NEG_INFINITY);
+        let min = AtomicF64::new(f64::INFINITY);
+        contend(|i| {
+            let x = (i as f64 - 20000.0) * 0.25;
+            max.fetch_max(x, Ordering::Relaxed);
+            min.fetch_min(x, Ordering::Relaxed);
+        });
+        assert_eq!(max.into_inner(), 19999.0 * 0.25);
+        assert_eq!(min.into_inner(), -20000.0 * 0.25);
+    }
+
+    #[test]
+    fn compare_exchange_under_contention() {
+        let x = AtomicF64::ZERO;
+        contend(|_| {
+            let mut current = x.get();
+            while let Err(actual) =
+                x.compare_exchange(current, current + 0.5, Ordering::AcqRel, Ordering::Acquire)
+            {
+                current = actual;
+            }
+        });
+        assert_eq!(x.get(), 20000.0);
+
+        assert_eq!(
+            x.compare_exchange(1.0, 2.0, Ordering::Relaxed, Ordering::Relaxed),
+            Err(20000.0)
+        );
+        assert_eq!(
+            x.compare_exchange(20000.0, 2.0, Ordering::Relaxed, Ordering::Relaxed),
+            Ok(20000.0)
+        );
+        assert_eq!(x.get(), 2.0);
+    }
+
+    #[test]
+    fn fetch_returns_previous() {
+        let x = AtomicF64::new(1.0);
+        assert_eq!(x.fetch_add(2.0, Ordering::AcqRel), 1.0);
+        assert_eq!(x.fetch_max(2.0, Ordering::Release), 3.0);
+        assert_eq!(x.fetch_min(-1.0, Ordering::SeqCst), 3.0);
+        assert_eq!(x.load(Ordering::Acquire), -1.0);
+
+        // NaN is ignored
+        assert_eq!(x.fetch_max(f64::NAN, Ordering::Relaxed), -1.0);
+        assert_eq!(x.get(), -1.0);
+    }
+}
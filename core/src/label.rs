@@ -1,16 +1,28 @@
 //! Traits and types used for representing groups of label-pairs
 
+#[cfg(feature = "bitflags")]
+mod flags;
 mod impls;
 
 pub(crate) mod group;
+pub(crate) mod known;
+pub(crate) mod limit;
 pub(crate) mod name;
 pub(crate) mod value;
 
-pub use group::{ComposedGroup, LabelGroup, LabelGroupSet, LabelGroupVisitor, NoLabels};
+#[cfg(feature = "bitflags")]
+pub use flags::BitFlags;
+pub use group::{
+    ComposedGroup, LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelPair, NoLabels,
+    ReassignedGroups,
+};
+pub use known::KnownLabels;
+pub use limit::{LengthLimitPolicy, LengthLimited, LruCapped};
 pub use name::LabelName;
 pub use value::{
-    DynamicLabelSet, FixedCardinalityLabel, FixedCardinalitySet, LabelSet, LabelTestVisitor,
-    LabelValue, LabelVisitor, StaticLabelSet,
+    assert_cardinality_below, ConstCardinality, DynamicLabelSet, FixedCardinalityLabel,
+    FixedCardinalitySet, LabelSet, LabelTestVisitor, LabelValue, LabelVisitor, StaticLabelSet,
+    WithAggregate,
 };
 
 #[cfg(all(test, feature = "lasso"))]
@@ -97,4 +109,152 @@ mod tests {
             }
         }
     }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "kebab-case")]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = RequestSet)]
+    struct Request<'a> {
+        method: Method,
+        #[label(fixed_with = RodeoReader)]
+        route: &'a str,
+        kind: ErrorKind,
+    }
+
+    #[test]
+    fn dense_encoding_mixed_fixed() {
+        let rodeo: Rodeo = ["/home", "/login", "/logout"].into_iter().collect();
+        let set = RequestSet::new(rodeo.into_reader());
+        assert_eq!(set.cardinality(), Some(2 * 3 * 3));
+
+        let mut seen = vec![false; 18];
+        for method in [Method::Get, Method::Post] {
+            for route in set.route.strings() {
+                for kind in [ErrorKind::User, ErrorKind::Internal, ErrorKind::Network] {
+                    let request = Request {
+                        method,
+                        route,
+                        kind,
+                    };
+                    let unique = set.encode(request).unwrap();
+                    let index = set.encode_dense(unique).unwrap();
+                    assert!(!std::mem::replace(&mut seen[index], true));
+                    assert_eq!(set.decode_dense(index), request);
+                }
+            }
+        }
+        assert!(seen.into_iter().all(|x| x));
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = SessionSet)]
+    struct Session<'a> {
+        kind: ErrorKind,
+        #[label(dynamic = ThreadedRodeo, default)]
+        user: &'a str,
+        #[label(fixed_with = RodeoReader)]
+        route: &'a str,
+    }
+
+    #[test]
+    fn dynamic_shorthand() {
+        let rodeo: Rodeo = ["/home", "/login"].into_iter().collect();
+        let set = SessionSet::new(rodeo.into_reader());
+        assert_eq!(set.cardinality(), None);
+
+        let session = Session {
+            kind: ErrorKind::Network,
+            user: "alice",
+            route: "/login",
+        };
+        let index = set.encode(session).unwrap();
+        assert_eq!(set.decode(&index), session);
+        assert_eq!(set.encode_dense(index), None);
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = ErrorsSet3)]
+    struct Error3<'a> {
+        kind: ErrorKind,
+        #[label(fixed_with = RodeoReader, fallback = "unknown")]
+        route: &'a str,
+    }
+
+    #[test]
+    fn fallback_labels() {
+        let error = |route| Error3 {
+            kind: ErrorKind::User,
+            route,
+        };
+
+        let rodeo: Rodeo = ["/home", "unknown"].into_iter().collect();
+        let set = ErrorsSet3::new(rodeo.into_reader());
+        let index = set.encode(error("/missing")).unwrap();
+        assert_eq!(set.decode(&index), error("unknown"));
+
+        let errors = crate::CounterVec::with_label_set(set);
+        errors.inc(error("/home"));
+        errors.inc(error("/not/a/route"));
+        errors.inc(error("/another/missing/route"));
+
+        let count = |route| {
+            let id = errors.with_labels(error(route));
+            errors
+                .get_metric(id)
+                .count
+                .load(std::sync::atomic::Ordering::Relaxed)
+        };
+        assert_eq!(count("/home"), 1);
+        assert_eq!(count("unknown"), 2);
+    }
+
+    /// A hand-written label that does not implement `ConstCardinality`
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    struct Shard(u8);
+
+    impl super::LabelValue for Shard {
+        fn visit<V: super::LabelVisitor>(&self, v: V) -> V::Output {
+            v.write_int(self.0 as i64)
+        }
+    }
+
+    impl super::FixedCardinalityLabel for Shard {
+        fn cardinality() -> usize {
+            4
+        }
+        fn encode(&self) -> usize {
+            self.0 as usize
+        }
+        fn decode(value: usize) -> Self {
+            Shard(value as u8)
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = ShardSet)]
+    struct ShardError {
+        kind: ErrorKind,
+        shard: Shard,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = MethodErrorSet, const_cardinality)]
+    struct MethodError {
+        kind: ErrorKind,
+        method: Method,
+    }
+
+    #[test]
+    fn const_cardinality_opt_in() {
+        let set = ShardSet::default();
+        assert_eq!(set.cardinality(), Some(3 * 4));
+
+        const _: () = super::assert_cardinality_below::<MethodErrorSet, 7>();
+        assert_eq!(<MethodErrorSet as super::ConstCardinality>::CARDINALITY, 6);
+    }
 }
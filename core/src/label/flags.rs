@@ -0,0 +1,157 @@
+use bitflags::Flags;
+
+use super::{
+    FixedCardinalityLabel, LabelGroup, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor,
+};
+
+/// A [`FixedCardinalityLabel`] for a [`bitflags`] type, which captures several boolean attributes in one dimension.
+///
+/// Each combination of the named flags encodes to a single index in `0..2^k`, where `k` is the number of named flags,
+/// so a set of booleans only takes up a single dimension in a dense metric vec.
+///
+/// When used as a field of a [`LabelGroup`](macro@crate::LabelGroup), the flags are written as a single label,
+/// with the names of the set flags joined by `|`. When used as a label group on its own, eg in a
+/// [`StaticLabelSet`](crate::label::StaticLabelSet), each flag is written as its own label with a `true` or `false` value.
+///
+/// Every named flag should be a distinct single bit. Composite flags, and any unnamed bits, are not encoded.
+///
+/// ```
+/// use measured::CounterVec;
+/// use measured::label::{BitFlags, StaticLabelSet};
+///
+/// bitflags::bitflags! {
+///     #[derive(Clone, Copy)]
+///     struct Features: u8 {
+///         const dark_mode = 1 << 0;
+///         const beta = 1 << 1;
+///     }
+/// }
+///
+/// let requests = CounterVec::<StaticLabelSet<BitFlags<Features>>>::new();
+/// // encoded as `{dark_mode="true",beta="false"}`
+/// requests.inc(BitFlags(Features::dark_mode));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitFlags<F>(pub F);
+
+fn named_flags<F: Flags>() -> impl Iterator<Item = &'static bitflags::Flag<F>> {
+    F::FLAGS.iter().filter(|flag| flag.is_named())
+}
+
+impl<F: Flags + Copy> LabelValue for BitFlags<F> {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        let mut names = String::new();
+        for flag in named_flags::<F>().filter(|flag| self.0.contains(*flag.value())) {
+            if !names.is_empty() {
+                names.push('|');
+            }
+            names.push_str(flag.name());
+        }
+        v.write_str(&names)
+    }
+}
+
+impl<F: Flags + Copy> FixedCardinalityLabel for BitFlags<F> {
+    fn cardinality() -> usize {
+        let k = named_flags::<F>().count();
+        assert!(k < usize::BITS as usize, "too many flags to encode");
+        1 << k
+    }
+
+    fn encode(&self) -> usize {
+        named_flags::<F>()
+            .enumerate()
+            .filter(|(_, flag)| self.0.contains(*flag.value()))
+            .fold(0, |index, (i, _)| index | (1 << i))
+    }
+
+    fn decode(value: usize) -> Self {
+        let mut flags = F::empty();
+        for (i, flag) in named_flags::<F>().enumerate() {
+            if value & (1 << i) != 0 {
+                flags.insert(*flag.value());
+            }
+        }
+        BitFlags(flags)
+    }
+}
+
+impl<F: Flags + Copy> LabelGroup for BitFlags<F> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        for flag in named_flags::<F>() {
+            let value = if self.0.contains(*flag.value()) {
+                "true"
+            } else {
+                "false"
+            };
+            v.write_value(LabelName::from_str(flag.name()), &value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::{LabelGroupSet, LabelTestVisitor, LabelValue, StaticLabelSet},
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        CounterVec, FixedCardinalityLabel,
+    };
+
+    use super::BitFlags;
+
+    bitflags::bitflags! {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        struct Features: u16 {
+            const dark_mode = 1 << 0;
+            const beta = 1 << 3;
+            const offline = 1 << 7;
+        }
+    }
+
+    #[test]
+    fn roundtrip_all_combinations() {
+        assert_eq!(BitFlags::<Features>::cardinality(), 8);
+
+        let mut seen = Vec::new();
+        for index in 0..8 {
+            let flags = BitFlags::<Features>::decode(index);
+            assert_eq!(flags.encode(), index);
+            seen.push(flags.0);
+        }
+        // every combination is distinct
+        for (i, a) in seen.iter().enumerate() {
+            assert!(seen[i + 1..].iter().all(|b| a != b));
+        }
+
+        assert_eq!(BitFlags(Features::empty()).encode(), 0);
+        assert_eq!(BitFlags(Features::all()).encode(), 7);
+        assert_eq!(BitFlags(Features::offline).encode(), 4);
+
+        let set = StaticLabelSet::<BitFlags<Features>>::new();
+        assert_eq!(LabelGroupSet::cardinality(&set), Some(8));
+    }
+
+    #[test]
+    fn combined_label_value() {
+        let flags = BitFlags(Features::dark_mode | Features::offline);
+        assert_eq!(flags.visit(LabelTestVisitor), "dark_mode|offline");
+        assert_eq!(BitFlags(Features::empty()).visit(LabelTestVisitor), "");
+    }
+
+    #[test]
+    fn individual_boolean_labels() {
+        let requests = CounterVec::<StaticLabelSet<BitFlags<Features>>>::new();
+        requests.inc(BitFlags(Features::beta));
+
+        let mut enc = BufferedTextEncoder::new();
+        requests
+            .collect_family_into(MetricName::from_str("requests"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE requests counter\n\
+            requests{dark_mode=\"false\",beta=\"true\",offline=\"false\"} 1\n"
+        );
+    }
+}
@@ -29,6 +29,10 @@ pub trait LabelGroup {
     }
 }
 
+/// A predicate over the compressed representations of a [`LabelGroupSet`], returned by
+/// [`LabelGroupSet::take_reassigned`].
+pub type ReassignedGroups<'a, U> = Box<dyn Fn(&U) -> bool + 'a>;
+
 /// `LabelGroupSet` is a helper for [`LabelGroup`]s.
 ///
 /// The `LabelGroup` pairs might need some extra data in order to encode/decode the values into their
@@ -52,6 +56,14 @@ pub trait LabelGroupSet {
     fn encode(&self, value: Self::Group<'_>) -> Option<Self::Unique>;
     /// Decodes the compressed representation into the label values
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_>;
+
+    /// Take the label groups whose encoding was handed to new label values since the last call,
+    /// as a predicate over the compressed representation. See [`LabelSet::take_reassigned`](super::LabelSet::take_reassigned).
+    ///
+    /// Returns `None` if no encoding was reassigned.
+    fn take_reassigned(&self) -> Option<ReassignedGroups<'_, Self::Unique>> {
+        None
+    }
 }
 
 /// A [`LabelGroup`] with no label pairs
@@ -61,10 +73,35 @@ impl LabelGroup for NoLabels {
     fn visit_values(&self, _v: &mut impl LabelGroupVisitor) {}
 }
 
+/// A [`LabelGroup`] with a single label pair.
+///
+/// This is useful for metrics which write several samples per series, where each sample
+/// adds its own label, like the `le` label of a histogram bucket or the `quantile` label of a summary.
+///
+/// ```
+/// use measured::label::{LabelGroup, LabelName, LabelPair, NoLabels};
+///
+/// const QUANTILE: &LabelName = LabelName::from_str("quantile");
+/// let labels = NoLabels.compose_with(LabelPair(QUANTILE, 0.99));
+/// ```
+pub struct LabelPair<'a, V>(pub &'a super::LabelName, pub V);
+
+impl<V: super::LabelValue> LabelGroup for LabelPair<'_, V> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        v.write_value(self.0, &self.1);
+    }
+}
+
 /// `ComposedGroup` represents either a combine [`LabelGroup`] or a [`LabelGroupSet`]. See [`LabelGroup::compose_with`]
 #[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
 pub struct ComposedGroup<A, B>(pub A, pub B);
 
+impl<A: super::ConstCardinality, B: super::ConstCardinality> super::ConstCardinality
+    for ComposedGroup<A, B>
+{
+    const CARDINALITY: usize = A::CARDINALITY.saturating_mul(B::CARDINALITY);
+}
+
 impl<A: LabelGroupSet, B: LabelGroupSet> LabelGroupSet for ComposedGroup<A, B> {
     type Group<'a> = ComposedGroup<A::Group<'a>, B::Group<'a>>;
 
@@ -115,6 +152,15 @@ impl<A: LabelGroupSet, B: LabelGroupSet> LabelGroupSet for ComposedGroup<A, B> {
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
         ComposedGroup(self.0.decode(&value.0), self.1.decode(&value.1))
     }
+
+    fn take_reassigned(&self) -> Option<ReassignedGroups<'_, Self::Unique>> {
+        match (self.0.take_reassigned(), self.1.take_reassigned()) {
+            (None, None) => None,
+            (Some(a), None) => Some(Box::new(move |value| a(&value.0))),
+            (None, Some(b)) => Some(Box::new(move |value| b(&value.1))),
+            (Some(a), Some(b)) => Some(Box::new(move |value| a(&value.0) || b(&value.1))),
+        }
+    }
 }
 
 impl<A: LabelGroup, B: LabelGroup> LabelGroup for ComposedGroup<A, B> {
@@ -154,6 +200,10 @@ impl<T: LabelGroupSet + ?Sized> LabelGroupSet for &'static T {
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
         T::decode(self, value)
     }
+
+    fn take_reassigned(&self) -> Option<ReassignedGroups<'_, Self::Unique>> {
+        T::take_reassigned(self)
+    }
 }
 
 impl<T: LabelGroupSet + ?Sized> LabelGroupSet for Arc<T> {
@@ -180,6 +230,10 @@ impl<T: LabelGroupSet + ?Sized> LabelGroupSet for Arc<T> {
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
         T::decode(self, value)
     }
+
+    fn take_reassigned(&self) -> Option<ReassignedGroups<'_, Self::Unique>> {
+        T::take_reassigned(self)
+    }
 }
 
 #[cfg(test)]
@@ -42,6 +42,18 @@ impl LabelValue for str {
     }
 }
 
+impl LabelValue for i64 {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        v.write_int(*self)
+    }
+}
+
+impl LabelValue for f64 {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        v.write_float(*self)
+    }
+}
+
 impl<T: LabelValue + ?Sized> LabelValue for &T {
     fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
         T::visit(self, v)
@@ -139,6 +151,10 @@ impl<T: LabelSet + ?Sized> LabelSet for Arc<T> {
     fn decode(&self, value: usize) -> Self::Value<'_> {
         T::decode(self, value)
     }
+
+    fn take_reassigned(&self) -> Vec<usize> {
+        T::take_reassigned(self)
+    }
 }
 
 #[cfg(test)]
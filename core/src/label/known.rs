@@ -0,0 +1,179 @@
+use core::hash::Hash;
+
+use rustc_hash::FxHashMap;
+
+use super::{LabelGroup, LabelGroupSet};
+
+/// A [`LabelGroupSet`] of an enumerated list of label groups.
+///
+/// Some label sets are fixed, but irregular, like a curated list of known routes with the methods they support.
+/// They have no clean encoding as a product of [`FixedCardinalityLabel`](super::FixedCardinalityLabel)s, but every
+/// valid label group is known up front. This set assigns each of them an index in the order they are given,
+/// so a metric vec can store them densely, and encoding a label group is a single hash lookup.
+///
+/// The label groups must be `'static`, so they are usually owned or hold `&'static str`s.
+/// Label groups that were not given are unknown to the set. See [`MetricVec::from_known_labels`](crate::metric::MetricVec::from_known_labels).
+///
+/// ```
+/// use measured::{CounterVec, FixedCardinalityLabel};
+/// use measured::label::{LabelGroup, LabelGroupVisitor, LabelName};
+///
+/// #[derive(FixedCardinalityLabel, Clone, Copy, PartialEq, Eq, Hash)]
+/// enum Method { Get, Post }
+///
+/// #[derive(Clone, PartialEq, Eq, Hash)]
+/// struct Route {
+///     method: Method,
+///     route: &'static str,
+/// }
+///
+/// impl LabelGroup for Route {
+///     fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+///         v.write_value(LabelName::from_str("method"), &self.method);
+///         v.write_value(LabelName::from_str("route"), &self.route);
+///     }
+/// }
+///
+/// let requests = CounterVec::from_known_labels([
+///     Route { method: Method::Get, route: "/users" },
+///     Route { method: Method::Post, route: "/users" },
+///     Route { method: Method::Get, route: "/health" },
+/// ]);
+/// requests.inc(&Route { method: Method::Get, route: "/health" });
+/// ```
+#[derive(Clone, Debug)]
+pub struct KnownLabels<G> {
+    groups: Vec<G>,
+    indices: FxHashMap<G, usize>,
+}
+
+impl<G: Hash + Eq + Clone> KnownLabels<G> {
+    /// Enumerate the known label groups. Duplicates are only counted once.
+    pub fn new(groups: impl IntoIterator<Item = G>) -> Self {
+        groups.into_iter().collect()
+    }
+
+    /// The known label groups, in the order of their index
+    pub fn groups(&self) -> &[G] {
+        &self.groups
+    }
+}
+
+impl<G: Hash + Eq + Clone> FromIterator<G> for KnownLabels<G> {
+    fn from_iter<T: IntoIterator<Item = G>>(iter: T) -> Self {
+        let mut groups = Vec::new();
+        let mut indices = FxHashMap::default();
+        for group in iter {
+            indices.entry(group).or_insert_with_key(|group| {
+                groups.push(group.clone());
+                groups.len() - 1
+            });
+        }
+        Self { groups, indices }
+    }
+}
+
+impl<G: LabelGroup + Hash + Eq + 'static> LabelGroupSet for KnownLabels<G> {
+    type Group<'a> = &'a G;
+
+    fn cardinality(&self) -> Option<usize> {
+        Some(self.groups.len())
+    }
+
+    fn encode_dense(&self, value: usize) -> Option<usize> {
+        Some(value)
+    }
+
+    fn decode_dense(&self, value: usize) -> Self::Group<'_> {
+        &self.groups[value]
+    }
+
+    type Unique = usize;
+
+    fn encode(&self, value: Self::Group<'_>) -> Option<usize> {
+        self.indices.get(value).copied()
+    }
+
+    fn decode(&self, value: &usize) -> Self::Group<'_> {
+        &self.groups[*value]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::FixedCardinalityLabel;
+
+    use crate::{
+        label::{LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelName},
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        CounterVec,
+    };
+
+    use super::KnownLabels;
+
+    #[derive(FixedCardinalityLabel, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    #[label(crate = crate, rename_all = "snake_case")]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+    struct Route {
+        method: Method,
+        route: &'static str,
+    }
+
+    impl LabelGroup for Route {
+        fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+            v.write_value(LabelName::from_str("method"), &self.method);
+            v.write_value(LabelName::from_str("route"), &self.route);
+        }
+    }
+
+    fn route(method: Method, route: &'static str) -> Route {
+        Route { method, route }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let set = KnownLabels::new([
+            route(Method::Get, "/users"),
+            route(Method::Post, "/users"),
+            route(Method::Get, "/users"),
+            route(Method::Get, "/health"),
+        ]);
+
+        assert_eq!(set.cardinality(), Some(3));
+        assert_eq!(set.encode(&route(Method::Post, "/users")), Some(1));
+        assert_eq!(set.encode(&route(Method::Get, "/health")), Some(2));
+        assert_eq!(set.encode(&route(Method::Post, "/health")), None);
+        for (i, group) in set.groups().iter().enumerate() {
+            assert_eq!(set.decode(&i), group);
+            assert_eq!(set.decode_dense(set.encode_dense(i).unwrap()), group);
+        }
+    }
+
+    #[test]
+    fn dense_vec() {
+        let requests = CounterVec::from_known_labels([
+            route(Method::Get, "/users"),
+            route(Method::Post, "/users"),
+        ]);
+        requests.inc(&route(Method::Post, "/users"));
+        assert!(requests
+            .try_with_labels(&route(Method::Get, "/unknown"))
+            .is_none());
+
+        let mut enc = BufferedTextEncoder::new();
+        requests
+            .collect_family_into(MetricName::from_str("requests"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE requests counter\n\
+            requests{method=\"post\",route=\"/users\"} 1\n"
+        );
+    }
+}
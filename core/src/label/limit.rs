@@ -0,0 +1,491 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use parking_lot::{Mutex, RwLock};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::{DynamicLabelSet, FixedCardinalitySet, LabelSet};
+
+/// What to do with a label value that exceeds the length limit of a [`LengthLimited`] label set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthLimitPolicy {
+    /// Truncate the value (on a `char` boundary) and append the marker, such that the
+    /// result still fits within the limit.
+    Truncate(&'static str),
+    /// Replace the whole value with the given string, eg `"<long>"`. The replacement must fit within the limit.
+    Replace(&'static str),
+    /// Refuse to encode the value. Using [`MetricVec::with_labels`](crate::metric::MetricVec::with_labels)
+    /// will then panic, so prefer [`MetricVec::try_with_labels`](crate::metric::MetricVec::try_with_labels).
+    Reject,
+}
+
+/// A [`LabelSet`] for string values that enforces a maximum length (in bytes) on the label values.
+///
+/// Label values derived from user input, like URLs or user agents, can be arbitrarily long. This bloats
+/// both the exposition and any interned string table, like a [`lasso::ThreadedRodeo`].
+/// Values that are within the limit are passed as-is to the inner label set.
+///
+/// The [`Default`] label set is unlimited.
+///
+#[cfg_attr(feature = "lasso", doc = "```")]
+#[cfg_attr(not(feature = "lasso"), doc = "```ignore")]
+/// use lasso::ThreadedRodeo;
+/// use measured::label::{LengthLimited, LengthLimitPolicy};
+///
+/// #[derive(measured::LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request<'a> {
+///     #[label(dynamic_with = LengthLimited<ThreadedRodeo>)]
+///     user_agent: &'a str,
+/// }
+///
+/// let set = RequestSet::new(LengthLimited::new(
+///     ThreadedRodeo::new(),
+///     64,
+///     LengthLimitPolicy::Replace("<too-long>"),
+/// ));
+/// ```
+pub struct LengthLimited<S> {
+    inner: S,
+    max_len: usize,
+    policy: LengthLimitPolicy,
+}
+
+impl<S> LengthLimited<S> {
+    /// Limit the values in the label set to at most `max_len` bytes.
+    ///
+    /// # Panics
+    /// Panics if the policy is [`LengthLimitPolicy::Replace`] with a replacement longer than `max_len`.
+    pub fn new(inner: S, max_len: usize, policy: LengthLimitPolicy) -> Self {
+        if let LengthLimitPolicy::Replace(replacement) = policy {
+            assert!(
+                replacement.len() <= max_len,
+                "the replacement {replacement:?} is longer than the limit of {max_len} bytes"
+            );
+        }
+        Self {
+            inner,
+            max_len,
+            policy,
+        }
+    }
+
+    /// Borrow the inner label set
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: Default> Default for LengthLimited<S> {
+    fn default() -> Self {
+        Self::new(S::default(), usize::MAX, LengthLimitPolicy::Reject)
+    }
+}
+
+thread_local! {
+    /// A reused buffer for the truncated values, so that encoding an over-long value does not allocate
+    static TRUNCATED: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Truncate the string on a char boundary to be at most `len` bytes
+fn truncate(s: &str, mut len: usize) -> &str {
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    &s[..len]
+}
+
+impl<S> LabelSet for LengthLimited<S>
+where
+    S: for<'a> LabelSet<Value<'a> = &'a str>,
+{
+    type Value<'a> = &'a str;
+
+    fn dynamic_cardinality(&self) -> Option<usize> {
+        self.inner.dynamic_cardinality()
+    }
+
+    fn encode(&self, value: Self::Value<'_>) -> Option<usize> {
+        if value.len() <= self.max_len {
+            return self.inner.encode(value);
+        }
+
+        match self.policy {
+            LengthLimitPolicy::Truncate(marker) => {
+                let prefix = truncate(value, self.max_len.saturating_sub(marker.len()));
+                let marker = truncate(marker, self.max_len);
+                TRUNCATED.with_borrow_mut(|truncated| {
+                    truncated.clear();
+                    truncated.push_str(prefix);
+                    truncated.push_str(marker);
+                    self.inner.encode(truncated)
+                })
+            }
+            LengthLimitPolicy::Replace(replacement) => self.inner.encode(replacement),
+            LengthLimitPolicy::Reject => None,
+        }
+    }
+
+    fn decode(&self, value: usize) -> Self::Value<'_> {
+        self.inner.decode(value)
+    }
+
+    fn take_reassigned(&self) -> Vec<usize> {
+        self.inner.take_reassigned()
+    }
+}
+
+impl<S> FixedCardinalitySet for LengthLimited<S> where
+    S: FixedCardinalitySet + for<'a> LabelSet<Value<'a> = &'a str>
+{
+}
+
+impl<S> DynamicLabelSet for LengthLimited<S> where
+    S: DynamicLabelSet + for<'a> LabelSet<Value<'a> = &'a str>
+{
+}
+
+/// A [`DynamicLabelSet`] that caps a single dynamic label dimension at `capacity` distinct values,
+/// evicting a value that was not seen recently when a new one arrives.
+///
+/// This bounds one unbounded label, like a `route`, while the other labels of the group stay as they are.
+/// The label values are interned by the inner label set, like a [`lasso::ThreadedRodeo`], and this set only keeps
+/// track of which `capacity` values currently own a slot. Eviction uses the CLOCK approximation of LRU, so encoding
+/// a value that already owns a slot only takes a shared lock.
+///
+/// When a value is evicted, its slot is given to the new value and the [`MetricVec`](crate::metric::MetricVec)
+/// removes the series of that slot, so the new value starts from zero. The evicted value is folded into the
+/// `other` label value for as long as it is among the last `capacity` evicted values, so a value that keeps coming
+/// back cannot push out the others repeatedly. Updates that race with an eviction might be lost.
+///
+/// The inner label set keeps every value that was ever given a slot.
+///
+#[cfg_attr(feature = "lasso", doc = "```")]
+#[cfg_attr(not(feature = "lasso"), doc = "```ignore")]
+/// use lasso::ThreadedRodeo;
+/// use measured::label::LruCapped;
+///
+/// #[derive(measured::LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request<'a> {
+///     #[label(dynamic_with = LruCapped<ThreadedRodeo>)]
+///     route: &'a str,
+/// }
+///
+/// // at most 100 routes, any others are reported as `route="other"`
+/// let set = RequestSet::new(LruCapped::new(ThreadedRodeo::new(), 100, "other"));
+/// ```
+pub struct LruCapped<S> {
+    inner: S,
+    capacity: usize,
+    other: &'static str,
+    slots: Box<[LruSlot]>,
+    index: RwLock<LruIndex>,
+    /// The slots which were given to a new value since the last [`LabelSet::take_reassigned`]
+    reassigned: Mutex<Vec<usize>>,
+    has_reassigned: AtomicBool,
+}
+
+struct LruSlot {
+    /// The inner index of the value that owns the slot
+    owner: AtomicUsize,
+    /// Whether the value was seen since the clock hand last passed the slot
+    referenced: AtomicBool,
+}
+
+#[derive(Default)]
+struct LruIndex {
+    /// The slot owned by each value
+    owners: FxHashMap<Box<str>, usize>,
+    /// The value that owns each slot
+    values: Vec<Box<str>>,
+    hand: usize,
+    /// The last `capacity` evicted values, which count towards `other`
+    evicted: FxHashSet<Box<str>>,
+    evicted_order: VecDeque<Box<str>>,
+}
+
+impl<S> LruCapped<S> {
+    /// Cap the label set at `capacity` values, reporting evicted values as `other`.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is 0.
+    pub fn new(inner: S, capacity: usize, other: &'static str) -> Self {
+        assert!(capacity > 0, "LruCapped needs a capacity of at least 1");
+        Self {
+            inner,
+            capacity,
+            other,
+            slots: (0..capacity)
+                .map(|_| LruSlot {
+                    owner: AtomicUsize::new(0),
+                    referenced: AtomicBool::new(false),
+                })
+                .collect(),
+            index: RwLock::new(LruIndex::default()),
+            reassigned: Mutex::new(Vec::new()),
+            has_reassigned: AtomicBool::new(false),
+        }
+    }
+
+    /// Borrow the inner label set
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> LruCapped<S>
+where
+    S: for<'a> LabelSet<Value<'a> = &'a str>,
+{
+    /// Give the value a slot, evicting the value of another slot if they are all taken
+    fn admit(&self, value: &str) -> Option<usize> {
+        let index = &mut *self.index.write();
+        // another thread might have admitted or evicted the value in the meantime
+        if let Some(&slot) = index.owners.get(value) {
+            return Some(slot);
+        }
+        if index.evicted.contains(value) {
+            return Some(self.capacity);
+        }
+
+        let owner = self.inner.encode(value)?;
+
+        let slot = if index.values.len() < self.capacity {
+            index.values.push(value.into());
+            index.values.len() - 1
+        } else {
+            // sweep the clock hand until it finds a slot that was not seen since the last sweep
+            while self.slots[index.hand]
+                .referenced
+                .swap(false, Ordering::Relaxed)
+            {
+                index.hand = (index.hand + 1) % self.capacity;
+            }
+            let slot = index.hand;
+            index.hand = (index.hand + 1) % self.capacity;
+
+            let evicted = std::mem::replace(&mut index.values[slot], value.into());
+            index.owners.remove(&evicted);
+            if !index.evicted.contains(&evicted) {
+                index.evicted.insert(evicted.clone());
+                index.evicted_order.push_back(evicted);
+            }
+            if index.evicted_order.len() > self.capacity {
+                if let Some(oldest) = index.evicted_order.pop_front() {
+                    index.evicted.remove(&oldest);
+                }
+            }
+
+            let mut reassigned = self.reassigned.lock();
+            reassigned.push(slot);
+            self.has_reassigned.store(true, Ordering::Release);
+            slot
+        };
+
+        // the value is only marked as referenced once it is seen again
+        self.slots[slot].owner.store(owner, Ordering::Release);
+        self.slots[slot].referenced.store(false, Ordering::Relaxed);
+        index.owners.insert(value.into(), slot);
+        Some(slot)
+    }
+}
+
+impl<S> LabelSet for LruCapped<S>
+where
+    S: for<'a> LabelSet<Value<'a> = &'a str>,
+{
+    type Value<'a> = &'a str;
+
+    fn dynamic_cardinality(&self) -> Option<usize> {
+        // the slots, plus one for `other`
+        Some(self.capacity + 1)
+    }
+
+    fn encode(&self, value: Self::Value<'_>) -> Option<usize> {
+        if value == self.other {
+            return Some(self.capacity);
+        }
+
+        {
+            let index = self.index.read();
+            if let Some(&slot) = index.owners.get(value) {
+                let referenced = &self.slots[slot].referenced;
+                if !referenced.load(Ordering::Relaxed) {
+                    referenced.store(true, Ordering::Relaxed);
+                }
+                return Some(slot);
+            }
+            if !index.evicted.is_empty() && index.evicted.contains(value) {
+                return Some(self.capacity);
+            }
+        }
+
+        self.admit(value)
+    }
+
+    fn decode(&self, value: usize) -> Self::Value<'_> {
+        if value == self.capacity {
+            return self.other;
+        }
+        self.inner
+            .decode(self.slots[value].owner.load(Ordering::Acquire))
+    }
+
+    fn take_reassigned(&self) -> Vec<usize> {
+        if !self.has_reassigned.load(Ordering::Acquire) {
+            return Vec::new();
+        }
+        let mut reassigned = self.reassigned.lock();
+        self.has_reassigned.store(false, Ordering::Relaxed);
+        std::mem::take(&mut *reassigned)
+    }
+}
+
+impl<S> DynamicLabelSet for LruCapped<S> where S: for<'a> LabelSet<Value<'a> = &'a str> {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::label::LabelSet;
+
+    use super::{LengthLimitPolicy, LengthLimited, LruCapped};
+
+    /// A very simple interner for testing
+    #[derive(Default)]
+    struct Interner(Mutex<Vec<&'static str>>);
+
+    impl LabelSet for Interner {
+        type Value<'a> = &'a str;
+
+        fn dynamic_cardinality(&self) -> Option<usize> {
+            None
+        }
+
+        fn encode(&self, value: Self::Value<'_>) -> Option<usize> {
+            let mut strings = self.0.lock().unwrap();
+            if let Some(i) = strings.iter().position(|s| *s == value) {
+                return Some(i);
+            }
+            strings.push(Box::leak(value.into()));
+            Some(strings.len() - 1)
+        }
+
+        fn decode(&self, value: usize) -> Self::Value<'_> {
+            self.0.lock().unwrap()[value]
+        }
+    }
+
+    fn round_trip(set: &LengthLimited<Interner>, value: &str) -> Option<&'static str> {
+        let index = set.encode(value)?;
+        Some(set.inner().0.lock().unwrap()[index])
+    }
+
+    #[test]
+    fn at_limit() {
+        for policy in [
+            LengthLimitPolicy::Truncate("..."),
+            LengthLimitPolicy::Replace("<long>"),
+            LengthLimitPolicy::Reject,
+        ] {
+            let set = LengthLimited::new(Interner::default(), 8, policy);
+            assert_eq!(round_trip(&set, "abcdefgh"), Some("abcdefgh"));
+        }
+    }
+
+    #[test]
+    fn over_limit() {
+        let set = LengthLimited::new(Interner::default(), 8, LengthLimitPolicy::Truncate("..."));
+        assert_eq!(round_trip(&set, "abcdefghi"), Some("abcde..."));
+        // do not split a multi-byte char
+        assert_eq!(round_trip(&set, "abcdéfghi"), Some("abcd..."));
+
+        let set = LengthLimited::new(Interner::default(), 8, LengthLimitPolicy::Replace("<long>"));
+        assert_eq!(round_trip(&set, "abcdefghi"), Some("<long>"));
+
+        let set = LengthLimited::new(Interner::default(), 8, LengthLimitPolicy::Reject);
+        assert_eq!(round_trip(&set, "abcdefghi"), None);
+    }
+
+    #[test]
+    #[should_panic = "longer than the limit"]
+    fn replacement_over_limit() {
+        LengthLimited::new(
+            Interner::default(),
+            8,
+            LengthLimitPolicy::Replace("<too-long>"),
+        );
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let set = LengthLimited::<Interner>::default();
+        let long = "a".repeat(4096);
+        assert_eq!(round_trip(&set, &long), Some(&*long));
+    }
+
+    #[test]
+    fn lru_eviction_to_other() {
+        let set = LruCapped::new(Interner::default(), 2, "other");
+        let round_trip = |value| set.decode(set.encode(value).unwrap());
+
+        assert_eq!(round_trip("/a"), "/a");
+        assert_eq!(round_trip("/b"), "/b");
+        // "/a" is now more recent than "/b"
+        assert_eq!(round_trip("/a"), "/a");
+
+        // "/b" is evicted to make room
+        let c = set.encode("/c").unwrap();
+        assert_eq!(set.decode(c), "/c");
+        assert_eq!(set.take_reassigned(), vec![c]);
+        assert_eq!(set.take_reassigned(), Vec::<usize>::new());
+        assert_eq!(set.encode("/b"), Some(2));
+        assert_eq!(round_trip("/b"), "other");
+        assert_eq!(round_trip("/a"), "/a");
+
+        // "/c" is the least recently seen
+        assert_eq!(round_trip("/d"), "/d");
+        assert_eq!(round_trip("/c"), "other");
+        assert_eq!(round_trip("other"), "other");
+        assert_eq!(set.dynamic_cardinality(), Some(3));
+    }
+
+    #[test]
+    fn lru_capped_fresh_series() {
+        use crate::{CounterVec, LabelGroup};
+
+        #[derive(Clone, Copy, LabelGroup)]
+        #[label(crate = crate, set = RouteSet)]
+        struct Route<'a> {
+            #[label(dynamic_with = LruCapped<Interner>)]
+            route: &'a str,
+        }
+
+        let requests = CounterVec::with_label_set(RouteSet::new(LruCapped::new(
+            Interner::default(),
+            2,
+            "other",
+        )));
+        // "/a" is evicted by "/c", so it is counted as `other` afterwards
+        for route in ["/a", "/b", "/c", "/a", "/a"] {
+            requests.inc(Route { route });
+        }
+
+        let count = |route| {
+            let id = requests.with_labels(Route { route });
+            requests
+                .get_metric(id)
+                .count
+                .load(std::sync::atomic::Ordering::Relaxed)
+        };
+        // "/c" does not inherit the count of "/a"
+        assert_eq!(count("/c"), 1);
+        assert_eq!(count("/b"), 1);
+        assert_eq!(count("other"), 2);
+        assert_eq!(requests.get_cardinality().0, 3);
+    }
+}
@@ -23,6 +23,15 @@ impl LabelName {
     }
 }
 
+/// Whether the string is a valid label name, without panicking like [`LabelName::from_str`]
+pub(crate) fn is_label_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    bytes
+        .next()
+        .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
 const fn assert_label_name(name: &str) {
     assert!(!name.is_empty(), "string should not be empty");
 
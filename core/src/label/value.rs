@@ -13,6 +13,14 @@ impl<T> Default for StaticLabelSet<T> {
     }
 }
 
+impl<T> Clone for StaticLabelSet<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for StaticLabelSet<T> {}
+
 impl<T> StaticLabelSet<T> {
     /// Create a new `StaticLabelSet`
     pub const fn new() -> Self {
@@ -68,6 +76,84 @@ impl<T: FixedCardinalityLabel + LabelGroup> LabelGroupSet for StaticLabelSet<T>
     }
 }
 
+/// `WithAggregate` extends a [`FixedCardinalityLabel`] with an extra `Aggregate` sentinel value.
+///
+/// Recording into the `Aggregate` value gives you a rollup series for that dimension, eg
+/// `inc({method: GET, status: Aggregate})` records into the series for all statuses of `GET` requests.
+/// This saves you from doing the rollup with a `sum by` at query time.
+///
+/// The aggregate is encoded as the last index, after all of the values of `T`.
+/// It is exported with the label value `"__all__"`.
+///
+/// # Cardinality
+///
+/// Each dimension wrapped with `WithAggregate` has a cardinality of `T::cardinality() + 1`.
+/// As the cardinality of a label group is the product of the cardinalities of its dimensions, wrapping
+/// multiple dimensions compounds. For instance, 4 methods and 5 statuses have a cardinality of 20,
+/// but with both dimensions wrapped this grows to 30. Dense metric vecs reserve a slot for every combination.
+///
+/// ```
+/// use measured::{label::WithAggregate, FixedCardinalityLabel};
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone, Debug, PartialEq)]
+/// enum Method {
+///     Get,
+///     Post,
+/// }
+///
+/// assert_eq!(WithAggregate::<Method>::cardinality(), 3);
+/// assert_eq!(WithAggregate::Value(Method::Post).encode(), 1);
+/// assert_eq!(WithAggregate::<Method>::Aggregate.encode(), 2);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WithAggregate<T> {
+    /// A regular label value
+    Value(T),
+    /// The sentinel value representing all values of this dimension
+    Aggregate,
+}
+
+impl<T> WithAggregate<T> {
+    /// The label value that the `Aggregate` sentinel is exported as
+    pub const AGGREGATE_VALUE: &'static str = "__all__";
+}
+
+impl<T> From<T> for WithAggregate<T> {
+    fn from(value: T) -> Self {
+        Self::Value(value)
+    }
+}
+
+impl<T: LabelValue> LabelValue for WithAggregate<T> {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        match self {
+            WithAggregate::Value(t) => t.visit(v),
+            WithAggregate::Aggregate => v.write_str(Self::AGGREGATE_VALUE),
+        }
+    }
+}
+
+impl<T: FixedCardinalityLabel> FixedCardinalityLabel for WithAggregate<T> {
+    fn cardinality() -> usize {
+        T::cardinality() + 1
+    }
+
+    fn encode(&self) -> usize {
+        match self {
+            WithAggregate::Value(t) => t.encode(),
+            WithAggregate::Aggregate => T::cardinality(),
+        }
+    }
+
+    fn decode(value: usize) -> Self {
+        if value == T::cardinality() {
+            WithAggregate::Aggregate
+        } else {
+            WithAggregate::Value(T::decode(value))
+        }
+    }
+}
+
 /// A [`LabelVisitor`] that is useful for testing purposes
 #[derive(Default, Debug)]
 pub struct LabelTestVisitor;
@@ -133,6 +219,75 @@ pub trait FixedCardinalityLabel: LabelValue + Copy {
     fn decode(value: usize) -> Self;
 }
 
+/// A [`FixedCardinalityLabel`] or [`LabelGroupSet`] whose cardinality is known at compile time.
+/// See [`assert_cardinality_below`]
+///
+/// This is implemented by the derived [`FixedCardinalityLabel`]s, [`StaticLabelSet`]s of them, and the sets of
+/// derived [`LabelGroup`](macro@crate::LabelGroup)s marked with `#[label(const_cardinality)]`.
+pub trait ConstCardinality {
+    /// The number of possible label values, saturating at [`usize::MAX`]
+    const CARDINALITY: usize;
+}
+
+struct CardinalityBelow<L, const N: usize>(PhantomData<L>);
+
+impl<L: ConstCardinality, const N: usize> CardinalityBelow<L, N> {
+    const ASSERT: () = assert!(
+        L::CARDINALITY < N,
+        "the label set has too many possible values for a dense metric vec, consider sparse storage"
+    );
+}
+
+/// Fail the build if the cardinality of the label set `L` is not below `N`.
+///
+/// A dense metric vec allocates a metric for every possible combination of label values up-front,
+/// so adding a label to a derived [`LabelGroup`](macro@crate::LabelGroup) can silently multiply its memory usage.
+/// Pinning the cardinality in a `const` turns that into a compile error.
+///
+/// ```
+/// use measured::{FixedCardinalityLabel, LabelGroup};
+/// use measured::label::{assert_cardinality_below, StaticLabelSet};
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// enum Method { Get, Post, Put }
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// enum Status { Ok, ClientError, ServerError }
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestSet, const_cardinality)]
+/// struct Request {
+///     method: Method,
+///     status: Status,
+/// }
+///
+/// const _: () = assert_cardinality_below::<RequestSet, 16>();
+/// const _: () = assert_cardinality_below::<StaticLabelSet<Method>, 4>();
+/// ```
+///
+/// ```compile_fail
+/// # use measured::{FixedCardinalityLabel, label::{assert_cardinality_below, StaticLabelSet}};
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// enum Method { Get, Post, Put }
+///
+/// const _: () = assert_cardinality_below::<StaticLabelSet<Method>, 3>();
+/// ```
+///
+/// Outside of a `const`, the assertion is checked when the calling code is compiled to a binary,
+/// so it might not be reported by `cargo check`.
+pub const fn assert_cardinality_below<L: ConstCardinality, const N: usize>() {
+    #[allow(clippy::let_unit_value)]
+    let () = CardinalityBelow::<L, N>::ASSERT;
+}
+
+impl<T: ConstCardinality> ConstCardinality for StaticLabelSet<T> {
+    const CARDINALITY: usize = T::CARDINALITY;
+}
+
+impl<T: ConstCardinality> ConstCardinality for WithAggregate<T> {
+    const CARDINALITY: usize = T::CARDINALITY.saturating_add(1);
+}
+
 /// `FixedCardinalitySet` is an immutable [`LabelSet`] that has a known fixed size.
 ///
 /// An example of a dynamic label that has a fixed capacity is an API path with parameters removed
@@ -192,15 +347,29 @@ pub trait LabelSet {
     /// If the integer is outside the range of this set, the behaviour is not defined.
     /// It would most likely panic.
     fn decode(&self, value: usize) -> Self::Value<'_>;
+
+    /// Take the encodings which were handed to a new label value since the last call.
+    ///
+    /// Most label sets never reuse an encoding, but [`LruCapped`](super::LruCapped) does when it evicts a value.
+    /// A [`MetricVec`](crate::metric::MetricVec) removes the series of these encodings, so that the new label value
+    /// does not inherit the samples of the old one.
+    fn take_reassigned(&self) -> Vec<usize> {
+        Vec::new()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use measured_derive::MetricGroup;
+    use measured_derive::{LabelGroup, MetricGroup};
 
-    use crate::CounterVec;
+    use crate::{
+        label::{LabelGroupSet, LabelTestVisitor},
+        metric::{group::MetricGroup, name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        CounterVec,
+    };
 
-    use super::StaticLabelSet;
+    use super::{FixedCardinalityLabel, LabelValue, StaticLabelSet, WithAggregate};
 
     #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
     #[label(crate = crate)]
@@ -216,4 +385,117 @@ mod tests {
     struct Metrics {
         errors: CounterVec<StaticLabelSet<ErrorKind>>,
     }
+
+    #[test]
+    fn singleton_label() {
+        let metrics = Metrics::default();
+        metrics.errors.inc(ErrorKind::Network);
+
+        let mut enc = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut enc).unwrap();
+        let output = enc.finish();
+        let output = std::str::from_utf8(&output).unwrap();
+        assert!(output.contains("{kind=\"network\"} 1\n"));
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case")]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+    #[label(crate = crate, set = RequestSet)]
+    struct Request {
+        method: WithAggregate<Method>,
+        kind: WithAggregate<ErrorKind>,
+    }
+
+    #[test]
+    fn aggregate_encoding() {
+        assert_eq!(WithAggregate::<ErrorKind>::cardinality(), 4);
+        for i in 0..4 {
+            assert_eq!(WithAggregate::<ErrorKind>::decode(i).encode(), i);
+        }
+        assert_eq!(
+            WithAggregate::<ErrorKind>::decode(3),
+            WithAggregate::Aggregate
+        );
+        assert_eq!(
+            WithAggregate::Value(ErrorKind::Network).visit(LabelTestVisitor),
+            "network"
+        );
+        assert_eq!(
+            WithAggregate::<ErrorKind>::Aggregate.visit(LabelTestVisitor),
+            "__all__"
+        );
+    }
+
+    #[test]
+    fn aggregate_rollup() {
+        let set = RequestSet::new();
+        // each dimension gets an extra slot for the aggregate
+        assert_eq!(set.cardinality(), Some(3 * 4));
+
+        let counters = CounterVec::with_label_set(set);
+        for kind in [ErrorKind::User, ErrorKind::Network] {
+            let kind = WithAggregate::Value(kind);
+            for method in [WithAggregate::Value(Method::Get), WithAggregate::Aggregate] {
+                counters.inc(Request { method, kind });
+                counters.inc(Request {
+                    method,
+                    kind: WithAggregate::Aggregate,
+                });
+            }
+        }
+
+        let get = |method, kind| {
+            let id = counters.with_labels(Request { method, kind });
+            counters
+                .get_metric(id)
+                .count
+                .load(std::sync::atomic::Ordering::Relaxed)
+        };
+        assert_eq!(get(WithAggregate::Aggregate, WithAggregate::Aggregate), 2);
+        assert_eq!(
+            get(WithAggregate::Value(Method::Get), WithAggregate::Aggregate),
+            2
+        );
+        assert_eq!(
+            get(
+                WithAggregate::Value(Method::Get),
+                WithAggregate::Value(ErrorKind::User)
+            ),
+            1
+        );
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, singleton = "status")]
+    enum Status {
+        Ok = 200,
+        NotFound = 404,
+        #[label(other)]
+        Other,
+    }
+
+    #[test]
+    fn other_variant() {
+        let errors = CounterVec::<StaticLabelSet<Status>>::new();
+        for status in ["200", "404", "418", "ok"] {
+            errors.inc(Status::from(status));
+        }
+        assert_eq!(Status::from("404"), Status::NotFound);
+        assert_eq!(Status::from("418").encode(), 2);
+        assert_eq!(Status::decode(2), Status::Other);
+        assert_eq!(Status::decode(100), Status::Other);
+        assert_eq!(Status::Other.visit(LabelTestVisitor), "other");
+
+        let mut enc = BufferedTextEncoder::new();
+        errors
+            .collect_family_into(MetricName::from_str("responses"), &mut enc)
+            .unwrap();
+        assert!(enc.finish().ends_with(b"responses{status=\"other\"} 2\n"));
+    }
 }
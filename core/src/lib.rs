@@ -8,15 +8,25 @@ extern crate alloc;
 use metric::{
     counter::CounterState,
     gauge::{FloatGaugeState, GaugeState},
-    histogram::HistogramState,
+    histogram::{adaptive::AdaptiveHistogramState, HistogramState},
+    sampled::SampledCounterState,
+    summary::SummaryState,
+    unknown::UnknownState,
     Metric, MetricVec,
 };
 
+pub mod atomic;
 #[cfg(any(doc, test))]
 pub mod docs;
 pub mod label;
 pub mod metric;
+pub mod sample;
+pub mod snapshot;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod text;
+#[cfg(feature = "tracing")]
+pub mod tracing;
 
 /// Implement [`FixedCardinalityLabel`] on an `enum`
 ///
@@ -36,13 +46,19 @@ pub mod text;
 /// # Variant attributes
 ///
 /// * `rename = "..."` - Rename this variant.
+/// * `other` - The catch-all variant, for values which don't match any other variant. At most one variant can be `other`.
+///   [`decode`](label::FixedCardinalityLabel::decode) returns it for any integer out of range, and `From<&str>`
+///   returns it for any string which is not the label value of another variant.
 ///
 /// # Outputs
 ///
 /// * `impl FixedCardinalityLabel for T { ... }`
+/// * `impl ConstCardinality for T { ... }`
 /// * `impl LabelValue for T { ... }`
 /// * `impl LabelGroup for T { ... }`
 ///     - If `singleton` is specified
+/// * `impl From<&str> for T { ... }`
+///     - If a variant is `other`
 ///
 /// # Example
 ///
@@ -132,6 +148,30 @@ pub mod text;
 /// assert_eq!(StatusCode::ImATeapot.visit(LabelTestVisitor), "IM-A-TEAPOT");
 /// assert_eq!(StatusCode::InternalServerError.visit(LabelTestVisitor), "INTERNAL-SERVER-ERROR");
 /// ```
+///
+/// ## Catch-all
+///
+/// ```
+/// #[derive(measured::FixedCardinalityLabel)]
+/// #[derive(Debug, Copy, Clone, PartialEq)]
+/// enum StatusClass {
+///     #[label(rename = "2xx")]
+///     Success,
+///     #[label(rename = "4xx")]
+///     ClientError,
+///     #[label(rename = "5xx")]
+///     ServerError,
+///     #[label(other)]
+///     Other,
+/// }
+///
+/// use measured::label::FixedCardinalityLabel as _;
+///
+/// assert_eq!(StatusClass::from("4xx"), StatusClass::ClientError);
+/// assert_eq!(StatusClass::from("1xx"), StatusClass::Other);
+/// assert_eq!(StatusClass::from("1xx").encode(), 3);
+/// assert_eq!(StatusClass::decode(7), StatusClass::Other);
+/// ```
 pub use measured_derive::FixedCardinalityLabel;
 
 pub use label::FixedCardinalityLabel;
@@ -145,20 +185,26 @@ pub use label::FixedCardinalityLabel;
 /// # Container attributes
 ///
 /// * `set = Ident` - The name that the corresponding [`LabelGroupSet`](label::LabelGroupSet) should take on. (**required**)
+/// * `const_cardinality` - Also implement [`ConstCardinality`](label::ConstCardinality) for the set.
+///   Every field must be a plain `fixed` label whose type implements [`ConstCardinality`](label::ConstCardinality).
 ///
 /// # Field attributes
 ///
 /// * `fixed` - The field type implements [`FixedCardinalityLabel`] (**implied**)
 /// * `fixed_with = Type` - The field corresponds to a [`FixedCardinalitySet`](label::FixedCardinalitySet)
-/// * `dynamic_with = Type` - The field corresponds to a [`DynamicLabelSet`](label::DynamicLabelSet)
+/// * `dynamic_with = Type` - The field corresponds to a [`DynamicLabelSet`](label::DynamicLabelSet). `dynamic = Type` is accepted as shorthand.
 /// * `default` - The generated [`LabelGroupSet`](label::LabelGroupSet) can default this field.
 /// * `rename = "..."` - Rename this label.
+/// * `fallback = expr` - If the value is not in the label set, record it under this value instead.
+///   The fallback value must itself be in the set.
 ///
 /// # Outputs
 ///
 /// * `impl LabelGroup for T { ... }`
 /// * `struct TSet { ... }`
 /// * `impl LabelGroupSet for TSet { ... }`
+/// * `impl ConstCardinality for TSet { ... }`
+///     - only implemented with the `const_cardinality` attribute.
 /// * `impl TSet { pub fn new(...) -> Self {} }`
 ///     - `new` contains args for all the non-default fields.
 /// * `impl Default for TSet { ... }`
@@ -238,6 +284,7 @@ pub use label::group::LabelGroup;
 /// These are for fields that implement [`MetricFamilyEncoding`](metric::MetricFamilyEncoding)
 ///
 /// * `rename = "..."` - By default, metrics take on the field name in snake case. rename allows renaming them.
+/// * `unit = "..."` - The unit of the metric, like `"seconds"`. The metric name should end with it. See [`Encoding::write_unit`](metric::group::Encoding::write_unit).
 /// * `metadata = expr` - The metadata to initialise a [`Metric`] or [`MetricVec`] with.
 /// * `label_set = expr` - The [`LabelGroupSet`](label::LabelGroupSet) to initialise a [`MetricVec`] with.
 /// * `init = expr` - The expression needed to initialise the metric, if it cannot be defaulted.
@@ -250,6 +297,28 @@ pub use measured_derive::MetricGroup;
 
 pub use metric::group::MetricGroup;
 
+/// Create histogram [`Thresholds`](metric::histogram::Thresholds) from duration literals.
+///
+/// Each bucket upper bound is written as a number with a unit suffix: `ns`, `us`, `ms`, or `s`.
+/// The durations are converted to seconds with [`Thresholds::from_durations`](metric::histogram::Thresholds::from_durations).
+///
+/// ```
+/// use measured::{duration_buckets, Histogram};
+///
+/// let thresholds = duration_buckets![500us, 5ms, 10ms, 25ms, 2.5s];
+/// assert_eq!(thresholds.get(), &[0.0005, 0.005, 0.01, 0.025, 2.5]);
+///
+/// let latency = Histogram::with_metadata(thresholds);
+/// latency.get_metric().observe_duration(std::time::Duration::from_millis(7));
+/// ```
+///
+/// The durations must be strictly increasing, which is checked at compile time.
+///
+/// ```compile_fail
+/// let thresholds = measured::duration_buckets![10ms, 5ms];
+/// ```
+pub use measured_derive::duration_buckets;
+
 /// A [`Metric`] that counts individual observations from an event or sample stream in configurable buckets.
 /// Similar to a Summary, it also provides a sum of observations and an observation count.
 ///
@@ -317,6 +386,81 @@ pub type Histogram<const N: usize> = Metric<HistogramState<N>>;
 /// ```
 pub type HistogramVec<L, const N: usize> = MetricVec<HistogramState<N>, L>;
 
+/// A [`Histogram`] that adapts its bucket bounds to the observed values.
+///
+/// The buckets are exponential, and start with the bounds given by the
+/// [`AdaptiveThresholds`](metric::histogram::adaptive::AdaptiveThresholds).
+/// When sampled, if many of the observations since the last re-bucketing fell outside of the current bounds, all bounds are
+/// shifted up or down by half of the buckets. This is useful when you can't predict the scale of your observations ahead of time.
+///
+/// # Re-bucketing
+///
+/// Because the bounds stay on the same exponential grid, most buckets map exactly onto a new bucket.
+/// The count and sum are always preserved. However, re-bucketing loses some of the per-bucket history:
+/// * When shifting up, the lowest buckets are merged into one, and any previous `+Inf` observations stay in `+Inf`.
+/// * When shifting down, the previous lowest bucket is counted entirely in the new bucket with the same upper bound.
+///
+/// The exported `le` labels also change, so the bucket series before and after a re-bucketing should not be compared directly.
+///
+/// ```
+/// use measured::AdaptiveHistogram;
+/// use measured::metric::histogram::adaptive::AdaptiveThresholds;
+/// use measured::metric::name::MetricName;
+/// use measured::metric::MetricFamilyEncoding;
+/// use measured::text::BufferedTextEncoder;
+///
+/// // create a histogram with 8 buckets, starting at 0.001, 0.002, ..., 0.128
+/// let histogram = AdaptiveHistogram::with_metadata(AdaptiveThresholds::<8>::exponential_buckets(0.001, 2.0));
+/// for _ in 0..10 {
+///     histogram.observe(5.0);
+/// }
+///
+/// // sample the histogram and encode the value to a textual format.
+/// // this shifts the buckets up to 0.016, 0.032, ..., 2.048
+/// let mut text_encoder = BufferedTextEncoder::new();
+/// let name = MetricName::from_str("my_adaptive_histogram");
+/// histogram.collect_family_into(name, &mut text_encoder);
+/// let bytes = text_encoder.finish();
+/// ```
+pub type AdaptiveHistogram<const N: usize> = Metric<AdaptiveHistogramState<N>>;
+
+/// A collection of multiple [`AdaptiveHistogram`]s, keyed by [`LabelGroup`]s.
+///
+/// Each histogram in the vec adapts its bucket bounds independently.
+pub type AdaptiveHistogramVec<L, const N: usize> = MetricVec<AdaptiveHistogramState<N>, L>;
+
+/// A [`Metric`] that estimates quantiles of the observed values, like the median or the 99th percentile.
+///
+/// Unlike a [`Histogram`], the quantiles are computed client-side, to within the allowed error of each
+/// target [`Quantiles`](metric::summary::Quantiles). The quantiles are over all observations since the summary
+/// was created, and they cannot be aggregated across series. Like a histogram, it also provides a sum of
+/// observations and an observation count.
+///
+/// ```
+/// use measured::Summary;
+/// use measured::metric::name::MetricName;
+/// use measured::metric::summary::Quantiles;
+/// use measured::metric::MetricFamilyEncoding;
+/// use measured::text::BufferedTextEncoder;
+///
+/// // create a summary with the p50, p90 and p99, each within 1%
+/// let summary = Summary::with_metadata(Quantiles::new([0.5, 0.9, 0.99], 0.01));
+/// // observe a value
+/// summary.observe(1.0);
+///
+/// // sample the summary and encode the value to a textual format.
+/// let mut text_encoder = BufferedTextEncoder::new();
+/// let name = MetricName::from_str("my_first_summary");
+/// summary.collect_family_into(name, &mut text_encoder);
+/// let bytes = text_encoder.finish();
+/// ```
+pub type Summary = Metric<SummaryState>;
+
+/// A collection of multiple [`Summary`]s, keyed by [`LabelGroup`]s.
+///
+/// Every summary in the vec targets the same quantiles.
+pub type SummaryVec<L> = MetricVec<SummaryState, L>;
+
 /// A [`Metric`] that represents a single numerical value that only ever goes up.
 ///
 /// ```
@@ -499,3 +643,66 @@ pub type FloatGauge = Metric<FloatGaugeState>;
 /// let bytes = text_encoder.finish();
 /// ```
 pub type FloatGaugeVec<L> = MetricVec<FloatGaugeState, L>;
+
+/// A [`Counter`] that only records 1 in every N events, for extremely hot events where exact counts are not needed.
+///
+/// Each event is counted with a chance of 1 in N, decided by a generator local to the thread, so events that are
+/// not sampled do not touch any shared state. When encoded, the number of sampled events is multiplied by the
+/// [`SampleRate`](metric::sampled::SampleRate).
+///
+/// # Statistical error
+///
+/// The exported value is an unbiased estimate. After `n` events, its standard deviation is about
+/// `sqrt(n * (N - 1))`, so the relative error shrinks as more events are recorded.
+///
+/// ```
+/// use std::num::NonZeroU64;
+/// use measured::SampledCounter;
+/// use measured::metric::sampled::SampleRate;
+/// use measured::metric::name::MetricName;
+/// use measured::metric::MetricFamilyEncoding;
+/// use measured::text::BufferedTextEncoder;
+///
+/// // create a counter that records 1 in every 100 events
+/// let counter = SampledCounter::with_metadata(SampleRate::new(NonZeroU64::new(100).unwrap()));
+/// for _ in 0..1000 {
+///     counter.inc();
+/// }
+///
+/// // sample the counter and encode the value to a textual format.
+/// let mut text_encoder = BufferedTextEncoder::new();
+/// let name = MetricName::from_str("my_hot_counter");
+/// counter.collect_family_into(name, &mut text_encoder);
+/// let bytes = text_encoder.finish();
+/// ```
+pub type SampledCounter = Metric<SampledCounterState>;
+
+/// A collection of multiple [`SampledCounter`]s, keyed by [`LabelGroup`]s
+///
+/// All counters in the vec share the same [`SampleRate`](metric::sampled::SampleRate).
+/// The labels are only encoded for the events that are sampled.
+pub type SampledCounterVec<L> = MetricVec<SampledCounterState, L>;
+
+/// A metric with no type semantics, encoded with the `untyped` type, or `unknown` in OpenMetrics.
+///
+/// This is useful for passing through metrics from another exposition, eg when federating,
+/// where the type of the upstream metric is not known and should not be reclassified as a counter or gauge.
+///
+/// ```
+/// use measured::Unknown;
+/// use measured::metric::name::MetricName;
+/// use measured::metric::MetricFamilyEncoding;
+/// use measured::text::BufferedTextEncoder;
+///
+/// let upstream = Unknown::new();
+/// upstream.set(42.0);
+///
+/// let mut text_encoder = BufferedTextEncoder::new();
+/// let name = MetricName::from_str("upstream_value");
+/// upstream.collect_family_into(name, &mut text_encoder).unwrap();
+/// assert_eq!(text_encoder.finish(), "# TYPE upstream_value untyped\nupstream_value 42.0\n");
+/// ```
+pub type Unknown = Metric<UnknownState>;
+
+/// A collection of multiple [`Unknown`]s, keyed by [`LabelGroup`]s
+pub type UnknownVec<L> = MetricVec<UnknownState, L>;
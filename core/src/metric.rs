@@ -4,20 +4,40 @@ use core::hash::Hash;
 use std::{
     hash::BuildHasher,
     ops::{Deref, DerefMut},
-    sync::OnceLock,
+    sync::{Arc, OnceLock},
 };
 
-use crate::label::{LabelGroup, LabelGroupSet, NoLabels};
+use crate::label::{KnownLabels, LabelGroup, LabelGroupSet, NoLabels};
 use crossbeam_utils::CachePadded;
 
-use self::{group::Encoding, name::MetricNameEncoder};
+use self::{
+    group::Encoding,
+    name::{MetricName, MetricNameEncoder},
+    reset::Reset,
+    reset::ResetMetric,
+};
 
 pub mod counter;
+pub mod delta;
+pub mod double_buffered;
 pub mod gauge;
 pub mod group;
+pub mod help;
 pub mod histogram;
+pub mod local;
 pub mod name;
+pub mod rate;
+pub mod ratio;
+pub mod relabel;
+pub mod request;
+pub mod reset;
+pub mod sampled;
+pub mod sharded;
+pub mod slo;
 mod sparse;
+pub mod stale;
+pub mod summary;
+pub mod unknown;
 
 /// Defines a metric
 pub trait MetricType: Default {
@@ -119,6 +139,14 @@ pub struct MetricVec<M: MetricType, L: LabelGroupSet> {
     metrics: VecInner<L::Unique, M>,
     metadata: M::Metadata,
     label_set: L,
+    unknown_labels: UnknownLabels,
+}
+
+/// Whether updates with a label group that is not in the label set are dropped, instead of panicking.
+#[derive(Default)]
+struct UnknownLabels {
+    drop: bool,
+    dropped: std::sync::atomic::AtomicU64,
 }
 
 enum VecInner<U: Hash + Eq, M: MetricType> {
@@ -126,6 +154,32 @@ enum VecInner<U: Hash + Eq, M: MetricType> {
     Sparse(sparse::ShardedMap<U, M>),
 }
 
+/// Prints the current value of the metric
+impl<M: MetricType + core::fmt::Debug> core::fmt::Debug for Metric<M> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.metric.fmt(f)
+    }
+}
+
+/// Prints a summary of the metric vec: the metric type, how the series are stored, and how many series are active.
+///
+/// The series are not printed. Counting the series of a sparse metric vec briefly takes a read lock on each shard.
+impl<M: MetricType, L: LabelGroupSet> core::fmt::Debug for MetricVec<M, L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let storage = match &self.metrics {
+            VecInner::Dense(_) => "dense",
+            VecInner::Sparse(_) => "sparse",
+        };
+        let (series, cardinality) = self.get_cardinality();
+        f.debug_struct("MetricVec")
+            .field("metric", &core::any::type_name::<M>())
+            .field("storage", &storage)
+            .field("series", &series)
+            .field("cardinality", &cardinality)
+            .finish()
+    }
+}
+
 impl<M: MetricType> Metric<M>
 where
     M::Metadata: Default,
@@ -222,6 +276,19 @@ where
     }
 }
 
+impl<M: MetricType, G: LabelGroup + Hash + Eq + Clone + 'static> MetricVec<M, KnownLabels<G>>
+where
+    M::Metadata: Default,
+{
+    /// Create a new dense metric vec over an enumeration of every valid label group. See [`KnownLabels`].
+    ///
+    /// This allocates the dense storage up front, even if the enumeration has more than the usual limit
+    /// for dense metric vecs.
+    pub fn from_known_labels(groups: impl IntoIterator<Item = G>) -> Self {
+        Self::dense_with_label_set(KnownLabels::new(groups))
+    }
+}
+
 impl<M: MetricType, L: LabelGroupSet + Default> Default for MetricVec<M, L>
 where
     M::Metadata: Default,
@@ -247,7 +314,16 @@ impl<M: MetricType, U: Hash + Eq + Copy> VecInner<U, M> {
                 let m = metrics[id.hash as usize].get_or_init(M::default);
                 MetricLockGuardRepr::Dense(m)
             }
-            VecInner::Sparse(metrics) => MetricLockGuardRepr::Sparse(metrics.get_metric(id)),
+            VecInner::Sparse(metrics) => metrics.get_metric(id),
+        }
+    }
+
+    fn find_metric(&self, id: LabelIdInner<U>) -> Option<MetricLockGuardRepr<'_, M>> {
+        match self {
+            VecInner::Dense(metrics) => metrics[id.hash as usize]
+                .get()
+                .map(MetricLockGuardRepr::Dense),
+            VecInner::Sparse(metrics) => metrics.find_metric(id),
         }
     }
 
@@ -278,6 +354,7 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
             metrics,
             metadata,
             label_set,
+            unknown_labels: UnknownLabels::default(),
         }
     }
 
@@ -291,6 +368,7 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
             metrics: VecInner::Dense(new_dense(c)),
             metadata,
             label_set,
+            unknown_labels: UnknownLabels::default(),
         }
     }
 
@@ -300,6 +378,198 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
             metrics: VecInner::Sparse(sparse::ShardedMap::new()),
             metadata,
             label_set,
+            unknown_labels: UnknownLabels::default(),
+        }
+    }
+
+    /// Log a warning the first time the number of active series crosses `threshold`, and again at every
+    /// multiple of `threshold`. This gives an early warning of a cardinality explosion from runaway dynamic labels.
+    ///
+    /// The series are only counted when a new label group is inserted, so this adds no overhead to existing series.
+    /// The `name` is included in the warning to identify the metric.
+    ///
+    /// # Note
+    /// This does nothing if the metric vec is not 'sparse', as dense metric vecs already have a fixed cardinality.
+    ///
+    /// ```
+    /// use std::num::NonZeroUsize;
+    /// use measured::CounterVec;
+    /// use measured::label::StaticLabelSet;
+    ///
+    /// # #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+    /// # #[label(singleton = "code")]
+    /// # enum Code { Ok, Err }
+    /// let requests = CounterVec::<StaticLabelSet<Code>>::sparse()
+    ///     .with_cardinality_warning("requests", NonZeroUsize::new(1000).unwrap());
+    /// ```
+    #[cfg(feature = "log")]
+    pub fn with_cardinality_warning(
+        mut self,
+        name: &'static str,
+        threshold: std::num::NonZeroUsize,
+    ) -> Self {
+        if let VecInner::Sparse(metrics) = &mut self.metrics {
+            metrics.warning = Some(sparse::CardinalityWarning::new(name, threshold));
+        }
+        self
+    }
+
+    /// Stop creating new series once the metric vec holds `max` series. This is a hard limit
+    /// against a cardinality explosion from untrusted label values.
+    ///
+    /// Once the limit is reached, updates to any new label group are dropped. They are applied to a hidden
+    /// overflow series which is never encoded, so they have no visible effect. Existing series are
+    /// still updated as normal, and new series can be created again after a series is removed with
+    /// [`remove_metric`](MetricVec::remove_metric). Every dropped update is counted in
+    /// [`dropped_updates`](MetricVec::dropped_updates), which can be exposed as a metric with [`WithDroppedSeries`].
+    ///
+    /// # Note
+    /// This does nothing if the metric vec is not 'sparse', as dense metric vecs already have a fixed cardinality.
+    ///
+    /// ```
+    /// use std::num::NonZeroUsize;
+    /// use measured::CounterVec;
+    /// use measured::label::StaticLabelSet;
+    ///
+    /// # #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+    /// # #[label(singleton = "code")]
+    /// # enum Code { Ok, NotFound, Internal }
+    /// let requests = CounterVec::<StaticLabelSet<Code>>::sparse()
+    ///     .with_max_series(NonZeroUsize::new(2).unwrap());
+    ///
+    /// requests.inc(Code::Ok);
+    /// requests.inc(Code::NotFound);
+    /// requests.inc(Code::Internal); // dropped
+    ///
+    /// assert_eq!(requests.get_cardinality().0, 2);
+    /// assert_eq!(requests.dropped_updates(), 1);
+    /// ```
+    pub fn with_max_series(mut self, max: std::num::NonZeroUsize) -> Self {
+        if let VecInner::Sparse(metrics) = &mut self.metrics {
+            metrics.limit = Some(sparse::SeriesLimit::new(max.get(), None));
+        }
+        self
+    }
+
+    /// Like [`with_max_series`](MetricVec::with_max_series), but once the limit is reached, updates to any new
+    /// label group are routed to the `overflow` label group instead of being dropped.
+    ///
+    /// This keeps the totals of the metric family correct under a cardinality explosion, and makes the overflow
+    /// visible, like a `route="__overflow__"` series. The overflow series is collected once it was first accessed.
+    /// It does not count towards `max`, but it is included in [`get_cardinality`](MetricVec::get_cardinality).
+    /// Updates to the `overflow` label group itself also go to the overflow series. Every routed update is counted
+    /// in [`dropped_updates`](MetricVec::dropped_updates).
+    ///
+    /// # Note
+    /// This does nothing if the metric vec is not 'sparse', as dense metric vecs already have a fixed cardinality.
+    ///
+    /// ```
+    /// use std::num::NonZeroUsize;
+    /// use measured::{CounterVec, LabelGroup};
+    /// use measured::label::StaticLabelSet;
+    /// use measured::metric::name::MetricName;
+    /// use measured::metric::MetricFamilyEncoding;
+    /// use measured::text::BufferedTextEncoder;
+    ///
+    /// # #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+    /// # #[label(singleton = "code")]
+    /// # enum Code { Ok, NotFound, Internal, Overflow }
+    /// let requests = CounterVec::<StaticLabelSet<Code>>::sparse()
+    ///     .with_max_series_overflow(NonZeroUsize::new(1).unwrap(), Code::Overflow);
+    ///
+    /// requests.inc(Code::Ok);
+    /// requests.inc(Code::NotFound); // routed to the overflow
+    /// requests.inc(Code::Internal); // routed to the overflow
+    ///
+    /// let mut enc = BufferedTextEncoder::new();
+    /// requests.collect_family_into(MetricName::from_str("requests_total"), &mut enc).unwrap();
+    /// assert_eq!(
+    ///     enc.finish(),
+    ///     r#"## TYPE requests_total counter
+    /// requests_total{code="ok"} 1
+    /// requests_total{code="overflow"} 2
+    /// "#[1..],
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the `overflow` label group is not contained within the label set.
+    pub fn with_max_series_overflow(
+        mut self,
+        max: std::num::NonZeroUsize,
+        overflow: L::Group<'_>,
+    ) -> Self {
+        let key = self
+            .label_set
+            .encode(overflow)
+            .expect("label group was not contained within this label set");
+        if let VecInner::Sparse(metrics) = &mut self.metrics {
+            metrics.limit = Some(sparse::SeriesLimit::new(max.get(), Some(key)));
+        }
+        self
+    }
+
+    /// The number of updates that were dropped, or routed to the overflow series, because the metric vec
+    /// reached its series limit.
+    ///
+    /// This is always 0 if no limit was set with [`with_max_series`](MetricVec::with_max_series) or
+    /// [`with_max_series_overflow`](MetricVec::with_max_series_overflow).
+    pub fn dropped_updates(&self) -> u64 {
+        match &self.metrics {
+            VecInner::Sparse(sparse::ShardedMap {
+                limit: Some(limit), ..
+            }) => limit.dropped(),
+            _ => 0,
+        }
+    }
+
+    /// Record when each series was last accessed, so that idle series can be removed with
+    /// [`expire_older_than`](MetricVec::expire_older_than).
+    ///
+    /// This bounds the memory of a metric vec with churny label values, like a counter per client IP.
+    /// The access time is kept in whole seconds per series. Accesses do not read the system clock, but a
+    /// coarse clock shared by the metric vec, which is advanced when a series is created, when the metric vec
+    /// is collected, and by [`expire_older_than`](MetricVec::expire_older_than). An access is recorded at the
+    /// time the clock was last advanced, and the series is only written to once per second.
+    /// Collecting the metric vec does not count as an access.
+    ///
+    /// # Note
+    /// This does nothing if the metric vec is not 'sparse', as dense metric vecs cannot remove series.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use measured::CounterVec;
+    /// use measured::label::StaticLabelSet;
+    ///
+    /// # #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+    /// # #[label(singleton = "code")]
+    /// # enum Code { Ok, NotFound }
+    /// let requests = CounterVec::<StaticLabelSet<Code>>::sparse().with_idle_tracking();
+    /// requests.inc(Code::Ok);
+    ///
+    /// // run periodically
+    /// let expired = requests.expire_older_than(Duration::from_secs(600));
+    /// assert_eq!(expired, 0);
+    /// ```
+    pub fn with_idle_tracking(mut self) -> Self {
+        if let VecInner::Sparse(metrics) = &mut self.metrics {
+            metrics.track_accesses();
+        }
+        self
+    }
+
+    /// Remove every series that was not accessed within `age`, returning the number of removed series.
+    ///
+    /// Ages are measured in whole seconds, so a series can be kept for up to a second longer than `age`.
+    /// Accesses are recorded with the coarse clock described in [`with_idle_tracking`](MetricVec::with_idle_tracking),
+    /// so if the metric vec is rarely collected, a series can appear idle for longer than it is.
+    /// A series that is accessed again after it was removed starts again from its initial state.
+    ///
+    /// This always returns 0 if the metric vec was not created [`with_idle_tracking`](MetricVec::with_idle_tracking).
+    pub fn expire_older_than(&self, age: std::time::Duration) -> usize {
+        match &self.metrics {
+            VecInner::Dense(_) => 0,
+            VecInner::Sparse(metrics) => metrics.expire_older_than(age),
         }
     }
 
@@ -340,7 +610,26 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
     /// Returns None if the label group is not contained within the label set.
     pub fn try_with_labels(&self, label: L::Group<'_>) -> Option<LabelId<L>> {
         let id = self.label_set.encode(label)?;
+        self.remove_reassigned();
+        Some(self.id_from_unique(id))
+    }
+
+    /// Remove the series whose encoding the label set handed to a new label group, like the slots of an evicted
+    /// [`LruCapped`](crate::label::LruCapped) value, so that the new label group starts from a fresh series.
+    ///
+    /// Dense vecs cannot remove series, and label sets which reassign encodings are dynamic, so this only applies to
+    /// sparse vecs.
+    fn remove_reassigned(&self) {
+        let Some(reassigned) = self.label_set.take_reassigned() else {
+            return;
+        };
+        if let VecInner::Sparse(metrics) = &self.metrics {
+            metrics.remove_where(|id| reassigned(id));
+        }
+    }
 
+    /// Get the identifier for an already encoded label group
+    fn id_from_unique(&self, id: L::Unique) -> LabelId<L> {
         let hash = match &self.metrics {
             VecInner::Dense(metrics) => {
                 let index = self.label_set.encode_dense(id).expect("If the label set is fixed in cardinality, it must return a value here in the range of `0..cardinality`");
@@ -350,7 +639,44 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
             VecInner::Sparse(metrics) => metrics.hasher.hash_one(id),
         };
 
-        Some(LabelId(LabelIdInner { id, hash }))
+        LabelId(LabelIdInner { id, hash })
+    }
+
+    /// Get the identifier for the label group, or count the update as dropped if the label group
+    /// is not contained within the label set and the vec is set to drop unknown labels.
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set, and unknown labels are not dropped.
+    fn with_labels_or_drop(&self, label: L::Group<'_>) -> Option<LabelId<L>> {
+        if !self.unknown_labels.drop {
+            return Some(self.with_labels(label));
+        }
+
+        let id = self.try_with_labels(label);
+        if id.is_none() {
+            let dropped = self
+                .unknown_labels
+                .dropped
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            // only warn once, as this is likely to be in a hot path
+            #[cfg(feature = "log")]
+            if dropped == 0 {
+                log::warn!("dropping updates to a label group that is not in the label set");
+            }
+            #[cfg(not(feature = "log"))]
+            let _ = dropped;
+        }
+        id
+    }
+
+    /// Decode the identifier back into the label group it was created from.
+    ///
+    /// This is useful for diagnostics, such as logging which series a [`LabelId`] refers to.
+    ///
+    /// # Panics
+    /// Can panic or cause strange behaviour if the label ID comes from a different metric family.
+    pub fn decode_id(&self, id: LabelId<L>) -> L::Group<'_> {
+        self.label_set.decode(&id.0.id)
     }
 
     /// Get the individual metric at the given identifier.
@@ -361,6 +687,64 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
         MetricLockGuard(self.metrics.get_metric(id.0), &self.metadata)
     }
 
+    /// Get the individual metric at the given identifier, or `None` if the series was not created yet.
+    ///
+    /// Unlike [`get_metric`](Self::get_metric), this never creates the series, so it is suited to reading values.
+    ///
+    /// # Panics
+    /// Can panic or cause strange behaviour if the label ID comes from a different metric family.
+    pub fn find_metric(&self, id: LabelId<L>) -> Option<MetricLockGuard<'_, M>> {
+        let m = self.metrics.find_metric(id.0)?;
+        Some(MetricLockGuard(m, &self.metadata))
+    }
+
+    /// Get the index of the series in the dense storage, or `None` if this metric vec is 'sparse'.
+    ///
+    /// The index is computed once by [`with_labels`](Self::with_labels), so reusing a [`LabelId`] already
+    /// skips the encoding. The plain index is useful to key other per-series state, and to access the series
+    /// with [`get_metric_at`](Self::get_metric_at).
+    pub fn dense_index(&self, id: LabelId<L>) -> Option<usize> {
+        match &self.metrics {
+            VecInner::Dense(_) => Some(id.0.hash as usize),
+            VecInner::Sparse(_) => None,
+        }
+    }
+
+    /// Run `f` with the individual metric at the given index in the dense storage.
+    ///
+    /// ```
+    /// use measured::CounterVec;
+    /// use measured::label::StaticLabelSet;
+    ///
+    /// # #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+    /// # #[label(singleton = "code")]
+    /// # enum Code { Ok, Err }
+    /// let requests = CounterVec::<StaticLabelSet<Code>>::dense();
+    /// let index = requests.dense_index(requests.with_labels(Code::Ok)).unwrap();
+    ///
+    /// for _ in 0..10 {
+    ///     requests.get_metric_at(index, |m| m.inc());
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if this metric vec is 'sparse', or if the index is out of range.
+    /// The index should come from [`dense_index`](Self::dense_index) on this metric vec.
+    pub fn get_metric_at<R>(
+        &self,
+        dense_index: usize,
+        f: impl FnOnce(MetricLockGuard<'_, M>) -> R,
+    ) -> R {
+        let VecInner::Dense(metrics) = &self.metrics else {
+            panic!("get_metric_at needs a dense metric vec");
+        };
+        let m = metrics[dense_index].get_or_init(M::default);
+        f(MetricLockGuard(
+            MetricLockGuardRepr::Dense(m),
+            &self.metadata,
+        ))
+    }
+
     /// Remove the metric with the given label, returning it's inner state.
     ///
     /// # Note
@@ -377,11 +761,25 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
         }
     }
 
+    /// Remove the metric with the given label group, returning whether it was present.
+    ///
+    /// This is for evicting series that have gone stale, like the series of a deleted customer.
+    /// Use [`remove_metric`](Self::remove_metric) if the label ID is already known, or to get the removed state.
+    ///
+    /// # Note
+    /// 'dense' metrics cannot be removed, and will always return false.
+    /// A label group that is not contained within the label set is never present, and also returns false.
+    pub fn remove(&self, label: L::Group<'_>) -> bool {
+        self.try_with_labels(label)
+            .and_then(|id| self.remove_metric(id))
+            .is_some()
+    }
+
     /// Get the individual metric at the given identifier.
     ///
     /// # Panics
     /// Can panic or cause strange behaviour if the label ID comes from a different metric family.
-    pub fn get_metric_mut(&mut self, id: LabelId<L>) -> MetricMut<M> {
+    pub fn get_metric_mut(&mut self, id: LabelId<L>) -> MetricMut<'_, M> {
         MetricMut(self.metrics.get_metric_mut(id.0), &self.metadata)
     }
 
@@ -402,11 +800,41 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
     }
 }
 
+impl<M: ResetMetric, L: LabelGroupSet> MetricVec<M, L> {
+    /// Reset every series of this metric vec.
+    ///
+    /// The series of a 'dense' metric vec are reset to zero in place. The series of a 'sparse' metric vec
+    /// are removed, but the allocated capacity of the map is kept for the series that are added later.
+    ///
+    /// The reset is not atomic with concurrent updates. An update which races with the reset of its series
+    /// can be lost, if it lands just before the series is reset or removed. Use
+    /// [`DoubleBufferedVec`](double_buffered::DoubleBufferedVec) to reset on collection without losing updates.
+    pub fn clear(&self) {
+        match &self.metrics {
+            VecInner::Dense(metrics) => {
+                for metric in metrics.iter().filter_map(|m| m.get()) {
+                    metric.reset();
+                }
+            }
+            VecInner::Sparse(metrics) => {
+                metrics.clear();
+                if let Some(limit) = &metrics.limit {
+                    limit.overflow.reset();
+                }
+            }
+        }
+    }
+}
+
 /// Defines the encoding of a metric
 pub trait MetricEncoding<T: Encoding>: MetricType {
     /// Write the type information for this metric into the encoder
     fn write_type(name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err>;
-    /// Sample this metric into the encoder
+    /// Sample this metric into the encoder.
+    ///
+    /// A metric may write any number of samples for its series, as long as they all belong to the same family.
+    /// For example, histograms write a `_bucket` sample for each bucket, with an extra `le` label, followed by
+    /// the `_sum` and `_count` samples.
     fn collect_into(
         &self,
         metadata: &Self::Metadata,
@@ -431,6 +859,34 @@ impl<M: MetricFamilyEncoding<T>, T: Encoding> MetricFamilyEncoding<T> for Option
     }
 }
 
+/// Metrics can be shared across threads with an [`Arc`](std::sync::Arc), and still be collected as part of a
+/// [`MetricGroup`](group::MetricGroup). The updates go through the `Arc` without any extra synchronisation.
+///
+/// ```
+/// use std::sync::Arc;
+/// use measured::{Counter, MetricGroup};
+///
+/// #[derive(MetricGroup)]
+/// struct Metrics {
+///     /// jobs processed by all workers
+///     jobs: Arc<Counter>,
+/// }
+///
+/// let metrics = Metrics { jobs: Arc::new(Counter::new()) };
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let jobs = Arc::clone(&metrics.jobs);
+///         std::thread::spawn(move || jobs.inc())
+///     })
+///     .collect();
+/// # for w in workers { w.join().unwrap(); }
+/// ```
+impl<M: MetricFamilyEncoding<T> + ?Sized, T: Encoding> MetricFamilyEncoding<T> for Arc<M> {
+    fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
+        M::collect_family_into(self, name, enc)
+    }
+}
+
 impl<M: MetricEncoding<T>, T: Encoding> MetricFamilyEncoding<T> for Metric<M> {
     /// Collect this metric value into the given encoder with the given metric name
     fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
@@ -445,31 +901,468 @@ impl<M: MetricEncoding<T>, L: LabelGroupSet, T: Encoding> MetricFamilyEncoding<T
 {
     fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
         M::write_type(&name, enc)?;
+        self.collect_samples_into(&name, enc)
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
+    /// Sample all the metrics into the encoder, without writing the type information.
+    fn collect_samples_into<T: Encoding>(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut T,
+    ) -> Result<(), T::Err>
+    where
+        M: MetricEncoding<T>,
+    {
+        self.for_each_series(|labels, value| value.collect_into(&self.metadata, labels, &name, enc))
+    }
+
+    /// Visit every series that has been initialised, along with its labels.
+    fn for_each_series<E>(
+        &self,
+        mut f: impl FnMut(L::Group<'_>, &M) -> Result<(), E>,
+    ) -> Result<(), E> {
         match &self.metrics {
             VecInner::Dense(m) => {
                 for (index, value) in m.iter().enumerate() {
                     if let Some(value) = value.get() {
-                        value.collect_into(
-                            &self.metadata,
-                            self.label_set.decode_dense(index),
-                            &name,
-                            enc,
-                        )?;
+                        f(self.label_set.decode_dense(index), value)?;
                     }
                 }
             }
             VecInner::Sparse(m) => {
-                for shard in m.shards.iter() {
-                    for (k, v) in shard.read().iter() {
-                        v.collect_into(&self.metadata, self.label_set.decode(k), &name, enc)?;
-                    }
+                m.for_each_entry(|k, v| f(self.label_set.decode(k), v))?;
+                if let Some((k, v)) = m.overflow_series() {
+                    f(self.label_set.decode(k), v)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Merges two metric vecs into a single metric family.
+///
+/// If multiple components each define their own metric vec with the same name, collecting them separately
+/// would produce the `# TYPE` line twice, which is not a valid exposition.
+/// Merging them emits the type once, followed by the samples from both vecs.
+///
+/// Both vecs must have the same metric type, so mismatched families (eg a counter and a gauge) are a compile error.
+/// The label set types may differ, but every series must use the same label names, and the same series
+/// must not be defined by both vecs. Series that break these rules are skipped during collection.
+/// The number of skipped series is then written as a gauge with the [`SkippedSeries`] suffix after the family,
+/// and each one is logged as a warning if the `log` feature is enabled. Use [`MergedFamily::check`] to detect
+/// them up front.
+/// Each vec keeps its own metadata, like histogram thresholds.
+///
+/// ```
+/// use measured::{CounterVec, FixedCardinalityLabel, LabelGroup};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::{MergedFamily, MetricFamilyEncoding};
+/// use measured::metric::name::MetricName;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "plugin")]
+/// enum PluginA { Auth }
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "plugin")]
+/// enum PluginB { Cache }
+///
+/// let a = CounterVec::<StaticLabelSet<PluginA>>::new();
+/// let b = CounterVec::<StaticLabelSet<PluginB>>::new();
+/// a.inc(PluginA::Auth);
+/// b.inc(PluginB::Cache);
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// MergedFamily(&a, &b)
+///     .collect_family_into(MetricName::from_str("http_requests_total"), &mut enc)
+///     .unwrap();
+///
+/// assert_eq!(
+///     enc.finish(),
+///     r#"# TYPE http_requests_total counter
+/// http_requests_total{plugin="auth"} 1
+/// http_requests_total{plugin="cache"} 1
+/// "#
+/// );
+/// ```
+pub struct MergedFamily<A, B>(pub A, pub B);
+
+impl<M, L1, L2, T> MetricFamilyEncoding<T> for MergedFamily<&MetricVec<M, L1>, &MetricVec<M, L2>>
+where
+    M: MetricEncoding<T>,
+    L1: LabelGroupSet,
+    L2: LabelGroupSet,
+    T: Encoding,
+    gauge::GaugeState: MetricEncoding<T>,
+{
+    fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
+        M::write_type(&name, enc)?;
+
+        let mut series = MergedSeries::default();
+        self.0
+            .for_each_series(|labels, value| match series.insert(&labels) {
+                Ok(()) => value.collect_into(&self.0.metadata, labels, &name, enc),
+                Err(_err) => {
+                    #[cfg(feature = "log")]
+                    log::warn!("skipping series in merged metric family: {_err}");
+                    Ok(())
+                }
+            })?;
+        self.1
+            .for_each_series(|labels, value| match series.insert(&labels) {
+                Ok(()) => value.collect_into(&self.1.metadata, labels, &name, enc),
+                Err(_err) => {
+                    #[cfg(feature = "log")]
+                    log::warn!("skipping series in merged metric family: {_err}");
+                    Ok(())
                 }
+            })?;
+
+        if series.skipped == 0 {
+            return Ok(());
+        }
+        let skipped = name.by_ref().with_suffix(SkippedSeries);
+        gauge::GaugeState::write_type(&skipped, enc)?;
+        gauge::GaugeState::new(series.skipped as i64).collect_into(
+            &crate::atomic::MemoryOrdering::Relaxed,
+            NoLabels,
+            &skipped,
+            enc,
+        )
+    }
+}
+
+/// `_skipped_series`. The [`Suffix`](name::Suffix) of the gauge written by [`MergedFamily`]
+pub struct SkippedSeries;
+
+impl name::Suffix for SkippedSeries {
+    fn encode_text(&self, b: &mut impl std::io::Write) -> std::io::Result<()> {
+        b.write_all(b"_skipped_series")
+    }
+    fn encode_len(&self) -> usize {
+        15
+    }
+}
+
+impl<A: Reset, B: Reset> Reset for MergedFamily<A, B> {
+    fn reset(&self) {
+        self.0.reset();
+        self.1.reset();
+    }
+}
+
+impl<M, L1, L2> MergedFamily<&MetricVec<M, L1>, &MetricVec<M, L2>>
+where
+    M: MetricType,
+    L1: LabelGroupSet,
+    L2: LabelGroupSet,
+{
+    /// Checks that the series of both vecs form a valid metric family.
+    ///
+    /// Returns the first series that uses different label names than the others,
+    /// or that is defined by both vecs.
+    pub fn check(&self) -> Result<(), MergedFamilyError> {
+        let mut series = MergedSeries::default();
+        self.0.for_each_series(|labels, _| series.insert(&labels))?;
+        self.1.for_each_series(|labels, _| series.insert(&labels))
+    }
+}
+
+/// Tracks the series seen while merging metric vecs into one family.
+#[derive(Default)]
+struct MergedSeries {
+    /// The label names of the first series, as written by [`LabelBytes`](crate::sample::LabelBytes)
+    names: Option<Vec<u8>>,
+    /// The label names of the current series
+    scratch: Vec<u8>,
+    /// The label values of every series so far, back to back
+    values: Vec<u8>,
+    /// The range of each series in `values`
+    seen: hashbrown::HashTable<std::ops::Range<usize>>,
+    hasher: std::hash::BuildHasherDefault<rustc_hash::FxHasher>,
+    /// The number of series that were skipped
+    skipped: u64,
+}
+
+impl MergedSeries {
+    fn insert(&mut self, labels: &impl LabelGroup) -> Result<(), MergedFamilyError> {
+        self.scratch.clear();
+        let start = self.values.len();
+        labels.visit_values(&mut crate::sample::LabelBytes {
+            names: &mut self.scratch,
+            values: &mut self.values,
+        });
+        let res = self.check(start);
+        if res.is_err() {
+            self.values.truncate(start);
+            self.skipped += 1;
+        }
+        res
+    }
+
+    /// Checks the series at the end of `values`, starting at `start`
+    fn check(&mut self, start: usize) -> Result<(), MergedFamilyError> {
+        use crate::sample::split_label_bytes;
+
+        match &self.names {
+            Some(expected) if *expected != self.scratch => {
+                return Err(MergedFamilyError::LabelNames {
+                    expected: split_label_bytes(expected),
+                    found: split_label_bytes(&self.scratch),
+                })
             }
+            Some(_) => {}
+            None => self.names = Some(self.scratch.clone()),
         }
+
+        let values = &self.values;
+        let series = &values[start..];
+        let hash = self.hasher.hash_one(series);
+        if self
+            .seen
+            .find(hash, |range| values[range.clone()] == *series)
+            .is_some()
+        {
+            let pairs = split_label_bytes(&self.scratch)
+                .into_iter()
+                .zip(split_label_bytes(series))
+                .collect();
+            return Err(MergedFamilyError::DuplicateSeries(pairs));
+        }
+
+        let hasher = &self.hasher;
+        self.seen.insert_unique(hash, start..values.len(), |range| {
+            hasher.hash_one(&values[range.clone()])
+        });
         Ok(())
     }
 }
 
+/// The reason a [`MergedFamily`] is not a valid metric family.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergedFamilyError {
+    /// A series uses different label names than the series before it.
+    LabelNames {
+        /// The label names of the first series
+        expected: Vec<String>,
+        /// The label names of the offending series
+        found: Vec<String>,
+    },
+    /// The same series, given as its label pairs, is defined by both vecs.
+    DuplicateSeries(Vec<(String, String)>),
+}
+
+impl std::fmt::Display for MergedFamilyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergedFamilyError::LabelNames { expected, found } => {
+                write!(f, "series has label names {found:?}, expected {expected:?}")
+            }
+            MergedFamilyError::DuplicateSeries(labels) => {
+                write!(f, "series {labels:?} is defined more than once")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergedFamilyError {}
+
+/// Exposes a metric family under a second name, sharing the same underlying storage.
+///
+/// This is useful when renaming a metric, so that dashboards and alerts using the old name
+/// keep working while they are migrated. The samples are only recorded once,
+/// but they are encoded twice, which temporarily doubles the scrape size for this family.
+///
+/// The alias is encoded after the primary name. Any help text is only written for the primary name.
+///
+/// ```
+/// use measured::Counter;
+/// use measured::metric::{Aliased, MetricFamilyEncoding};
+/// use measured::metric::name::MetricName;
+/// use measured::text::BufferedTextEncoder;
+///
+/// let requests = Aliased::new(Counter::new(), MetricName::from_str("http_requests"));
+/// requests.inc();
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// requests
+///     .collect_family_into(MetricName::from_str("http_requests_total"), &mut enc)
+///     .unwrap();
+///
+/// assert_eq!(
+///     enc.finish(),
+///     r#"# TYPE http_requests_total counter
+/// http_requests_total 1
+///
+/// ## TYPE http_requests counter
+/// http_requests 1
+/// "#
+/// );
+/// ```
+pub struct Aliased<M> {
+    metric: M,
+    alias: &'static MetricName,
+}
+
+impl<M> Aliased<M> {
+    /// Also expose the metric under the `alias` name
+    pub fn new(metric: M, alias: &'static MetricName) -> Self {
+        Self { metric, alias }
+    }
+
+    /// The alias that this metric is also exposed as
+    pub fn alias(&self) -> &'static MetricName {
+        self.alias
+    }
+
+    /// Extract the inner metric
+    pub fn into_inner(self) -> M {
+        self.metric
+    }
+}
+
+impl<M> Deref for Aliased<M> {
+    type Target = M;
+
+    fn deref(&self) -> &Self::Target {
+        &self.metric
+    }
+}
+
+impl<M> DerefMut for Aliased<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.metric
+    }
+}
+
+impl<M: MetricFamilyEncoding<T>, T: Encoding> MetricFamilyEncoding<T> for Aliased<M> {
+    fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
+        self.metric.collect_family_into(name, enc)?;
+        self.metric.collect_family_into(self.alias, enc)
+    }
+}
+
+impl<M: Reset> Reset for Aliased<M> {
+    fn reset(&self) {
+        self.metric.reset();
+    }
+}
+
+/// `_dropped_updates_total`. The [`Suffix`](name::Suffix) of the counter written by [`WithDroppedSeries`]
+pub struct DroppedUpdatesTotal;
+
+impl name::Suffix for DroppedUpdatesTotal {
+    fn encode_text(&self, b: &mut impl std::io::Write) -> std::io::Result<()> {
+        b.write_all(b"_dropped_updates_total")
+    }
+    fn encode_len(&self) -> usize {
+        22
+    }
+}
+
+/// Exposes the updates that a metric vec dropped because of its series limit, next to the metric vec itself.
+///
+/// After the metric family of the metric vec, this writes a counter with the [`DroppedUpdatesTotal`] suffix,
+/// holding [`dropped_updates`](MetricVec::dropped_updates). That is every update to a label group that could
+/// not get its own series, so a label group that is updated many times while the metric vec is full is counted
+/// many times.
+///
+/// ```
+/// use std::num::NonZeroUsize;
+/// use measured::CounterVec;
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::{MetricFamilyEncoding, WithDroppedSeries};
+/// use measured::metric::name::MetricName;
+/// use measured::text::BufferedTextEncoder;
+///
+/// # #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+/// # #[label(singleton = "code")]
+/// # enum Code { Ok, NotFound }
+/// let requests = WithDroppedSeries::new(
+///     CounterVec::<StaticLabelSet<Code>>::sparse().with_max_series(NonZeroUsize::MIN),
+/// );
+///
+/// requests.inc(Code::Ok);
+/// requests.inc(Code::NotFound); // dropped
+/// requests.inc(Code::NotFound); // dropped
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// requests
+///     .collect_family_into(MetricName::from_str("requests_total"), &mut enc)
+///     .unwrap();
+///
+/// assert_eq!(
+///     enc.finish(),
+///     concat!(
+///         "# TYPE requests_total counter\n",
+///         "requests_total{code=\"ok\"} 1\n",
+///         "\n",
+///         "# TYPE requests_total_dropped_updates_total counter\n",
+///         "requests_total_dropped_updates_total 2\n",
+///     )
+/// );
+/// ```
+pub struct WithDroppedSeries<M> {
+    metric: M,
+}
+
+impl<M> WithDroppedSeries<M> {
+    /// Also expose the dropped updates of the metric vec
+    pub fn new(metric: M) -> Self {
+        Self { metric }
+    }
+
+    /// Extract the inner metric vec
+    pub fn into_inner(self) -> M {
+        self.metric
+    }
+}
+
+impl<M> Deref for WithDroppedSeries<M> {
+    type Target = M;
+
+    fn deref(&self) -> &Self::Target {
+        &self.metric
+    }
+}
+
+impl<M> DerefMut for WithDroppedSeries<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.metric
+    }
+}
+
+impl<M, L, T> MetricFamilyEncoding<T> for WithDroppedSeries<MetricVec<M, L>>
+where
+    M: MetricEncoding<T>,
+    L: LabelGroupSet,
+    T: Encoding,
+    counter::CounterState: MetricEncoding<T>,
+{
+    fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
+        self.metric.collect_family_into(&name, enc)?;
+
+        let dropped = name.by_ref().with_suffix(DroppedUpdatesTotal);
+        counter::CounterState::write_type(&dropped, enc)?;
+        counter::CounterState::new(self.metric.dropped_updates()).collect_into(
+            &crate::atomic::MemoryOrdering::Relaxed,
+            NoLabels,
+            &dropped,
+            enc,
+        )
+    }
+}
+
+impl<M: Reset> Reset for WithDroppedSeries<M> {
+    fn reset(&self) {
+        self.metric.reset();
+    }
+}
+
 pub struct LabelId<L: LabelGroupSet>(LabelIdInner<L::Unique>);
 
 #[derive(Clone, Copy)]
@@ -478,6 +1371,12 @@ pub struct LabelIdInner<U> {
     hash: u64,
 }
 
+impl<L: LabelGroupSet> core::fmt::Debug for LabelId<L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("LabelId").field(&self.0.hash).finish()
+    }
+}
+
 impl<L: LabelGroupSet> Clone for LabelId<L> {
     fn clone(&self) -> Self {
         *self
@@ -499,7 +1398,11 @@ impl<L: Hash + Eq> Eq for LabelIdInner<L> {}
 
 #[cfg(test)]
 mod tests {
-    use crate::{CounterVec, FixedCardinalityLabel, LabelGroup};
+    use crate::{
+        metric::{name::MetricName, MergedFamily, MergedFamilyError, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        CounterVec, FixedCardinalityLabel, LabelGroup,
+    };
 
     #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
     #[label(crate = crate, set = ErrorsSet)]
@@ -515,6 +1418,106 @@ mod tests {
         Network,
     }
 
+    #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+    #[label(crate = crate, set = ReasonSet)]
+    struct Reason {
+        reason: ErrorKind,
+    }
+
+    fn encode_merged(family: impl MetricFamilyEncoding<BufferedTextEncoder>) -> String {
+        let mut enc = BufferedTextEncoder::new();
+        family
+            .collect_family_into(MetricName::from_str("errors"), &mut enc)
+            .unwrap();
+        String::from_utf8(enc.finish().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn merged_family() {
+        let a = CounterVec::<ErrorsSet>::new();
+        let b = CounterVec::<ErrorsSet>::new();
+        a.inc(Error {
+            kind: ErrorKind::User,
+        });
+        b.inc(Error {
+            kind: ErrorKind::Network,
+        });
+
+        assert_eq!(MergedFamily(&a, &b).check(), Ok(()));
+        assert_eq!(
+            encode_merged(MergedFamily(&a, &b)),
+            r#"# TYPE errors counter
+errors{kind="user"} 1
+errors{kind="network"} 1
+"#
+        );
+    }
+
+    #[test]
+    fn merged_family_duplicate_series() {
+        let a = CounterVec::<ErrorsSet>::new();
+        let b = CounterVec::<ErrorsSet>::new();
+        a.inc(Error {
+            kind: ErrorKind::User,
+        });
+        b.inc_by(
+            Error {
+                kind: ErrorKind::User,
+            },
+            2,
+        );
+        b.inc(Error {
+            kind: ErrorKind::Network,
+        });
+
+        assert_eq!(
+            MergedFamily(&a, &b).check(),
+            Err(MergedFamilyError::DuplicateSeries(vec![(
+                "kind".to_owned(),
+                "user".to_owned()
+            )]))
+        );
+        assert_eq!(
+            encode_merged(MergedFamily(&a, &b)),
+            r#"# TYPE errors counter
+errors{kind="user"} 1
+errors{kind="network"} 1
+
+# TYPE errors_skipped_series gauge
+errors_skipped_series 1
+"#
+        );
+    }
+
+    #[test]
+    fn merged_family_label_names() {
+        let a = CounterVec::<ErrorsSet>::new();
+        let b = CounterVec::<ReasonSet>::new();
+        a.inc(Error {
+            kind: ErrorKind::User,
+        });
+        b.inc(Reason {
+            reason: ErrorKind::Network,
+        });
+
+        assert_eq!(
+            MergedFamily(&a, &b).check(),
+            Err(MergedFamilyError::LabelNames {
+                expected: vec!["kind".to_owned()],
+                found: vec!["reason".to_owned()],
+            })
+        );
+        assert_eq!(
+            encode_merged(MergedFamily(&a, &b)),
+            r#"# TYPE errors counter
+errors{kind="user"} 1
+
+# TYPE errors_skipped_series gauge
+errors_skipped_series 1
+"#
+        );
+    }
+
     #[test]
     fn dense_cardinality() {
         let errors = CounterVec::<ErrorsSet>::dense();
@@ -529,6 +1532,33 @@ mod tests {
         assert_eq!(errors.get_cardinality(), (2, Some(3)));
     }
 
+    #[test]
+    fn clear() {
+        let user = Error {
+            kind: ErrorKind::User,
+        };
+        let internal = Error {
+            kind: ErrorKind::Internal,
+        };
+
+        let errors = CounterVec::<ErrorsSet>::dense();
+        errors.inc_by(user, 3);
+        errors.clear();
+        // dense series are reset in place
+        assert_eq!(errors.get_cardinality(), (1, Some(3)));
+        assert_eq!(errors.get(user), 0);
+
+        let errors = CounterVec::<ErrorsSet>::sparse().with_max_series(std::num::NonZeroUsize::MIN);
+        errors.inc_by(user, 3);
+        errors.clear();
+        assert_eq!(errors.get_cardinality(), (0, Some(3)));
+
+        // the cleared series no longer count towards the limit
+        errors.inc(internal);
+        assert_eq!(errors.get(internal), 1);
+        assert_eq!(errors.dropped_updates(), 0);
+    }
+
     #[test]
     fn sparse_cardinality() {
         let errors = CounterVec::<ErrorsSet>::sparse();
@@ -564,6 +1594,141 @@ mod tests {
         assert_eq!(user_errors.count.into_inner(), 1)
     }
 
+    #[test]
+    fn remove_by_label() {
+        let errors = CounterVec::<ErrorsSet>::sparse();
+        let user = Error {
+            kind: ErrorKind::User,
+        };
+
+        errors.inc(user);
+        assert!(errors.remove(user));
+        assert!(!errors.remove(user));
+        assert_eq!(errors.get_cardinality(), (0, Some(3)));
+
+        let dense = CounterVec::<ErrorsSet>::dense();
+        dense.inc(user);
+        assert!(!dense.remove(user));
+        assert_eq!(dense.get_cardinality(), (1, Some(3)));
+    }
+
+    #[test]
+    fn expire_idle() {
+        let mut errors = CounterVec::<ErrorsSet>::sparse().with_idle_tracking();
+        let error = |kind| Error { kind };
+        errors.inc(error(ErrorKind::User));
+        errors.inc(error(ErrorKind::Network));
+
+        // pretend the series were created 2 minutes ago
+        let super::VecInner::Sparse(super::sparse::ShardedMap {
+            shards: super::sparse::Shards::Tracked(_, clock),
+            ..
+        }) = &mut errors.metrics
+        else {
+            unreachable!("the vec is sparse and tracked")
+        };
+        clock.start = std::time::Instant::now()
+            .checked_sub(std::time::Duration::from_secs(120))
+            .unwrap();
+        clock.refresh();
+
+        errors.inc(error(ErrorKind::Network));
+        assert_eq!(
+            errors.expire_older_than(std::time::Duration::from_secs(60)),
+            1
+        );
+        assert_eq!(errors.get_cardinality(), (1, Some(3)));
+        assert_eq!(errors.get(error(ErrorKind::Network)), 2);
+        // an expired series starts again from zero
+        assert_eq!(errors.get(error(ErrorKind::User)), 0);
+
+        // without tracking, nothing expires
+        let untracked = CounterVec::<ErrorsSet>::sparse();
+        untracked.inc(error(ErrorKind::User));
+        assert_eq!(untracked.expire_older_than(std::time::Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn decode_id() {
+        let dense = CounterVec::<ErrorsSet>::dense();
+        let sparse = CounterVec::<ErrorsSet>::sparse();
+
+        for kind in [ErrorKind::User, ErrorKind::Internal, ErrorKind::Network] {
+            let error = Error { kind };
+            assert_eq!(dense.decode_id(dense.with_labels(error)), error);
+            assert_eq!(sparse.decode_id(sparse.with_labels(error)), error);
+        }
+    }
+
+    #[test]
+    fn dense_index() {
+        let dense = CounterVec::<ErrorsSet>::dense();
+        let sparse = CounterVec::<ErrorsSet>::sparse();
+
+        let error = Error {
+            kind: ErrorKind::Network,
+        };
+        assert_eq!(sparse.dense_index(sparse.with_labels(error)), None);
+
+        let index = dense.dense_index(dense.with_labels(error)).unwrap();
+        assert_eq!(index, 2);
+        dense.get_metric_at(index, |m| m.inc_by(2));
+        dense.inc(error);
+        let count = dense.get_metric_at(index, |m| m.load(std::sync::atomic::Ordering::Relaxed));
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn shared_with_arc() {
+        use std::sync::Arc;
+
+        use crate::{
+            metric::{name::MetricName, MetricFamilyEncoding},
+            text::BufferedTextEncoder,
+        };
+
+        let errors = Arc::new(CounterVec::<ErrorsSet>::dense());
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                let errors = Arc::clone(&errors);
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        errors.inc(Error {
+                            kind: ErrorKind::User,
+                        });
+                    }
+                });
+            }
+        });
+
+        let encode = |metric: &dyn Fn(&mut BufferedTextEncoder)| {
+            let mut enc = BufferedTextEncoder::new();
+            metric(&mut enc);
+            enc.finish()
+        };
+        let shared = encode(&|enc| {
+            errors
+                .collect_family_into(MetricName::from_str("errors"), enc)
+                .unwrap()
+        });
+        let direct = encode(&|enc| {
+            (*errors)
+                .collect_family_into(MetricName::from_str("errors"), enc)
+                .unwrap()
+        });
+        assert_eq!(shared, direct);
+        assert_eq!(
+            shared,
+            "# TYPE errors counter\nerrors{kind=\"user\"} 4000\n"
+        );
+    }
+
+    #[test]
+    #[should_panic = "get_metric_at needs a dense metric vec"]
+    fn get_metric_at_sparse() {
+        CounterVec::<ErrorsSet>::sparse().get_metric_at(0, |m| m.inc());
+    }
+
     #[cfg(feature = "lasso")]
     #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
     #[label(crate = crate, set = ErrorsSet2)]
@@ -607,4 +1772,151 @@ mod tests {
             }
         }
     }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn cardinality_warning() {
+        use super::sparse::CardinalityWarning;
+
+        let warning = CardinalityWarning::new("errors", std::num::NonZeroUsize::new(2).unwrap());
+        assert!(!warning.inserted());
+        assert!(warning.inserted());
+
+        // dropping below and crossing the threshold again does not warn again
+        warning.removed(1);
+        assert!(!warning.inserted());
+
+        assert!(!warning.inserted());
+        assert!(warning.inserted());
+    }
+
+    #[test]
+    fn max_series() {
+        let user = Error {
+            kind: ErrorKind::User,
+        };
+        let internal = Error {
+            kind: ErrorKind::Internal,
+        };
+        let network = Error {
+            kind: ErrorKind::Network,
+        };
+
+        let mut errors =
+            CounterVec::<ErrorsSet>::sparse().with_max_series(std::num::NonZeroUsize::MIN);
+        errors.inc(user);
+        errors.inc(internal);
+        errors.inc_mut(network);
+        errors.inc(user);
+        assert_eq!(errors.get_cardinality(), (1, Some(3)));
+        assert_eq!(errors.dropped_updates(), 2);
+        assert_eq!(
+            errors
+                .get_metric(errors.with_labels(user))
+                .count
+                .load(std::sync::atomic::Ordering::Relaxed),
+            2
+        );
+
+        // removing a series makes room for a new one
+        errors.remove_metric(errors.with_labels(user));
+        errors.inc(internal);
+        assert_eq!(errors.get_cardinality(), (1, Some(3)));
+        assert_eq!(errors.dropped_updates(), 2);
+
+        // dense vecs are not limited
+        let errors = CounterVec::<ErrorsSet>::dense().with_max_series(std::num::NonZeroUsize::MIN);
+        errors.inc(user);
+        errors.inc(internal);
+        assert_eq!(errors.get_cardinality(), (2, Some(3)));
+        assert_eq!(errors.dropped_updates(), 0);
+    }
+
+    #[test]
+    fn max_series_overflow() {
+        let user = Error {
+            kind: ErrorKind::User,
+        };
+        let internal = Error {
+            kind: ErrorKind::Internal,
+        };
+        let network = Error {
+            kind: ErrorKind::Network,
+        };
+
+        let mut errors = CounterVec::<ErrorsSet>::sparse()
+            .with_max_series_overflow(std::num::NonZeroUsize::MIN, network);
+        errors.inc(user);
+        errors.inc(internal);
+        errors.inc_mut(internal);
+        errors.inc(network);
+        // the overflow series is counted once it is used
+        assert_eq!(errors.get_cardinality(), (2, Some(3)));
+        // updates to the overflow label group are not dropped
+        assert_eq!(errors.dropped_updates(), 2);
+        assert_eq!(errors.get(network), 3);
+
+        let mut series = vec![];
+        errors
+            .for_each_series(|labels, m| {
+                series.push((labels, m.get()));
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(series, [(user, 1), (network, 3)]);
+
+        errors.clear();
+        assert_eq!(errors.get(network), 0);
+    }
+
+    #[test]
+    fn debug() {
+        use crate::{metric::histogram::Thresholds, Counter, FloatGauge, Histogram};
+
+        let counter = Counter::new();
+        counter.inc_by(3);
+        assert_eq!(format!("{counter:?}"), "CounterState { count: 3 }");
+
+        let gauge = FloatGauge::new();
+        gauge.set(1.5);
+        assert_eq!(format!("{gauge:?}"), "FloatGaugeState { count: 1.5 }");
+
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([1.0, 2.0]));
+        histogram.observe(0.5);
+        histogram.observe(4.0);
+        assert_eq!(
+            format!("{histogram:?}"),
+            "HistogramState { count: 2, sum: 4.5 }"
+        );
+        let metric = histogram.get_metric();
+        let guard = metric.inner.write();
+        assert_eq!(
+            format!("{histogram:?}"),
+            "HistogramState { inner: <locked> }"
+        );
+        drop(guard);
+
+        let errors = CounterVec::<ErrorsSet>::sparse();
+        let id = errors.with_labels(Error {
+            kind: ErrorKind::User,
+        });
+        errors.inc(Error {
+            kind: ErrorKind::User,
+        });
+        assert_eq!(
+            format!("{errors:?}"),
+            "MetricVec { metric: \"measured::metric::counter::CounterState\", storage: \"sparse\", series: 1, cardinality: Some(3) }"
+        );
+        assert!(format!("{id:?}").starts_with("LabelId("));
+
+        let errors = CounterVec::<ErrorsSet>::dense();
+        let id = errors.with_labels(Error {
+            kind: ErrorKind::Network,
+        });
+        assert_eq!(format!("{id:?}"), "LabelId(2)");
+        assert_eq!(
+            format!("{errors:?}"),
+            "MetricVec { metric: \"measured::metric::counter::CounterState\", storage: \"dense\", series: 0, cardinality: Some(3) }"
+        );
+    }
 }
@@ -2,14 +2,14 @@
 
 use core::sync::atomic::AtomicU64;
 
-use crate::{label::LabelGroupSet, Counter, CounterVec, LabelGroup};
+use crate::{atomic::MemoryOrdering, label::LabelGroupSet, Counter, CounterVec, LabelGroup};
 
 use super::{
     group::Encoding, name::MetricNameEncoder, MetricEncoding, MetricLockGuard, MetricMut,
     MetricType,
 };
 
-#[derive(Default)]
+#[derive(Default, Debug)]
 /// The internal state that is used by [`Counter`] and [`CounterVec`]
 pub struct CounterState {
     pub count: AtomicU64,
@@ -27,17 +27,83 @@ impl CounterState {
         }
     }
 
-    /// Increment the counter value by 1
+    /// Read the current counter value with the given memory ordering.
+    ///
+    /// This is useful for building custom exporters on top of the counter state.
+    pub fn load(&self, ordering: core::sync::atomic::Ordering) -> u64 {
+        self.count.load(ordering)
+    }
+
+    /// Read the current counter value, with relaxed ordering
+    pub fn get(&self) -> u64 {
+        self.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Increment the counter value by 1, with relaxed ordering.
+    ///
+    /// This does not know the [`MemoryOrdering`] of the metric. Use [`CounterLockGuard::inc`] to follow it.
     pub fn inc(&self) {
         self.count
             .fetch_add(1, core::sync::atomic::Ordering::Relaxed);
     }
 
-    /// Increment the counter value by `x`
+    /// Increment the counter value by `x`, with relaxed ordering. See [`inc`](Self::inc)
     pub fn inc_by(&self, x: u64) {
         self.count
             .fetch_add(x, core::sync::atomic::Ordering::Relaxed);
     }
+
+    /// Increment the counter value by 1, returning the new value
+    pub fn inc_returning(&self) -> u64 {
+        self.inc_by_returning(1)
+    }
+
+    /// Increment the counter value by `x`, with relaxed ordering, returning the new value. See [`inc`](Self::inc)
+    ///
+    /// Every increment observes a distinct value, so the counter can be used as a source of sequence numbers.
+    pub fn inc_by_returning(&self, x: u64) -> u64 {
+        self.count
+            .fetch_add(x, core::sync::atomic::Ordering::Relaxed)
+            .wrapping_add(x)
+    }
+}
+
+impl CounterLockGuard<'_> {
+    /// Read the current counter value
+    pub fn get(self) -> u64 {
+        self.count.load(self.metadata().load())
+    }
+
+    /// Increment the counter value by 1
+    pub fn inc(self) {
+        self.inc_by(1);
+    }
+
+    /// Increment the counter value by `x`
+    pub fn inc_by(self, x: u64) {
+        self.count.fetch_add(x, self.metadata().store());
+    }
+
+    /// Increment the counter value by 1, returning the new value
+    pub fn inc_returning(self) -> u64 {
+        self.inc_by_returning(1)
+    }
+
+    /// Increment the counter value by `x`, returning the new value
+    pub fn inc_by_returning(self, x: u64) -> u64 {
+        self.count
+            .fetch_add(x, self.metadata().store())
+            .wrapping_add(x)
+    }
+
+    /// Reset the counter value to 0, returning the previous value.
+    ///
+    /// The value is swapped atomically, so every increment is either included in the returned value
+    /// or counted after the reset. Reading the value with [`get`](Self::get) before resetting it instead
+    /// would lose the increments in between.
+    pub fn reset(self) -> u64 {
+        self.count.swap(0, self.metadata().store())
+    }
 }
 
 impl CounterMut<'_> {
@@ -53,6 +119,26 @@ impl CounterMut<'_> {
 }
 
 impl<L: LabelGroupSet> CounterVec<L> {
+    /// Read the current counter value, keyed by the label group.
+    ///
+    /// This does not create the series. A series that was not created yet reads as 0.
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set.
+    pub fn get(&self, label: L::Group<'_>) -> u64 {
+        self.find_metric(self.with_labels(label))
+            .map_or(0, CounterLockGuard::get)
+    }
+
+    /// Read the current counter value, keyed by the label group, or `None` if the label group
+    /// is not contained within the label set.
+    ///
+    /// This does not create the series. A series that was not created yet reads as 0.
+    pub fn try_get(&self, label: L::Group<'_>) -> Option<u64> {
+        let id = self.try_with_labels(label)?;
+        Some(self.find_metric(id).map_or(0, CounterLockGuard::get))
+    }
+
     /// Increment the counter value by 1, keyed by the label group
     pub fn inc(&self, label: L::Group<'_>) {
         self.get_metric(self.with_labels(label)).inc();
@@ -63,6 +149,16 @@ impl<L: LabelGroupSet> CounterVec<L> {
         self.get_metric(self.with_labels(label)).inc_by(y);
     }
 
+    /// Increment the counter value by 1, keyed by the label group, returning the new value
+    pub fn inc_returning(&self, label: L::Group<'_>) -> u64 {
+        self.get_metric(self.with_labels(label)).inc_returning()
+    }
+
+    /// Increment the counter value by `y`, keyed by the label group, returning the new value
+    pub fn inc_by_returning(&self, label: L::Group<'_>, y: u64) -> u64 {
+        self.get_metric(self.with_labels(label)).inc_by_returning(y)
+    }
+
     /// Increment the counter value by 1, keyed by the label group
     pub fn inc_mut(&mut self, label: L::Group<'_>) {
         self.get_metric_mut(self.with_labels(label)).inc()
@@ -75,6 +171,11 @@ impl<L: LabelGroupSet> CounterVec<L> {
 }
 
 impl Counter {
+    /// Read the current counter value
+    pub fn get(&self) -> u64 {
+        self.get_metric().get()
+    }
+
     /// Increment the counter value by 1
     pub fn inc(&self) {
         self.get_metric().inc()
@@ -85,6 +186,21 @@ impl Counter {
         self.get_metric().inc_by(x)
     }
 
+    /// Increment the counter value by 1, returning the new value
+    pub fn inc_returning(&self) -> u64 {
+        self.get_metric().inc_returning()
+    }
+
+    /// Increment the counter value by `x`, returning the new value
+    pub fn inc_by_returning(&self, x: u64) -> u64 {
+        self.get_metric().inc_by_returning(x)
+    }
+
+    /// Reset the counter value to 0, returning the previous value. See [`CounterLockGuard::reset`]
+    pub fn reset(&self) -> u64 {
+        self.get_metric().reset()
+    }
+
     /// Increment the counter value by 1
     pub fn inc_mut(&mut self) {
         self.get_metric_mut().inc()
@@ -97,8 +213,8 @@ impl Counter {
 }
 
 impl MetricType for CounterState {
-    /// [`Counter`]s require no additional metadata
-    type Metadata = ();
+    /// The memory ordering of the updates and collections. Relaxed by default
+    type Metadata = MemoryOrdering;
 }
 
 pub fn write_counter<Enc: Encoding>(
@@ -113,5 +229,141 @@ where
     CounterState {
         count: AtomicU64::new(value),
     }
-    .collect_into(&(), labels, name, enc)
+    .collect_into(&MemoryOrdering::Relaxed, labels, name, enc)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::{
+        atomic::MemoryOrdering, label::StaticLabelSet, metric::group::MetricValue,
+        sample::for_each_sample, Counter, CounterVec, FixedCardinalityLabel, MetricGroup,
+    };
+
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "kind")]
+    enum Kind {
+        Read,
+        Write,
+    }
+
+    #[test]
+    fn get() {
+        let counter = Counter::new();
+        assert_eq!(counter.get(), 0);
+        counter.inc_by(3);
+        assert_eq!(counter.get(), 3);
+        assert_eq!(counter.get_metric().get(), 3);
+
+        let counters = CounterVec::<StaticLabelSet<Kind>>::new();
+        counters.inc_by(Kind::Write, 2);
+        assert_eq!(counters.get(Kind::Write), 2);
+        assert_eq!(counters.try_get(Kind::Read), Some(0));
+
+        let known = CounterVec::from_known_labels([Kind::Read]);
+        known.inc(&Kind::Read);
+        assert_eq!(known.try_get(&Kind::Read), Some(1));
+        assert_eq!(known.try_get(&Kind::Write), None);
+    }
+
+    #[test]
+    fn get_does_not_create_series() {
+        for counters in [
+            CounterVec::<StaticLabelSet<Kind>>::dense(),
+            CounterVec::<StaticLabelSet<Kind>>::sparse(),
+        ] {
+            assert_eq!(counters.get(Kind::Read), 0);
+            assert_eq!(counters.try_get(Kind::Write), Some(0));
+            assert_eq!(counters.get_cardinality().0, 0);
+
+            counters.inc(Kind::Read);
+            assert_eq!(counters.get(Kind::Read), 1);
+            assert_eq!(counters.get_cardinality().0, 1);
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let counter = Counter::new();
+        counter.inc_by(5);
+        assert_eq!(counter.reset(), 5);
+        assert_eq!(counter.get(), 0);
+        counter.inc();
+        assert_eq!(counter.reset(), 1);
+    }
+
+    #[test]
+    fn inc_returning() {
+        let counter = Counter::new();
+        assert_eq!(counter.inc_returning(), 1);
+        assert_eq!(counter.inc_by_returning(5), 6);
+        assert_eq!(counter.inc_returning(), 7);
+
+        let counters = CounterVec::<StaticLabelSet<Kind>>::new();
+        assert_eq!(counters.inc_returning(Kind::Read), 1);
+        assert_eq!(counters.inc_by_returning(Kind::Write, 3), 3);
+        assert_eq!(counters.inc_returning(Kind::Read), 2);
+    }
+
+    #[test]
+    fn unique_sequence_numbers_under_concurrency() {
+        let counter = Counter::new();
+        let mut seen: Vec<u64> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..1000)
+                            .map(|_| counter.inc_returning())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect()
+        });
+        seen.sort_unstable();
+        assert_eq!(seen, (1..=4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn acquire_release_publishes_prior_writes() {
+        const RECORDS: usize = 100000;
+
+        #[derive(MetricGroup)]
+        #[metric(crate = crate)]
+        struct Records {
+            written: Counter,
+        }
+
+        let records = Records {
+            written: Counter::with_metadata(MemoryOrdering::AcquireRelease),
+        };
+        let slots: Vec<AtomicU64> = (0..RECORDS).map(|_| AtomicU64::new(0)).collect();
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for (i, slot) in slots.iter().enumerate() {
+                    slot.store(i as u64 + 1, Ordering::Relaxed);
+                    records.written.inc();
+                }
+            });
+
+            let mut seen = 0;
+            while seen < RECORDS {
+                for_each_sample(&records, |sample| {
+                    let MetricValue::Int(value) = sample.value else {
+                        panic!("counters are integers")
+                    };
+                    seen = value as usize;
+                });
+                // every record counted by the collection is visible to the collecting thread
+                for (i, slot) in slots[..seen].iter().enumerate() {
+                    assert_eq!(slot.load(Ordering::Relaxed), i as u64 + 1);
+                }
+            }
+        });
+    }
 }
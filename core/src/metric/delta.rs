@@ -0,0 +1,158 @@
+//! Counters that also expose their increase since the previous collection. See [`DeltaCounter`]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{atomic::MemoryOrdering, label::NoLabels, Counter};
+
+use super::{
+    counter::CounterState,
+    gauge::GaugeState,
+    group::Encoding,
+    name::{Delta, MetricNameEncoder},
+    reset::Reset,
+    MetricEncoding, MetricFamilyEncoding,
+};
+
+/// A [`Counter`] which exposes its increase since the previous collection next to the cumulative value.
+///
+/// Every collection writes the counter family as usual, followed by a `<name>_delta` gauge family with the
+/// increase since the previous collection. This is useful for delta-oriented backends and local tools which
+/// cannot compute the increase of a counter themselves. The first collection reports the increase since
+/// the counter was created.
+///
+/// The delta depends on the collection interval: the same rate of events gives twice the delta when the metrics
+/// are collected half as often, and an irregular interval gives an irregular delta. As every collection resets
+/// the delta, the counter should only be collected by a single scraper, otherwise each scraper only sees part
+/// of the increase. The cumulative counter is not affected by this.
+///
+/// ```
+/// use measured::MetricGroup;
+/// use measured::metric::delta::DeltaCounter;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Queue {
+///     /// messages published
+///     published: DeltaCounter,
+/// }
+///
+/// let queue = Queue::default();
+/// queue.published.inc_by(10);
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// queue.collect_group_into(&mut enc).unwrap();
+/// queue.published.inc_by(5);
+/// queue.collect_group_into(&mut enc).unwrap();
+///
+/// let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+/// assert!(text.ends_with("published 15\n\n# TYPE published_delta gauge\npublished_delta 5\n"));
+/// ```
+#[derive(Default)]
+pub struct DeltaCounter {
+    counter: Counter,
+    /// the value of the counter at the previous collection
+    last: AtomicU64,
+}
+
+impl DeltaCounter {
+    /// Create a new counter, starting at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The underlying counter
+    pub fn counter(&self) -> &Counter {
+        &self.counter
+    }
+
+    /// Increment the counter value by 1
+    pub fn inc(&self) {
+        self.counter.inc();
+    }
+
+    /// Increment the counter value by `x`
+    pub fn inc_by(&self, x: u64) {
+        self.counter.inc_by(x);
+    }
+
+    /// Compute the increase since the previous call, and start a new delta window.
+    ///
+    /// Returns the cumulative value of the counter, and the increase.
+    pub fn delta(&self) -> (u64, u64) {
+        let current = self.counter.get_metric().load(Ordering::Relaxed);
+        let prev = self.last.swap(current, Ordering::Relaxed);
+        (current, current.saturating_sub(prev))
+    }
+}
+
+impl<Enc: Encoding> MetricFamilyEncoding<Enc> for DeltaCounter
+where
+    CounterState: MetricEncoding<Enc>,
+    GaugeState: MetricEncoding<Enc>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        // both families are written from the same read of the counter, so they are consistent
+        let (current, delta) = self.delta();
+
+        CounterState::write_type(&name, enc)?;
+        CounterState::new(current).collect_into(&MemoryOrdering::Relaxed, NoLabels, &name, enc)?;
+
+        let name = name.with_suffix(Delta);
+        let delta = i64::try_from(delta).unwrap_or(i64::MAX);
+        GaugeState::write_type(&name, enc)?;
+        GaugeState::new(delta).collect_into(&MemoryOrdering::Relaxed, NoLabels, name, enc)
+    }
+}
+
+impl Reset for DeltaCounter {
+    /// Reset the counter to zero, and start a new delta window
+    fn reset(&self) {
+        self.counter.reset();
+        self.last.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+    };
+
+    use super::DeltaCounter;
+
+    fn collect(counter: &DeltaCounter) -> String {
+        let mut enc = BufferedTextEncoder::new();
+        counter
+            .collect_family_into(MetricName::from_str("published"), &mut enc)
+            .unwrap();
+        String::from_utf8(enc.finish().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn delta_since_previous_collect() {
+        let counter = DeltaCounter::new();
+        counter.inc_by(100);
+        assert_eq!(
+            collect(&counter),
+            "# TYPE published counter\n\
+            published 100\n\
+            \n\
+            # TYPE published_delta gauge\n\
+            published_delta 100\n"
+        );
+
+        counter.inc_by(30);
+        assert_eq!(counter.delta(), (130, 30));
+        // no events since
+        assert_eq!(counter.delta(), (130, 0));
+
+        counter.inc();
+        assert!(collect(&counter)
+            .ends_with("published 131\n\n# TYPE published_delta gauge\npublished_delta 1\n"));
+    }
+}
@@ -0,0 +1,315 @@
+//! Metric vecs that can be collected and reset without blocking writers. See [`DoubleBufferedVec`]
+
+use std::{
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crossbeam_utils::Backoff;
+use parking_lot::Mutex;
+
+use crate::label::LabelGroupSet;
+
+use super::{
+    counter::CounterState,
+    group::Encoding,
+    histogram::{HistogramState, Thresholds},
+    name::MetricNameEncoder,
+    reset::ResetMetric,
+    LabelId, MetricEncoding, MetricLockGuard, MetricType, MetricVec,
+};
+
+/// The two buffers of a single series of a [`DoubleBufferedVec`]
+#[derive(Default)]
+pub struct BufferedState<M> {
+    buffers: [M; 2],
+    /// The number of writers updating each buffer. These are kept per series,
+    /// so writers to different series do not contend on them.
+    writers: [AtomicUsize; 2],
+}
+
+impl<M: MetricType> MetricType for BufferedState<M> {
+    type Metadata = M::Metadata;
+}
+
+/// A metric vec for delta exporters, like StatsD, which reports the change since the previous collection.
+///
+/// Every series has two buffers. Writers always update the active buffer. When collecting with
+/// [`collect_and_reset_into`](Self::collect_and_reset_into), the active buffer is flipped first, so new updates
+/// go to the other buffer while the previous one is encoded and reset to zero. Writers never wait on a collection:
+/// an update that races with the flip is retried on the new buffer.
+///
+/// Updates are only reported once the interval they landed in is collected, so the values are up to one
+/// collection interval stale. Series are not removed when they are reset, so a series which saw no updates
+/// in an interval is reported with a value of zero.
+///
+/// A collection waits for the updates that are still in progress on the previous buffer of each series.
+/// Updates only hold on to a buffer for the duration of a single atomic operation or read lock.
+///
+/// ```
+/// use measured::FixedCardinalityLabel;
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::counter::CounterState;
+/// use measured::metric::double_buffered::DoubleBufferedVec;
+/// use measured::metric::name::MetricName;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "operation")]
+/// enum Operation { Read, Write }
+///
+/// let ops = DoubleBufferedVec::<CounterState, StaticLabelSet<Operation>>::new();
+/// ops.inc(Operation::Read);
+///
+/// // every flush interval
+/// let mut enc = BufferedTextEncoder::new();
+/// ops.collect_and_reset_into(MetricName::from_str("ops"), &mut enc).unwrap();
+/// ```
+pub struct DoubleBufferedVec<M: MetricType, L: LabelGroupSet> {
+    vec: MetricVec<BufferedState<M>, L>,
+    active: AtomicUsize,
+    collect: Mutex<()>,
+}
+
+/// A shared ref to the active buffer of a series in a [`DoubleBufferedVec`].
+///
+/// The buffer will not be collected while this guard is held, so it is only held for a single update.
+pub(crate) struct BufferedGuard<'a, M: MetricType> {
+    metric: MetricLockGuard<'a, BufferedState<M>>,
+    index: usize,
+}
+
+impl<M: MetricType> Deref for BufferedGuard<'_, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.metric.buffers[self.index]
+    }
+}
+
+impl<M: MetricType> BufferedGuard<'_, M> {
+    /// The metadata of the metric vec
+    pub(crate) fn metadata(&self) -> &M::Metadata {
+        self.metric.metadata()
+    }
+}
+
+impl<M: MetricType> Drop for BufferedGuard<'_, M> {
+    fn drop(&mut self) {
+        self.metric.writers[self.index].fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet + Default> DoubleBufferedVec<M, L>
+where
+    M::Metadata: Default,
+{
+    /// Create a new double buffered metric vec
+    pub fn new() -> Self {
+        Self::with_label_set_and_metadata(L::default(), M::Metadata::default())
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet + Default> Default for DoubleBufferedVec<M, L>
+where
+    M::Metadata: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet> DoubleBufferedVec<M, L> {
+    /// Create a new double buffered metric vec with the given label set and metric metadata
+    pub fn with_label_set_and_metadata(label_set: L, metadata: M::Metadata) -> Self {
+        Self {
+            vec: MetricVec::with_label_set_and_metadata(label_set, metadata),
+            active: AtomicUsize::new(0),
+            collect: Mutex::new(()),
+        }
+    }
+
+    /// Get the identifier for a specific label group
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set.
+    pub fn with_labels(&self, label: L::Group<'_>) -> LabelId<L> {
+        self.vec.with_labels(label)
+    }
+
+    /// Get the active buffer of the series identified by the label id
+    pub(crate) fn get_metric(&self, id: LabelId<L>) -> BufferedGuard<'_, M> {
+        let metric = self.vec.get_metric(id);
+        loop {
+            let index = self.active.load(Ordering::SeqCst);
+            let writers = &metric.writers[index];
+            writers.fetch_add(1, Ordering::SeqCst);
+
+            // if the buffers were flipped in the meantime, the collector might not have seen this writer.
+            if self.active.load(Ordering::SeqCst) == index {
+                return BufferedGuard { metric, index };
+            }
+            writers.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    /// Encode the values accumulated since the previous collection, and reset them to zero.
+    ///
+    /// Only one collection runs at a time. Concurrent writers are moved over to the other buffer,
+    /// and are not blocked.
+    pub fn collect_and_reset_into<T: Encoding>(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut T,
+    ) -> Result<(), T::Err>
+    where
+        M: MetricEncoding<T> + ResetMetric,
+    {
+        let _collect = self.collect.lock();
+
+        let index = self.active.fetch_xor(1, Ordering::SeqCst);
+
+        M::write_type(&name, enc)?;
+        let metadata = self.vec.metadata();
+        let mut result = Ok(());
+        self.vec.for_each_series(|labels, state| {
+            // wait for the writers which saw the previous buffer to finish their update
+            let backoff = Backoff::new();
+            while state.writers[index].load(Ordering::SeqCst) != 0 {
+                backoff.snooze();
+            }
+
+            let metric = &state.buffers[index];
+            if result.is_ok() {
+                result = metric.collect_into(metadata, labels, &name, enc);
+            }
+            // reset even if encoding failed, so the values are not reported in a later interval
+            metric.reset();
+            Ok::<(), T::Err>(())
+        })?;
+        result
+    }
+}
+
+impl<L: LabelGroupSet> DoubleBufferedVec<CounterState, L> {
+    /// Increment the counter value by 1, keyed by the label group
+    pub fn inc(&self, label: L::Group<'_>) {
+        self.get_metric(self.with_labels(label)).inc();
+    }
+
+    /// Increment the counter value by `y`, keyed by the label group
+    pub fn inc_by(&self, label: L::Group<'_>, y: u64) {
+        self.get_metric(self.with_labels(label)).inc_by(y);
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> DoubleBufferedVec<HistogramState<N>, L> {
+    /// Create a new double buffered histogram vec with the given thresholds
+    pub fn with_metadata(thresholds: Thresholds<N>) -> Self
+    where
+        L: Default,
+    {
+        Self::with_label_set_and_metadata(L::default(), thresholds)
+    }
+
+    /// Add a single observation to the histogram, keyed by the label group
+    pub fn observe(&self, label: L::Group<'_>, x: f64) {
+        let metric = self.get_metric(self.with_labels(label));
+        let thresholds = metric.metadata();
+        metric
+            .inner
+            .read()
+            .observe(thresholds.bucket(x), thresholds.clamp(x));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{
+            counter::CounterState, histogram::Thresholds, name::MetricName, reset::ResetMetric,
+            MetricEncoding,
+        },
+        text::{parse_text_exposition, BufferedTextEncoder},
+        FixedCardinalityLabel,
+    };
+
+    use super::DoubleBufferedVec;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "operation")]
+    enum Operation {
+        Read,
+        Write,
+    }
+
+    fn collect<M>(vec: &DoubleBufferedVec<M, StaticLabelSet<Operation>>) -> Vec<(String, f64)>
+    where
+        M: ResetMetric + MetricEncoding<BufferedTextEncoder>,
+    {
+        let mut enc = BufferedTextEncoder::new();
+        vec.collect_and_reset_into(MetricName::from_str("ops"), &mut enc)
+            .unwrap();
+        let families = parse_text_exposition(&enc.finish()).unwrap();
+        families[0]
+            .samples
+            .iter()
+            .map(|s| (format!("{}{:?}", s.name, s.labels), s.value))
+            .collect()
+    }
+
+    #[test]
+    fn collect_resets() {
+        let ops = DoubleBufferedVec::<CounterState, StaticLabelSet<Operation>>::new();
+        ops.inc(Operation::Read);
+        ops.inc_by(Operation::Write, 3);
+
+        let total = |samples: Vec<(String, f64)>| samples.iter().map(|s| s.1).sum::<f64>();
+        assert_eq!(total(collect(&ops)), 4.0);
+        // the previous values were reset
+        assert_eq!(total(collect(&ops)), 0.0);
+
+        ops.inc(Operation::Read);
+        assert_eq!(total(collect(&ops)), 1.0);
+        assert_eq!(total(collect(&ops)), 0.0);
+
+        let latency = DoubleBufferedVec::<_, StaticLabelSet<Operation>>::with_metadata(
+            Thresholds::<2>::with_buckets([1.0, 2.0]),
+        );
+        latency.observe(Operation::Read, 1.5);
+        let samples = collect(&latency);
+        assert!(samples
+            .iter()
+            .any(|s| s.0.starts_with("ops_count") && s.1 == 1.0));
+        let samples = collect(&latency);
+        assert!(samples.iter().all(|s| s.1 == 0.0));
+    }
+
+    #[test]
+    fn no_lost_updates_under_concurrency() {
+        let ops = DoubleBufferedVec::<CounterState, StaticLabelSet<Operation>>::new();
+
+        let mut collected = 0.0;
+        std::thread::scope(|s| {
+            let writers: Vec<_> = (0..4)
+                .map(|_| {
+                    let ops = &ops;
+                    s.spawn(move || {
+                        for _ in 0..50000 {
+                            ops.inc(Operation::Write);
+                        }
+                    })
+                })
+                .collect();
+
+            while !writers.iter().all(|w| w.is_finished()) {
+                collected += collect(&ops).iter().map(|s| s.1).sum::<f64>();
+            }
+        });
+        collected += collect(&ops).iter().map(|s| s.1).sum::<f64>();
+
+        // every update is reported exactly once
+        assert_eq!(collected, 200000.0);
+    }
+}
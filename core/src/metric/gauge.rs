@@ -1,15 +1,21 @@
 //! All things gauges. See [`Gauge`]
 
-use core::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicI64, Ordering};
 
-use crate::{label::LabelGroupSet, FloatGauge, FloatGaugeVec, Gauge, GaugeVec, LabelGroup};
+use crate::{
+    atomic::MemoryOrdering,
+    label::{LabelGroupSet, LabelName, LabelPair},
+    FloatGauge, FloatGaugeVec, Gauge, GaugeVec, LabelGroup,
+};
+
+pub use crate::atomic::AtomicF64;
 
 use super::{
-    group::Encoding, name::MetricNameEncoder, MetricEncoding, MetricLockGuard, MetricMut,
-    MetricType,
+    group::Encoding, name::MetricNameEncoder, reset::Reset, reset::ResetMetric, MetricEncoding,
+    MetricFamilyEncoding, MetricLockGuard, MetricMut, MetricType,
 };
 
-#[derive(Default)]
+#[derive(Default, Debug)]
 /// The internal state that is used by [`Gauge`] and [`GaugeVec`]
 pub struct GaugeState {
     pub count: AtomicI64,
@@ -54,36 +60,52 @@ impl Gauge {
     pub fn set(&self, x: i64) {
         self.get_metric().set(x)
     }
+
+    /// Read the gauge value with the given memory ordering
+    pub fn load(&self, ordering: Ordering) -> i64 {
+        self.get_metric().load(ordering)
+    }
+
+    /// Read the gauge value, with relaxed ordering
+    pub fn get(&self) -> i64 {
+        self.load(Ordering::Relaxed)
+    }
 }
 
 impl GaugeLockGuard<'_> {
     /// Increment the gauge value by 1
     pub fn inc(self) {
-        self.count
-            .fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+        let ordering = self.metadata().store();
+        self.count.fetch_add(1, ordering);
     }
 
     /// Increment the gauge value by `x`
     pub fn inc_by(self, x: i64) {
-        self.count
-            .fetch_add(x, core::sync::atomic::Ordering::Relaxed);
+        let ordering = self.metadata().store();
+        self.count.fetch_add(x, ordering);
     }
 
     /// Decrement the gauge value by 1
     pub fn dec(self) {
-        self.count
-            .fetch_sub(1, core::sync::atomic::Ordering::Relaxed);
+        let ordering = self.metadata().store();
+        self.count.fetch_sub(1, ordering);
     }
 
     /// Decrement the gauge value by `x`
     pub fn dec_by(self, x: i64) {
-        self.count
-            .fetch_sub(x, core::sync::atomic::Ordering::Relaxed);
+        let ordering = self.metadata().store();
+        self.count.fetch_sub(x, ordering);
     }
 
     /// Set the gauge value to `x`
     pub fn set(self, x: i64) {
-        self.count.store(x, core::sync::atomic::Ordering::Relaxed);
+        let ordering = self.metadata().store();
+        self.count.store(x, ordering);
+    }
+
+    /// Read the gauge value with the given memory ordering
+    pub fn load(self, ordering: Ordering) -> i64 {
+        self.count.load(ordering)
     }
 }
 
@@ -142,8 +164,8 @@ impl<L: LabelGroupSet> GaugeVec<L> {
 }
 
 impl MetricType for GaugeState {
-    /// [`Gauge`]s require no additional metadata
-    type Metadata = ();
+    /// The memory ordering of the updates and collections. Relaxed by default
+    type Metadata = MemoryOrdering;
 }
 
 pub fn write_gauge<Enc: Encoding>(
@@ -158,10 +180,10 @@ where
     GaugeState {
         count: AtomicI64::new(value),
     }
-    .collect_into(&(), labels, name, enc)
+    .collect_into(&MemoryOrdering::Relaxed, labels, name, enc)
 }
 
-#[derive(Default)]
+#[derive(Default, Debug)]
 /// The internal state that is used by [`FloatGauge`] and [`FloatGaugeVec`]
 pub struct FloatGaugeState {
     pub count: AtomicF64,
@@ -206,32 +228,85 @@ impl FloatGauge {
     pub fn set(&self, x: f64) {
         self.get_metric().set(x)
     }
+
+    /// Read the gauge value with the given memory ordering
+    pub fn load(&self, ordering: Ordering) -> f64 {
+        self.get_metric().load(ordering)
+    }
+
+    /// Read the gauge value, with relaxed ordering
+    pub fn get(&self) -> f64 {
+        self.load(Ordering::Relaxed)
+    }
+
+    /// Set the gauge value to `new` if it is `current`. See [`FloatGaugeLockGuard::compare_and_set`]
+    pub fn compare_and_set(&self, current: f64, new: f64) -> Result<f64, f64> {
+        self.get_metric().compare_and_set(current, new)
+    }
+
+    /// Set the gauge value to the maximum of the value and `x`, returning the previous value
+    pub fn fetch_max(&self, x: f64) -> f64 {
+        self.get_metric().fetch_max(x)
+    }
+
+    /// Set the gauge value to the minimum of the value and `x`, returning the previous value
+    pub fn fetch_min(&self, x: f64) -> f64 {
+        self.get_metric().fetch_min(x)
+    }
 }
 
 impl FloatGaugeLockGuard<'_> {
     /// Increment the gauge value by 1
     pub fn inc(self) {
-        self.count.inc_by(1.0);
+        self.inc_by(1.0);
     }
 
     /// Increment the gauge value by `x`
     pub fn inc_by(self, x: f64) {
-        self.count.inc_by(x);
+        self.count.fetch_add(x, self.metadata().store());
     }
 
     /// Decrement the gauge value by 1
     pub fn dec(self) {
-        self.count.dec_by(1.0);
+        self.dec_by(1.0);
     }
 
     /// Decrement the gauge value by `x`
     pub fn dec_by(self, x: f64) {
-        self.count.dec_by(x);
+        self.count.fetch_sub(x, self.metadata().store());
     }
 
     /// Set the gauge value to `x`
     pub fn set(self, x: f64) {
-        self.count.set(x);
+        self.count.store(x, self.metadata().store());
+    }
+
+    /// Read the gauge value with the given memory ordering
+    pub fn load(self, ordering: Ordering) -> f64 {
+        self.count.load(ordering)
+    }
+
+    /// Set the gauge value to `new` if it is `current`, with acquire-release ordering.
+    ///
+    /// Returns the previous value, as `Ok` if `new` was set. The values are compared by their bits,
+    /// like [`AtomicF64::compare_exchange`].
+    pub fn compare_and_set(self, current: f64, new: f64) -> Result<f64, f64> {
+        self.count
+            .compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+    }
+
+    /// Set the gauge value to the maximum of the value and `x`, returning the previous value.
+    ///
+    /// Like [`f64::max`], NaN is ignored if the other value is not NaN.
+    pub fn fetch_max(self, x: f64) -> f64 {
+        self.count.fetch_max(x, Ordering::AcqRel)
+    }
+
+    /// Set the gauge value to the minimum of the value and `x`, returning the previous value.
+    ///
+    /// Like [`f64::min`], NaN is ignored if the other value is not NaN.
+    pub fn fetch_min(self, x: f64) -> f64 {
+        self.count.fetch_min(x, Ordering::AcqRel)
     }
 }
 
@@ -291,85 +366,260 @@ impl<L: LabelGroupSet> FloatGaugeVec<L> {
     pub fn set(&self, label: L::Group<'_>, y: f64) {
         self.get_metric(self.with_labels(label)).set(y);
     }
+
+    /// Set the gauge value to the maximum of the value and `y`, keyed by the label group. Returns the previous value
+    pub fn fetch_max(&self, label: L::Group<'_>, y: f64) -> f64 {
+        self.get_metric(self.with_labels(label)).fetch_max(y)
+    }
+
+    /// Set the gauge value to the minimum of the value and `y`, keyed by the label group. Returns the previous value
+    pub fn fetch_min(&self, label: L::Group<'_>, y: f64) -> f64 {
+        self.get_metric(self.with_labels(label)).fetch_min(y)
+    }
 }
 
 impl MetricType for FloatGaugeState {
-    /// [`Gauge`]s require no additional metadata
-    type Metadata = ();
+    /// The memory ordering of the updates and collections. Relaxed by default
+    type Metadata = MemoryOrdering;
 }
 
-/// A atomic float.
-#[derive(Debug, Default)]
-pub struct AtomicF64 {
-    inner: AtomicU64,
+pub fn write_float_gauge<Enc: Encoding>(
+    enc: &mut Enc,
+    name: impl MetricNameEncoder,
+    labels: impl LabelGroup,
+    value: f64,
+) -> Result<(), Enc::Err>
+where
+    FloatGaugeState: MetricEncoding<Enc>,
+{
+    FloatGaugeState {
+        count: AtomicF64::new(value),
+    }
+    .collect_into(&MemoryOrdering::Relaxed, labels, name, enc)
 }
 
-impl AtomicF64 {
-    #[allow(clippy::declare_interior_mutable_const)]
-    pub const ZERO: Self = Self {
-        inner: AtomicU64::new(0),
-    };
+/// A small, fixed number of related [`FloatGauge`]s that are set together, like the 1, 5 and 15 minute load averages.
+///
+/// Each gauge is written as its own series, distinguished by a single label whose name and values are fixed at
+/// compile time. This is a lighter alternative to a [`FloatGaugeVec`] for when the label values are known up front.
+///
+/// ```
+/// use measured::label::LabelName;
+/// use measured::metric::gauge::FixedGaugeSet;
+///
+/// // encoded as `node_load{period="1"}`, `node_load{period="5"}` and `node_load{period="15"}`
+/// static NODE_LOAD: FixedGaugeSet<3> =
+///     FixedGaugeSet::new(LabelName::from_str("period"), ["1", "5", "15"]);
+///
+/// NODE_LOAD.set([0.5, 0.75, 1.0]);
+/// ```
+pub struct FixedGaugeSet<const K: usize> {
+    label: &'static LabelName,
+    values: [&'static str; K],
+    gauges: [FloatGaugeState; K],
+}
 
-    pub fn new(val: f64) -> AtomicF64 {
-        AtomicF64 {
-            inner: AtomicU64::new(val.to_bits()),
-        }
-    }
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO_FLOAT_GAUGE: FloatGaugeState = FloatGaugeState {
+    count: AtomicF64::ZERO,
+};
 
-    #[inline]
-    pub fn set(&self, val: f64) {
-        self.inner.store(val.to_bits(), Ordering::Relaxed);
+impl<const K: usize> FixedGaugeSet<K> {
+    /// Create a new set of gauges, one for each label value, which are all initially zero.
+    pub const fn new(label: &'static LabelName, values: [&'static str; K]) -> Self {
+        Self {
+            label,
+            values,
+            gauges: [ZERO_FLOAT_GAUGE; K],
+        }
     }
 
-    #[inline]
-    pub fn get(&self) -> f64 {
-        f64::from_bits(self.inner.load(Ordering::Relaxed))
+    /// Set the value of every gauge, in the same order as the label values
+    pub fn set(&self, values: [f64; K]) {
+        for (gauge, x) in self.gauges.iter().zip(values) {
+            gauge.count.set(x);
+        }
     }
 
-    #[inline]
-    pub fn get_ex(&mut self) -> f64 {
-        f64::from_bits(*self.inner.get_mut())
+    /// Set the value of the gauge at the given index of the label values
+    ///
+    /// # Panics
+    /// Panics if `index` is not less than `K`.
+    pub fn set_at(&self, index: usize, x: f64) {
+        self.gauges[index].count.set(x);
     }
 
-    #[inline]
-    pub fn set_mut(&mut self, f: f64) {
-        *self.inner.get_mut() = f.to_bits();
+    /// Read the value of every gauge, in the same order as the label values
+    pub fn get(&self) -> [f64; K] {
+        core::array::from_fn(|i| self.gauges[i].count.get())
     }
+}
 
-    #[inline]
-    pub fn inc_by(&self, delta: f64) {
-        loop {
-            let current = self.inner.load(Ordering::Acquire);
-            let new = f64::from_bits(current) + delta;
-            let result = self.inner.compare_exchange_weak(
-                current,
-                new.to_bits(),
-                Ordering::Release,
-                Ordering::Relaxed,
-            );
-            if result.is_ok() {
-                return;
-            }
+impl<Enc: Encoding, const K: usize> MetricFamilyEncoding<Enc> for FixedGaugeSet<K>
+where
+    FloatGaugeState: MetricEncoding<Enc>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        FloatGaugeState::write_type(&name, enc)?;
+        for (gauge, value) in self.gauges.iter().zip(self.values) {
+            gauge.collect_into(
+                &MemoryOrdering::Relaxed,
+                LabelPair(self.label, value),
+                &name,
+                enc,
+            )?;
         }
+        Ok(())
     }
+}
 
-    #[inline]
-    pub fn dec_by(&self, delta: f64) {
-        self.inc_by(-delta);
+impl<const K: usize> Reset for FixedGaugeSet<K> {
+    fn reset(&self) {
+        for gauge in &self.gauges {
+            gauge.reset();
+        }
     }
 }
 
-pub fn write_float_gauge<Enc: Encoding>(
-    enc: &mut Enc,
-    name: impl MetricNameEncoder,
-    labels: impl LabelGroup,
-    value: f64,
-) -> Result<(), Enc::Err>
-where
-    FloatGaugeState: MetricEncoding<Enc>,
-{
-    FloatGaugeState {
-        count: AtomicF64::new(value),
+#[cfg(test)]
+mod tests {
+    use crate::{
+        atomic::MemoryOrdering,
+        label::LabelName,
+        metric::{group::MetricValue, name::MetricName, MetricFamilyEncoding},
+        sample::for_each_sample,
+        text::{parse_text_exposition, BufferedTextEncoder},
+    };
+
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::FixedGaugeSet;
+    use crate::{FloatGauge, Gauge, MetricGroup};
+
+    #[test]
+    fn negative_and_fractional_values() {
+        let gauge = Gauge::new();
+        gauge.inc_by(2);
+        gauge.dec_by(5);
+        assert_eq!(gauge.get(), -3);
+
+        let float = FloatGauge::new();
+        float.set(-1.5);
+        float.inc_by(0.25);
+        assert_eq!(float.get(), -1.25);
+
+        let mut enc = BufferedTextEncoder::new();
+        gauge
+            .collect_family_into(MetricName::from_str("depth"), &mut enc)
+            .unwrap();
+        float
+            .collect_family_into(MetricName::from_str("temperature"), &mut enc)
+            .unwrap();
+        let text = enc.finish();
+        assert_eq!(
+            text,
+            "# TYPE depth gauge
+depth -3
+
+# TYPE temperature gauge
+temperature -1.25
+"
+        );
+
+        let families = parse_text_exposition(&text).unwrap();
+        assert_eq!(families[0].samples[0].value, -3.0);
+        assert_eq!(families[1].samples[0].value, -1.25);
+    }
+
+    #[test]
+    fn fixed_gauge_set() {
+        let load = FixedGaugeSet::new(LabelName::from_str("period"), ["1", "5", "15"]);
+        load.set([0.5, 0.75, 1.0]);
+        load.set_at(2, 1.25);
+        assert_eq!(load.get(), [0.5, 0.75, 1.25]);
+
+        let mut enc = BufferedTextEncoder::new();
+        load.collect_family_into(MetricName::from_str("node_load"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE node_load gauge\n\
+            node_load{period=\"1\"} 0.5\n\
+            node_load{period=\"5\"} 0.75\n\
+            node_load{period=\"15\"} 1.25\n"
+        );
+    }
+
+    #[test]
+    fn float_gauge_cas_under_contention() {
+        let epoch = FloatGauge::new();
+        let high_water = FloatGauge::new();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let (epoch, high_water) = (&epoch, &high_water);
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        // every thread advances the epoch by one, unless another thread already did
+                        let current = epoch.load(Ordering::Acquire);
+                        let _ = epoch.compare_and_set(current, current + 1.0);
+                        high_water.fetch_max((t * 1000 + i) as f64);
+                    }
+                });
+            }
+        });
+        let epoch = epoch.load(Ordering::Acquire);
+        assert!((1000.0..=4000.0).contains(&epoch));
+        assert_eq!(high_water.load(Ordering::Acquire), 3999.0);
+
+        let gauge = FloatGauge::new();
+        gauge.set(1.0);
+        assert_eq!(gauge.compare_and_set(2.0, 3.0), Err(1.0));
+        assert_eq!(gauge.compare_and_set(1.0, 3.0), Ok(1.0));
+        assert_eq!(gauge.fetch_min(-1.0), 3.0);
+        assert_eq!(gauge.fetch_max(f64::NAN), -1.0);
+        assert_eq!(gauge.load(Ordering::Relaxed), -1.0);
+    }
+
+    #[test]
+    fn float_gauge_acquire_release() {
+        const RECORDS: usize = 1000;
+
+        #[derive(MetricGroup)]
+        #[metric(crate = crate)]
+        struct Records {
+            progress: FloatGauge,
+        }
+
+        let records = Records {
+            progress: FloatGauge::with_metadata(MemoryOrdering::AcquireRelease),
+        };
+        let slots: Vec<AtomicU64> = (0..RECORDS).map(|_| AtomicU64::new(0)).collect();
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for (i, slot) in slots.iter().enumerate() {
+                    slot.store(i as u64 + 1, Ordering::Relaxed);
+                    records.progress.inc();
+                }
+            });
+
+            let mut seen = 0;
+            while seen < RECORDS {
+                for_each_sample(&records, |sample| {
+                    let MetricValue::Float(value) = sample.value else {
+                        panic!("float gauges are floats")
+                    };
+                    seen = value as usize;
+                });
+                // every record counted by the collection is visible to the collecting thread
+                for (i, slot) in slots[..seen].iter().enumerate() {
+                    assert_eq!(slot.load(Ordering::Relaxed), i as u64 + 1);
+                }
+            }
+        });
     }
-    .collect_into(&(), labels, name, enc)
 }
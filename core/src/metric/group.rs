@@ -1,13 +1,27 @@
 //! Groups of metrics
 
-use std::sync::Arc;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use rustc_hash::FxHashSet;
 
 pub use crate::label::ComposedGroup;
 
 use super::{
-    name::{MetricNameEncoder, WithNamespace},
+    counter::CounterState,
+    name::{
+        assert_separator, InvalidMetricName, MetricName, MetricNameBuf, MetricNameEncoder,
+        WithNamespace, WithRuntimeNamespace,
+    },
+    reset::Reset,
     MetricEncoding,
 };
+use crate::{atomic::MemoryOrdering, label::NoLabels};
 
 /// Values that prometheus supports in the text format
 #[derive(Clone, Copy, Debug)]
@@ -23,6 +37,14 @@ pub trait Encoding {
 
     /// Write the help text for a metric
     fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err>;
+
+    /// Write the unit of a metric, like `seconds` or `bytes`. Like the help text, this is written before the metric.
+    ///
+    /// Only some formats have units, so this does nothing by default.
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Self::Err> {
+        let _ = (name, unit);
+        Ok(())
+    }
 }
 
 impl<E: Encoding> Encoding for &mut E {
@@ -30,6 +52,9 @@ impl<E: Encoding> Encoding for &mut E {
     fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
         E::write_help(self, name, help)
     }
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Self::Err> {
+        E::write_unit(self, name, unit)
+    }
 }
 
 /// A `MetricGroup` defines a group of [`MetricFamilyEncoding`](super::MetricFamilyEncoding)s
@@ -69,11 +94,101 @@ where
     fn collect_group_into(&self, enc: &mut E) -> Result<(), E::Err> {
         self.inner.collect_group_into(&mut WithNamespace {
             namespace: self.namespace,
+            separator: self.separator,
             inner: enc,
         })
     }
 }
 
+impl<G: Reset> Reset for WithNamespace<G> {
+    fn reset(&self) {
+        self.inner.reset();
+    }
+}
+
+/// A [`MetricGroup`] where every metric name is prefixed with a namespace that is only known at runtime,
+/// like a service name read from the config.
+///
+/// This is the runtime counterpart of [`WithNamespace`]. The namespace is validated once when it is created,
+/// and is applied to every family in the group, on top of any namespaces and suffixes of the metrics themselves.
+///
+/// ```
+/// use measured::{Counter, MetricGroup};
+/// use measured::metric::group::RuntimeNamespace;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Metrics {
+///     requests: Counter,
+/// }
+///
+/// let service = String::from("billing");
+/// let metrics = RuntimeNamespace::try_new(service, Metrics::default()).unwrap();
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// metrics.collect_group_into(&mut enc).unwrap();
+/// assert!(std::str::from_utf8(&enc.finish()).unwrap().contains("billing_requests"));
+/// ```
+pub struct RuntimeNamespace<G> {
+    namespace: MetricNameBuf,
+    separator: &'static str,
+    inner: G,
+}
+
+impl<G> RuntimeNamespace<G> {
+    /// Create a new group with the given namespace
+    ///
+    /// # Errors
+    /// Will error if the namespace contains invalid metric name characters
+    pub fn try_new(namespace: impl Into<String>, inner: G) -> Result<Self, InvalidMetricName> {
+        Ok(Self {
+            namespace: MetricNameBuf::try_new(namespace)?,
+            separator: "_",
+            inner,
+        })
+    }
+
+    /// Change the separator between the namespace and the names. The default is `_`.
+    ///
+    /// # Panics
+    /// Will panic if the `separator` string contains invalid metric name characters
+    pub fn with_separator(mut self, separator: &'static str) -> Self {
+        assert_separator(separator);
+        self.separator = separator;
+        self
+    }
+
+    /// The namespace of this group
+    pub fn namespace(&self) -> &MetricNameBuf {
+        &self.namespace
+    }
+
+    /// The inner group
+    pub fn inner(&self) -> &G {
+        &self.inner
+    }
+}
+
+impl<G, E> MetricGroup<E> for RuntimeNamespace<G>
+where
+    G: for<'a, 'b> MetricGroup<WithRuntimeNamespace<'a, &'b mut E>>,
+    E: Encoding,
+{
+    fn collect_group_into(&self, enc: &mut E) -> Result<(), E::Err> {
+        self.inner.collect_group_into(&mut WithRuntimeNamespace {
+            namespace: &self.namespace,
+            separator: self.separator,
+            inner: enc,
+        })
+    }
+}
+
+impl<G: Reset> Reset for RuntimeNamespace<G> {
+    fn reset(&self) {
+        self.inner.reset();
+    }
+}
+
 impl<M: MetricGroup<T>, T: Encoding> MetricGroup<T> for Option<M> {
     fn collect_group_into(&self, enc: &mut T) -> Result<(), T::Err> {
         if let Some(this) = self {
@@ -89,17 +204,104 @@ impl<M: MetricGroup<T>, T: Encoding> MetricGroup<T> for Arc<M> {
     }
 }
 
+/// A [`MetricGroup`] which counts how many times it was collected, and exposes the count as the final family,
+/// `scrape_sequence_total`.
+///
+/// The sequence is incremented once at the start of every collection, and each collection exposes the number
+/// it was assigned, so a scraper sees consecutive numbers unless it missed a scrape, or another scraper collected
+/// in between. Together with the scrape timestamps, this helps to tell from the server side whether scrapes were
+/// dropped.
+///
+/// ```
+/// use measured::{Counter, MetricGroup};
+/// use measured::metric::group::ScrapeSequence;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Metrics {
+///     requests: Counter,
+/// }
+///
+/// let metrics = ScrapeSequence::new(Metrics::default());
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// metrics.collect_group_into(&mut enc).unwrap();
+/// assert!(std::str::from_utf8(&enc.finish()).unwrap().ends_with("scrape_sequence_total 1\n"));
+/// ```
+pub struct ScrapeSequence<G> {
+    inner: G,
+    sequence: AtomicU64,
+}
+
+impl<G> ScrapeSequence<G> {
+    /// Count the collections of the metric group
+    pub fn new(inner: G) -> Self {
+        Self {
+            inner,
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// The number of collections so far
+    pub fn sequence(&self) -> u64 {
+        self.sequence.load(Ordering::Relaxed)
+    }
+
+    /// Access the inner metric group
+    pub fn inner(&self) -> &G {
+        &self.inner
+    }
+}
+
+impl<G, E> MetricGroup<E> for ScrapeSequence<G>
+where
+    G: MetricGroup<E>,
+    E: Encoding,
+    CounterState: MetricEncoding<E>,
+{
+    fn collect_group_into(&self, enc: &mut E) -> Result<(), E::Err> {
+        const NAME: &MetricName = MetricName::from_str("scrape_sequence_total");
+
+        // assigned before collecting, so a collection only counts itself once, even if the inner group fails
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        self.inner.collect_group_into(enc)?;
+
+        enc.write_help(NAME, "The number of times the metrics were collected")?;
+        CounterState::write_type(NAME, enc)?;
+        CounterState::new(sequence).collect_into(&MemoryOrdering::Relaxed, NoLabels, NAME, enc)
+    }
+}
+
+impl<G: Reset> Reset for ScrapeSequence<G> {
+    /// Reset the inner group, and start counting the collections from zero again
+    fn reset(&self) {
+        self.sequence.store(0, Ordering::Relaxed);
+        self.inner.reset();
+    }
+}
+
 impl<E: Encoding> Encoding for WithNamespace<E> {
     type Err = E::Err;
     fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
         self.inner.write_help(
             WithNamespace {
                 namespace: self.namespace,
+                separator: self.separator,
                 inner: name,
             },
             help,
         )
     }
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Self::Err> {
+        self.inner.write_unit(
+            WithNamespace {
+                namespace: self.namespace,
+                separator: self.separator,
+                inner: name,
+            },
+            unit,
+        )
+    }
 }
 
 impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithNamespace<E>> for M {
@@ -107,6 +309,7 @@ impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithNamespace<E>> for M {
         M::write_type(
             WithNamespace {
                 namespace: enc.namespace,
+                separator: enc.separator,
                 inner: name,
             },
             &mut enc.inner,
@@ -124,6 +327,65 @@ impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithNamespace<E>> for M {
             labels,
             WithNamespace {
                 namespace: enc.namespace,
+                separator: enc.separator,
+                inner: name,
+            },
+            &mut enc.inner,
+        )
+    }
+}
+
+impl<E: Encoding> Encoding for WithRuntimeNamespace<'_, E> {
+    type Err = E::Err;
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.inner.write_help(
+            WithRuntimeNamespace {
+                namespace: self.namespace,
+                separator: self.separator,
+                inner: name,
+            },
+            help,
+        )
+    }
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Self::Err> {
+        self.inner.write_unit(
+            WithRuntimeNamespace {
+                namespace: self.namespace,
+                separator: self.separator,
+                inner: name,
+            },
+            unit,
+        )
+    }
+}
+
+impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithRuntimeNamespace<'_, E>> for M {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut WithRuntimeNamespace<'_, E>,
+    ) -> Result<(), E::Err> {
+        M::write_type(
+            WithRuntimeNamespace {
+                namespace: enc.namespace,
+                separator: enc.separator,
+                inner: name,
+            },
+            &mut enc.inner,
+        )
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl crate::label::LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut WithRuntimeNamespace<'_, E>,
+    ) -> Result<(), E::Err> {
+        self.collect_into(
+            metadata,
+            labels,
+            WithRuntimeNamespace {
+                namespace: enc.namespace,
+                separator: enc.separator,
                 inner: name,
             },
             &mut enc.inner,
@@ -146,6 +408,138 @@ impl<'a, M: MetricEncoding<E>, E: Encoding> MetricEncoding<&'a mut E> for M {
     }
 }
 
+/// An encoder which checks that every metric family is only written once.
+///
+/// Metrics can be split into several [`MetricGroup`]s, one per subsystem, and served from the same endpoint by
+/// composing them with [`ComposedGroup`], each optionally with its own prefix using [`WithNamespace`].
+/// Prometheus rejects an exposition that declares the same family twice, so collecting through this encoder
+/// returns [`DuplicateFamilyError::Duplicate`] as soon as a family name is repeated.
+///
+/// The set of seen names is not cleared, so a new `UniqueFamilies` should be created for each collection.
+///
+/// ```
+/// use measured::{Counter, MetricGroup};
+/// use measured::metric::group::{ComposedGroup, DuplicateFamilyError, UniqueFamilies};
+/// use measured::metric::name::WithNamespace;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Storage {
+///     requests: Counter,
+/// }
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Api {
+///     requests: Counter,
+/// }
+///
+/// let mut enc = BufferedTextEncoder::new();
+///
+/// let metrics = ComposedGroup(Storage::default(), Api::default());
+/// let err = metrics.collect_group_into(&mut UniqueFamilies::new(&mut enc)).unwrap_err();
+/// assert!(matches!(err, DuplicateFamilyError::Duplicate(name) if name == "requests"));
+///
+/// let metrics = ComposedGroup(
+///     WithNamespace::new("storage", Storage::default()),
+///     WithNamespace::new("api", Api::default()),
+/// );
+/// metrics.collect_group_into(&mut UniqueFamilies::new(&mut enc)).unwrap();
+/// ```
+pub struct UniqueFamilies<E> {
+    inner: E,
+    seen: FxHashSet<Vec<u8>>,
+}
+
+impl<E: Encoding> UniqueFamilies<E> {
+    /// Wrap the encoder, with no metric families seen yet
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            seen: FxHashSet::default(),
+        }
+    }
+
+    /// Extract the inner encoder
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+
+    fn check(&mut self, name: &impl MetricNameEncoder) -> Result<(), DuplicateFamilyError<E::Err>> {
+        let mut b = Vec::with_capacity(name.encode_len());
+        name.encode_utf8(&mut b)
+            .expect("writing to a vec should not fail");
+        if let Some(name) = self.seen.replace(b) {
+            return Err(DuplicateFamilyError::Duplicate(
+                String::from_utf8(name).expect("metric names should be valid utf8"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The error returned by [`UniqueFamilies`]
+#[derive(Debug)]
+pub enum DuplicateFamilyError<E> {
+    /// A metric family with this name was already written
+    Duplicate(String),
+    /// The inner encoder failed
+    Encoder(E),
+}
+
+impl<E: fmt::Display> fmt::Display for DuplicateFamilyError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DuplicateFamilyError::Duplicate(name) => {
+                write!(f, "metric family {name} was written more than once")
+            }
+            DuplicateFamilyError::Encoder(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for DuplicateFamilyError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DuplicateFamilyError::Duplicate(_) => None,
+            DuplicateFamilyError::Encoder(e) => Some(e),
+        }
+    }
+}
+
+impl<E: Encoding> Encoding for UniqueFamilies<E> {
+    type Err = DuplicateFamilyError<E::Err>;
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.inner
+            .write_help(name, help)
+            .map_err(DuplicateFamilyError::Encoder)
+    }
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Self::Err> {
+        self.inner
+            .write_unit(name, unit)
+            .map_err(DuplicateFamilyError::Encoder)
+    }
+}
+
+impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<UniqueFamilies<E>> for M {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut UniqueFamilies<E>,
+    ) -> Result<(), DuplicateFamilyError<E::Err>> {
+        enc.check(&name)?;
+        M::write_type(name, &mut enc.inner).map_err(DuplicateFamilyError::Encoder)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl crate::label::LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut UniqueFamilies<E>,
+    ) -> Result<(), DuplicateFamilyError<E::Err>> {
+        self.collect_into(metadata, labels, name, &mut enc.inner)
+            .map_err(DuplicateFamilyError::Encoder)
+    }
+}
+
 #[cfg(all(feature = "lasso", test))]
 mod tests {
     use lasso::{Rodeo, RodeoReader};
@@ -279,4 +673,146 @@ http_request_errors{kind="network",route="/api/v1/products/:id/purchase"} 0
 "#
         );
     }
+
+    #[test]
+    fn unique_families_across_groups() {
+        use super::{ComposedGroup, DuplicateFamilyError, UniqueFamilies};
+        use crate::metric::name::WithNamespace;
+
+        #[derive(MetricGroup, Default)]
+        #[metric(crate = crate)]
+        struct Cache {
+            hits: Counter,
+        }
+
+        #[derive(MetricGroup, Default)]
+        #[metric(crate = crate)]
+        struct Db {
+            cache_hits: Counter,
+            queries: Counter,
+        }
+
+        let mut enc = BufferedTextEncoder::new();
+
+        // distinct names are fine
+        let metrics = ComposedGroup(WithNamespace::new("app", Cache::default()), Db::default());
+        metrics
+            .collect_group_into(&mut UniqueFamilies::new(&mut enc))
+            .unwrap();
+        enc.finish();
+
+        // the names only collide after the namespace is applied
+        let metrics = ComposedGroup(
+            WithNamespace::new("app_cache", Cache::default()),
+            WithNamespace::new("app", Db::default()),
+        );
+        let err = metrics
+            .collect_group_into(&mut UniqueFamilies::new(&mut enc))
+            .unwrap_err();
+        assert!(matches!(&err, DuplicateFamilyError::Duplicate(name) if name == "app_cache_hits"));
+        assert_eq!(
+            err.to_string(),
+            "metric family app_cache_hits was written more than once"
+        );
+    }
+
+    #[test]
+    fn runtime_namespace() {
+        use super::{ComposedGroup, RuntimeNamespace};
+        use crate::metric::name::{InvalidMetricName, WithNamespace};
+
+        #[derive(MetricGroup, Default)]
+        #[metric(crate = crate)]
+        struct Http {
+            requests: Counter,
+        }
+
+        #[derive(MetricGroup)]
+        #[metric(crate = crate)]
+        struct App {
+            /// request latency
+            #[metric(metadata = Thresholds::with_buckets([1.0]))]
+            latency: Histogram<1>,
+
+            #[metric(namespace = "http")]
+            http: Http,
+        }
+
+        let app = App {
+            latency: Histogram::with_metadata(Thresholds::with_buckets([1.0])),
+            http: Http::default(),
+        };
+        app.latency.observe(0.5);
+        let metrics = RuntimeNamespace::try_new(
+            String::from("myapp"),
+            ComposedGroup(app, WithNamespace::new("db", Http::default())),
+        )
+        .unwrap();
+
+        let mut enc = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut enc).unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# HELP myapp_latency request latency\n\
+            # TYPE myapp_latency histogram\n\
+            myapp_latency_bucket{le=\"1.0\"} 1\n\
+            myapp_latency_bucket{le=\"+Inf\"} 1\n\
+            myapp_latency_sum 0.5\n\
+            myapp_latency_count 1\n\
+            \n\
+            # TYPE myapp_http_requests counter\n\
+            myapp_http_requests 0\n\
+            \n\
+            # TYPE myapp_db_requests counter\n\
+            myapp_db_requests 0\n"
+        );
+
+        let metrics = RuntimeNamespace::try_new("myapp", Http::default())
+            .unwrap()
+            .with_separator(":");
+        metrics.collect_group_into(&mut enc).unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE myapp:requests counter\nmyapp:requests 0\n"
+        );
+
+        assert!(matches!(
+            RuntimeNamespace::try_new("my app", Http::default()),
+            Err(InvalidMetricName::InvalidChars)
+        ));
+    }
+
+    #[test]
+    fn scrape_sequence() {
+        use super::ScrapeSequence;
+
+        #[derive(MetricGroup, Default)]
+        #[metric(crate = crate)]
+        struct Http {
+            requests: Counter,
+        }
+
+        let metrics = ScrapeSequence::new(Http::default());
+        assert_eq!(metrics.sequence(), 0);
+
+        let mut enc = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut enc).unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE requests counter\n\
+            requests 0\n\
+            \n\
+            # HELP scrape_sequence_total The number of times the metrics were collected\n\
+            # TYPE scrape_sequence_total counter\n\
+            scrape_sequence_total 1\n"
+        );
+
+        metrics.inner().requests.inc();
+        metrics.collect_group_into(&mut enc).unwrap();
+        let text = enc.finish();
+        assert!(std::str::from_utf8(&text)
+            .unwrap()
+            .ends_with("requests 1\n\n# HELP scrape_sequence_total The number of times the metrics were collected\n# TYPE scrape_sequence_total counter\nscrape_sequence_total 2\n"));
+        assert_eq!(metrics.sequence(), 2);
+    }
 }
@@ -0,0 +1,182 @@
+//! Metrics which carry their own help text and unit. See [`Described`]
+
+use core::ops::{Deref, DerefMut};
+
+use crate::label::LabelGroupSet;
+
+use super::{
+    group::Encoding, name::MetricNameEncoder, reset::Reset, Metric, MetricFamilyEncoding,
+    MetricType, MetricVec,
+};
+
+/// A metric family with a help text, and optionally a unit, that is written before the family is collected.
+///
+/// The [`MetricGroup`](crate::MetricGroup) derive takes the help text of a metric from its doc comment.
+/// This is for metric families that are collected some other way, like in a hand-written [`MetricGroup`](super::group::MetricGroup)
+/// implementation, or a help text that is only known at runtime. A metric with a doc comment in a derived group
+/// should not also be described, as its help text would be written twice.
+///
+/// ```
+/// use measured::{Counter, MetricGroup};
+/// use measured::metric::help::Described;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(MetricGroup)]
+/// struct Metrics {
+///     sent_bytes_total: Described<Counter>,
+/// }
+///
+/// let metrics = Metrics {
+///     sent_bytes_total: Counter::new().with_help("total bytes sent\nover all connections").with_unit("bytes"),
+/// };
+/// metrics.sent_bytes_total.inc_by(512);
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// metrics.collect_group_into(&mut enc).unwrap();
+/// assert_eq!(
+///     enc.finish(),
+///     r#"# HELP sent_bytes_total total bytes sent\nover all connections
+/// ## TYPE sent_bytes_total counter
+/// sent_bytes_total 512
+/// "#,
+/// );
+/// ```
+pub struct Described<M> {
+    metric: M,
+    help: &'static str,
+    unit: Option<&'static str>,
+}
+
+impl<M> Described<M> {
+    /// Describe the metric family with the help text
+    pub fn new(metric: M, help: &'static str) -> Self {
+        Self {
+            metric,
+            help,
+            unit: None,
+        }
+    }
+
+    /// Also describe the unit of the metric family, like `seconds` or `bytes`.
+    /// See [`Encoding::write_unit`]
+    pub fn with_unit(self, unit: &'static str) -> Self {
+        Self {
+            unit: Some(unit),
+            ..self
+        }
+    }
+
+    /// The help text of the metric family
+    pub fn help(&self) -> &'static str {
+        self.help
+    }
+
+    /// The unit of the metric family, if any
+    pub fn unit(&self) -> Option<&'static str> {
+        self.unit
+    }
+
+    /// Extract the inner metric
+    pub fn into_inner(self) -> M {
+        self.metric
+    }
+}
+
+impl<M> Deref for Described<M> {
+    type Target = M;
+
+    fn deref(&self) -> &Self::Target {
+        &self.metric
+    }
+}
+
+impl<M> DerefMut for Described<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.metric
+    }
+}
+
+impl<M: MetricType> Metric<M> {
+    /// Describe this metric with the help text. See [`Described`]
+    pub fn with_help(self, help: &'static str) -> Described<Self> {
+        Described::new(self, help)
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
+    /// Describe this metric vec with the help text. See [`Described`]
+    pub fn with_help(self, help: &'static str) -> Described<Self> {
+        Described::new(self, help)
+    }
+}
+
+impl<M: MetricFamilyEncoding<T>, T: Encoding> MetricFamilyEncoding<T> for Described<M> {
+    fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
+        enc.write_help(&name, self.help)?;
+        if let Some(unit) = self.unit {
+            enc.write_unit(&name, unit)?;
+        }
+        self.metric.collect_family_into(name, enc)
+    }
+}
+
+impl<M: Reset> Reset for Described<M> {
+    fn reset(&self) {
+        self.metric.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::{BufferedTextEncoder, TextFormat},
+        CounterVec, FixedCardinalityLabel, Gauge,
+    };
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "kind")]
+    enum Kind {
+        Read,
+    }
+
+    #[test]
+    fn help_and_unit() {
+        let ops = CounterVec::<StaticLabelSet<Kind>>::new()
+            .with_help(r#"operations, by "kind" \ type"#)
+            .with_unit("ops");
+        ops.inc(Kind::Read);
+
+        let mut enc = BufferedTextEncoder::new();
+        ops.collect_family_into(MetricName::from_str("done_ops_total"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# HELP done_ops_total operations, by \"kind\" \\\\ type\n\
+            # TYPE done_ops_total counter\n\
+            done_ops_total{kind=\"read\"} 1\n"
+        );
+
+        let mut enc = BufferedTextEncoder::new().with_format(TextFormat::OpenMetrics);
+        ops.collect_family_into(MetricName::from_str("done_ops_total"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE done_ops counter\n\
+            # UNIT done_ops ops\n\
+            # HELP done_ops operations, by \\\"kind\\\" \\\\ type\n\
+            done_ops_total{kind=\"read\"} 1\n\
+            # EOF\n"
+        );
+    }
+
+    #[test]
+    fn without_help() {
+        let up = Gauge::new();
+        let mut enc = BufferedTextEncoder::new();
+        up.collect_family_into(MetricName::from_str("up"), &mut enc)
+            .unwrap();
+        assert_eq!(enc.finish(), "# TYPE up gauge\nup 0\n");
+    }
+}
@@ -1,14 +1,23 @@
 //! All things histograms. See [`Histogram`]
 
+#[cfg(feature = "log")]
+use std::sync::atomic::AtomicBool;
 use std::{
     sync::atomic::{AtomicU64, Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use parking_lot::RwLock;
 
-use super::{gauge::AtomicF64, MetricLockGuard, MetricMut, MetricType};
-use crate::{label::LabelGroupSet, Histogram, HistogramVec};
+use super::{gauge::AtomicF64, reset::ResetMetric, MetricLockGuard, MetricMut, MetricType};
+use crate::{
+    label::{ComposedGroup, LabelGroupSet},
+    Histogram, HistogramVec,
+};
+
+pub mod adaptive;
+pub mod dual;
+pub mod quantile;
 
 /// The inner state of a histogram.
 ///
@@ -26,14 +35,33 @@ pub struct HistogramStateInner<const N: usize> {
 
 impl<const N: usize> HistogramStateInner<N> {
     /// Add a single observation to the [`Histogram`].
+    ///
+    /// The sum is updated before the bucket count, so that [`load_count_and_sum`](Self::load_count_and_sum)
+    /// never sees an observation in the count that is missing from the sum.
     pub fn observe(&self, bucket: usize, x: f64) {
         assert!(bucket <= N);
+        self.sum.inc_by(x);
         if bucket < N {
-            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+            self.buckets[bucket].fetch_add(1, Ordering::Release);
         } else {
-            self.inf.fetch_add(1, Ordering::Relaxed);
+            self.inf.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Add the observations of another histogram, with the same write order as [`observe`](Self::observe).
+    ///
+    /// The sum is added first, and the counts are published with release ordering after it, so that
+    /// [`load_count_and_sum`](Self::load_count_and_sum) never sees counts without their sum.
+    pub(crate) fn add(&self, buckets: [u64; N], inf: u64, sum: f64) {
+        self.sum.inc_by(sum);
+        for (bucket, count) in self.buckets.iter().zip(buckets) {
+            if count > 0 {
+                bucket.fetch_add(count, Ordering::Release);
+            }
+        }
+        if inf > 0 {
+            self.inf.fetch_add(inf, Ordering::Release);
         }
-        self.sum.inc_by(x);
     }
 
     /// Add a single observation to the [`Histogram`].
@@ -48,6 +76,41 @@ impl<const N: usize> HistogramStateInner<N> {
         self.sum.set_mut(v + x);
     }
 
+    /// Read the current bucket counts with the given memory ordering.
+    ///
+    /// Each bucket is loaded individually, so concurrent observations might only be partially reflected.
+    /// Hold the write lock of the [`HistogramState`] if a consistent view is needed.
+    pub fn load_buckets(&self, ordering: Ordering) -> [u64; N] {
+        core::array::from_fn(|i| self.buckets[i].load(ordering))
+    }
+
+    /// Read the current count of observations greater than the largest bucket with the given memory ordering.
+    pub fn load_inf(&self, ordering: Ordering) -> u64 {
+        self.inf.load(ordering)
+    }
+
+    /// Read the current sum of all observations with the given memory ordering.
+    pub fn load_sum(&self, ordering: Ordering) -> f64 {
+        self.sum.load(ordering)
+    }
+
+    /// Read the current total number of observations with the given memory ordering.
+    pub fn load_count(&self, ordering: Ordering) -> u64 {
+        self.buckets.iter().map(|b| b.load(ordering)).sum::<u64>() + self.inf.load(ordering)
+    }
+
+    /// Read the total number of observations, and the sum of at least those observations.
+    ///
+    /// This only needs the read lock of the [`HistogramState`], so it does not block concurrent observations.
+    /// Observations that happen while reading might be included in the sum but not yet in the count,
+    /// but never the other way around, so `sum / count` is a fair average even while the histogram is updated.
+    /// Hold the write lock of the [`HistogramState`] if an exact pair is needed.
+    pub fn load_count_and_sum(&self) -> (u64, f64) {
+        let count = self.load_count(Ordering::Acquire);
+        let sum = self.sum.load(Ordering::Acquire);
+        (count, sum)
+    }
+
     pub(crate) fn sample(&mut self) -> ([u64; N], u64, f64) {
         let mut output = [0; N];
         #[allow(clippy::needless_range_loop)]
@@ -66,6 +129,21 @@ pub struct HistogramState<const N: usize> {
     pub inner: RwLock<HistogramStateInner<N>>,
 }
 
+/// Prints the current count and sum of the histogram, without waiting if it is currently being sampled
+impl<const N: usize> core::fmt::Debug for HistogramState<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut d = f.debug_struct("HistogramState");
+        match self.inner.try_read() {
+            Some(inner) => {
+                let (count, sum) = inner.load_count_and_sum();
+                d.field("count", &count).field("sum", &sum)
+            }
+            None => d.field("inner", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
 /// A shared ref to an individual histogram
 pub type HistogramLockGuard<'a, const N: usize> = MetricLockGuard<'a, HistogramState<N>>;
 /// A unique ref to an individual histogram
@@ -85,19 +163,244 @@ impl<const N: usize> Default for HistogramState<N> {
     }
 }
 
+impl<const N: usize> HistogramState<N> {
+    /// Take a consistent snapshot of the buckets, with the bounds from `thresholds`.
+    ///
+    /// This takes the write lock, so no observation is partially included.
+    pub fn buckets_snapshot(&self, thresholds: &Thresholds<N>) -> BucketsSnapshot {
+        let (buckets, inf, sum) = self.inner.write().sample();
+
+        let mut count = 0;
+        let mut cumulative = Vec::with_capacity(N + 1);
+        for (&le, bucket) in thresholds.get().iter().zip(buckets) {
+            count += bucket;
+            cumulative.push((le, count));
+        }
+        count += inf;
+        cumulative.push((f64::INFINITY, count));
+
+        BucketsSnapshot {
+            cumulative,
+            sum,
+            count,
+        }
+    }
+}
+
 impl<const N: usize> MetricType for HistogramState<N> {
     type Metadata = Thresholds<N>;
 }
 
+/// A consistent snapshot of the buckets of a histogram, for computing quantiles or feeding custom exporters.
+/// See [`Histogram::buckets_snapshot`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BucketsSnapshot {
+    /// The `(le, count)` pair of every bucket, in increasing order of `le`, followed by `+Inf`.
+    ///
+    /// The counts are cumulative, like in the prometheus exposition: each count is the number of observations
+    /// less than or equal to `le`, so the `+Inf` count is the total count. See [`raw`](Self::raw) for the
+    /// number of observations in each bucket.
+    pub cumulative: Vec<(f64, u64)>,
+    /// The sum of all observations
+    pub sum: f64,
+    /// The total number of observations
+    pub count: u64,
+}
+
+impl BucketsSnapshot {
+    /// The `(le, count)` pair of every bucket, followed by `+Inf`, where each count is the number of observations
+    /// greater than the previous bound and less than or equal to `le`.
+    pub fn raw(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        let prev = core::iter::once(0).chain(self.cumulative.iter().map(|&(_, count)| count));
+        self.cumulative
+            .iter()
+            .zip(prev)
+            .map(|(&(le, count), prev)| (le, count - prev))
+    }
+
+    /// Estimate the `q` quantile of the observations, with `q` between `0.0` and `1.0`.
+    ///
+    /// Like Prometheus' `histogram_quantile`, the observations are assumed to be spread evenly within each bucket,
+    /// and the lowest bucket starts at 0, unless its bound is negative. The estimate is only as precise as the buckets.
+    /// If the quantile falls in the `+Inf` bucket, the largest finite bound is returned.
+    ///
+    /// Returns `None` if there are no observations.
+    ///
+    /// ```
+    /// use measured::Histogram;
+    /// use measured::metric::histogram::Thresholds;
+    ///
+    /// let histogram = Histogram::with_metadata(Thresholds::with_buckets([1.0, 2.0, 4.0]));
+    /// for x in [0.5, 1.5, 1.5, 3.0] {
+    ///     histogram.observe(x);
+    /// }
+    /// let snapshot = histogram.buckets_snapshot();
+    /// assert_eq!(snapshot.quantile(0.5), Some(1.5));
+    /// assert_eq!(snapshot.quantile(1.0), Some(4.0));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `q` is not within `0.0..=1.0`.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        assert!(
+            (0.0..=1.0).contains(&q),
+            "quantile {q} is not within 0.0..=1.0"
+        );
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = q * self.count as f64;
+        let i = self
+            .cumulative
+            .iter()
+            .position(|&(_, count)| count as f64 >= rank)
+            .unwrap_or(self.cumulative.len() - 1);
+
+        let (end, count) = self.cumulative[i];
+        if end == f64::INFINITY {
+            // the `+Inf` bucket has no upper bound to interpolate to
+            return Some(self.cumulative.get(i.wrapping_sub(1)).map_or(0.0, |b| b.0));
+        }
+
+        let (start, below) = match i.checked_sub(1) {
+            Some(prev) => self.cumulative[prev],
+            // the lowest bucket has no lower bound
+            None if end <= 0.0 => return Some(end),
+            None => (0.0, 0),
+        };
+        let in_bucket = (count - below) as f64;
+        if in_bucket == 0.0 {
+            return Some(end);
+        }
+        Some(start + (end - start) * (rank - below as f64) / in_bucket)
+    }
+}
+
 /// `Thresholds` defines the size of buckets used in a [`Histogram`]
+///
+/// Bucket bounds can be negative, for distributions like temperature deltas or queue growth rates.
+/// As prometheus buckets are cumulative, the lowest bucket acts as the underflow bucket: it counts every
+/// observation less than or equal to the lowest bound, down to `-Inf`. Every observation is counted
+/// in the histogram `count` and `sum`, regardless of the bucket it lands in.
+///
+/// ```
+/// use measured::Histogram;
+/// use measured::metric::histogram::Thresholds;
+///
+/// let histogram = Histogram::with_metadata(Thresholds::with_buckets([-10.0, -1.0, 0.0, 1.0, 10.0]));
+/// histogram.observe(-25.0); // counted in the `le="-10"` bucket
+/// histogram.observe(-0.5); // counted in the `le="0"` bucket
+/// ```
+#[derive(Clone)]
 pub struct Thresholds<const N: usize> {
     le: [f64; N],
+    /// The range that observations are clamped to before being added to the sum
+    clamp: (f64, f64),
+}
+
+/// The error returned by [`Thresholds::from_slice`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidThresholds {
+    /// The number of bucket bounds did not match the number of buckets of the histogram
+    Length {
+        /// The number of buckets of the histogram
+        expected: usize,
+        /// The number of bucket bounds given
+        actual: usize,
+    },
+    /// The bucket bound at `index` was not greater than the previous bound
+    NotIncreasing {
+        /// The index of the bound that was not greater than the previous bound
+        index: usize,
+        /// The previous bound
+        previous: f64,
+        /// The bound at `index`
+        bound: f64,
+    },
+}
+
+impl core::fmt::Display for InvalidThresholds {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvalidThresholds::Length { expected, actual } => {
+                write!(f, "expected {expected} histogram buckets, got {actual}")
+            }
+            InvalidThresholds::NotIncreasing {
+                previous, bound, ..
+            } => write!(
+                f,
+                "consecutive histogram buckets must not decrease or be equal, {previous} >= {bound}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidThresholds {}
+
+/// Check that the bucket bounds are strictly monotonically increasing
+fn check_buckets(buckets: &[f64]) -> Result<(), InvalidThresholds> {
+    for (i, w) in buckets.windows(2).enumerate() {
+        // NaN is not comparable, so it is rejected too
+        if w[0].partial_cmp(&w[1]) != Some(core::cmp::Ordering::Less) {
+            return Err(InvalidThresholds::NotIncreasing {
+                index: i + 1,
+                previous: w[0],
+                bound: w[1],
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Round a computed bucket bound to 15 significant digits, the precision that every `f64` can represent.
+///
+/// This removes the error of the float arithmetic, so that the bound is formatted the same way as if it was
+/// written as a literal. This does not allocate.
+fn round_bound(x: f64) -> f64 {
+    use core::fmt::Write;
+
+    struct Buf {
+        b: [u8; 32],
+        len: usize,
+    }
+    impl Write for Buf {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            self.b
+                .get_mut(self.len..end)
+                .ok_or(core::fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    if !x.is_finite() {
+        return x;
+    }
+    let mut buf = Buf { b: [0; 32], len: 0 };
+    write!(buf, "{x:.14e}").expect("a rounded float should fit in 32 bytes");
+    core::str::from_utf8(&buf.b[..buf.len])
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .expect("a formatted float should parse")
 }
 
 impl<const N: usize> Thresholds<N> {
+    fn from_le(le: [f64; N]) -> Self {
+        Thresholds {
+            le,
+            clamp: (f64::NEG_INFINITY, f64::INFINITY),
+        }
+    }
+
     /// Create `N` buckets, where the lowest bucket has an upper bound of `start` and each following bucket’s upper bound is `factor` times the previous bucket’s upper bound.
     /// The final +Inf bucket is not counted and not included.
     ///
+    /// The upper bounds are rounded to 15 significant digits, so that they are exposed in the `le` label as
+    /// `0.3` rather than `0.30000000000000004`.
+    ///
     /// # Panics
     /// The function panics if `start` is zero or negative, or if `factor` is less than or equal 1.
     pub fn exponential_buckets(start: f64, factor: f64) -> Self {
@@ -111,14 +414,84 @@ impl<const N: usize> Thresholds<N> {
             "exponential_buckets needs a factor greater than 1, factor: {factor}",
         );
 
-        let buckets = core::array::from_fn(|i| start * factor.powi(i as i32));
+        let buckets = core::array::from_fn(|i| round_bound(start * factor.powi(i as i32)));
 
-        Thresholds { le: buckets }
+        Thresholds::from_le(buckets)
+    }
+
+    /// Like [`Thresholds::exponential_buckets`], but the largest bucket upper bound never exceeds `max`.
+    ///
+    /// If `start * factor^(N-1)` would be greater than `max`, the factor is reduced so that the `N` buckets
+    /// grow exponentially from `start` up to exactly `max` instead. Otherwise, the buckets are the same as
+    /// [`Thresholds::exponential_buckets`]. The final +Inf bucket is not counted and not included.
+    ///
+    /// ```
+    /// use measured::metric::histogram::Thresholds;
+    ///
+    /// let thresholds = Thresholds::<4>::exponential_buckets_capped(1.0, 10.0, 8.0);
+    /// assert_eq!(thresholds.get(), &[1.0, 2.0, 4.0, 8.0]);
+    /// ```
+    ///
+    /// # Panics
+    /// The function panics if `start` is zero or negative, if `factor` is less than or equal 1,
+    /// or if `max` is less than `start`. If `N` is greater than 1, `max` must also be strictly greater than `start`.
+    pub fn exponential_buckets_capped(start: f64, factor: f64, max: f64) -> Self {
+        assert!(
+            max > start || (N <= 1 && max == start),
+            "exponential_buckets_capped needs a max greater than start, start: {start}, max: {max}",
+        );
+
+        if N <= 1 || start * factor.powi(N as i32 - 1) <= max {
+            return Self::exponential_buckets(start, factor);
+        }
+
+        let factor = (max / start).powf(1.0 / (N - 1) as f64);
+        let mut thresholds = Self::exponential_buckets(start, factor);
+        // avoid any rounding errors in the largest bucket
+        thresholds.le[N - 1] = max;
+        thresholds
+    }
+
+    /// Create `N` buckets for sizes in bytes, where the lowest bucket has an upper bound of `start` bytes and each
+    /// following bucket's upper bound is `factor` times the previous bucket's upper bound.
+    /// The final +Inf bucket is not counted and not included.
+    ///
+    /// With a power of two start and factor, the bounds are the usual `1KiB, 4KiB, 16KiB, ...` sizes. The bounds are
+    /// whole numbers, and they are exposed in the `le` label as such, like `16384.0`, without an exponent.
+    ///
+    /// ```
+    /// use measured::metric::histogram::Thresholds;
+    ///
+    /// let thresholds = Thresholds::<4>::byte_buckets(1024, 4);
+    /// assert_eq!(thresholds.get(), &[1024.0, 4096.0, 16384.0, 65536.0]);
+    /// ```
+    ///
+    /// # Panics
+    /// The function panics if `start` is zero, if `factor` is less than 2, or if the largest bound overflows a `u64`.
+    pub fn byte_buckets(start: u64, factor: u64) -> Self {
+        assert!(start > 0, "byte_buckets needs a positive start value");
+        assert!(
+            factor > 1,
+            "byte_buckets needs a factor of at least 2, factor: {factor}",
+        );
+
+        let buckets = core::array::from_fn(|i| {
+            let bound = u32::try_from(i)
+                .ok()
+                .and_then(|i| factor.checked_pow(i))
+                .and_then(|f| start.checked_mul(f))
+                .unwrap_or_else(|| panic!("byte_buckets bound {i} overflows a u64"));
+            bound as f64
+        });
+
+        Thresholds::from_le(buckets)
     }
 
     /// Create `N` buckets, each `width`  wide, where the lowest bucket has an upper bound of `start`.
     /// The final +Inf bucket is not counted and not included.
     ///
+    /// Like [`Thresholds::exponential_buckets`], the upper bounds are rounded to 15 significant digits.
+    ///
     /// # Panics
     /// The function panics `width` is zero or negative.
     pub fn linear_buckets(start: f64, width: f64) -> Self {
@@ -127,9 +500,9 @@ impl<const N: usize> Thresholds<N> {
             "linear_buckets needs a width greate than 0, width: {width}",
         );
 
-        let buckets = core::array::from_fn(|i| start + width * i as f64);
+        let buckets = core::array::from_fn(|i| round_bound(start + width * i as f64));
 
-        Thresholds { le: buckets }
+        Thresholds::from_le(buckets)
     }
 
     /// Create the histogram thresholds with the given sizes
@@ -137,26 +510,190 @@ impl<const N: usize> Thresholds<N> {
     /// # Panics
     /// Will panic if the buckets are not strictly monotonically increasing
     pub fn with_buckets(buckets: [f64; N]) -> Self {
-        for i in 0..N - 1 {
+        if let Err(err) = check_buckets(&buckets) {
+            panic!("{err}");
+        }
+        Thresholds::from_le(buckets)
+    }
+
+    /// Create the histogram thresholds from a slice of bucket upper bounds, such as one loaded from configuration.
+    ///
+    /// This is the fallible version of [`Thresholds::with_buckets`], and the inverse of [`Thresholds::to_vec`].
+    ///
+    /// ```
+    /// use measured::metric::histogram::{InvalidThresholds, Thresholds};
+    ///
+    /// let config: Vec<f64> = vec![0.1, 0.5, 1.0];
+    /// let thresholds = Thresholds::<3>::from_slice(&config).unwrap();
+    /// assert_eq!(thresholds.to_vec(), config);
+    ///
+    /// assert_eq!(
+    ///     Thresholds::<4>::from_slice(&config).err(),
+    ///     Some(InvalidThresholds::Length { expected: 4, actual: 3 }),
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if the slice does not have exactly `N` bounds, or if the bounds are not strictly
+    /// monotonically increasing.
+    pub fn from_slice(buckets: &[f64]) -> Result<Self, InvalidThresholds> {
+        let buckets: [f64; N] = buckets.try_into().map_err(|_| InvalidThresholds::Length {
+            expected: N,
+            actual: buckets.len(),
+        })?;
+        check_buckets(&buckets)?;
+        Ok(Thresholds::from_le(buckets))
+    }
+
+    /// Copy the bucket upper bounds into a `Vec`, such as to store them in configuration.
+    ///
+    /// The clamp set by [`Thresholds::with_clamp`] is not included.
+    pub fn to_vec(&self) -> Vec<f64> {
+        self.le.to_vec()
+    }
+
+    /// Create the histogram thresholds from the given bucket upper bounds, converted to seconds.
+    ///
+    /// Use this with [`HistogramLockGuard::observe_duration`] to make latency buckets self-documenting.
+    /// The [`duration_buckets!`](crate::duration_buckets) macro accepts the durations as literals like `5ms`.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use measured::metric::histogram::Thresholds;
+    ///
+    /// let thresholds = Thresholds::from_durations([
+    ///     Duration::from_millis(5),
+    ///     Duration::from_millis(250),
+    ///     Duration::from_secs(1),
+    /// ]);
+    /// assert_eq!(thresholds.get(), &[0.005, 0.25, 1.0]);
+    /// ```
+    ///
+    /// # Panics
+    /// Will panic if the durations are not strictly monotonically increasing
+    pub fn from_durations(durations: [std::time::Duration; N]) -> Self {
+        for i in 0..N.saturating_sub(1) {
             assert!(
-                buckets[i] < buckets[i + 1],
-                "consecutive histogram buckets must not decrease or be equal",
+                durations[i] < durations[i + 1],
+                "consecutive histogram buckets must not decrease or be equal, {:?} >= {:?}",
+                durations[i],
+                durations[i + 1],
             );
         }
-        Thresholds { le: buckets }
+        Thresholds::from_le(durations.map(|d| d.as_secs_f64()))
+    }
+
+    /// Clamp observations to the range `min..=max` before they are added to the histogram sum.
+    ///
+    /// The observations are still counted in the bucket of the original value, so an outlier still
+    /// lands in the top bucket, but a single absurd value, like a clock glitch, cannot poison the average.
+    /// By default, observations are not clamped.
+    ///
+    /// # Panics
+    /// The function panics if `min` is greater than `max`, or if either is NaN.
+    pub fn with_clamp(self, min: f64, max: f64) -> Self {
+        assert!(
+            min <= max,
+            "histogram clamp needs min <= max, min: {min}, max: {max}",
+        );
+        Thresholds {
+            le: self.le,
+            clamp: (min, max),
+        }
     }
 
     /// View the bucket upper bounds
     pub fn get(&self) -> &[f64; N] {
         &self.le
     }
+
+    /// Clamp the observation to the configured range, see [`Thresholds::with_clamp`]
+    #[inline]
+    pub fn clamp(&self, x: f64) -> f64 {
+        x.clamp(self.clamp.0, self.clamp.1)
+    }
+
+    /// The duration elapsed since the given instant, as observed by the timer methods like
+    /// [`HistogramLockGuard::observe_duration_since`].
+    ///
+    /// A monotonic clock glitch, or an instant that was captured in the future, gives a zero duration
+    /// rather than an odd value. If observations are clamped with [`Thresholds::with_clamp`], a duration above
+    /// the clamp is anomalous too, and it is clamped like any other observation. With the `log` feature enabled,
+    /// the first anomalous duration of each kind is logged as a warning.
+    pub fn elapsed_since(&self, since: Instant) -> Duration {
+        let Some(d) = Instant::now().checked_duration_since(since) else {
+            #[cfg(feature = "log")]
+            {
+                static WARNED: AtomicBool = AtomicBool::new(false);
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    log::warn!(
+                        "histogram timer was started in the future, observing a zero duration"
+                    );
+                }
+            }
+            return Duration::ZERO;
+        };
+
+        #[cfg(feature = "log")]
+        if d.as_secs_f64() > self.clamp.1 {
+            static WARNED: AtomicBool = AtomicBool::new(false);
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                log::warn!(
+                    "histogram timer observed {d:?}, more than the clamp of {}s",
+                    self.clamp.1
+                );
+            }
+        }
+        d
+    }
+
+    /// Find the index of the bucket that the observation `x` belongs in.
+    /// Returns `N` if `x` is greater than every bucket upper bound.
+    ///
+    /// For small `N`, this counts the number of bounds that `x` is greater than, which
+    /// is branchless and can be vectorised. For larger `N`, a binary search is used.
+    #[inline]
+    pub fn bucket(&self, x: f64) -> usize {
+        if N <= 16 {
+            // since the buckets are sorted, this count is equivalent to the partition point.
+            self.le.iter().map(|le| (x > *le) as usize).sum()
+        } else {
+            self.le.partition_point(|le| x > *le)
+        }
+    }
 }
 
 impl<const N: usize> HistogramLockGuard<'_, N> {
     /// Add a single observation to the [`Histogram`].
     pub fn observe(self, x: f64) {
-        let bucket = self.metadata().le.partition_point(|le| x > *le);
-        self.inner.read().observe(bucket, x);
+        let bucket = self.metadata().bucket(x);
+        self.inner.read().observe(bucket, self.metadata().clamp(x));
+    }
+
+    /// Add a single observation to the [`Histogram`], and return whether it is greater than `threshold`.
+    ///
+    /// This is for SLO tracking, like counting the requests that were slower than the objective next to their
+    /// latency histogram. The observation is always recorded. The comparison uses `x` before it is clamped,
+    /// and is `false` if `x` is NaN.
+    pub fn observe_checking(self, x: f64, threshold: f64) -> bool {
+        self.observe(x);
+        x > threshold
+    }
+
+    /// Add a single observation to the [`Histogram`], in a bucket that was already computed by the caller,
+    /// eg with [`Thresholds::bucket`].
+    ///
+    /// This skips the search through the bucket thresholds. `bucket` is the index of the bucket in the thresholds,
+    /// or `N` for the `+Inf` bucket. The value `x` is still clamped and added to the sum.
+    ///
+    /// # Panics
+    /// Panics if `bucket` is greater than `N`.
+    pub fn observe_at_bucket(self, bucket: usize, x: f64) {
+        assert!(
+            bucket <= N,
+            "bucket index {bucket} is out of range for a histogram with {N} buckets"
+        );
+        self.inner.read().observe(bucket, self.metadata().clamp(x));
     }
 
     /// Observe the duration in seconds
@@ -164,18 +701,53 @@ impl<const N: usize> HistogramLockGuard<'_, N> {
         self.observe(duration.as_secs_f64());
     }
 
-    /// Observe the duration in seconds since the given instant
+    /// Observe the duration in seconds since the given instant.
+    ///
+    /// An instant in the future is observed as a zero duration. See [`Thresholds::elapsed_since`].
     pub fn observe_duration_since(self, since: std::time::Instant) -> std::time::Duration {
-        let d = since.elapsed();
+        let d = self.metadata().elapsed_since(since);
         self.observe_duration(d);
         d
     }
+
+    /// Reset all the buckets, the count and the sum to zero.
+    ///
+    /// This takes the write lock of the [`HistogramState`], so concurrent observations wait for the reset,
+    /// and each of them is either fully reset or fully counted after it.
+    pub fn reset(self) {
+        ResetMetric::reset(&*self);
+    }
 }
 
 impl<const N: usize> HistogramMut<'_, N> {
     /// Add a single observation to the [`Histogram`].
     pub fn observe(mut self, x: f64) {
-        let bucket = self.metadata().le.partition_point(|le| x > *le);
+        let bucket = self.metadata().bucket(x);
+        let x = self.metadata().clamp(x);
+        self.inner.get_mut().observe(bucket, x);
+    }
+
+    /// Add a single observation to the [`Histogram`], and return whether it is greater than `threshold`.
+    /// See [`HistogramLockGuard::observe_checking`]
+    pub fn observe_checking(self, x: f64, threshold: f64) -> bool {
+        self.observe(x);
+        x > threshold
+    }
+
+    /// Add a single observation to the [`Histogram`], in a bucket that was already computed by the caller,
+    /// eg with [`Thresholds::bucket`].
+    ///
+    /// This skips the search through the bucket thresholds. `bucket` is the index of the bucket in the thresholds,
+    /// or `N` for the `+Inf` bucket. The value `x` is still clamped and added to the sum.
+    ///
+    /// # Panics
+    /// Panics if `bucket` is greater than `N`.
+    pub fn observe_at_bucket(mut self, bucket: usize, x: f64) {
+        assert!(
+            bucket <= N,
+            "bucket index {bucket} is out of range for a histogram with {N} buckets"
+        );
+        let x = self.metadata().clamp(x);
         self.inner.get_mut().observe(bucket, x);
     }
 
@@ -184,9 +756,11 @@ impl<const N: usize> HistogramMut<'_, N> {
         self.observe(duration.as_secs_f64());
     }
 
-    /// Observe the duration in seconds since the given instant
+    /// Observe the duration in seconds since the given instant.
+    ///
+    /// An instant in the future is observed as a zero duration. See [`Thresholds::elapsed_since`].
     pub fn observe_duration_since(self, since: std::time::Instant) -> std::time::Duration {
-        let d = since.elapsed();
+        let d = self.metadata().elapsed_since(since);
         self.observe_duration(d);
         d
     }
@@ -198,6 +772,17 @@ impl<const N: usize> Histogram<N> {
         self.get_metric().observe(x);
     }
 
+    /// Add a single observation to the [`Histogram`], and return whether it is greater than `threshold`.
+    /// See [`HistogramLockGuard::observe_checking`]
+    pub fn observe_checking(&self, x: f64, threshold: f64) -> bool {
+        self.get_metric().observe_checking(x, threshold)
+    }
+
+    /// Reset all the buckets, the count and the sum to zero. See [`HistogramLockGuard::reset`]
+    pub fn reset(&self) {
+        self.get_metric().reset();
+    }
+
     /// Create a [`HistogramVecTimer`] object that automatically observes a duration when the timer is dropped.
     pub fn start_timer(&self) -> HistogramTimer<'_, N> {
         HistogramTimer {
@@ -205,12 +790,95 @@ impl<const N: usize> Histogram<N> {
             start: std::time::Instant::now(),
         }
     }
+
+    /// Take a consistent snapshot of the buckets, sum and count of the [`Histogram`].
+    ///
+    /// ```
+    /// use measured::Histogram;
+    /// use measured::metric::histogram::Thresholds;
+    ///
+    /// let histogram = Histogram::with_metadata(Thresholds::with_buckets([1.0, 2.0]));
+    /// histogram.observe(0.5);
+    /// histogram.observe(1.5);
+    ///
+    /// let snapshot = histogram.buckets_snapshot();
+    /// assert_eq!(snapshot.cumulative, [(1.0, 1), (2.0, 2), (f64::INFINITY, 2)]);
+    /// assert_eq!(snapshot.raw().collect::<Vec<_>>(), [(1.0, 1), (2.0, 1), (f64::INFINITY, 0)]);
+    /// assert_eq!((snapshot.sum, snapshot.count), (2.0, 2));
+    /// ```
+    pub fn buckets_snapshot(&self) -> BucketsSnapshot {
+        let metric = self.get_metric();
+        metric.buckets_snapshot(metric.metadata())
+    }
 }
 
 impl<L: LabelGroupSet, const N: usize> HistogramVec<L, N> {
+    /// Drop observations with a label group that is not contained within the label set, instead of panicking.
+    ///
+    /// This applies to [`observe`](Self::observe), [`observe_duration`](Self::observe_duration) and
+    /// [`observe_duration_since`](Self::observe_duration_since). A label value that cannot be encoded, like
+    /// an unknown value for a fixed label set, should not take down a request handler. The dropped observations
+    /// are counted in [`dropped_unknown_labels`](Self::dropped_unknown_labels), and a warning is logged the first time
+    /// if the `log` feature is enabled.
+    ///
+    /// Other methods, like [`start_timer`](Self::start_timer), still panic.
+    pub fn drop_unknown_labels(mut self) -> Self {
+        self.unknown_labels.drop = true;
+        self
+    }
+
+    /// The number of observations that were dropped because their label group was not contained within the label set.
+    ///
+    /// This is always 0 unless [`drop_unknown_labels`](Self::drop_unknown_labels) was set.
+    pub fn dropped_unknown_labels(&self) -> u64 {
+        self.unknown_labels.dropped.load(Ordering::Relaxed)
+    }
+
     /// Add a single observation to the [`Histogram`], keyed by the label group.
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set, unless
+    /// [`drop_unknown_labels`](Self::drop_unknown_labels) is set.
     pub fn observe(&self, label: L::Group<'_>, y: f64) {
-        self.get_metric(self.with_labels(label)).observe(y);
+        if let Some(id) = self.with_labels_or_drop(label) {
+            self.get_metric(id).observe(y);
+        }
+    }
+
+    /// Add a single observation to the [`Histogram`], keyed by the label group, and return whether it is greater
+    /// than `threshold`. See [`HistogramLockGuard::observe_checking`]
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set, unless
+    /// [`drop_unknown_labels`](Self::drop_unknown_labels) is set.
+    pub fn observe_checking(&self, label: L::Group<'_>, y: f64, threshold: f64) -> bool {
+        if let Some(id) = self.with_labels_or_drop(label) {
+            self.get_metric(id).observe(y);
+        }
+        y > threshold
+    }
+
+    /// Add a single observation to the [`Histogram`], keyed by the label group.
+    ///
+    /// Returns `false`, without observing anything, if the label group is not contained within the label set.
+    pub fn try_observe(&self, label: L::Group<'_>, y: f64) -> bool {
+        match self.try_with_labels(label) {
+            Some(id) => {
+                self.get_metric(id).observe(y);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take a consistent snapshot of the buckets, sum and count of the histogram, keyed by the label group.
+    /// See [`Histogram::buckets_snapshot`]
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set.
+    pub fn buckets_snapshot(&self, label: L::Group<'_>) -> BucketsSnapshot {
+        self.get_metric(self.with_labels(label))
+            .buckets_snapshot(self.metadata())
     }
 
     /// Create a [`HistogramVecTimer`] object that automatically observes a duration when the timer is dropped.
@@ -226,20 +894,83 @@ impl<L: LabelGroupSet, const N: usize> HistogramVec<L, N> {
     }
 
     /// Observe the duration in seconds
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set, unless
+    /// [`drop_unknown_labels`](Self::drop_unknown_labels) is set.
     pub fn observe_duration(&self, label: L::Group<'_>, duration: std::time::Duration) {
         self.observe(label, duration.as_secs_f64());
     }
 
     /// Observe the duration in seconds since the given instant
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set, unless
+    /// [`drop_unknown_labels`](Self::drop_unknown_labels) is set.
     pub fn observe_duration_since(
         &self,
         label: L::Group<'_>,
         since: std::time::Instant,
     ) -> Duration {
-        let d = since.elapsed();
+        let d = self.metadata().elapsed_since(since);
         self.observe_duration(label, d);
         d
     }
+
+    /// Observe the duration in seconds since the given instant.
+    ///
+    /// Returns `None`, without observing anything, if the label group is not contained within the label set.
+    pub fn try_observe_duration_since(
+        &self,
+        label: L::Group<'_>,
+        since: std::time::Instant,
+    ) -> Option<Duration> {
+        let id = self.try_with_labels(label)?;
+        Some(self.get_metric(id).observe_duration_since(since))
+    }
+
+    /// Create a [`HistogramVecPartialTimer`] that only knows part of the label group.
+    ///
+    /// The remaining labels, such as the outcome of the operation, are provided when the duration is observed
+    /// with [`HistogramVecPartialTimer::observe_with`], which builds the full label group from the partial labels.
+    /// If the label set is a [`ComposedGroup`], the partial labels can be the first label group, and the timer
+    /// can be finished with [`HistogramVecPartialTimer::observe`] and the second label group.
+    ///
+    /// ```
+    /// use measured::{FixedCardinalityLabel, HistogramVec, LabelGroup};
+    /// use measured::metric::histogram::Thresholds;
+    ///
+    /// #[derive(Clone, Copy, FixedCardinalityLabel)]
+    /// #[label(rename_all = "snake_case")]
+    /// enum Method { Get, Post }
+    ///
+    /// #[derive(Clone, Copy, FixedCardinalityLabel)]
+    /// #[label(rename_all = "snake_case")]
+    /// enum Status { Ok, Error }
+    ///
+    /// #[derive(LabelGroup)]
+    /// #[label(set = RequestSet)]
+    /// struct Request {
+    ///     method: Method,
+    ///     status: Status,
+    /// }
+    ///
+    /// let latency = HistogramVec::<RequestSet, 2>::with_label_set_and_metadata(
+    ///     RequestSet::default(),
+    ///     Thresholds::with_buckets([0.1, 1.0]),
+    /// );
+    ///
+    /// let timer = latency.start_partial_timer(Method::Get);
+    /// // ... handle the request
+    /// timer.observe_with(|method| Request { method, status: Status::Ok });
+    /// ```
+    pub fn start_partial_timer<P>(&self, partial: P) -> HistogramVecPartialTimer<'_, L, P, N> {
+        HistogramVecPartialTimer {
+            vec: self,
+            partial,
+            start: std::time::Instant::now(),
+        }
+    }
 }
 
 /// See [`HistogramVec::start_timer`]
@@ -270,6 +1001,47 @@ impl<'a, L: LabelGroupSet, const N: usize> Drop for HistogramVecTimer<'a, L, N>
     }
 }
 
+/// See [`HistogramVec::start_partial_timer`]
+///
+/// Unlike [`HistogramVecTimer`], the duration cannot be observed on drop, as the remaining labels are not known.
+/// The timer must be finished with [`observe_with`](Self::observe_with), or discarded with [`forget`](Self::forget).
+#[must_use = "the duration is only observed when the timer is finished with the remaining labels"]
+pub struct HistogramVecPartialTimer<'a, L: LabelGroupSet, P, const N: usize> {
+    vec: &'a HistogramVec<L, N>,
+    partial: P,
+    start: std::time::Instant,
+}
+
+impl<'a, L: LabelGroupSet, P, const N: usize> HistogramVecPartialTimer<'a, L, P, N> {
+    /// Discard the timer, do not observe the duration.
+    pub fn forget(self) {}
+
+    /// Stop the timer and record the duration since the timer was started in the histogram, in seconds,
+    /// keyed by the label group that `labels` builds from the partial labels.
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set, unless
+    /// [`drop_unknown_labels`](HistogramVec::drop_unknown_labels) is set.
+    pub fn observe_with<'g>(self, labels: impl FnOnce(P) -> L::Group<'g>) -> Duration {
+        self.vec
+            .observe_duration_since(labels(self.partial), self.start)
+    }
+}
+
+impl<'a, 'g, A: LabelGroupSet, B: LabelGroupSet, const N: usize>
+    HistogramVecPartialTimer<'a, ComposedGroup<A, B>, A::Group<'g>, N>
+{
+    /// Stop the timer and record the duration since the timer was started in the histogram, in seconds,
+    /// keyed by the partial label group combined with the rest of the labels.
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set, unless
+    /// [`drop_unknown_labels`](HistogramVec::drop_unknown_labels) is set.
+    pub fn observe(self, rest: B::Group<'g>) -> Duration {
+        self.observe_with(|partial| ComposedGroup(partial, rest))
+    }
+}
+
 /// See [`Histogram::start_timer`]
 pub struct HistogramTimer<'a, const N: usize> {
     vec: Option<&'a Histogram<N>>,
@@ -296,3 +1068,565 @@ impl<'a, const N: usize> Drop for HistogramTimer<'a, N> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::Ordering,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        Histogram,
+    };
+
+    use super::{InvalidThresholds, Thresholds};
+
+    fn check<const N: usize>(thresholds: Thresholds<N>) {
+        let le = thresholds.get();
+        let mut values = vec![f64::NEG_INFINITY, f64::INFINITY, f64::NAN, 0.0, -1.0];
+        for &x in le {
+            values.extend([x, x - 0.0001, x + 0.0001]);
+        }
+        for x in values {
+            assert_eq!(
+                thresholds.bucket(x),
+                le.partition_point(|le| x > *le),
+                "bucket for {x}"
+            );
+        }
+    }
+
+    #[test]
+    fn bucket_matches_binary_search() {
+        check(Thresholds::<1>::linear_buckets(1.0, 1.0));
+        check(Thresholds::<8>::exponential_buckets(0.1, 2.0));
+        check(Thresholds::<16>::linear_buckets(-4.0, 0.5));
+        check(Thresholds::<17>::linear_buckets(-4.0, 0.5));
+        check(Thresholds::<64>::exponential_buckets(0.001, 1.5));
+    }
+
+    #[test]
+    fn raw_accessors() {
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([1.0, 2.0]));
+        for x in [0.5, 1.5, 1.5, 4.0] {
+            histogram.observe(x);
+        }
+
+        let m = histogram.get_metric();
+        let inner = m.inner.read();
+        assert_eq!(inner.load_buckets(Ordering::Acquire), [1, 2]);
+        assert_eq!(inner.load_inf(Ordering::Acquire), 1);
+        assert_eq!(inner.load_count(Ordering::Acquire), 4);
+        assert_eq!(inner.load_sum(Ordering::Acquire), 7.5);
+    }
+
+    #[test]
+    fn reset() {
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([1.0, 2.0]));
+        for x in [0.5, 1.5, 4.0] {
+            histogram.observe(x);
+        }
+        histogram.reset();
+
+        {
+            let m = histogram.get_metric();
+            let inner = m.inner.read();
+            assert_eq!(inner.load_count_and_sum(), (0, 0.0));
+            assert_eq!(inner.load_buckets(Ordering::Relaxed), [0, 0]);
+            assert_eq!(inner.load_inf(Ordering::Relaxed), 0);
+        }
+
+        histogram.observe(1.5);
+        assert_eq!(
+            histogram.get_metric().inner.read().load_count_and_sum(),
+            (1, 1.5)
+        );
+    }
+
+    #[test]
+    fn count_and_sum_consistent_under_concurrency() {
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([0.5, 2.0]));
+
+        std::thread::scope(|s| {
+            let writers: Vec<_> = (0..4)
+                .map(|_| {
+                    let histogram = &histogram;
+                    s.spawn(move || {
+                        for _ in 0..100000 {
+                            // every observation adds exactly 1 to the sum
+                            histogram.observe(1.0);
+                        }
+                    })
+                })
+                .collect();
+
+            while !writers.iter().all(|w| w.is_finished()) {
+                let m = histogram.get_metric();
+                let (count, sum) = m.inner.read().load_count_and_sum();
+                assert!(
+                    sum >= count as f64,
+                    "sum {sum} is missing observations in count {count}"
+                );
+            }
+        });
+
+        let m = histogram.get_metric();
+        assert_eq!(m.inner.read().load_count_and_sum(), (400000, 400000.0));
+    }
+
+    #[test]
+    fn clamp() {
+        let thresholds = Thresholds::<2>::with_buckets([1.0, 2.0]).with_clamp(0.0, 10.0);
+        let histogram = Histogram::with_metadata(thresholds);
+        histogram.observe(1.5);
+        histogram.observe(1e18);
+        histogram.observe(-5.0);
+
+        let m = histogram.get_metric();
+        let inner = m.inner.read();
+        // the outliers still land in the correct buckets
+        assert_eq!(inner.load_buckets(Ordering::Relaxed), [1, 1]);
+        assert_eq!(inner.load_inf(Ordering::Relaxed), 1);
+        // but the sum only includes the clamped values
+        assert_eq!(inner.load_sum(Ordering::Relaxed), 11.5);
+    }
+
+    #[test]
+    fn no_clamp_by_default() {
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([1.0, 2.0]));
+        histogram.observe(1e18);
+        histogram.observe(-5.0);
+        assert_eq!(
+            histogram
+                .get_metric()
+                .inner
+                .read()
+                .load_sum(Ordering::Relaxed),
+            1e18 - 5.0
+        );
+    }
+
+    #[test]
+    fn negative_observations() {
+        let thresholds = Thresholds::<5>::with_buckets([-10.0, -1.0, 0.0, 1.0, 10.0]);
+        assert_eq!(thresholds.bucket(f64::NEG_INFINITY), 0);
+        assert_eq!(thresholds.bucket(-1e9), 0);
+        assert_eq!(thresholds.bucket(-10.0), 0);
+        assert_eq!(thresholds.bucket(-9.0), 1);
+        assert_eq!(thresholds.bucket(-0.5), 2);
+        assert_eq!(thresholds.bucket(0.0), 2);
+        assert_eq!(thresholds.bucket(11.0), 5);
+
+        let histogram = Histogram::with_metadata(thresholds);
+        for x in [-25.0, -10.0, -3.0, -0.5, 0.5, 20.0] {
+            histogram.observe(x);
+        }
+
+        let m = histogram.get_metric();
+        let inner = m.inner.read();
+        assert_eq!(inner.load_buckets(Ordering::Relaxed), [2, 1, 1, 1, 0]);
+        assert_eq!(inner.load_inf(Ordering::Relaxed), 1);
+        assert_eq!(inner.load_count(Ordering::Relaxed), 6);
+        assert_eq!(inner.load_sum(Ordering::Relaxed), -18.0);
+    }
+
+    #[test]
+    fn le_labels_are_not_long_forms() {
+        let thresholds = Thresholds::<3>::linear_buckets(0.1, 0.1);
+        assert_eq!(thresholds.get(), &[0.1, 0.2, 0.3]);
+        assert_eq!(
+            Thresholds::<4>::exponential_buckets(0.1, 3.0).get(),
+            &[0.1, 0.3, 0.9, 2.7]
+        );
+
+        let histogram = Histogram::with_metadata(thresholds);
+        histogram.observe(0.3);
+
+        let mut enc = BufferedTextEncoder::new();
+        histogram
+            .collect_family_into(MetricName::from_str("latency"), &mut enc)
+            .unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        assert!(text.contains("latency_bucket{le=\"0.3\"} 1\n"), "{text}");
+        assert!(!text.contains("0.30000000000000004"));
+
+        // literal bounds are formatted in the shortest form that round trips
+        let histogram = Histogram::with_metadata(Thresholds::with_buckets([0.1, 0.2, 0.3]));
+        let mut enc = BufferedTextEncoder::new();
+        histogram
+            .collect_family_into(MetricName::from_str("latency"), &mut enc)
+            .unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        assert!(text.contains("latency_bucket{le=\"0.3\"} 0\n"), "{text}");
+    }
+
+    #[test]
+    fn observe_checking() {
+        let histogram = Histogram::with_metadata(Thresholds::with_buckets([0.1, 0.5]));
+        let mut violations = 0;
+        for x in [0.05, 0.25, 0.25, 0.75, f64::NAN] {
+            let slow = histogram.observe_checking(x, 0.25);
+            assert_eq!(slow, x > 0.25);
+            violations += slow as u64;
+        }
+        assert_eq!(violations, 1);
+
+        let snapshot = histogram.buckets_snapshot();
+        assert_eq!(snapshot.count, 5);
+        // NaN is recorded in the lowest bucket
+        assert_eq!(
+            snapshot.raw().map(|(_, c)| c).collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+    }
+
+    #[test]
+    fn byte_buckets() {
+        let thresholds = Thresholds::<6>::byte_buckets(1024, 1024);
+        assert_eq!(thresholds.get()[5], (1u64 << 60) as f64);
+        assert!(thresholds.get().windows(2).all(|w| w[0] < w[1]));
+
+        let histogram = Histogram::with_metadata(thresholds);
+        histogram.observe(20000.0);
+
+        let mut enc = BufferedTextEncoder::new();
+        histogram
+            .collect_family_into(MetricName::from_str("response_bytes"), &mut enc)
+            .unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        assert!(
+            text.contains("response_bytes_bucket{le=\"1048576.0\"} 1\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("response_bytes_bucket{le=\"1152921504606846976.0\"} 1\n"),
+            "{text}"
+        );
+        // no exponents, like `1.152921504606847e18`
+        assert!(!text.contains("e1"), "{text}");
+    }
+
+    #[test]
+    #[should_panic = "byte_buckets bound 6 overflows a u64"]
+    fn byte_buckets_overflow() {
+        Thresholds::<7>::byte_buckets(1024, 1024);
+    }
+
+    #[test]
+    fn exponential_buckets_capped() {
+        // not capped
+        assert_eq!(
+            Thresholds::<4>::exponential_buckets_capped(1.0, 2.0, 100.0).get(),
+            Thresholds::<4>::exponential_buckets(1.0, 2.0).get(),
+        );
+
+        // capped
+        let thresholds = Thresholds::<5>::exponential_buckets_capped(0.001, 10.0, 1.0);
+        let le = thresholds.get();
+        assert_eq!(le[0], 0.001);
+        assert_eq!(le[4], 1.0);
+        assert!(le.windows(2).all(|w| w[0] < w[1]));
+        check(thresholds);
+
+        assert_eq!(
+            Thresholds::<1>::exponential_buckets_capped(1.0, 2.0, 1.0).get(),
+            &[1.0]
+        );
+    }
+
+    #[test]
+    #[should_panic = "exponential_buckets_capped needs a max greater than start"]
+    fn exponential_buckets_capped_below_start() {
+        Thresholds::<4>::exponential_buckets_capped(1.0, 2.0, 0.5);
+    }
+
+    #[test]
+    fn from_durations() {
+        let thresholds = Thresholds::from_durations([
+            Duration::from_micros(500),
+            Duration::from_millis(5),
+            Duration::from_secs(2),
+        ]);
+        assert_eq!(thresholds.get(), &[0.0005, 0.005, 2.0]);
+        check(thresholds);
+    }
+
+    #[test]
+    fn timer_anomalies() {
+        let histogram =
+            Histogram::with_metadata(Thresholds::with_buckets([0.5, 1.0]).with_clamp(0.0, 1.0));
+
+        // a timer started in the future is a zero duration
+        let future = Instant::now() + Duration::from_secs(3600);
+        let d = histogram.get_metric().observe_duration_since(future);
+        assert_eq!(d, Duration::ZERO);
+
+        // a huge duration is clamped in the sum, but still counted in the +Inf bucket
+        let past = Instant::now() - Duration::from_secs(10);
+        let d = histogram.get_metric().observe_duration_since(past);
+        assert!(d >= Duration::from_secs(10));
+
+        let metric = histogram.get_metric();
+        let inner = metric.inner.read();
+        assert_eq!(inner.buckets[0].load(Ordering::Relaxed), 1);
+        assert_eq!(inner.inf.load(Ordering::Relaxed), 1);
+        assert_eq!(inner.load_sum(Ordering::Relaxed), 1.0);
+    }
+
+    #[test]
+    fn slice_roundtrip() {
+        let thresholds = Thresholds::<4>::exponential_buckets(0.001, 2.0);
+        let config = thresholds.to_vec();
+        assert_eq!(config, [0.001, 0.002, 0.004, 0.008]);
+        let parsed = Thresholds::<4>::from_slice(&config).unwrap();
+        assert_eq!(parsed.get(), thresholds.get());
+
+        assert_eq!(
+            Thresholds::<3>::from_slice(&config).err(),
+            Some(InvalidThresholds::Length {
+                expected: 3,
+                actual: 4
+            })
+        );
+        assert_eq!(
+            Thresholds::<3>::from_slice(&[1.0, 2.0, 2.0]).err(),
+            Some(InvalidThresholds::NotIncreasing {
+                index: 2,
+                previous: 2.0,
+                bound: 2.0
+            })
+        );
+        assert!(Thresholds::<2>::from_slice(&[1.0, f64::NAN]).is_err());
+        assert!(Thresholds::<0>::from_slice(&[]).is_ok());
+    }
+
+    #[test]
+    #[should_panic = "consecutive histogram buckets must not decrease or be equal, 2 >= 1"]
+    fn with_buckets_not_increasing() {
+        Thresholds::with_buckets([2.0, 1.0]);
+    }
+
+    #[test]
+    #[should_panic = "consecutive histogram buckets must not decrease or be equal, 10ms >= 5ms"]
+    fn from_durations_not_increasing() {
+        Thresholds::from_durations([Duration::from_millis(10), Duration::from_millis(5)]);
+    }
+
+    #[cfg(feature = "lasso")]
+    #[test]
+    fn try_observe_duration_since() {
+        use lasso::{Rodeo, RodeoReader};
+
+        use crate::HistogramVec;
+
+        #[derive(Clone, Copy, crate::LabelGroup)]
+        #[label(crate = crate, set = RouteSet)]
+        struct Route<'a> {
+            #[label(fixed_with = RodeoReader)]
+            route: &'a str,
+        }
+
+        let rodeo: Rodeo = ["/home"].into_iter().collect();
+        let histogram = HistogramVec::with_label_set_and_metadata(
+            RouteSet::new(rodeo.into_reader()),
+            Thresholds::<2>::with_buckets([1.0, 2.0]),
+        );
+
+        let start = Instant::now();
+        assert!(histogram
+            .try_observe_duration_since(Route { route: "/home" }, start)
+            .is_some());
+        assert!(histogram
+            .try_observe_duration_since(Route { route: "/unknown" }, start)
+            .is_none());
+
+        let id = histogram.with_labels(Route { route: "/home" });
+        let m = histogram.get_metric(id);
+        assert_eq!(m.inner.read().load_count(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn partial_timer() {
+        use crate::{
+            label::{ComposedGroup, StaticLabelSet},
+            FixedCardinalityLabel, HistogramVec,
+        };
+
+        #[derive(Clone, Copy, FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "method")]
+        enum Method {
+            Get,
+            Post,
+        }
+
+        #[derive(Clone, Copy, FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "status")]
+        enum Status {
+            Ok,
+            Error,
+        }
+
+        let latency = HistogramVec::with_label_set_and_metadata(
+            ComposedGroup(
+                StaticLabelSet::<Method>::new(),
+                StaticLabelSet::<Status>::new(),
+            ),
+            Thresholds::<2>::with_buckets([1.0, 2.0]),
+        );
+
+        latency.start_partial_timer(Method::Get).observe(Status::Ok);
+        latency.start_partial_timer(Method::Get).observe(Status::Ok);
+        latency
+            .start_partial_timer(Method::Post)
+            .observe(Status::Error);
+        latency.start_partial_timer(Method::Post).forget();
+        latency
+            .start_partial_timer(Method::Get)
+            .observe_with(|method| ComposedGroup(method, Status::Error));
+
+        let count = |method, status| {
+            let id = latency.with_labels(ComposedGroup(method, status));
+            let m = latency.get_metric(id);
+            let count = m.inner.read().load_count(Ordering::Relaxed);
+            count
+        };
+        assert_eq!(count(Method::Get, Status::Ok), 2);
+        assert_eq!(count(Method::Get, Status::Error), 1);
+        assert_eq!(count(Method::Post, Status::Error), 1);
+        assert_eq!(count(Method::Post, Status::Ok), 0);
+    }
+
+    #[test]
+    fn observe_at_bucket() {
+        let thresholds = Thresholds::<2>::with_buckets([1.0, 2.0]).with_clamp(0.0, 10.0);
+        let mut histogram = Histogram::with_metadata(thresholds);
+        histogram.get_metric().observe_at_bucket(0, 0.5);
+        histogram.get_metric().observe_at_bucket(2, 100.0);
+        histogram.get_metric_mut().observe_at_bucket(1, 1.5);
+
+        let m = histogram.get_metric();
+        let inner = m.inner.read();
+        assert_eq!(inner.load_buckets(Ordering::Relaxed), [1, 1]);
+        assert_eq!(inner.load_inf(Ordering::Relaxed), 1);
+        // the sum is still clamped
+        assert_eq!(inner.load_sum(Ordering::Relaxed), 12.0);
+    }
+
+    #[test]
+    #[should_panic = "bucket index 3 is out of range for a histogram with 2 buckets"]
+    fn observe_at_bucket_out_of_range() {
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([1.0, 2.0]));
+        histogram.get_metric().observe_at_bucket(3, 0.5);
+    }
+
+    #[test]
+    fn observation_on_bound_counts_in_that_bucket() {
+        // both the counting search for small N and the binary search for large N
+        let small = Thresholds::<3>::with_buckets([1.0, 2.0, 5.0]);
+        assert_eq!(small.bucket(1.0), 0);
+        assert_eq!(small.bucket(2.0), 1);
+        assert_eq!(small.bucket(5.0), 2);
+        assert_eq!(small.bucket(5.0 + f64::EPSILON * 8.0), 3);
+
+        let large = Thresholds::<32>::linear_buckets(1.0, 1.0);
+        for (i, &le) in large.get().iter().enumerate() {
+            assert_eq!(large.bucket(le), i, "le={le}");
+        }
+        assert_eq!(large.bucket(32.5), 32);
+
+        let histogram = Histogram::with_metadata(small);
+        for x in [1.0, 2.0, 2.0, 5.0] {
+            histogram.observe(x);
+        }
+        let m = histogram.get_metric();
+        let inner = m.inner.read();
+        assert_eq!(inner.load_buckets(Ordering::Relaxed), [1, 2, 1]);
+        assert_eq!(inner.load_inf(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn buckets_snapshot() {
+        use crate::{label::StaticLabelSet, FixedCardinalityLabel, HistogramVec};
+
+        #[derive(Clone, Copy, FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "operation")]
+        enum Operation {
+            Read,
+            Write,
+        }
+
+        let latency = HistogramVec::<StaticLabelSet<Operation>, 3>::with_metadata(
+            Thresholds::with_buckets([0.1, 1.0, 10.0]),
+        );
+        for x in [0.05, 0.5, 0.5, 20.0] {
+            latency.observe(Operation::Read, x);
+        }
+
+        let snapshot = latency.buckets_snapshot(Operation::Read);
+        assert_eq!(
+            snapshot.cumulative,
+            [(0.1, 1), (1.0, 3), (10.0, 3), (f64::INFINITY, 4)]
+        );
+        assert_eq!(
+            snapshot.raw().collect::<Vec<_>>(),
+            [(0.1, 1), (1.0, 2), (10.0, 0), (f64::INFINITY, 1)]
+        );
+        assert_eq!(snapshot.count, 4);
+        assert_eq!(snapshot.sum, 21.05);
+
+        let snapshot = latency.buckets_snapshot(Operation::Write);
+        assert!(snapshot.cumulative.iter().all(|&(_, count)| count == 0));
+        assert_eq!((snapshot.sum, snapshot.count), (0.0, 0));
+    }
+
+    #[cfg(feature = "lasso")]
+    #[test]
+    fn unknown_labels() {
+        use lasso::{Rodeo, RodeoReader};
+
+        use crate::HistogramVec;
+
+        #[derive(Clone, Copy, crate::LabelGroup)]
+        #[label(crate = crate, set = RouteSet)]
+        struct Route<'a> {
+            #[label(fixed_with = RodeoReader)]
+            route: &'a str,
+        }
+
+        let new = || {
+            let rodeo: Rodeo = ["/home"].into_iter().collect();
+            HistogramVec::with_label_set_and_metadata(
+                RouteSet::new(rodeo.into_reader()),
+                Thresholds::<2>::with_buckets([1.0, 2.0]),
+            )
+        };
+        let count = |histogram: &HistogramVec<RouteSet, 2>| {
+            let id = histogram.with_labels(Route { route: "/home" });
+            let m = histogram.get_metric(id);
+            let count = m.inner.read().load_count(Ordering::Relaxed);
+            count
+        };
+
+        let histogram = new();
+        assert!(histogram.try_observe(Route { route: "/home" }, 1.5));
+        assert!(!histogram.try_observe(Route { route: "/unknown" }, 1.5));
+        assert_eq!(count(&histogram), 1);
+
+        let histogram = new().drop_unknown_labels();
+        histogram.observe(Route { route: "/home" }, 1.5);
+        histogram.observe(Route { route: "/unknown" }, 1.5);
+        histogram.observe_duration(Route { route: "/unknown" }, Duration::from_secs(1));
+        assert_eq!(count(&histogram), 1);
+        assert_eq!(histogram.dropped_unknown_labels(), 2);
+
+        let histogram = new();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            histogram.observe(Route { route: "/unknown" }, 1.5)
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(histogram.dropped_unknown_labels(), 0);
+    }
+}
@@ -0,0 +1,258 @@
+//! Histograms that adapt their bucket bounds to the observed values. See [`AdaptiveHistogram`]
+
+use std::sync::atomic::Ordering;
+
+use parking_lot::RwLock;
+
+use super::{HistogramState, HistogramStateInner, Thresholds};
+use crate::{
+    label::LabelGroupSet,
+    metric::{reset::ResetMetric, MetricLockGuard, MetricType},
+    AdaptiveHistogram, AdaptiveHistogramVec,
+};
+
+/// The bucket layout for an [`AdaptiveHistogram`].
+///
+/// The bucket bounds are always `start * factor^(i + scale)` for some integer `scale` that
+/// is adjusted as observations come in. The initial scale is 0.
+pub struct AdaptiveThresholds<const N: usize> {
+    base: Thresholds<N>,
+    factor: f64,
+}
+
+impl<const N: usize> AdaptiveThresholds<N> {
+    /// Create `N` exponential buckets, where the lowest bucket initially has an upper bound of `start`
+    /// and each following bucket’s upper bound is `factor` times the previous bucket’s upper bound.
+    ///
+    /// # Panics
+    /// The function panics if `start` is zero or negative, if `factor` is less than or equal 1, or if `N` is less than 2.
+    pub fn exponential_buckets(start: f64, factor: f64) -> Self {
+        assert!(N >= 2, "adaptive histograms need at least 2 buckets");
+        Self {
+            base: Thresholds::exponential_buckets(start, factor),
+            factor,
+        }
+    }
+
+    /// How many buckets the bounds are shifted by when re-bucketing.
+    const fn shift() -> i32 {
+        (N / 2) as i32
+    }
+}
+
+/// The inner state of an adaptive histogram
+pub struct AdaptiveHistogramStateInner<const N: usize> {
+    /// The histogram counts, relative to the current scale
+    pub histogram: HistogramStateInner<N>,
+    /// The current scale of the bucket bounds
+    pub scale: i32,
+    /// `factor^scale`, cached
+    multiplier: f64,
+    /// The number of observations in (bucket 0, +Inf, total) at the last re-bucket
+    last: (u64, u64, u64),
+}
+
+/// The state of an adaptive histogram. See [`AdaptiveHistogram`]
+pub struct AdaptiveHistogramState<const N: usize> {
+    /// A rwlock over the inner histogram state.
+    /// The read lock is acquired for observations.
+    /// The write lock is acquired for sampling and re-bucketing.
+    pub inner: RwLock<AdaptiveHistogramStateInner<N>>,
+}
+
+impl<const N: usize> Default for AdaptiveHistogramState<N> {
+    fn default() -> Self {
+        Self {
+            inner: RwLock::new(AdaptiveHistogramStateInner {
+                histogram: HistogramState::<N>::default().inner.into_inner(),
+                scale: 0,
+                multiplier: 1.0,
+                last: (0, 0, 0),
+            }),
+        }
+    }
+}
+
+impl<const N: usize> MetricType for AdaptiveHistogramState<N> {
+    type Metadata = AdaptiveThresholds<N>;
+}
+
+/// A shared ref to an individual adaptive histogram
+pub type AdaptiveHistogramLockGuard<'a, const N: usize> =
+    MetricLockGuard<'a, AdaptiveHistogramState<N>>;
+
+impl<const N: usize> AdaptiveHistogramStateInner<N> {
+    /// The current bucket upper bounds
+    pub fn bounds(&self, thresholds: &AdaptiveThresholds<N>) -> [f64; N] {
+        thresholds.base.get().map(|le| le * self.multiplier)
+    }
+
+    /// The number of observations recorded
+    pub fn count(&self) -> u64 {
+        self.histogram.load_count(Ordering::Relaxed)
+    }
+
+    fn observe(&self, thresholds: &AdaptiveThresholds<N>, x: f64) {
+        let bucket = thresholds.base.bucket(x / self.multiplier);
+        self.histogram.observe(bucket, x);
+    }
+
+    /// Re-bucket the histogram if the observations since the last re-bucket mostly fall outside the current bounds,
+    /// then sample the histogram, returning the bucket bounds, the bucket counts, the +Inf count, and the sum.
+    ///
+    /// The bounds are shifted up by `N/2` buckets if more than `1/N` of the new observations were greater than the
+    /// largest bound, or down by `N/2` buckets if more than half of the new observations fell in the lowest bucket.
+    pub fn sample(&mut self, thresholds: &AdaptiveThresholds<N>) -> ([f64; N], [u64; N], u64, f64) {
+        let (mut buckets, mut inf, sum) = self.histogram.sample();
+
+        let total = buckets.iter().sum::<u64>() + inf;
+        let new_total = total - self.last.2;
+        let new_low = buckets[0] - self.last.0;
+        let new_inf = inf - self.last.1;
+
+        let k = AdaptiveThresholds::<N>::shift();
+        let shift = if new_total < N as u64 {
+            0
+        } else if new_inf * N as u64 > new_total {
+            k
+        } else if new_low * 2 > new_total && new_inf == 0 {
+            -k
+        } else {
+            0
+        };
+
+        if shift != 0 {
+            let mut shifted = [0; N];
+            for (i, count) in buckets.into_iter().enumerate() {
+                // the new bounds line up with the old bounds, so most buckets map exactly onto a new bucket.
+                match i as i32 - shift {
+                    // merge the lowest buckets when scaling up.
+                    ..=0 => shifted[0] += count,
+                    // when scaling down, the largest buckets overflow into +Inf.
+                    j if j as usize >= N => inf += count,
+                    j => shifted[j as usize] += count,
+                }
+            }
+            // When scaling down, the observations in the old lowest bucket are all counted in
+            // the new bucket with the same upper bound. When scaling up, the +Inf observations stay in +Inf.
+            // Either way, the count and sum are unchanged.
+            buckets = shifted;
+
+            for (b, count) in self.histogram.buckets.iter_mut().zip(buckets) {
+                *b.get_mut() = count;
+            }
+            *self.histogram.inf.get_mut() = inf;
+
+            self.scale += shift;
+            self.multiplier = thresholds.factor.powi(self.scale);
+            self.last = (buckets[0], inf, total);
+        }
+
+        (self.bounds(thresholds), buckets, inf, sum)
+    }
+}
+
+impl<const N: usize> AdaptiveHistogramState<N> {
+    /// The current bucket upper bounds
+    pub fn bounds(&self, thresholds: &AdaptiveThresholds<N>) -> [f64; N] {
+        self.inner.read().bounds(thresholds)
+    }
+}
+
+impl<const N: usize> AdaptiveHistogramLockGuard<'_, N> {
+    /// Add a single observation to the [`AdaptiveHistogram`].
+    pub fn observe(self, x: f64) {
+        self.inner.read().observe(self.metadata(), x);
+    }
+}
+
+impl<const N: usize> AdaptiveHistogram<N> {
+    /// Add a single observation to the [`AdaptiveHistogram`].
+    pub fn observe(&self, x: f64) {
+        self.get_metric().observe(x);
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> AdaptiveHistogramVec<L, N> {
+    /// Add a single observation to the [`AdaptiveHistogram`], keyed by the label group.
+    pub fn observe(&self, label: L::Group<'_>, y: f64) {
+        self.get_metric(self.with_labels(label)).observe(y);
+    }
+}
+
+impl<const N: usize> ResetMetric for AdaptiveHistogramState<N> {
+    /// Reset the counts to zero, and the bucket bounds back to the initial scale
+    fn reset(&self) {
+        *self.inner.write() = Self::default().inner.into_inner();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::AdaptiveHistogram;
+
+    use super::AdaptiveThresholds;
+
+    fn sample<const N: usize>(h: &AdaptiveHistogram<N>) -> ([f64; N], [u64; N], u64, f64) {
+        let m = h.get_metric();
+        let sample = m.inner.write().sample(m.metadata());
+        sample
+    }
+
+    #[test]
+    fn scales_up() {
+        let h = AdaptiveHistogram::with_metadata(AdaptiveThresholds::<4>::exponential_buckets(
+            1.0, 2.0,
+        ));
+        let m = h.get_metric();
+        assert_eq!(m.bounds(m.metadata()), [1.0, 2.0, 4.0, 8.0]);
+        drop(m);
+
+        for x in [0.5, 1.5, 3.0, 100.0, 100.0] {
+            h.observe(x);
+        }
+
+        // more than 1/4 of observations overflowed
+        let (le, buckets, inf, sum) = sample(&h);
+        assert_eq!(le, [4.0, 8.0, 16.0, 32.0]);
+        assert_eq!(buckets, [3, 0, 0, 0]);
+        assert_eq!(inf, 2);
+        assert_eq!(sum, 205.0);
+
+        // new observations are bucketed with the new bounds
+        for x in [5.0, 20.0, 30.0, 30.0] {
+            h.observe(x);
+        }
+        let (le, buckets, inf, _) = sample(&h);
+        assert_eq!(le, [4.0, 8.0, 16.0, 32.0]);
+        assert_eq!(buckets, [3, 1, 0, 3]);
+        assert_eq!(inf, 2);
+        assert_eq!(h.get_metric().inner.read().count(), 9);
+    }
+
+    #[test]
+    fn scales_down() {
+        let h = AdaptiveHistogram::with_metadata(AdaptiveThresholds::<4>::exponential_buckets(
+            1.0, 2.0,
+        ));
+
+        for x in [0.1, 0.2, 0.3, 3.0, 8.0] {
+            h.observe(x);
+        }
+
+        let (le, buckets, inf, sum) = sample(&h);
+        assert_eq!(le, [0.25, 0.5, 1.0, 2.0]);
+        assert_eq!(buckets, [0, 0, 3, 0]);
+        assert_eq!(inf, 2);
+        assert!((sum - 11.6).abs() < 1e-9);
+
+        // steady state
+        for x in [0.1, 0.3, 0.6, 1.5] {
+            h.observe(x);
+        }
+        let (le, buckets, inf, _) = sample(&h);
+        assert_eq!(le, [0.25, 0.5, 1.0, 2.0]);
+        assert_eq!(buckets, [1, 1, 4, 1]);
+        assert_eq!(inf, 2);
+    }
+}
@@ -0,0 +1,216 @@
+//! Pairs of histograms that are recorded together. See [`DualHistogram`]
+
+use std::time::Duration;
+
+use super::{HistogramState, Thresholds};
+use crate::{
+    label::LabelGroupSet,
+    metric::{
+        group::{Encoding, MetricGroup},
+        name::MetricName,
+        reset::Reset,
+        reset::ResetMetric,
+        MetricEncoding, MetricType, MetricVec,
+    },
+};
+
+/// The state of a single series of a [`DualHistogram`]
+pub struct DualState<const N1: usize, const N2: usize> {
+    /// The first histogram, eg the request latencies
+    pub first: HistogramState<N1>,
+    /// The second histogram, eg the response sizes
+    pub second: HistogramState<N2>,
+}
+
+impl<const N1: usize, const N2: usize> Default for DualState<N1, N2> {
+    fn default() -> Self {
+        Self {
+            first: HistogramState::default(),
+            second: HistogramState::default(),
+        }
+    }
+}
+
+/// The metadata of a [`DualHistogram`]
+pub struct DualThresholds<const N1: usize, const N2: usize> {
+    /// The buckets of the first histogram
+    pub first: Thresholds<N1>,
+    /// The buckets of the second histogram
+    pub second: Thresholds<N2>,
+}
+
+impl<const N1: usize, const N2: usize> MetricType for DualState<N1, N2> {
+    type Metadata = DualThresholds<N1, N2>;
+}
+
+/// Two histograms with their own bucket layouts, which share the same labels and are always observed together,
+/// like the latency and the response size of an RPC.
+///
+/// Both histograms are backed by a single metric vec, so the labels are only encoded once per
+/// [`record`](Self::record), rather than once per histogram.
+///
+/// ```
+/// use measured::{FixedCardinalityLabel, MetricGroup};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::histogram::dual::DualHistogram;
+/// use measured::metric::histogram::Thresholds;
+/// use measured::metric::name::MetricName;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "endpoint")]
+/// enum Endpoint { Search, Checkout }
+///
+/// let rpc = DualHistogram::<StaticLabelSet<Endpoint>, 8, 6>::new(
+///     MetricName::from_str("rpc_duration_seconds"),
+///     MetricName::from_str("rpc_response_size_bytes"),
+///     StaticLabelSet::new(),
+///     Thresholds::exponential_buckets(0.001, 4.0),
+///     Thresholds::exponential_buckets(64.0, 8.0),
+/// );
+/// rpc.record(Endpoint::Search, 0.02, 1500.0);
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// rpc.collect_group_into(&mut enc).unwrap();
+/// ```
+pub struct DualHistogram<L: LabelGroupSet, const N1: usize, const N2: usize> {
+    vec: MetricVec<DualState<N1, N2>, L>,
+    first_name: &'static MetricName,
+    second_name: &'static MetricName,
+}
+
+impl<L: LabelGroupSet, const N1: usize, const N2: usize> DualHistogram<L, N1, N2> {
+    /// Create the histograms, which are exposed as the `first_name` and `second_name` histograms
+    pub fn new(
+        first_name: &'static MetricName,
+        second_name: &'static MetricName,
+        label_set: L,
+        first: Thresholds<N1>,
+        second: Thresholds<N2>,
+    ) -> Self {
+        Self {
+            vec: MetricVec::with_label_set_and_metadata(
+                label_set,
+                DualThresholds { first, second },
+            ),
+            first_name,
+            second_name,
+        }
+    }
+
+    /// View the underlying metric vec
+    pub fn metric_vec(&self) -> &MetricVec<DualState<N1, N2>, L> {
+        &self.vec
+    }
+
+    /// Observe `x` in the first histogram and `y` in the second histogram, keyed by the label group
+    pub fn record(&self, label: L::Group<'_>, x: f64, y: f64) {
+        let metric = self.vec.get_metric(self.vec.with_labels(label));
+        let DualThresholds { first, second } = metric.metadata();
+
+        metric
+            .first
+            .inner
+            .read()
+            .observe(first.bucket(x), first.clamp(x));
+        metric
+            .second
+            .inner
+            .read()
+            .observe(second.bucket(y), second.clamp(y));
+    }
+
+    /// Observe the duration in seconds in the first histogram and `y` in the second histogram, keyed by the label group
+    pub fn record_duration(&self, label: L::Group<'_>, duration: Duration, y: f64) {
+        self.record(label, duration.as_secs_f64(), y);
+    }
+}
+
+impl<L, const N1: usize, const N2: usize, Enc> MetricGroup<Enc> for DualHistogram<L, N1, N2>
+where
+    L: LabelGroupSet,
+    Enc: Encoding,
+    HistogramState<N1>: MetricEncoding<Enc> + MetricType<Metadata = Thresholds<N1>>,
+    HistogramState<N2>: MetricEncoding<Enc> + MetricType<Metadata = Thresholds<N2>>,
+{
+    fn collect_group_into(&self, enc: &mut Enc) -> Result<(), Enc::Err> {
+        let DualThresholds { first, second } = self.vec.metadata();
+
+        HistogramState::<N1>::write_type(self.first_name, enc)?;
+        self.vec.for_each_series(|labels, state| {
+            state
+                .first
+                .collect_into(first, labels, self.first_name, enc)
+        })?;
+
+        HistogramState::<N2>::write_type(self.second_name, enc)?;
+        self.vec.for_each_series(|labels, state| {
+            state
+                .second
+                .collect_into(second, labels, self.second_name, enc)
+        })
+    }
+}
+
+impl<const N1: usize, const N2: usize> ResetMetric for DualState<N1, N2> {
+    fn reset(&self) {
+        self.first.reset();
+        self.second.reset();
+    }
+}
+
+impl<L: LabelGroupSet, const N1: usize, const N2: usize> Reset for DualHistogram<L, N1, N2> {
+    fn reset(&self) {
+        self.vec.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{group::MetricGroup, histogram::Thresholds, name::MetricName},
+        text::BufferedTextEncoder,
+        FixedCardinalityLabel,
+    };
+
+    use super::DualHistogram;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "endpoint")]
+    enum Endpoint {
+        Search,
+        Checkout,
+    }
+
+    #[test]
+    fn different_bucket_layouts() {
+        let rpc = DualHistogram::<StaticLabelSet<Endpoint>, 2, 1>::new(
+            MetricName::from_str("rpc_duration_seconds"),
+            MetricName::from_str("rpc_response_size_bytes"),
+            StaticLabelSet::new(),
+            Thresholds::with_buckets([0.1, 1.0]),
+            Thresholds::with_buckets([1024.0]),
+        );
+        rpc.record(Endpoint::Search, 0.05, 100.0);
+        rpc.record(Endpoint::Search, 0.5, 2048.0);
+
+        let mut enc = BufferedTextEncoder::new();
+        rpc.collect_group_into(&mut enc).unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE rpc_duration_seconds histogram\n\
+            rpc_duration_seconds_bucket{endpoint=\"search\",le=\"0.1\"} 1\n\
+            rpc_duration_seconds_bucket{endpoint=\"search\",le=\"1.0\"} 2\n\
+            rpc_duration_seconds_bucket{endpoint=\"search\",le=\"+Inf\"} 2\n\
+            rpc_duration_seconds_sum{endpoint=\"search\"} 0.55\n\
+            rpc_duration_seconds_count{endpoint=\"search\"} 2\n\
+            \n\
+            # TYPE rpc_response_size_bytes histogram\n\
+            rpc_response_size_bytes_bucket{endpoint=\"search\",le=\"1024.0\"} 1\n\
+            rpc_response_size_bytes_bucket{endpoint=\"search\",le=\"+Inf\"} 2\n\
+            rpc_response_size_bytes_sum{endpoint=\"search\"} 2148.0\n\
+            rpc_response_size_bytes_count{endpoint=\"search\"} 2\n"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Histograms which also expose quantile estimates. See [`QuantileHistogramVec`]
+
+use core::ops::Deref;
+
+use super::{HistogramState, Thresholds};
+use crate::{
+    atomic::MemoryOrdering,
+    label::{LabelGroup, LabelGroupSet, LabelName, LabelPair},
+    metric::{
+        gauge::FloatGaugeState,
+        group::Encoding,
+        name::{MetricNameEncoder, Quantile},
+        MetricEncoding, MetricFamilyEncoding,
+    },
+    HistogramVec,
+};
+
+/// A [`HistogramVec`] which also exposes estimates of some quantiles, as gauges computed from the buckets
+/// at collection time.
+///
+/// Next to the histogram family, every series gets a `<name>_quantile` gauge family with one sample for each
+/// configured quantile, labelled with `quantile="0.99"`. This is useful for backends that cannot compute quantiles
+/// from histogram buckets themselves.
+///
+/// The quantiles are estimated by interpolating within the buckets, with [`BucketsSnapshot::quantile`](super::BucketsSnapshot::quantile),
+/// so they are only as precise as the bucket layout. They are computed over every observation since the histogram
+/// was created, not over a recent window like the quantiles of a summary. A series with no observations reports NaN.
+///
+/// ```
+/// use measured::{FixedCardinalityLabel, HistogramVec, MetricGroup};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::histogram::{quantile::QuantileHistogramVec, Thresholds};
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "route")]
+/// enum Route { Home, Users }
+///
+/// #[derive(MetricGroup)]
+/// struct Metrics {
+///     /// request latency, with p50 and p99 estimates
+///     latency: QuantileHistogramVec<StaticLabelSet<Route>, 8>,
+/// }
+///
+/// let metrics = Metrics {
+///     latency: HistogramVec::with_metadata(Thresholds::exponential_buckets(0.001, 4.0))
+///         .with_quantiles([0.5, 0.99]),
+/// };
+/// metrics.latency.observe(Route::Home, 0.02);
+/// ```
+pub struct QuantileHistogramVec<L: LabelGroupSet, const N: usize> {
+    vec: HistogramVec<L, N>,
+    quantiles: Vec<f64>,
+}
+
+impl<L: LabelGroupSet, const N: usize> QuantileHistogramVec<L, N> {
+    /// Expose estimates of the given quantiles of the histogram vec.
+    ///
+    /// # Panics
+    /// Panics if a quantile is not within `0.0..=1.0`.
+    pub fn new(vec: HistogramVec<L, N>, quantiles: impl Into<Vec<f64>>) -> Self {
+        let quantiles = quantiles.into();
+        for q in &quantiles {
+            assert!(
+                (0.0..=1.0).contains(q),
+                "quantile {q} is not within 0.0..=1.0"
+            );
+        }
+        Self { vec, quantiles }
+    }
+
+    /// The quantiles that are estimated
+    pub fn quantiles(&self) -> &[f64] {
+        &self.quantiles
+    }
+
+    /// Extract the inner histogram vec
+    pub fn into_inner(self) -> HistogramVec<L, N> {
+        self.vec
+    }
+
+    fn thresholds(&self) -> &Thresholds<N> {
+        self.vec.metadata()
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> Deref for QuantileHistogramVec<L, N> {
+    type Target = HistogramVec<L, N>;
+
+    fn deref(&self) -> &HistogramVec<L, N> {
+        &self.vec
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> HistogramVec<L, N> {
+    /// Also expose estimates of the given quantiles of this histogram vec. See [`QuantileHistogramVec`]
+    ///
+    /// # Panics
+    /// Panics if a quantile is not within `0.0..=1.0`.
+    pub fn with_quantiles(self, quantiles: impl Into<Vec<f64>>) -> QuantileHistogramVec<L, N> {
+        QuantileHistogramVec::new(self, quantiles)
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize, Enc: Encoding> MetricFamilyEncoding<Enc>
+    for QuantileHistogramVec<L, N>
+where
+    HistogramState<N>: MetricEncoding<Enc>,
+    FloatGaugeState: MetricEncoding<Enc>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        const QUANTILE: &LabelName = LabelName::from_str("quantile");
+
+        self.vec.collect_family_into(&name, enc)?;
+
+        let name = name.with_suffix(Quantile);
+        FloatGaugeState::write_type(&name, enc)?;
+        let thresholds = self.thresholds();
+        self.vec.for_each_series(|labels, state| {
+            let snapshot = state.buckets_snapshot(thresholds);
+            for &q in &self.quantiles {
+                let value = snapshot.quantile(q).unwrap_or(f64::NAN);
+                FloatGaugeState::new(value).collect_into(
+                    &MemoryOrdering::Relaxed,
+                    labels.by_ref().compose_with(LabelPair(QUANTILE, q)),
+                    &name,
+                    enc,
+                )?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{histogram::Thresholds, name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        FixedCardinalityLabel, HistogramVec,
+    };
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "route")]
+    enum Route {
+        Home,
+        Users,
+    }
+
+    #[test]
+    fn quantile_gauges() {
+        let latency = HistogramVec::<StaticLabelSet<Route>, 3>::dense_with_metadata(
+            Thresholds::with_buckets([1.0, 2.0, 4.0]),
+        )
+        .with_quantiles([0.5, 0.99]);
+        for x in [0.5, 1.5, 1.5, 3.0] {
+            latency.observe(Route::Home, x);
+        }
+        // initialise the series without observations
+        latency.get_metric(latency.with_labels(Route::Users));
+
+        let mut enc = BufferedTextEncoder::new();
+        latency
+            .collect_family_into(MetricName::from_str("latency"), &mut enc)
+            .unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        let (_, quantiles) = text.split_once("# TYPE latency_quantile").unwrap();
+        assert_eq!(
+            quantiles,
+            r#" gauge
+latency_quantile{route="home",quantile="0.5"} 1.5
+latency_quantile{route="home",quantile="0.99"} 3.92
+latency_quantile{route="users",quantile="0.5"} NaN
+latency_quantile{route="users",quantile="0.99"} NaN
+"#
+        );
+    }
+
+    #[test]
+    #[should_panic = "quantile 99 is not within 0.0..=1.0"]
+    fn percent_quantile() {
+        HistogramVec::<StaticLabelSet<Route>, 1>::with_metadata(Thresholds::with_buckets([1.0]))
+            .with_quantiles([99.0]);
+    }
+}
@@ -0,0 +1,370 @@
+//! Metrics that are owned by a single task, and merged into shared metrics later. See [`LocalMetric`]
+
+use std::{cell::Cell, marker::PhantomData, sync::atomic::Ordering};
+
+use rustc_hash::FxHashMap;
+
+use crate::label::LabelGroupSet;
+
+use super::{
+    counter::CounterState, histogram::HistogramState, Metric, MetricMut, MetricType, MetricVec,
+};
+
+/// Metric state that can be added into another instance of the same metric.
+pub trait MergeMetric: MetricType {
+    /// Add the values of `other` into this metric
+    fn merge(&self, other: Self);
+
+    /// Add the current values of `other` into this metric, leaving `other` as it is
+    fn merge_from(&self, other: &Self);
+}
+
+impl MergeMetric for CounterState {
+    fn merge(&self, other: Self) {
+        self.inc_by(other.count.into_inner());
+    }
+
+    fn merge_from(&self, other: &Self) {
+        self.inc_by(other.count.load(Ordering::Relaxed));
+    }
+}
+
+impl<const N: usize> MergeMetric for HistogramState<N> {
+    fn merge(&self, other: Self) {
+        let mut other = other.inner.into_inner();
+        let (buckets, inf, sum) = other.sample();
+        self.inner.read().add(buckets, inf, sum);
+    }
+
+    fn merge_from(&self, other: &Self) {
+        // like `load_count_and_sum`, the counts are loaded before the sum, which `observe` writes first.
+        let (buckets, inf, sum) = {
+            let other = other.inner.read();
+            (
+                other.load_buckets(Ordering::Acquire),
+                other.load_inf(Ordering::Acquire),
+                other.load_sum(Ordering::Acquire),
+            )
+        };
+        self.inner.read().add(buckets, inf, sum);
+    }
+}
+
+/// A metric that is owned by a single task, like a connection handler.
+///
+/// The metric is updated through [`MetricMut`], which needs no locks or atomic read-modify-write operations.
+/// When the task is finished, the local values are added into a shared [`Metric`] or [`MetricVec`] with
+/// [`Metric::merge_local`] or [`MetricVec::merge_local`]. The local metric borrows the metadata of the shared metric,
+/// so histograms use the same thresholds.
+///
+/// Local metrics are not [`Sync`], as they are only meant to be used by a single task.
+///
+/// ```
+/// use measured::{CounterVec, HistogramVec, FixedCardinalityLabel};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::histogram::Thresholds;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "protocol")]
+/// enum Protocol { Http, Grpc }
+///
+/// let bytes = CounterVec::<StaticLabelSet<Protocol>>::new();
+/// let latency = HistogramVec::<StaticLabelSet<Protocol>, 4>::with_metadata(
+///     Thresholds::exponential_buckets(0.01, 10.0),
+/// );
+///
+/// // for each connection
+/// let mut conn_bytes = bytes.local();
+/// let mut conn_latency = latency.local();
+/// for _request in 0..3 {
+///     conn_bytes.inc_by(1024);
+///     conn_latency.observe(0.02);
+/// }
+///
+/// // when the connection closes
+/// bytes.merge_local(Protocol::Http, conn_bytes);
+/// latency.merge_local(Protocol::Http, conn_latency);
+/// ```
+pub struct LocalMetric<'a, M: MetricType> {
+    metric: M,
+    metadata: &'a M::Metadata,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<'a, M: MetricType> LocalMetric<'a, M> {
+    /// Create a new local metric with the given metadata
+    pub fn new(metadata: &'a M::Metadata) -> Self {
+        Self {
+            metric: M::default(),
+            metadata,
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// Get the local metric
+    pub fn get_metric_mut(&mut self) -> MetricMut<'_, M> {
+        MetricMut(&mut self.metric, self.metadata)
+    }
+
+    /// Extract the inner metric state
+    pub fn into_inner(self) -> M {
+        self.metric
+    }
+}
+
+impl LocalMetric<'_, CounterState> {
+    /// Increment the counter value by 1
+    pub fn inc(&mut self) {
+        self.get_metric_mut().inc();
+    }
+
+    /// Increment the counter value by `x`
+    pub fn inc_by(&mut self, x: u64) {
+        self.get_metric_mut().inc_by(x);
+    }
+}
+
+impl<const N: usize> LocalMetric<'_, HistogramState<N>> {
+    /// Add a single observation to the histogram
+    pub fn observe(&mut self, x: f64) {
+        self.get_metric_mut().observe(x);
+    }
+
+    /// Observe the duration in seconds
+    pub fn observe_duration(&mut self, duration: std::time::Duration) {
+        self.get_metric_mut().observe_duration(duration);
+    }
+}
+
+impl<M: MetricType> Metric<M> {
+    /// Create a new [`LocalMetric`] that shares the metadata of this metric
+    pub fn local(&self) -> LocalMetric<'_, M> {
+        LocalMetric::new(&self.metadata)
+    }
+
+    /// Add the values of the local metric into this metric
+    pub fn merge_local(&self, local: LocalMetric<'_, M>)
+    where
+        M: MergeMetric,
+    {
+        self.get_metric().merge(local.into_inner());
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
+    /// Create a new [`LocalMetric`] that shares the metadata of this metric vec
+    pub fn local(&self) -> LocalMetric<'_, M> {
+        LocalMetric::new(&self.metadata)
+    }
+
+    /// Add the values of the local metric into the metric keyed by the label group
+    pub fn merge_local(&self, label: L::Group<'_>, local: LocalMetric<'_, M>)
+    where
+        M: MergeMetric,
+    {
+        self.get_metric(self.with_labels(label))
+            .merge(local.into_inner());
+    }
+}
+
+/// A buffer of updates to a [`MetricVec`], which are applied together when the scope is flushed.
+///
+/// A request that updates the same series many times can record the updates in a scope, keyed by label group.
+/// The updates go to series owned by the scope through [`MetricMut`], so they touch no shared atomics.
+/// When the scope is flushed with [`flush`](Self::flush), or dropped, the buffered values are merged
+/// into the metric vec in one batch, one merge per series.
+///
+/// Until then, the updates are not visible to collections of the metric vec. Like [`LocalMetric`],
+/// scopes are not [`Sync`].
+///
+/// ```
+/// use measured::{CounterVec, FixedCardinalityLabel};
+/// use measured::label::StaticLabelSet;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "table")]
+/// enum Table { Users, Orders }
+///
+/// let rows_read = CounterVec::<StaticLabelSet<Table>>::new();
+///
+/// // for each request
+/// let mut scope = rows_read.scope();
+/// for _row in 0..100 {
+///     scope.inc(Table::Users);
+/// }
+/// scope.inc_by(Table::Orders, 3);
+/// drop(scope);
+///
+/// let users = rows_read.get_metric(rows_read.with_labels(Table::Users));
+/// assert_eq!(users.load(std::sync::atomic::Ordering::Relaxed), 100);
+/// ```
+pub struct MetricScope<'a, M: MergeMetric, L: LabelGroupSet> {
+    vec: &'a MetricVec<M, L>,
+    series: FxHashMap<L::Unique, M>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<'a, M: MergeMetric, L: LabelGroupSet> MetricScope<'a, M, L> {
+    /// Create a new empty scope over the metric vec
+    pub fn new(vec: &'a MetricVec<M, L>) -> Self {
+        Self {
+            vec,
+            series: FxHashMap::default(),
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// Get the scoped metric keyed by the label group
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set.
+    pub fn get_metric_mut(&mut self, label: L::Group<'_>) -> MetricMut<'_, M> {
+        let id = self
+            .vec
+            .label_set
+            .encode(label)
+            .expect("label group was not contained within this label set");
+        MetricMut(self.series.entry(id).or_default(), &self.vec.metadata)
+    }
+
+    /// Apply all the buffered updates to the metric vec, and clear the scope
+    pub fn flush(&mut self) {
+        for (id, metric) in self.series.drain() {
+            self.vec
+                .get_metric(self.vec.id_from_unique(id))
+                .merge(metric);
+        }
+    }
+}
+
+impl<M: MergeMetric, L: LabelGroupSet> Drop for MetricScope<'_, M, L> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl<L: LabelGroupSet> MetricScope<'_, CounterState, L> {
+    /// Increment the scoped counter value by 1, keyed by the label group
+    pub fn inc(&mut self, label: L::Group<'_>) {
+        self.get_metric_mut(label).inc();
+    }
+
+    /// Increment the scoped counter value by `x`, keyed by the label group
+    pub fn inc_by(&mut self, label: L::Group<'_>, x: u64) {
+        self.get_metric_mut(label).inc_by(x);
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> MetricScope<'_, HistogramState<N>, L> {
+    /// Add a single observation to the scoped histogram, keyed by the label group
+    pub fn observe(&mut self, label: L::Group<'_>, x: f64) {
+        self.get_metric_mut(label).observe(x);
+    }
+
+    /// Observe the duration in seconds, keyed by the label group
+    pub fn observe_duration(&mut self, label: L::Group<'_>, duration: std::time::Duration) {
+        self.get_metric_mut(label).observe_duration(duration);
+    }
+}
+
+impl<M: MergeMetric, L: LabelGroupSet> MetricVec<M, L> {
+    /// Create a new [`MetricScope`] that buffers updates to this metric vec
+    pub fn scope(&self) -> MetricScope<'_, M, L> {
+        MetricScope::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use crate::{
+        label::StaticLabelSet, metric::histogram::Thresholds, Counter, CounterVec,
+        FixedCardinalityLabel, Histogram, HistogramVec,
+    };
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate, singleton = "table")]
+    enum Table {
+        Users,
+        Orders,
+    }
+
+    #[test]
+    fn merge_counter() {
+        let counter = Counter::new();
+        counter.inc();
+
+        let mut local = counter.local();
+        local.inc();
+        local.inc_by(5);
+        counter.merge_local(local);
+
+        assert_eq!(counter.get_metric().load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn merge_histogram() {
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([1.0, 2.0]));
+        histogram.observe(0.5);
+
+        let mut local = histogram.local();
+        local.observe(1.5);
+        local.observe(3.0);
+        histogram.merge_local(local);
+
+        let metric = histogram.get_metric();
+        let inner = metric.inner.read();
+        assert_eq!(inner.load_buckets(Ordering::Relaxed), [1, 1]);
+        assert_eq!(inner.load_inf(Ordering::Relaxed), 1);
+        assert_eq!(inner.load_sum(Ordering::Relaxed), 5.0);
+    }
+
+    #[test]
+    fn scope_matches_direct_updates() {
+        let direct = CounterVec::<StaticLabelSet<Table>>::new();
+        let scoped = CounterVec::<StaticLabelSet<Table>>::new();
+        let thresholds = || Thresholds::<2>::with_buckets([1.0, 2.0]);
+        let direct_latency = HistogramVec::<StaticLabelSet<Table>, 2>::with_metadata(thresholds());
+        let scoped_latency = HistogramVec::<StaticLabelSet<Table>, 2>::with_metadata(thresholds());
+
+        let mut scope = scoped.scope();
+        let mut latency_scope = scoped_latency.scope();
+        for i in 0..100 {
+            let table = if i % 3 == 0 {
+                Table::Orders
+            } else {
+                Table::Users
+            };
+            direct.inc_by(table, i);
+            scope.inc_by(table, i);
+            direct_latency.observe(table, i as f64 / 50.0);
+            latency_scope.observe(table, i as f64 / 50.0);
+        }
+
+        // nothing is applied before the flush
+        let count = |vec: &CounterVec<StaticLabelSet<Table>>, table| {
+            vec.get_metric(vec.with_labels(table))
+                .load(Ordering::Relaxed)
+        };
+        assert_eq!(count(&scoped, Table::Users), 0);
+
+        scope.flush();
+        drop(latency_scope);
+        for table in [Table::Users, Table::Orders] {
+            assert_eq!(count(&scoped, table), count(&direct, table));
+            assert_eq!(
+                scoped_latency.buckets_snapshot(table),
+                direct_latency.buckets_snapshot(table)
+            );
+        }
+
+        // a flushed scope can be reused
+        scope.inc(Table::Users);
+        drop(scope);
+        assert_eq!(
+            count(&scoped, Table::Users),
+            count(&direct, Table::Users) + 1
+        );
+    }
+}
@@ -1,6 +1,6 @@
 //! Metric names and name encodings
 
-use std::io::Write;
+use std::{borrow::Borrow, io::Write, ops::Deref};
 
 /// `MetricName` represents a type that can be encoded into the name of a metric when collected.
 pub trait MetricNameEncoder {
@@ -57,6 +57,7 @@ impl std::error::Error for InvalidMetricName {}
 /// Represents a string-based [`MetricNameEncoder`]
 ///
 /// Metric names may contain ASCII letters, digits, underscores, and colons. It must match the regex `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+#[repr(transparent)]
 pub struct MetricName(str);
 
 const fn const_assert_metric_name(name: &str) {
@@ -100,7 +101,7 @@ impl MetricName {
     /// # Panics
     /// Will panic if the string contains invalid characters
     #[must_use]
-    pub const fn from_str(value: &'static str) -> &Self {
+    pub const fn from_str(value: &'static str) -> &'static Self {
         const_assert_metric_name(value);
 
         // SAFETY: `MetricName` is transparent over `str`. There's no way to do this safely.
@@ -123,10 +124,7 @@ impl MetricName {
     /// Add a namespace prefix to this metric name.
     #[must_use]
     pub const fn in_namespace(&self, ns: &'static str) -> WithNamespace<&'_ Self> {
-        WithNamespace {
-            namespace: MetricName::from_str(ns),
-            inner: self,
-        }
+        WithNamespace::new(ns, self)
     }
 
     /// Adds a semantic suffix to this metric name.
@@ -148,6 +146,105 @@ impl MetricNameEncoder for MetricName {
     }
 }
 
+impl ToOwned for MetricName {
+    type Owned = MetricNameBuf;
+
+    fn to_owned(&self) -> MetricNameBuf {
+        MetricNameBuf(self.0.into())
+    }
+}
+
+/// An owned [`MetricName`], for names that are only known at runtime.
+///
+/// The name is validated once when it is created, and stored as the encoded bytes, so encoding it is a
+/// single copy into the output buffer. Composite names, like a [`MetricName`] with a namespace and suffix,
+/// can also be encoded once up-front with [`from_encoder`](Self::from_encoder), rather than on every collection.
+///
+/// ```
+/// use measured::metric::name::{MetricName, MetricNameBuf, MetricNameEncoder, Total};
+///
+/// let service = String::from("billing");
+/// let name = MetricNameBuf::try_new(format!("{service}_requests")).unwrap();
+/// assert_eq!(name.encode_len(), "billing_requests".len());
+///
+/// let name = MetricNameBuf::from_encoder(
+///     MetricName::from_str("requests").in_namespace("billing").with_suffix(Total),
+/// );
+/// assert_eq!(name.encode_len(), "billing_requests_total".len());
+/// ```
+#[derive(Clone)]
+pub struct MetricNameBuf(Box<str>);
+
+impl MetricNameBuf {
+    /// Construct a [`MetricNameBuf`] from a string
+    ///
+    /// # Errors
+    /// Will error if the string contains invalid characters
+    pub fn try_new(value: impl Into<String>) -> Result<Self, InvalidMetricName> {
+        let value = value.into();
+        try_assert_metric_name(&value)?;
+        Ok(Self(value.into_boxed_str()))
+    }
+
+    /// Encode the name once, and store the result.
+    ///
+    /// # Panics
+    /// Panics if the encoder produces an invalid metric name.
+    pub fn from_encoder(name: impl MetricNameEncoder) -> Self {
+        let mut b = Vec::with_capacity(name.encode_len());
+        name.encode_utf8(&mut b)
+            .expect("writing to a vec should not fail");
+        let name = String::from_utf8(b).expect("metric names should be valid utf8");
+        if let Err(err) = try_assert_metric_name(&name) {
+            panic!("invalid metric name {name:?}: {err}");
+        }
+        Self(name.into_boxed_str())
+    }
+
+    /// View this as a borrowed [`MetricName`]
+    pub fn as_name(&self) -> &MetricName {
+        // SAFETY: `MetricName` is transparent over `str`, and the name was validated on construction.
+        unsafe { &*(&*self.0 as *const str as *const MetricName) }
+    }
+}
+
+impl TryFrom<String> for MetricNameBuf {
+    type Error = InvalidMetricName;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::try_new(value)
+    }
+}
+
+impl Deref for MetricNameBuf {
+    type Target = MetricName;
+
+    fn deref(&self) -> &MetricName {
+        self.as_name()
+    }
+}
+
+impl Borrow<MetricName> for MetricNameBuf {
+    fn borrow(&self) -> &MetricName {
+        self.as_name()
+    }
+}
+
+impl core::fmt::Debug for MetricNameBuf {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl MetricNameEncoder for MetricNameBuf {
+    fn encode_utf8(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(self.0.as_bytes())
+    }
+    fn encode_len(&self) -> usize {
+        self.0.len()
+    }
+}
+
 /// `Suffix` defines semantic suffixes as suggested by Prometheus
 ///
 /// Included suffixes:
@@ -155,6 +252,8 @@ impl MetricNameEncoder for MetricName {
 /// * [`Count`] - Used internally for histograms
 /// * [`Sum`] - Used internally for histograms
 /// * [`Bucket`] - Used internally for histograms
+/// * [`Quantile`] - Used for the quantile estimates of histograms
+/// * [`Delta`] - Used for the per-collection increase of counters
 pub trait Suffix {
     /// Write `_` followed by the suffix value with to the underlying writer
     fn encode_text(&self, b: &mut impl Write) -> std::io::Result<()>;
@@ -173,8 +272,14 @@ impl<T: MetricNameEncoder + ?Sized> MetricNameEncoder for &T {
 }
 
 /// See [`MetricName::in_namespace`]
+///
+/// The namespace and the name are joined with a separator, which defaults to `_`.
+/// If the namespace already ends with the separator, it is not repeated, so `my_ns_` and `my_ns` both
+/// produce `my_ns_name`. Names that start with the separator are not trimmed, so a name
+/// `_internal` in the namespace `my_ns` is encoded as `my_ns__internal`.
 pub struct WithNamespace<T: ?Sized> {
     pub(crate) namespace: &'static MetricName,
+    pub(crate) separator: &'static str,
     pub(crate) inner: T,
 }
 
@@ -186,10 +291,23 @@ impl<T> WithNamespace<T> {
     pub const fn new(ns: &'static str, inner: T) -> Self {
         Self {
             namespace: MetricName::from_str(ns),
+            separator: "_",
             inner,
         }
     }
 
+    /// Change the separator between the namespace and the name. The default is `_`.
+    ///
+    /// The separator may be empty, to concatenate the namespace and name directly.
+    ///
+    /// # Panics
+    /// Will panic if the `separator` string contains invalid metric name characters
+    pub fn with_separator(mut self, separator: &'static str) -> Self {
+        assert_separator(separator);
+        self.separator = separator;
+        self
+    }
+
     /// Adds a semantic suffix to this metric name.
     pub const fn with_suffix<S: Suffix>(self, suffix: S) -> WithSuffix<S, Self> {
         WithSuffix {
@@ -199,14 +317,61 @@ impl<T> WithNamespace<T> {
     }
 }
 
+pub(crate) fn assert_separator(separator: &str) {
+    assert!(
+        separator
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b':'),
+        "separator should only contain [a-zA-Z0-9_:]"
+    );
+}
+
+/// The separator to write after the namespace, skipped if the namespace already ends with it
+fn namespace_separator(namespace: &MetricName, separator: &'static str) -> &'static str {
+    if namespace.0.ends_with(separator) {
+        ""
+    } else {
+        separator
+    }
+}
+
+impl<T: ?Sized> WithNamespace<T> {
+    fn separator(&self) -> &'static str {
+        namespace_separator(self.namespace, self.separator)
+    }
+}
+
 impl<T: MetricNameEncoder + ?Sized> MetricNameEncoder for WithNamespace<T> {
     fn encode_utf8(&self, b: &mut impl Write) -> std::io::Result<()> {
         b.write_all(self.namespace.0.as_bytes())?;
-        b.write_all(b"_")?;
+        b.write_all(self.separator().as_bytes())?;
         self.inner.encode_utf8(b)
     }
     fn encode_len(&self) -> usize {
-        self.namespace.0.len() + 1 + self.inner.encode_len()
+        self.namespace.0.len() + self.separator().len() + self.inner.encode_len()
+    }
+}
+
+/// A namespace prefix that is only known at runtime, borrowed for the duration of a collection.
+///
+/// This is the name and encoder used by [`RuntimeNamespace`](super::group::RuntimeNamespace),
+/// and joins the namespace and the name the same way as [`WithNamespace`].
+pub struct WithRuntimeNamespace<'a, T: ?Sized> {
+    pub(crate) namespace: &'a MetricName,
+    pub(crate) separator: &'static str,
+    pub(crate) inner: T,
+}
+
+impl<T: MetricNameEncoder + ?Sized> MetricNameEncoder for WithRuntimeNamespace<'_, T> {
+    fn encode_utf8(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(self.namespace.0.as_bytes())?;
+        b.write_all(namespace_separator(self.namespace, self.separator).as_bytes())?;
+        self.inner.encode_utf8(b)
+    }
+    fn encode_len(&self) -> usize {
+        self.namespace.0.len()
+            + namespace_separator(self.namespace, self.separator).len()
+            + self.inner.encode_len()
     }
 }
 
@@ -234,6 +399,10 @@ pub struct Count;
 pub struct Sum;
 /// `_bucket`. A [`Suffix`] that is used internally for histograms
 pub struct Bucket;
+/// `_quantile`. A [`Suffix`] that is used for the quantile estimates of histograms
+pub struct Quantile;
+/// `_delta`. A [`Suffix`] that is used for the per-collection increase of counters
+pub struct Delta;
 
 impl Suffix for Total {
     fn encode_text(&self, b: &mut impl Write) -> std::io::Result<()> {
@@ -270,3 +439,96 @@ impl Suffix for Bucket {
         7
     }
 }
+
+impl Suffix for Quantile {
+    fn encode_text(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(b"_quantile")
+    }
+    fn encode_len(&self) -> usize {
+        9
+    }
+}
+
+impl Suffix for Delta {
+    fn encode_text(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(b"_delta")
+    }
+    fn encode_len(&self) -> usize {
+        6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        InvalidMetricName, MetricName, MetricNameBuf, MetricNameEncoder, Total, WithNamespace,
+    };
+
+    fn encode(name: impl MetricNameEncoder) -> String {
+        let mut b = vec![];
+        name.encode_utf8(&mut b).unwrap();
+        assert_eq!(b.len(), name.encode_len());
+        String::from_utf8(b).unwrap()
+    }
+
+    #[test]
+    fn namespace_separator() {
+        let name = MetricName::from_str("requests");
+        assert_eq!(encode(name.in_namespace("my_ns")), "my_ns_requests");
+        // the separator is not doubled up
+        assert_eq!(encode(name.in_namespace("my_ns_")), "my_ns_requests");
+        // names that start with an underscore are kept as-is
+        assert_eq!(
+            encode(MetricName::from_str("_internal").in_namespace("my_ns")),
+            "my_ns__internal"
+        );
+
+        assert_eq!(
+            encode(WithNamespace::new("my_ns", name).with_separator(":")),
+            "my_ns:requests"
+        );
+        assert_eq!(
+            encode(WithNamespace::new("my_ns", name).with_separator("")),
+            "my_nsrequests"
+        );
+    }
+
+    #[test]
+    fn owned_names() {
+        let name = MetricNameBuf::try_new(String::from("requests")).unwrap();
+        assert_eq!(encode(&name), "requests");
+        assert_eq!(encode(name.in_namespace("my_ns")), "my_ns_requests");
+        assert_eq!(
+            encode(MetricName::from_str("requests").to_owned()),
+            "requests"
+        );
+
+        let name = MetricNameBuf::from_encoder(name.in_namespace("my_ns").with_suffix(Total));
+        assert_eq!(encode(name), "my_ns_requests_total");
+
+        assert!(matches!(
+            MetricNameBuf::try_new("1xx"),
+            Err(InvalidMetricName::StartsWithNumber)
+        ));
+        assert!(matches!(
+            MetricNameBuf::try_from(String::from("bad name")),
+            Err(InvalidMetricName::InvalidChars)
+        ));
+    }
+
+    #[test]
+    #[should_panic = "invalid metric name"]
+    fn owned_name_from_invalid_encoder() {
+        struct Spaced;
+        impl MetricNameEncoder for Spaced {
+            fn encode_utf8(&self, b: &mut impl std::io::Write) -> std::io::Result<()> {
+                b.write_all(b"bad name")
+            }
+            fn encode_len(&self) -> usize {
+                "bad name".len()
+            }
+        }
+
+        MetricNameBuf::from_encoder(Spaced);
+    }
+}
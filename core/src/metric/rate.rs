@@ -0,0 +1,170 @@
+//! Gauges that expose the rate of a counter. See [`RateGauge`]
+
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::{atomic::MemoryOrdering, label::NoLabels, Counter};
+
+use super::{
+    gauge::FloatGaugeState, group::Encoding, name::MetricNameEncoder, reset::Reset, MetricEncoding,
+    MetricFamilyEncoding,
+};
+
+/// A [`Counter`] which is exposed as a gauge of its rate, in events per second.
+///
+/// On every collection, the rate is computed as the increase of the counter since the previous collection,
+/// divided by the time elapsed since then. This is useful for backends that cannot compute rates from counters
+/// themselves. The rate is only as granular as the collection interval: bursts shorter than the interval
+/// are averaged out, and the first collection reports the rate since the gauge was created.
+///
+/// As every collection resets the rate window, the gauge should only be collected by a single scraper.
+///
+/// ```
+/// use measured::MetricGroup;
+/// use measured::metric::rate::RateGauge;
+///
+/// #[derive(MetricGroup)]
+/// struct Queue {
+///     /// messages published per second
+///     publish_rate: RateGauge,
+/// }
+///
+/// let queue = Queue { publish_rate: RateGauge::new() };
+/// queue.publish_rate.inc_by(10);
+/// ```
+pub struct RateGauge {
+    counter: Counter,
+    last: Mutex<(u64, Instant)>,
+}
+
+impl Default for RateGauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateGauge {
+    /// Create a new rate gauge, with the rate window starting now
+    pub fn new() -> Self {
+        Self {
+            counter: Counter::new(),
+            last: Mutex::new((0, Instant::now())),
+        }
+    }
+
+    /// The underlying counter
+    pub fn counter(&self) -> &Counter {
+        &self.counter
+    }
+
+    /// Increment the counter value by 1
+    pub fn inc(&self) {
+        self.counter.inc();
+    }
+
+    /// Increment the counter value by `x`
+    pub fn inc_by(&self, x: u64) {
+        self.counter.inc_by(x);
+    }
+
+    /// Compute the rate since the previous call, and start a new rate window
+    pub fn rate(&self) -> f64 {
+        self.rate_at(Instant::now())
+    }
+
+    fn rate_at(&self, now: Instant) -> f64 {
+        let mut last = self.last.lock();
+        let current = self.counter.get_metric().count.load(Ordering::Relaxed);
+        let (prev, since) = std::mem::replace(&mut *last, (current, now));
+
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed == Duration::ZERO {
+            return 0.0;
+        }
+        current.saturating_sub(prev) as f64 / elapsed.as_secs_f64()
+    }
+}
+
+impl<Enc: Encoding> MetricFamilyEncoding<Enc> for RateGauge
+where
+    FloatGaugeState: MetricEncoding<Enc>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        FloatGaugeState::write_type(&name, enc)?;
+        FloatGaugeState::new(self.rate()).collect_into(
+            &MemoryOrdering::Relaxed,
+            NoLabels,
+            name,
+            enc,
+        )
+    }
+}
+
+impl Reset for RateGauge {
+    /// Reset the counter to zero, and start a new rate window now
+    fn reset(&self) {
+        let mut last = self.last.lock();
+        self.counter.reset();
+        *last = (0, Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+    };
+
+    use super::RateGauge;
+
+    #[test]
+    fn rate_since_previous_collect() {
+        let gauge = RateGauge::new();
+        let start = gauge.last.lock().1;
+
+        gauge.inc_by(100);
+        assert_eq!(gauge.rate_at(start + Duration::from_secs(2)), 50.0);
+
+        gauge.inc_by(30);
+        assert_eq!(gauge.rate_at(start + Duration::from_secs(5)), 10.0);
+
+        // no time has elapsed
+        assert_eq!(gauge.rate_at(start + Duration::from_secs(5)), 0.0);
+        // no events
+        assert_eq!(gauge.rate_at(start + Duration::from_secs(6)), 0.0);
+    }
+
+    #[test]
+    fn encodes_as_gauge() {
+        let gauge = RateGauge::new();
+        *gauge.last.lock() = (0, Instant::now() - Duration::from_secs(10));
+        gauge.inc_by(10);
+
+        let mut enc = BufferedTextEncoder::new();
+        gauge
+            .collect_family_into(MetricName::from_str("publish_rate"), &mut enc)
+            .unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        let (header, value) = text.trim_end().split_once('\n').unwrap();
+        assert_eq!(header, "# TYPE publish_rate gauge");
+
+        // roughly one event per second, allowing for the time taken by the test
+        let rate: f64 = value
+            .strip_prefix("publish_rate ")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(rate <= 1.0 && rate > 0.9, "{rate}");
+    }
+}
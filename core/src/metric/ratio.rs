@@ -0,0 +1,183 @@
+//! Gauges for values between 0 and 1. See [`RatioGauge`]
+
+use crate::FloatGauge;
+
+use super::{
+    gauge::FloatGaugeState, group::Encoding, name::MetricNameEncoder, reset::Reset, MetricEncoding,
+    MetricFamilyEncoding,
+};
+
+/// What a [`RatioGauge`] does with a value outside of `0.0..=1.0`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RatioPolicy {
+    /// Panic in debug builds. In release builds, the value is clamped, like [`RatioPolicy::Clamp`]
+    #[default]
+    DebugAssert,
+    /// Clamp the value into `0.0..=1.0`. NaN is ignored, and the gauge keeps its previous value
+    Clamp,
+}
+
+/// A [`FloatGauge`] for a ratio, like a cache hit ratio or a utilization, which must be between `0.0` and `1.0`.
+///
+/// A value outside of that range is a bug, most often a percentage from 0 to 100 that was passed instead
+/// of a fraction. By default, such a value panics in debug builds, so the mistake is caught in tests,
+/// and it is clamped in release builds. See [`RatioPolicy`]. The ratio is encoded as a normal gauge.
+///
+/// ```
+/// use measured::MetricGroup;
+/// use measured::metric::ratio::RatioGauge;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Cache {
+///     /// the ratio of lookups that hit the cache
+///     hit_ratio: RatioGauge,
+/// }
+///
+/// let cache = Cache::default();
+/// cache.hit_ratio.set_ratio(90, 100);
+/// assert_eq!(cache.hit_ratio.get(), 0.9);
+/// ```
+#[derive(Default)]
+pub struct RatioGauge {
+    gauge: FloatGauge,
+    policy: RatioPolicy,
+}
+
+impl RatioGauge {
+    /// Create a new ratio gauge, with the [`RatioPolicy::DebugAssert`] policy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new ratio gauge, with the given policy for values out of range
+    pub fn with_policy(policy: RatioPolicy) -> Self {
+        Self {
+            gauge: FloatGauge::new(),
+            policy,
+        }
+    }
+
+    /// The policy for values out of range
+    pub fn policy(&self) -> RatioPolicy {
+        self.policy
+    }
+
+    /// Set the ratio to `x`
+    ///
+    /// # Panics
+    /// With the [`RatioPolicy::DebugAssert`] policy, panics in debug builds if `x` is not within `0.0..=1.0`.
+    pub fn set(&self, x: f64) {
+        if self.policy == RatioPolicy::DebugAssert {
+            debug_assert!(
+                (0.0..=1.0).contains(&x),
+                "ratio gauge value {x} is not within 0.0..=1.0",
+            );
+        }
+        // NaN fails the comparisons in `clamp`, so it is checked first
+        if x.is_nan() {
+            return;
+        }
+        self.gauge.set(x.clamp(0.0, 1.0));
+    }
+
+    /// Set the ratio to `numerator / denominator`. Nothing is set if the denominator is zero
+    pub fn set_ratio(&self, numerator: u64, denominator: u64) {
+        if denominator != 0 {
+            self.set(numerator as f64 / denominator as f64);
+        }
+    }
+
+    /// Get the current ratio
+    pub fn get(&self) -> f64 {
+        self.gauge.get_metric().count.get()
+    }
+}
+
+impl<Enc: Encoding> MetricFamilyEncoding<Enc> for RatioGauge
+where
+    FloatGaugeState: MetricEncoding<Enc>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        self.gauge.collect_family_into(name, enc)
+    }
+}
+
+impl Reset for RatioGauge {
+    fn reset(&self) {
+        self.gauge.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+    };
+
+    use super::{RatioGauge, RatioPolicy};
+
+    #[test]
+    fn clamp() {
+        let ratio = RatioGauge::with_policy(RatioPolicy::Clamp);
+
+        ratio.set(0.25);
+        assert_eq!(ratio.get(), 0.25);
+
+        // below 0
+        ratio.set(-0.5);
+        assert_eq!(ratio.get(), 0.0);
+
+        // above 1, like a percentage
+        ratio.set(75.0);
+        assert_eq!(ratio.get(), 1.0);
+
+        // NaN is ignored
+        ratio.set(0.5);
+        ratio.set(f64::NAN);
+        assert_eq!(ratio.get(), 0.5);
+
+        // no lookups yet
+        ratio.set_ratio(0, 0);
+        assert_eq!(ratio.get(), 0.5);
+        ratio.set_ratio(1, 4);
+        assert_eq!(ratio.get(), 0.25);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "ratio gauge value -0.5 is not within 0.0..=1.0"]
+    fn debug_assert_below_zero() {
+        RatioGauge::new().set(-0.5);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "ratio gauge value 75 is not within 0.0..=1.0"]
+    fn debug_assert_above_one() {
+        RatioGauge::new().set(75.0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "ratio gauge value NaN is not within 0.0..=1.0"]
+    fn debug_assert_nan() {
+        RatioGauge::new().set(f64::NAN);
+    }
+
+    #[test]
+    fn encodes_as_gauge() {
+        let ratio = RatioGauge::new();
+        ratio.set(0.5);
+
+        let mut enc = BufferedTextEncoder::new();
+        ratio
+            .collect_family_into(MetricName::from_str("hit_ratio"), &mut enc)
+            .unwrap();
+        assert_eq!(enc.finish(), "# TYPE hit_ratio gauge\nhit_ratio 0.5\n");
+    }
+}
@@ -0,0 +1,581 @@
+//! Rename or drop labels at collection time. See [`Relabeler`] and [`LabelPrefix`]
+
+use crate::label::{LabelGroup, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor};
+
+use super::{
+    group::{Encoding, MetricGroup},
+    name::MetricNameEncoder,
+    reset::Reset,
+    MetricEncoding,
+};
+
+/// A single rule of a [`Relabeler`]
+#[derive(Clone, Debug)]
+pub struct RelabelRule {
+    metric: Option<String>,
+    action: Action,
+}
+
+#[derive(Clone, Debug)]
+enum Action {
+    Rename { from: String, to: String },
+    DropLabel { label: String },
+    DropSeries { label: String, value: String },
+}
+
+impl RelabelRule {
+    /// Rename the label `from` to `to`.
+    ///
+    /// # Panics
+    /// Panics if `to` is not a valid label name.
+    pub fn rename_label(from: impl Into<String>, to: impl Into<String>) -> Self {
+        let to = to.into();
+        LabelName::from_str(&to);
+        Self::new(Action::Rename {
+            from: from.into(),
+            to,
+        })
+    }
+
+    /// Remove the label from the series
+    pub fn drop_label(label: impl Into<String>) -> Self {
+        Self::new(Action::DropLabel {
+            label: label.into(),
+        })
+    }
+
+    /// Remove every series where the label has the given value
+    pub fn drop_series(label: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::new(Action::DropSeries {
+            label: label.into(),
+            value: value.into(),
+        })
+    }
+
+    /// Only apply this rule to the samples with the given metric name, including any namespace.
+    ///
+    /// The samples of a histogram are matched by the name of the histogram, without the `_bucket`, `_sum`
+    /// and `_count` suffixes.
+    pub fn for_metric(mut self, name: impl Into<String>) -> Self {
+        self.metric = Some(name.into());
+        self
+    }
+
+    fn new(action: Action) -> Self {
+        Self {
+            metric: None,
+            action,
+        }
+    }
+}
+
+/// Relabeling rules, applied to every sample as it is collected.
+///
+/// A rule can rename a label, drop a label, or drop a whole series based on the value of one of its labels,
+/// optionally only for a single metric. The rules are applied in order, and every rule sees the label names as
+/// they were written by the metric, before any renames. Collecting through [`Relabeler::encoder`] applies the rules
+/// without changing the instrumentation.
+///
+/// This is a last resort, for sanitizing labels that cannot be fixed where they are recorded. The series are still
+/// stored with all their labels, so dropping a label does not reduce the memory used by the metric. It also does
+/// not merge the values of the series which only differed by that label, so they will be exposed as duplicates.
+/// Prefer to remove the label from the instrumentation, or drop the whole series.
+///
+/// ```
+/// use measured::{CounterVec, FixedCardinalityLabel, LabelGroup, MetricGroup};
+/// use measured::metric::relabel::{RelabelRule, Relabeler};
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(FixedCardinalityLabel, Clone, Copy)]
+/// #[label(rename_all = "snake_case")]
+/// enum Status { Ok, NotFound }
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestLabelSet)]
+/// struct Request {
+///     status: Status,
+/// }
+///
+/// #[derive(MetricGroup)]
+/// struct Metrics {
+///     requests: CounterVec<RequestLabelSet>,
+/// }
+///
+/// let metrics = Metrics { requests: CounterVec::dense_with_label_set(RequestLabelSet::new()) };
+/// metrics.requests.inc(Request { status: Status::Ok });
+/// metrics.requests.inc(Request { status: Status::NotFound });
+///
+/// let relabeler = Relabeler::new()
+///     .with_rule(RelabelRule::drop_series("status", "not_found"))
+///     .with_rule(RelabelRule::rename_label("status", "code"));
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// metrics.collect_group_into(&mut relabeler.encoder(&mut enc)).unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Relabeler {
+    rules: Vec<RelabelRule>,
+}
+
+impl Relabeler {
+    /// Create a relabeler with no rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule, to be applied after the existing rules
+    pub fn with_rule(mut self, rule: RelabelRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Add a rule, to be applied after the existing rules
+    pub fn push(&mut self, rule: RelabelRule) {
+        self.rules.push(rule);
+    }
+
+    /// Wrap the encoder, so that all the samples collected through it are relabeled
+    pub fn encoder<E: Encoding>(&self, inner: E) -> Relabeled<'_, E> {
+        Relabeled {
+            inner,
+            rules: &self.rules,
+        }
+    }
+}
+
+/// An encoder which applies the rules of a [`Relabeler`] to every sample
+pub struct Relabeled<'a, E> {
+    inner: E,
+    rules: &'a [RelabelRule],
+}
+
+impl<E> Relabeled<'_, E> {
+    /// Extract the inner encoder
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+}
+
+impl<E: Encoding> Encoding for Relabeled<'_, E> {
+    type Err = E::Err;
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.inner.write_help(name, help)
+    }
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Self::Err> {
+        self.inner.write_unit(name, unit)
+    }
+}
+
+impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<Relabeled<'_, E>> for M {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut Relabeled<'_, E>) -> Result<(), E::Err> {
+        M::write_type(name, &mut enc.inner)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut Relabeled<'_, E>,
+    ) -> Result<(), E::Err> {
+        let rules = matching_rules(enc.rules, &name);
+        if rules.is_empty() {
+            return self.collect_into(metadata, labels, name, &mut enc.inner);
+        }
+
+        let mut check = DropSeries {
+            rules: &rules,
+            drop: false,
+        };
+        labels.visit_values(&mut check);
+        if check.drop {
+            return Ok(());
+        }
+
+        let labels = RelabeledGroup {
+            group: labels,
+            rules: &rules,
+        };
+        self.collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
+/// The rules which apply to the metric name
+fn matching_rules<'a>(rules: &'a [RelabelRule], name: &impl MetricNameEncoder) -> Vec<&'a Action> {
+    let mut encoded = None;
+    rules
+        .iter()
+        .filter(|rule| match &rule.metric {
+            None => true,
+            Some(metric) => {
+                let encoded = encoded.get_or_insert_with(|| {
+                    let mut b = Vec::with_capacity(name.encode_len());
+                    name.encode_utf8(&mut b)
+                        .expect("writing to a vec should not fail");
+                    b
+                });
+                metric.as_bytes() == encoded.as_slice()
+            }
+        })
+        .map(|rule| &rule.action)
+        .collect()
+}
+
+/// Checks whether any of the labels match a [`Action::DropSeries`] rule
+struct DropSeries<'a> {
+    rules: &'a [&'a Action],
+    drop: bool,
+}
+
+impl LabelGroupVisitor for DropSeries<'_> {
+    type Output = ();
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        for rule in self.rules {
+            if let Action::DropSeries { label, value } = rule {
+                if !self.drop && label == name.as_str() {
+                    self.drop = x.visit(ValueEquals(value));
+                }
+            }
+        }
+    }
+}
+
+/// Compares a label value to a string
+struct ValueEquals<'a>(&'a str);
+
+impl LabelVisitor for ValueEquals<'_> {
+    type Output = bool;
+    fn write_int(self, x: i64) -> bool {
+        self.write_str(itoa::Buffer::new().format(x))
+    }
+    fn write_float(self, x: f64) -> bool {
+        self.write_str(ryu::Buffer::new().format(x))
+    }
+    fn write_str(self, x: &str) -> bool {
+        self.0 == x
+    }
+}
+
+/// A label group with the rename and drop rules applied
+struct RelabeledGroup<'a, G> {
+    group: G,
+    rules: &'a [&'a Action],
+}
+
+impl<G: LabelGroup> LabelGroup for RelabeledGroup<'_, G> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        self.group.visit_values(&mut RelabelVisitor {
+            inner: v,
+            rules: self.rules,
+        });
+    }
+}
+
+struct RelabelVisitor<'a, V> {
+    inner: &'a mut V,
+    rules: &'a [&'a Action],
+}
+
+impl<V: LabelGroupVisitor> LabelGroupVisitor for RelabelVisitor<'_, V> {
+    type Output = ();
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        let mut renamed = name;
+        for rule in self.rules {
+            match rule {
+                Action::DropLabel { label } if label == name.as_str() => return,
+                Action::Rename { from, to } if from == name.as_str() => {
+                    renamed = LabelName::from_str(to);
+                }
+                _ => {}
+            }
+        }
+        self.inner.write_value(renamed, x);
+    }
+}
+
+/// Prefixes the names of all the labels of a [`MetricGroup`], or of all the samples collected through an encoder.
+///
+/// This is the label counterpart of [`WithNamespace`](super::name::WithNamespace), for combining the metrics of
+/// subsystems whose label names would otherwise mean different things, like two `host` labels which become
+/// `db_host` and `cache_host`. The prefix is applied to every label the metric writes, including labels composed
+/// into the label group, like the `quantile` of a [`QuantileHistogramVec`](super::histogram::quantile::QuantileHistogramVec).
+/// The labels added by the encoding of a metric type itself, like the `le` label of histogram buckets, are not prefixed.
+///
+/// Encoder wrappers transform the labels from the outside in. A [`Relabeler`] encoder which wraps a `LabelPrefix` encoder
+/// sees the original label names, and one which is wrapped by a `LabelPrefix`, including by a `LabelPrefix` group,
+/// sees the prefixed names.
+///
+/// ```
+/// use measured::{CounterVec, FixedCardinalityLabel, MetricGroup};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::{group::ComposedGroup, relabel::LabelPrefix};
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(FixedCardinalityLabel, Clone, Copy)]
+/// #[label(rename_all = "snake_case", singleton = "host")]
+/// enum Host { Primary, Replica }
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Db {
+///     queries: CounterVec<StaticLabelSet<Host>>,
+/// }
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Cache {
+///     lookups: CounterVec<StaticLabelSet<Host>>,
+/// }
+///
+/// let metrics = ComposedGroup(
+///     LabelPrefix::new("db_", Db::default()),
+///     LabelPrefix::new("cache_", Cache::default()),
+/// );
+/// metrics.0.inner().queries.inc(Host::Primary);
+/// metrics.1.inner().lookups.inc(Host::Replica);
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// metrics.collect_group_into(&mut enc).unwrap();
+/// let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+/// assert!(text.contains("queries{db_host=\"primary\"} 1\n"));
+/// assert!(text.contains("lookups{cache_host=\"replica\"} 1\n"));
+/// ```
+pub struct LabelPrefix<T> {
+    prefix: &'static str,
+    inner: T,
+}
+
+impl<T> LabelPrefix<T> {
+    /// Prefix the label names of `inner` with `prefix`. The prefix is prepended as is, without any separator.
+    ///
+    /// # Panics
+    /// Will panic if `prefix` is not a valid label name
+    pub const fn new(prefix: &'static str, inner: T) -> Self {
+        LabelName::from_str(prefix);
+        Self { prefix, inner }
+    }
+
+    /// Access the inner value
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Extract the inner value
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<G, E> MetricGroup<E> for LabelPrefix<G>
+where
+    G: for<'a> MetricGroup<LabelPrefix<&'a mut E>>,
+    E: Encoding,
+{
+    fn collect_group_into(&self, enc: &mut E) -> Result<(), E::Err> {
+        self.inner.collect_group_into(&mut LabelPrefix {
+            prefix: self.prefix,
+            inner: enc,
+        })
+    }
+}
+
+impl<G: Reset> Reset for LabelPrefix<G> {
+    fn reset(&self) {
+        self.inner.reset();
+    }
+}
+
+impl<E: Encoding> Encoding for LabelPrefix<E> {
+    type Err = E::Err;
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.inner.write_help(name, help)
+    }
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Self::Err> {
+        self.inner.write_unit(name, unit)
+    }
+}
+
+impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<LabelPrefix<E>> for M {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut LabelPrefix<E>) -> Result<(), E::Err> {
+        M::write_type(name, &mut enc.inner)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut LabelPrefix<E>,
+    ) -> Result<(), E::Err> {
+        let labels = PrefixedGroup {
+            group: labels,
+            prefix: enc.prefix,
+        };
+        self.collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
+/// A label group with a prefix on all the label names
+struct PrefixedGroup<G> {
+    group: G,
+    prefix: &'static str,
+}
+
+impl<G: LabelGroup> LabelGroup for PrefixedGroup<G> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        self.group.visit_values(&mut PrefixVisitor {
+            inner: v,
+            prefix: self.prefix,
+            buf: String::new(),
+        });
+    }
+}
+
+struct PrefixVisitor<'a, V> {
+    inner: &'a mut V,
+    prefix: &'static str,
+    /// reused for every label name of the group
+    buf: String,
+}
+
+impl<V: LabelGroupVisitor> LabelGroupVisitor for PrefixVisitor<'_, V> {
+    type Output = ();
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        self.buf.clear();
+        self.buf.push_str(self.prefix);
+        self.buf.push_str(name.as_str());
+        self.inner.write_value(LabelName::from_str(&self.buf), x);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, LabelGroup, MetricGroup};
+
+    use crate::{
+        metric::{group::MetricGroup, histogram::Thresholds},
+        text::BufferedTextEncoder,
+        CounterVec, HistogramVec,
+    };
+
+    use super::{LabelPrefix, RelabelRule, Relabeler};
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate, rename_all = "snake_case")]
+    enum Status {
+        Ok,
+        NotFound,
+    }
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate, rename_all = "snake_case")]
+    enum Region {
+        East,
+        West,
+    }
+
+    #[derive(LabelGroup)]
+    #[label(crate = crate, set = RequestLabelSet)]
+    struct Request {
+        status: Status,
+        region: Region,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        requests: CounterVec<RequestLabelSet>,
+        latency: HistogramVec<RequestLabelSet, 1>,
+    }
+
+    fn metrics() -> Metrics {
+        let metrics = Metrics {
+            requests: CounterVec::sparse_with_label_set(RequestLabelSet::new()),
+            latency: HistogramVec::sparse_with_label_set_and_metadata(
+                RequestLabelSet::new(),
+                Thresholds::with_buckets([1.0]),
+            ),
+        };
+        for (status, region) in [(Status::Ok, Region::East), (Status::NotFound, Region::West)] {
+            metrics.requests.inc(Request { status, region });
+            metrics.latency.observe(Request { status, region }, 0.5);
+        }
+        metrics
+    }
+
+    fn collect(metrics: &Metrics, relabeler: &Relabeler) -> String {
+        let mut enc = BufferedTextEncoder::new();
+        metrics
+            .collect_group_into(&mut relabeler.encoder(&mut enc))
+            .unwrap();
+        String::from_utf8(enc.finish().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn rename_and_drop() {
+        let metrics = metrics();
+
+        let relabeler = Relabeler::new()
+            .with_rule(RelabelRule::drop_series("status", "not_found"))
+            .with_rule(RelabelRule::rename_label("status", "code"))
+            .with_rule(RelabelRule::drop_label("region").for_metric("requests"));
+
+        let text = collect(&metrics, &relabeler);
+        assert_eq!(
+            text,
+            r#"# TYPE requests counter
+requests{code="ok"} 1
+
+# TYPE latency histogram
+latency_bucket{code="ok",region="east",le="1.0"} 1
+latency_bucket{code="ok",region="east",le="+Inf"} 1
+latency_sum{code="ok",region="east"} 0.5
+latency_count{code="ok",region="east"} 1
+"#
+        );
+    }
+
+    #[test]
+    fn no_rules() {
+        let metrics = metrics();
+        let text = collect(&metrics, &Relabeler::new());
+        assert!(text.contains("requests{status=\"not_found\",region=\"west\"} 1\n"));
+        assert!(text.contains("latency_count{status=\"ok\",region=\"east\"} 1\n"));
+    }
+
+    #[test]
+    fn label_prefix() {
+        let metrics = LabelPrefix::new("app_", metrics());
+
+        let mut enc = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut enc).unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        assert!(text.contains("requests{app_status=\"ok\",app_region=\"east\"} 1\n"));
+        // the bucket label of the histogram encoding is not prefixed
+        assert!(text.contains(
+            "latency_bucket{app_status=\"not_found\",app_region=\"west\",le=\"1.0\"} 1\n"
+        ));
+        assert!(!text.contains("app_le"));
+    }
+
+    #[test]
+    fn label_prefix_with_relabeler() {
+        let metrics = LabelPrefix::new("app_", metrics());
+        let relabeler = Relabeler::new()
+            .with_rule(RelabelRule::drop_label("app_region"))
+            .with_rule(RelabelRule::rename_label("status", "code"));
+
+        // the relabeler wraps the prefixed labels, so it sees the prefixed names
+        let mut enc = BufferedTextEncoder::new();
+        metrics
+            .collect_group_into(&mut relabeler.encoder(&mut enc))
+            .unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        assert!(text.contains("requests{app_status=\"ok\"} 1\n"));
+
+        // the relabeler wraps the prefix encoder, so it sees the original names
+        let mut enc = BufferedTextEncoder::new();
+        metrics
+            .inner()
+            .collect_group_into(&mut relabeler.encoder(LabelPrefix::new("app_", &mut enc)))
+            .unwrap();
+        let text = String::from_utf8(enc.finish().to_vec()).unwrap();
+        assert!(text.contains("requests{app_code=\"ok\",app_region=\"east\"} 1\n"));
+    }
+}
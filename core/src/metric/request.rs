@@ -0,0 +1,302 @@
+//! Request counters and latency histograms that are recorded together. See [`RequestMetrics`]
+
+use std::{sync::atomic::Ordering, time::Duration};
+
+use parking_lot::Mutex;
+
+use crate::{atomic::MemoryOrdering, label::LabelGroupSet};
+
+use super::{
+    counter::CounterState,
+    group::{Encoding, MetricGroup},
+    histogram::{HistogramState, Thresholds},
+    name::MetricName,
+    reset::Reset,
+    reset::ResetMetric,
+    MetricEncoding, MetricType, MetricVec, VecInner,
+};
+
+/// The state of a single series of [`RequestMetrics`]
+pub struct RequestState<const N: usize> {
+    /// The number of requests
+    pub requests: CounterState,
+    /// The request latencies
+    pub latency: HistogramState<N>,
+}
+
+impl<const N: usize> Default for RequestState<N> {
+    fn default() -> Self {
+        Self {
+            requests: CounterState::default(),
+            latency: HistogramState::default(),
+        }
+    }
+}
+
+impl<const N: usize> MetricType for RequestState<N> {
+    type Metadata = Thresholds<N>;
+}
+
+/// A request counter and a latency histogram which share the same labels, and are always updated together.
+///
+/// Both families are backed by a single metric vec, so the labels are only encoded once per [`record`](Self::record).
+/// The counter is incremented while the histogram read lock is held, and each series is sampled under the
+/// histogram write lock before either family is encoded. A scrape therefore never sees the request count and the
+/// histogram count disagree, even while requests are being recorded concurrently.
+///
+/// ```
+/// use measured::{FixedCardinalityLabel, MetricGroup};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::histogram::Thresholds;
+/// use measured::metric::name::MetricName;
+/// use measured::metric::request::RequestMetrics;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "method")]
+/// enum Method { Get, Post }
+///
+/// let http = RequestMetrics::<StaticLabelSet<Method>, 4>::new(
+///     MetricName::from_str("http_requests_total"),
+///     MetricName::from_str("http_request_duration_seconds"),
+///     StaticLabelSet::new(),
+///     Thresholds::exponential_buckets(0.01, 4.0),
+/// );
+/// http.record(Method::Get, 0.02);
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// http.collect_group_into(&mut enc).unwrap();
+/// ```
+pub struct RequestMetrics<L: LabelGroupSet, const N: usize> {
+    vec: MetricVec<RequestState<N>, L>,
+    requests_name: &'static MetricName,
+    latency_name: &'static MetricName,
+    /// The samples of the last collection, kept so that collections do not allocate
+    samples: Mutex<Vec<Sample<L::Unique, N>>>,
+}
+
+impl<L: LabelGroupSet, const N: usize> RequestMetrics<L, N> {
+    /// Create the request metrics, which are exposed as the `requests_name` counter and the `latency_name` histogram
+    pub fn new(
+        requests_name: &'static MetricName,
+        latency_name: &'static MetricName,
+        label_set: L,
+        thresholds: Thresholds<N>,
+    ) -> Self {
+        Self {
+            vec: MetricVec::with_label_set_and_metadata(label_set, thresholds),
+            requests_name,
+            latency_name,
+            samples: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// View the underlying metric vec
+    pub fn metric_vec(&self) -> &MetricVec<RequestState<N>, L> {
+        &self.vec
+    }
+
+    /// Count a request and observe its latency `x`, keyed by the label group
+    pub fn record(&self, label: L::Group<'_>, x: f64) {
+        let metric = self.vec.get_metric(self.vec.with_labels(label));
+        let thresholds = metric.metadata();
+        let bucket = thresholds.bucket(x);
+
+        let latency = metric.latency.inner.read();
+        latency.observe(bucket, thresholds.clamp(x));
+        metric.requests.inc();
+    }
+
+    /// Count a request and observe its duration in seconds, keyed by the label group
+    pub fn record_duration(&self, label: L::Group<'_>, duration: Duration) {
+        self.record(label, duration.as_secs_f64());
+    }
+}
+
+enum SeriesKey<U> {
+    Dense(usize),
+    Sparse(U),
+}
+
+/// A consistent snapshot of a single series
+struct Sample<U, const N: usize> {
+    key: SeriesKey<U>,
+    requests: u64,
+    buckets: [u64; N],
+    inf: u64,
+    sum: f64,
+}
+
+impl<const N: usize> RequestState<N> {
+    fn sample<U>(&self, key: SeriesKey<U>) -> Sample<U, N> {
+        let mut latency = self.latency.inner.write();
+        let requests = self.requests.load(Ordering::Relaxed);
+        let (buckets, inf, sum) = latency.sample();
+        Sample {
+            key,
+            requests,
+            buckets,
+            inf,
+            sum,
+        }
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> RequestMetrics<L, N> {
+    /// Sample every series into the reused buffer
+    fn snapshot(&self, samples: &mut Vec<Sample<L::Unique, N>>) {
+        samples.clear();
+        match &self.vec.metrics {
+            VecInner::Dense(m) => {
+                for (index, value) in m.iter().enumerate() {
+                    if let Some(value) = value.get() {
+                        samples.push(value.sample(SeriesKey::Dense(index)));
+                    }
+                }
+            }
+            VecInner::Sparse(m) => {
+                m.for_each_entry(|k, v| {
+                    samples.push(v.sample(SeriesKey::Sparse(*k)));
+                    Ok::<(), core::convert::Infallible>(())
+                })
+                .unwrap_or_else(|never| match never {});
+                if let Some((k, v)) = m.overflow_series() {
+                    samples.push(v.sample(SeriesKey::Sparse(*k)));
+                }
+            }
+        }
+    }
+}
+
+impl<L, const N: usize, Enc> MetricGroup<Enc> for RequestMetrics<L, N>
+where
+    L: LabelGroupSet,
+    Enc: Encoding,
+    CounterState: MetricEncoding<Enc>,
+    HistogramState<N>: MetricEncoding<Enc> + MetricType<Metadata = Thresholds<N>>,
+{
+    fn collect_group_into(&self, enc: &mut Enc) -> Result<(), Enc::Err> {
+        let samples = &mut *self.samples.lock();
+        self.snapshot(samples);
+        let label_set = &self.vec.label_set;
+
+        CounterState::write_type(self.requests_name, enc)?;
+        for sample in &*samples {
+            let labels = match &sample.key {
+                SeriesKey::Dense(index) => label_set.decode_dense(*index),
+                SeriesKey::Sparse(key) => label_set.decode(key),
+            };
+            CounterState::new(sample.requests).collect_into(
+                &MemoryOrdering::Relaxed,
+                labels,
+                self.requests_name,
+                enc,
+            )?;
+        }
+
+        // every sample is encoded through the same histogram state
+        let mut latency = HistogramState::<N>::default();
+        HistogramState::<N>::write_type(self.latency_name, enc)?;
+        for sample in &*samples {
+            let labels = match &sample.key {
+                SeriesKey::Dense(index) => label_set.decode_dense(*index),
+                SeriesKey::Sparse(key) => label_set.decode(key),
+            };
+            let inner = latency.inner.get_mut();
+            for (bucket, count) in inner.buckets.iter_mut().zip(sample.buckets) {
+                *bucket.get_mut() = count;
+            }
+            *inner.inf.get_mut() = sample.inf;
+            inner.sum.set_mut(sample.sum);
+            latency.collect_into(self.vec.metadata(), labels, self.latency_name, enc)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<const N: usize> ResetMetric for RequestState<N> {
+    fn reset(&self) {
+        self.requests.reset();
+        self.latency.reset();
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> Reset for RequestMetrics<L, N> {
+    fn reset(&self) {
+        self.vec.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{group::MetricGroup, histogram::Thresholds, name::MetricName},
+        text::{parse_text_exposition, BufferedTextEncoder},
+        FixedCardinalityLabel,
+    };
+
+    use super::RequestMetrics;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "method")]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[test]
+    fn consistent_under_concurrency() {
+        let metrics = RequestMetrics::<StaticLabelSet<Method>, 4>::new(
+            MetricName::from_str("requests_total"),
+            MetricName::from_str("request_duration_seconds"),
+            StaticLabelSet::new(),
+            Thresholds::exponential_buckets(0.001, 4.0),
+        );
+
+        std::thread::scope(|s| {
+            let writers: Vec<_> = (0..4)
+                .map(|i| {
+                    let metrics = &metrics;
+                    s.spawn(move || {
+                        let method = if i % 2 == 0 {
+                            Method::Get
+                        } else {
+                            Method::Post
+                        };
+                        for j in 0..20000 {
+                            metrics.record(method, j as f64 * 1e-6);
+                        }
+                    })
+                })
+                .collect();
+
+            // keep scraping while the requests are being recorded
+            while !writers.iter().all(|w| w.is_finished()) {
+                let mut enc = BufferedTextEncoder::new();
+                metrics.collect_group_into(&mut enc).unwrap();
+                let families = parse_text_exposition(&enc.finish()).unwrap();
+                assert_eq!(families.len(), 2);
+
+                for requests in &families[0].samples {
+                    let count = families[1]
+                        .samples
+                        .iter()
+                        .find(|s| {
+                            s.name == "request_duration_seconds_count"
+                                && s.label("method") == requests.label("method")
+                        })
+                        .unwrap();
+                    assert_eq!(requests.value, count.value);
+                }
+            }
+        });
+
+        let mut enc = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut enc).unwrap();
+        let families = parse_text_exposition(&enc.finish()).unwrap();
+        assert_eq!(families[0].samples[0].value, 40000.0);
+        assert_eq!(families[0].samples[1].value, 40000.0);
+    }
+}
@@ -0,0 +1,291 @@
+//! Reset all the metrics of a group, for test isolation. See [`reset_all`]
+
+use core::convert::Infallible;
+use std::sync::{atomic::Ordering, Arc};
+
+use crate::label::{ComposedGroup, LabelGroupSet};
+
+use super::{
+    counter::CounterState,
+    gauge::{FloatGaugeState, GaugeState},
+    histogram::HistogramState,
+    summary::SummaryState,
+    Metric, MetricType, MetricVec,
+};
+
+/// Reset every metric in the group back to zero. This is a testing aid.
+///
+/// Tests which share a global metric group see the updates of each other. Calling this at the start of a test
+/// gives it a clean slate, so it can make exact assertions on the metric values. Every metric in the group is
+/// reset with [`Reset`], which `#[derive(MetricGroup)]` implements for any group whose fields all implement it.
+/// A group with a metric that cannot be reset does not compile. The series of sparse metric vecs are zeroed,
+/// but not removed.
+///
+/// Do not use this outside of tests. Counters must only ever increase, and a scraper would see the reset as
+/// a restart of the process.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use measured::{Counter, MetricGroup};
+/// use measured::metric::reset::reset_all;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Metrics {
+///     requests: Counter,
+/// }
+///
+/// let metrics = Metrics::default();
+/// metrics.requests.inc();
+///
+/// reset_all(&metrics);
+/// assert_eq!(metrics.requests.get_metric().load(Ordering::Relaxed), 0);
+/// ```
+///
+/// A metric which does not implement [`Reset`] cannot be reset, so neither can its group:
+///
+/// ```compile_fail
+/// use measured::MetricGroup;
+/// use measured::metric::{group::Encoding, name::MetricNameEncoder, MetricFamilyEncoding};
+/// use measured::metric::reset::reset_all;
+///
+/// struct Uptime;
+///
+/// impl<Enc: Encoding> MetricFamilyEncoding<Enc> for Uptime {
+///     fn collect_family_into(&self, _: impl MetricNameEncoder, _: &mut Enc) -> Result<(), Enc::Err> {
+///         Ok(())
+///     }
+/// }
+///
+/// #[derive(MetricGroup)]
+/// struct Metrics {
+///     uptime: Uptime,
+/// }
+///
+/// reset_all(&Metrics { uptime: Uptime });
+/// ```
+pub fn reset_all<G: Reset + ?Sized>(group: &G) {
+    group.reset();
+}
+
+/// A metric, or a group of metrics, that can be reset back to its initial state. See [`reset_all`]
+///
+/// Metrics which keep state besides their value, like the value at the previous collection,
+/// reset that state too. Collecting the metrics afterwards reports them as if they were just created.
+pub trait Reset {
+    /// Reset every metric back to its initial state
+    fn reset(&self);
+}
+
+/// Metric state that can be reset back to zero
+pub trait ResetMetric: MetricType {
+    /// Reset the metric to zero
+    fn reset(&self);
+}
+
+impl ResetMetric for CounterState {
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+    }
+}
+
+impl ResetMetric for GaugeState {
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+    }
+}
+
+impl ResetMetric for FloatGaugeState {
+    fn reset(&self) {
+        self.count.set(0.0);
+    }
+}
+
+impl<const N: usize> ResetMetric for HistogramState<N> {
+    fn reset(&self) {
+        let mut inner = self.inner.write();
+        for bucket in &mut inner.buckets {
+            *bucket.get_mut() = 0;
+        }
+        *inner.inf.get_mut() = 0;
+        inner.sum.set_mut(0.0);
+    }
+}
+
+impl ResetMetric for SummaryState {
+    fn reset(&self) {
+        self.inner.write().reset();
+    }
+}
+
+impl<M: ResetMetric> Reset for Metric<M> {
+    fn reset(&self) {
+        self.metric.reset();
+    }
+}
+
+impl<M: ResetMetric, L: LabelGroupSet> Reset for MetricVec<M, L> {
+    fn reset(&self) {
+        self.for_each_series(|_, metric| {
+            metric.reset();
+            Ok::<(), Infallible>(())
+        })
+        .unwrap_or_else(|never| match never {});
+    }
+}
+
+impl<T: Reset + ?Sized> Reset for &T {
+    fn reset(&self) {
+        T::reset(self);
+    }
+}
+
+impl<T: Reset + ?Sized> Reset for Arc<T> {
+    fn reset(&self) {
+        T::reset(self);
+    }
+}
+
+impl<T: Reset> Reset for Option<T> {
+    fn reset(&self) {
+        if let Some(inner) = self {
+            inner.reset();
+        }
+    }
+}
+
+impl<A: Reset, B: Reset> Reset for ComposedGroup<A, B> {
+    fn reset(&self) {
+        self.0.reset();
+        self.1.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, MetricGroup};
+
+    use std::time::Duration;
+
+    use crate::{
+        label::StaticLabelSet,
+        metric::{
+            delta::DeltaCounter, group::ScrapeSequence, histogram::Thresholds, name::WithNamespace,
+            stale::StaleGauge,
+        },
+        text::BufferedTextEncoder,
+        CounterVec, FloatGauge, Gauge, Histogram,
+    };
+
+    use super::reset_all;
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "route")]
+    enum Route {
+        Home,
+        Users,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Db {
+        connections: Gauge,
+        utilization: FloatGauge,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        requests: CounterVec<StaticLabelSet<Route>>,
+        latency: Histogram<2>,
+        #[metric(namespace = "db")]
+        db: Db,
+    }
+
+    fn collect(metrics: &impl crate::MetricGroup<BufferedTextEncoder>) -> String {
+        let mut enc = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut enc).unwrap();
+        String::from_utf8(enc.finish().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn reset_every_metric() {
+        let metrics = Metrics {
+            requests: CounterVec::new(),
+            latency: Histogram::with_metadata(Thresholds::with_buckets([1.0, 2.0])),
+            db: Db {
+                connections: Gauge::new(),
+                utilization: FloatGauge::new(),
+            },
+        };
+        metrics.requests.inc(Route::Home);
+        metrics.latency.observe(1.5);
+        metrics.db.connections.set(4);
+        metrics.db.utilization.set(0.5);
+
+        reset_all(&metrics);
+        // the touched series is kept, at zero
+        assert_eq!(
+            collect(&metrics),
+            r#"# TYPE requests counter
+requests{route="home"} 0
+
+# TYPE latency histogram
+latency_bucket{le="1.0"} 0
+latency_bucket{le="2.0"} 0
+latency_bucket{le="+Inf"} 0
+latency_sum 0.0
+latency_count 0
+
+# TYPE db_connections gauge
+db_connections 0
+
+# TYPE db_utilization gauge
+db_utilization 0.0
+"#
+        );
+
+        // namespaced groups are reset too
+        let namespaced = WithNamespace::new("app", metrics);
+        namespaced.inner.requests.inc(Route::Users);
+        reset_all(&namespaced);
+        assert!(collect(&namespaced).contains("app_requests{route=\"users\"} 0\n"));
+    }
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Queue {
+        published: DeltaCounter,
+        lag: StaleGauge,
+    }
+
+    #[test]
+    fn reset_metric_state() {
+        let metrics = ScrapeSequence::new(Queue {
+            published: DeltaCounter::new(),
+            lag: StaleGauge::new().with_ttl(Duration::from_secs(60)),
+        });
+        metrics.inner().published.inc_by(7);
+        metrics.inner().lag.set(5);
+        collect(&metrics);
+
+        metrics.inner().published.inc_by(3);
+        reset_all(&metrics);
+        assert_eq!(metrics.sequence(), 0);
+        assert_eq!(metrics.inner().lag.last_update(), None);
+
+        // the delta window starts again at zero, and the gauge is stale as if it was never set
+        assert_eq!(
+            collect(&metrics),
+            r#"# TYPE published counter
+published 0
+
+# TYPE published_delta gauge
+published_delta 0
+
+# TYPE lag gauge
+# HELP scrape_sequence_total The number of times the metrics were collected
+# TYPE scrape_sequence_total counter
+scrape_sequence_total 1
+"#
+        );
+    }
+}
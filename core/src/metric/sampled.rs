@@ -0,0 +1,201 @@
+//! Counters that only record a sample of events. See [`SampledCounter`]
+
+use core::{
+    cell::Cell,
+    num::NonZeroU64,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{label::LabelGroupSet, SampledCounter, SampledCounterVec};
+
+use super::{reset::ResetMetric, MetricLockGuard, MetricType};
+
+/// The internal state that is used by [`SampledCounter`] and [`SampledCounterVec`]
+#[derive(Default)]
+pub struct SampledCounterState {
+    /// The number of sampled events. This must be multiplied by the [`SampleRate`] to estimate the total.
+    pub count: AtomicU64,
+}
+
+/// How many events are represented by each sampled event. Only 1 in every `rate` events are recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleRate {
+    rate: NonZeroU64,
+}
+
+impl SampleRate {
+    /// Record 1 in every `rate` events.
+    pub const fn new(rate: NonZeroU64) -> Self {
+        Self { rate }
+    }
+
+    /// Get the sample rate
+    pub const fn get(self) -> u64 {
+        self.rate.get()
+    }
+
+    /// Decide whether an event is sampled, with a chance of 1 in `rate`.
+    ///
+    /// The decision is made by a generator local to this thread, so it does not touch any shared state,
+    /// and the events of one counter do not affect which events of another counter are sampled.
+    pub fn sample(self) -> bool {
+        let rate = self.rate.get();
+        if rate == 1 {
+            return true;
+        }
+        SAMPLER
+            .try_with(|state| {
+                // xorshift64
+                let mut x = state.get();
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                state.set(x);
+                x % rate == 0
+            })
+            .unwrap_or(false)
+    }
+}
+
+thread_local! {
+    /// The state of the generator that decides which events are sampled on this thread
+    static SAMPLER: Cell<u64> = Cell::new(sampler_seed());
+}
+
+/// A distinct, non-zero seed for the generator of each thread
+fn sampler_seed() -> u64 {
+    static THREADS: AtomicU64 = AtomicU64::new(0);
+    // splitmix64 of the thread number
+    let mut z = THREADS
+        .fetch_add(1, Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) | 1
+}
+
+/// A reference to a specific sampled counter.
+pub type SampledCounterLockGuard<'a> = MetricLockGuard<'a, SampledCounterState>;
+
+impl MetricType for SampledCounterState {
+    type Metadata = SampleRate;
+}
+
+impl SampledCounterState {
+    /// Record an event. Each event is counted with a chance of 1 in `rate`.
+    pub fn inc(&self, rate: SampleRate) {
+        if rate.sample() {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Estimate the total number of events seen by this counter
+    pub fn estimate(&self, rate: SampleRate) -> f64 {
+        self.count.load(Ordering::Relaxed) as f64 * rate.get() as f64
+    }
+}
+
+impl SampledCounterLockGuard<'_> {
+    /// Record an event. Only 1 in every [`SampleRate`] events are actually counted.
+    pub fn inc(self) {
+        SampledCounterState::inc(&self, *self.metadata());
+    }
+}
+
+impl SampledCounter {
+    /// Record an event. Only 1 in every [`SampleRate`] events are actually counted.
+    pub fn inc(&self) {
+        self.get_metric().inc()
+    }
+}
+
+impl<L: LabelGroupSet> SampledCounterVec<L> {
+    /// Record an event, keyed by the label group. Only 1 in every [`SampleRate`] events are actually counted.
+    pub fn inc(&self, label: L::Group<'_>) {
+        // avoid encoding the labels if this event will not be sampled.
+        if self.metadata().sample() {
+            self.get_metric(self.with_labels(label))
+                .count
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ResetMetric for SampledCounterState {
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU64;
+
+    use crate::{
+        label::StaticLabelSet,
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        SampledCounter,
+    };
+
+    use super::SampleRate;
+
+    #[test]
+    fn sampled_counter() {
+        let rate = SampleRate::new(NonZeroU64::new(16).unwrap());
+        let counter = SampledCounter::with_metadata(rate);
+
+        for _ in 0..100_000 {
+            counter.inc();
+        }
+
+        // the standard deviation is about 400
+        let estimate = counter.get_metric().estimate(rate);
+        assert!((96_000.0..=104_000.0).contains(&estimate), "{estimate}");
+        assert_eq!(estimate % 16.0, 0.0);
+    }
+
+    #[test]
+    fn every_event_at_rate_1() {
+        let rate = SampleRate::new(NonZeroU64::new(1).unwrap());
+        let counter = SampledCounter::with_metadata(rate);
+
+        for _ in 0..1000 {
+            counter.inc();
+        }
+
+        let mut enc = BufferedTextEncoder::new();
+        counter
+            .collect_family_into(MetricName::from_str("events_total"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE events_total counter\nevents_total 1000.0\n"
+        );
+    }
+
+    #[test]
+    fn independent_series() {
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, singleton = "kind")]
+        enum Kind {
+            Hot,
+            Cold,
+        }
+
+        let rate = SampleRate::new(NonZeroU64::new(2).unwrap());
+        let counters = crate::SampledCounterVec::<StaticLabelSet<Kind>>::with_metadata(rate);
+
+        // strictly interleaved events are not all sampled into the same series
+        for _ in 0..10_000 {
+            counters.inc(Kind::Hot);
+            counters.inc(Kind::Cold);
+        }
+
+        for kind in [Kind::Hot, Kind::Cold] {
+            let id = counters.with_labels(kind);
+            let estimate = counters.get_metric(id).estimate(rate);
+            assert!((9_000.0..=11_000.0).contains(&estimate), "{estimate}");
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Metric vecs that are split into shards to reduce contention, and merged on collection. See [`ShardedMetric`]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crossbeam_utils::CachePadded;
+
+use crate::label::LabelGroupSet;
+
+use super::{
+    counter::CounterState,
+    group::Encoding,
+    histogram::{HistogramState, Thresholds},
+    local::MergeMetric,
+    name::MetricNameEncoder,
+    reset::Reset,
+    reset::ResetMetric,
+    MetricEncoding, MetricFamilyEncoding, MetricType, MetricVec,
+};
+
+/// A metric vec split into several shards, so that threads updating the same series do not contend on the
+/// same cache line. Each thread is assigned to one of the shards.
+///
+/// On collection, the shards are merged into a single metric vec, so the family is written once,
+/// with a single `# TYPE` line and one sample per series.
+///
+/// All the shards share a clone of the same label set, so the label set should be cheap to clone,
+/// and a dynamic label set needs to be shared, like an `Arc<lasso::ThreadedRodeo>`.
+///
+/// ```
+/// use measured::FixedCardinalityLabel;
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::counter::CounterState;
+/// use measured::metric::sharded::ShardedMetric;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "operation")]
+/// enum Operation { Read, Write }
+///
+/// let ops = ShardedMetric::<CounterState, StaticLabelSet<Operation>>::new();
+/// ops.inc(Operation::Read);
+/// ```
+pub struct ShardedMetric<M: MetricType, L: LabelGroupSet> {
+    shards: Box<[CachePadded<MetricVec<M, L>>]>,
+}
+
+/// The shard index of the current thread
+fn thread_index() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static INDEX: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    INDEX.with(|index| *index)
+}
+
+fn default_shards() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+impl<M: MetricType, L: LabelGroupSet + Default + Clone> ShardedMetric<M, L>
+where
+    M::Metadata: Default + Clone,
+{
+    /// Create a new sharded metric vec, with a shard for each CPU
+    pub fn new() -> Self {
+        Self::with_label_set_and_metadata(default_shards(), L::default(), M::Metadata::default())
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet + Default + Clone> Default for ShardedMetric<M, L>
+where
+    M::Metadata: Default + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet + Clone> ShardedMetric<M, L>
+where
+    M::Metadata: Clone,
+{
+    /// Create a new sharded metric vec with the given number of shards, label set and metric metadata
+    ///
+    /// # Panics
+    /// Panics if `shards` is 0.
+    pub fn with_label_set_and_metadata(shards: usize, label_set: L, metadata: M::Metadata) -> Self {
+        assert!(shards > 0, "a sharded metric needs at least 1 shard");
+        Self {
+            shards: (0..shards)
+                .map(|_| {
+                    CachePadded::new(MetricVec::with_label_set_and_metadata(
+                        label_set.clone(),
+                        metadata.clone(),
+                    ))
+                })
+                .collect(),
+        }
+    }
+
+    /// Merge all the shards into a single metric vec
+    pub fn merge(&self) -> MetricVec<M, L>
+    where
+        M: MergeMetric,
+    {
+        let first = &self.shards[0];
+        let merged = MetricVec::<M, L>::with_label_set_and_metadata(
+            first.label_set.clone(),
+            first.metadata.clone(),
+        );
+        for shard in self.shards.iter() {
+            shard
+                .for_each_series(|labels, metric| {
+                    merged
+                        .get_metric(merged.with_labels(labels))
+                        .merge_from(metric);
+                    Ok::<(), core::convert::Infallible>(())
+                })
+                .unwrap_or_else(|never| match never {});
+        }
+        merged
+    }
+}
+
+impl<M: MetricType, L: LabelGroupSet> ShardedMetric<M, L> {
+    /// The shard for the current thread
+    pub fn shard(&self) -> &MetricVec<M, L> {
+        &self.shards[thread_index() % self.shards.len()]
+    }
+
+    /// All the shards
+    pub fn shards(&self) -> impl Iterator<Item = &MetricVec<M, L>> {
+        self.shards.iter().map(|shard| &**shard)
+    }
+}
+
+impl<L: LabelGroupSet> ShardedMetric<CounterState, L> {
+    /// Increment the counter value by 1, keyed by the label group
+    pub fn inc(&self, label: L::Group<'_>) {
+        self.shard().inc(label);
+    }
+
+    /// Increment the counter value by `y`, keyed by the label group
+    pub fn inc_by(&self, label: L::Group<'_>, y: u64) {
+        self.shard().inc_by(label, y);
+    }
+}
+
+impl<L: LabelGroupSet + Default + Clone, const N: usize> ShardedMetric<HistogramState<N>, L> {
+    /// Create a new sharded histogram vec with the given thresholds, with a shard for each CPU
+    pub fn with_metadata(thresholds: Thresholds<N>) -> Self {
+        Self::with_label_set_and_metadata(default_shards(), L::default(), thresholds)
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> ShardedMetric<HistogramState<N>, L> {
+    /// Add a single observation to the histogram, keyed by the label group
+    pub fn observe(&self, label: L::Group<'_>, y: f64) {
+        self.shard().observe(label, y);
+    }
+}
+
+impl<M, L, T> MetricFamilyEncoding<T> for ShardedMetric<M, L>
+where
+    M: MetricEncoding<T> + MergeMetric,
+    M::Metadata: Clone,
+    L: LabelGroupSet + Clone,
+    T: Encoding,
+{
+    fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
+        self.merge().collect_family_into(name, enc)
+    }
+}
+
+impl<M: ResetMetric, L: LabelGroupSet> Reset for ShardedMetric<M, L> {
+    fn reset(&self) {
+        for shard in self.shards.iter() {
+            shard.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{
+            counter::CounterState,
+            histogram::{HistogramState, Thresholds},
+            name::MetricName,
+            MetricFamilyEncoding,
+        },
+        text::BufferedTextEncoder,
+        FixedCardinalityLabel,
+    };
+
+    use super::ShardedMetric;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "operation")]
+    enum Operation {
+        Read,
+        Write,
+    }
+
+    #[test]
+    fn merged_into_one_family() {
+        let ops =
+            ShardedMetric::<CounterState, StaticLabelSet<Operation>>::with_label_set_and_metadata(
+                4,
+                StaticLabelSet::new(),
+                Default::default(),
+            );
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        ops.inc(Operation::Read);
+                    }
+                    ops.inc_by(Operation::Write, 2);
+                });
+            }
+        });
+        // the updates were spread over the shards
+        assert!(ops.shards().filter(|s| s.get_cardinality().0 > 0).count() > 1);
+
+        let mut enc = BufferedTextEncoder::new();
+        ops.collect_family_into(MetricName::from_str("ops"), &mut enc)
+            .unwrap();
+        let output = enc.finish();
+        assert_eq!(
+            output,
+            "# TYPE ops counter\n\
+            ops{operation=\"read\"} 800\n\
+            ops{operation=\"write\"} 16\n"
+        );
+    }
+
+    #[test]
+    fn merged_histogram() {
+        let latency = ShardedMetric::<HistogramState<2>, StaticLabelSet<Operation>>::with_label_set_and_metadata(
+            2,
+            StaticLabelSet::new(),
+            Thresholds::with_buckets([1.0, 2.0]),
+        );
+        latency
+            .shards()
+            .next()
+            .unwrap()
+            .observe(Operation::Read, 0.5);
+        latency
+            .shards()
+            .nth(1)
+            .unwrap()
+            .observe(Operation::Read, 1.5);
+        latency
+            .shards()
+            .nth(1)
+            .unwrap()
+            .observe(Operation::Read, 4.0);
+
+        let mut enc = BufferedTextEncoder::new();
+        latency
+            .collect_family_into(MetricName::from_str("latency"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE latency histogram\n\
+            latency_bucket{operation=\"read\",le=\"1.0\"} 1\n\
+            latency_bucket{operation=\"read\",le=\"2.0\"} 2\n\
+            latency_bucket{operation=\"read\",le=\"+Inf\"} 3\n\
+            latency_sum{operation=\"read\"} 6.0\n\
+            latency_count{operation=\"read\"} 3\n"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Latency histograms that also count SLO violations. See [`SloHistogram`]
+
+use std::{io::Write, time::Duration};
+
+use crate::{atomic::MemoryOrdering, label::LabelGroupSet};
+
+use super::{
+    counter::CounterState,
+    group::Encoding,
+    histogram::{HistogramState, Thresholds},
+    name::{MetricNameEncoder, Suffix},
+    reset::Reset,
+    reset::ResetMetric,
+    MetricEncoding, MetricFamilyEncoding, MetricType, MetricVec,
+};
+
+/// The state of a single series of a [`SloHistogram`]
+pub struct SloState<const N: usize> {
+    /// The observed latencies
+    pub latency: HistogramState<N>,
+    /// The number of observations that were slower than the SLO threshold
+    pub violations: CounterState,
+}
+
+impl<const N: usize> Default for SloState<N> {
+    fn default() -> Self {
+        Self {
+            latency: HistogramState::default(),
+            violations: CounterState::default(),
+        }
+    }
+}
+
+/// The metadata of a [`SloHistogram`]
+pub struct SloThresholds<const N: usize> {
+    /// The histogram buckets
+    pub buckets: Thresholds<N>,
+    /// Observations greater than this are counted as SLO violations
+    pub slo: f64,
+}
+
+impl<const N: usize> MetricType for SloState<N> {
+    type Metadata = SloThresholds<N>;
+}
+
+/// `_slo_violations_total`. The [`Suffix`] of the violations counter of a [`SloHistogram`]
+pub struct SloViolationsTotal;
+
+impl Suffix for SloViolationsTotal {
+    fn encode_text(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(b"_slo_violations_total")
+    }
+    fn encode_len(&self) -> usize {
+        21
+    }
+}
+
+/// A latency histogram which also counts the observations that were slower than an SLO threshold.
+///
+/// Both are backed by a single metric vec, so the labels are only encoded once per [`observe`](Self::observe).
+/// The family is encoded as a histogram with the given name, followed by a counter with the
+/// [`SloViolationsTotal`] suffix, which can be used to compute the SLO burn rate.
+///
+/// ```
+/// use measured::{FixedCardinalityLabel, MetricGroup};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::histogram::Thresholds;
+/// use measured::metric::slo::SloHistogram;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "endpoint")]
+/// enum Endpoint { Search, Checkout }
+///
+/// #[derive(MetricGroup)]
+/// struct Http {
+///     /// request latency, with the requests slower than 250ms counted in `request_duration_seconds_slo_violations_total`
+///     request_duration_seconds: SloHistogram<StaticLabelSet<Endpoint>, 8>,
+/// }
+///
+/// let http = Http {
+///     request_duration_seconds: SloHistogram::new(Thresholds::exponential_buckets(0.01, 2.0), 0.25),
+/// };
+/// http.request_duration_seconds.observe(Endpoint::Search, 0.3);
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// http.collect_group_into(&mut enc).unwrap();
+/// ```
+pub struct SloHistogram<L: LabelGroupSet, const N: usize> {
+    vec: MetricVec<SloState<N>, L>,
+}
+
+impl<L: LabelGroupSet + Default, const N: usize> SloHistogram<L, N> {
+    /// Create the histogram, counting observations greater than `slo` as violations
+    pub fn new(buckets: Thresholds<N>, slo: f64) -> Self {
+        Self::with_label_set(L::default(), buckets, slo)
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> SloHistogram<L, N> {
+    /// Create the histogram with the given label set, counting observations greater than `slo` as violations
+    pub fn with_label_set(label_set: L, buckets: Thresholds<N>, slo: f64) -> Self {
+        Self {
+            vec: MetricVec::with_label_set_and_metadata(label_set, SloThresholds { buckets, slo }),
+        }
+    }
+
+    /// View the underlying metric vec
+    pub fn metric_vec(&self) -> &MetricVec<SloState<N>, L> {
+        &self.vec
+    }
+
+    /// Observe the latency `x`, keyed by the label group, and count a violation if it exceeds the SLO threshold
+    pub fn observe(&self, label: L::Group<'_>, x: f64) {
+        let metric = self.vec.get_metric(self.vec.with_labels(label));
+        let SloThresholds { buckets, slo } = metric.metadata();
+
+        metric
+            .latency
+            .inner
+            .read()
+            .observe(buckets.bucket(x), buckets.clamp(x));
+        if x > *slo {
+            metric.violations.inc();
+        }
+    }
+
+    /// Observe the duration in seconds, keyed by the label group
+    pub fn observe_duration(&self, label: L::Group<'_>, duration: Duration) {
+        self.observe(label, duration.as_secs_f64());
+    }
+}
+
+impl<L, const N: usize, Enc> MetricFamilyEncoding<Enc> for SloHistogram<L, N>
+where
+    L: LabelGroupSet,
+    Enc: Encoding,
+    CounterState: MetricEncoding<Enc>,
+    HistogramState<N>: MetricEncoding<Enc> + MetricType<Metadata = Thresholds<N>>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        let buckets = &self.vec.metadata().buckets;
+        HistogramState::<N>::write_type(&name, enc)?;
+        self.vec.for_each_series(|labels, state| {
+            state.latency.collect_into(buckets, labels, &name, enc)
+        })?;
+
+        let violations = name.by_ref().with_suffix(SloViolationsTotal);
+        CounterState::write_type(&violations, enc)?;
+        self.vec.for_each_series(|labels, state| {
+            state
+                .violations
+                .collect_into(&MemoryOrdering::Relaxed, labels, &violations, enc)
+        })
+    }
+}
+
+impl<const N: usize> ResetMetric for SloState<N> {
+    fn reset(&self) {
+        self.latency.reset();
+        self.violations.reset();
+    }
+}
+
+impl<L: LabelGroupSet, const N: usize> Reset for SloHistogram<L, N> {
+    fn reset(&self) {
+        self.vec.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use crate::{
+        label::StaticLabelSet,
+        metric::{histogram::Thresholds, name::MetricName, MetricFamilyEncoding},
+        text::{parse_text_exposition, BufferedTextEncoder},
+        FixedCardinalityLabel,
+    };
+
+    use super::SloHistogram;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "endpoint")]
+    enum Endpoint {
+        Search,
+        Checkout,
+    }
+
+    #[test]
+    fn counts_violations() {
+        let latency = SloHistogram::<StaticLabelSet<Endpoint>, 4>::new(
+            Thresholds::with_buckets([0.1, 0.25, 0.5, 1.0]),
+            0.25,
+        );
+
+        // observations equal to the threshold are within the SLO
+        for x in [0.05, 0.25, 0.3, 0.7, 2.0] {
+            latency.observe(Endpoint::Search, x);
+        }
+        latency.observe(Endpoint::Checkout, 0.1);
+
+        let vec = latency.metric_vec();
+        let search = vec.get_metric(vec.with_labels(Endpoint::Search));
+        assert_eq!(search.violations.count.load(Ordering::Relaxed), 3);
+        assert_eq!(search.latency.inner.read().load_count(Ordering::Relaxed), 5);
+
+        let mut enc = BufferedTextEncoder::new();
+        latency
+            .collect_family_into(MetricName::from_str("request_duration_seconds"), &mut enc)
+            .unwrap();
+        let families = parse_text_exposition(&enc.finish()).unwrap();
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].name, "request_duration_seconds");
+
+        let violations = &families[1];
+        assert_eq!(
+            violations.name,
+            "request_duration_seconds_slo_violations_total"
+        );
+        let count = |endpoint| {
+            violations
+                .samples
+                .iter()
+                .find(|s| s.label("endpoint") == Some(endpoint))
+                .unwrap()
+                .value
+        };
+        assert_eq!(count("search"), 3.0);
+        assert_eq!(count("checkout"), 0.0);
+    }
+}
@@ -4,10 +4,14 @@ use hashbrown::HashTable;
 use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::{
     hash::{BuildHasher, BuildHasherDefault},
-    sync::OnceLock,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
-use super::{LabelIdInner, MetricType};
+use super::{LabelIdInner, MetricLockGuardRepr, MetricType};
 
 pub(super) struct ShardedMap<K, V> {
     // FxHasher performed the fastest in all my benchmarks.
@@ -17,9 +21,205 @@ pub(super) struct ShardedMap<K, V> {
     // hasher: BuildHasherDefault<twox_hash::Xxh3Hash64>,
     // hasher: BuildHasherDefault<ahash::AHasher>,
     // hasher: std::hash::RandomState,
-    #[allow(clippy::type_complexity)]
-    pub(super) shards: Box<[CachePadded<RwLock<HashTable<(K, V)>>>]>,
+    pub(super) shards: Shards<K, V>,
     shift: u32,
+    #[cfg(feature = "log")]
+    pub(super) warning: Option<CardinalityWarning>,
+    pub(super) limit: Option<SeriesLimit<K, V>>,
+}
+
+type Shard<K, V, S> = CachePadded<RwLock<HashTable<(K, V, S)>>>;
+
+pub(super) enum Shards<K, V> {
+    Untracked(Box<[Shard<K, V, ()>]>),
+    /// Each entry also holds the time it was last accessed, see [`AccessClock`]
+    Tracked(Box<[Shard<K, V, AtomicU32>]>, AccessClock),
+}
+
+/// The time an entry was last accessed, which is only stored if accesses are tracked
+pub(super) trait AccessStamp {
+    fn new(now: u32) -> Self;
+    fn touch(&self, now: u32);
+    fn last_access(&mut self) -> u32;
+}
+
+impl AccessStamp for () {
+    fn new(_now: u32) -> Self {}
+    #[inline]
+    fn touch(&self, _now: u32) {}
+    fn last_access(&mut self) -> u32 {
+        0
+    }
+}
+
+impl AccessStamp for AtomicU32 {
+    fn new(now: u32) -> Self {
+        AtomicU32::new(now)
+    }
+
+    #[inline]
+    fn touch(&self, now: u32) {
+        // avoid writing to the cache line if the second has not changed.
+        if self.load(Ordering::Relaxed) != now {
+            self.store(now, Ordering::Relaxed);
+        }
+    }
+
+    fn last_access(&mut self) -> u32 {
+        *self.get_mut()
+    }
+}
+
+/// Records when series are accessed, in whole seconds since the clock was created.
+///
+/// Accesses only load the shared `now` counter. The counter is refreshed from the system clock when a new series
+/// is created, when the vec is collected, and before idle series are expired, and changes at most once per second.
+/// Between refreshes, accesses are recorded at the time of the last refresh.
+pub(super) struct AccessClock {
+    pub(super) start: Instant,
+    now: AtomicU32,
+}
+
+impl AccessClock {
+    pub(super) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            now: AtomicU32::new(0),
+        }
+    }
+
+    #[inline]
+    fn now(&self) -> u32 {
+        self.now.load(Ordering::Relaxed)
+    }
+
+    /// Advance the shared counter to the current time
+    pub(super) fn refresh(&self) -> u32 {
+        let now = self
+            .start
+            .elapsed()
+            .as_secs()
+            .try_into()
+            .unwrap_or(u32::MAX);
+        if self.now.load(Ordering::Relaxed) < now {
+            self.now.fetch_max(now, Ordering::Relaxed);
+        }
+        now
+    }
+}
+
+/// Caps the number of active series.
+pub(super) struct SeriesLimit<K, M> {
+    max: usize,
+    len: AtomicUsize,
+    dropped: AtomicU64,
+    /// Absorbs all updates to series that were dropped.
+    pub(super) overflow: M,
+    /// The key that the overflow series is encoded with. Without a key, the overflow series is never encoded.
+    pub(super) overflow_key: Option<K>,
+    /// Whether the overflow series was accessed
+    overflow_used: AtomicBool,
+}
+
+impl<K, M: Default> SeriesLimit<K, M> {
+    pub(super) fn new(max: usize, overflow_key: Option<K>) -> Self {
+        Self {
+            max,
+            len: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            overflow: M::default(),
+            overflow_key,
+            overflow_used: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<K: PartialEq, M> SeriesLimit<K, M> {
+    /// Whether the key is the key of the overflow series, which is never inserted into the map
+    fn is_overflow(&self, key: &K) -> bool {
+        self.overflow_key.as_ref() == Some(key)
+    }
+
+    /// The overflow series, for an update that could not get its own series
+    fn overflow(&self) -> &M {
+        self.overflow_used.store(true, Ordering::Relaxed);
+        &self.overflow
+    }
+
+    /// Whether the limit is reached. If it is, the update is counted as dropped.
+    fn is_full(&self) -> bool {
+        let full = self.len.load(Ordering::Relaxed) >= self.max;
+        if full {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        full
+    }
+
+    /// Reserve space for a new series. Returns false, and counts the series as dropped, if the limit is reached.
+    fn try_insert(&self) -> bool {
+        let inserted = self
+            .len
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+                (len < self.max).then_some(len + 1)
+            })
+            .is_ok();
+        if !inserted {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        inserted
+    }
+
+    fn removed(&self, n: usize) {
+        self.len.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    pub(super) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Tracks the number of active series, to warn when it grows too large.
+#[cfg(feature = "log")]
+pub(super) struct CardinalityWarning {
+    name: &'static str,
+    threshold: std::num::NonZeroUsize,
+    len: std::sync::atomic::AtomicUsize,
+    max: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(feature = "log")]
+impl CardinalityWarning {
+    pub(super) fn new(name: &'static str, threshold: std::num::NonZeroUsize) -> Self {
+        Self {
+            name,
+            threshold,
+            len: Default::default(),
+            max: Default::default(),
+        }
+    }
+
+    /// Record a new series. Returns true if a warning was logged.
+    pub(super) fn inserted(&self) -> bool {
+        use std::sync::atomic::Ordering;
+
+        let len = self.len.fetch_add(1, Ordering::Relaxed) + 1;
+        // only warn the first time each multiple of the threshold is reached.
+        if !len.is_multiple_of(self.threshold.get())
+            || self.max.fetch_max(len, Ordering::Relaxed) >= len
+        {
+            return false;
+        }
+        log::warn!(
+            "metric {} has reached {len} active series (warning threshold {})",
+            self.name,
+            self.threshold
+        );
+        true
+    }
+
+    pub(super) fn removed(&self, n: usize) {
+        self.len.fetch_sub(n, std::sync::atomic::Ordering::Relaxed);
+    }
 }
 
 // taken from dashmap
@@ -32,86 +232,320 @@ fn default_shard_amount() -> usize {
 
 pub(super) type SparseLockGuard<'a, M> = MappedRwLockReadGuard<'a, M>;
 
+fn new_shards<K, V, S>(shards: usize) -> Box<[Shard<K, V, S>]> {
+    let mut vec = Vec::with_capacity(shards);
+    vec.resize_with(shards, || CachePadded::new(RwLock::new(HashTable::new())));
+    vec.into_boxed_slice()
+}
+
 impl<M: MetricType, U: Hash + Eq> ShardedMap<U, M> {
     pub(super) fn new() -> Self {
         let shards = default_shard_amount();
-        let mut vec = Vec::with_capacity(shards);
-        vec.resize_with(shards, || CachePadded::new(RwLock::new(HashTable::new())));
         ShardedMap {
             hasher: Default::default(),
-            shards: vec.into_boxed_slice(),
+            shards: Shards::Untracked(new_shards(shards)),
             shift: (std::mem::size_of::<usize>() * 8) as u32 - shards.trailing_zeros(),
+            #[cfg(feature = "log")]
+            warning: None,
+            limit: None,
         }
     }
+
+    /// Start recording when each entry is accessed. Existing entries count as accessed now.
+    pub(super) fn track_accesses(&mut self) {
+        let Shards::Untracked(untracked) = &mut self.shards else {
+            return;
+        };
+        let clock = AccessClock::new();
+        let mut tracked = new_shards(untracked.len());
+        for (from, to) in untracked.iter_mut().zip(tracked.iter_mut()) {
+            let to = to.get_mut();
+            for (k, v, ()) in from.get_mut().drain() {
+                to.insert_unique(
+                    self.hasher.hash_one(&k),
+                    (k, v, AtomicU32::new(0)),
+                    |(k, ..)| self.hasher.hash_one(k),
+                );
+            }
+        }
+        self.shards = Shards::Tracked(tracked, clock);
+    }
 }
 
 impl<M: MetricType, U: Hash + Eq + Copy> ShardedMap<U, M> {
-    pub(super) fn get_metric(&self, id: LabelIdInner<U>) -> SparseLockGuard<'_, M> {
-        let shard = &self.shards[((id.hash as usize) << 7) >> self.shift];
+    pub(super) fn get_metric(&self, id: LabelIdInner<U>) -> MetricLockGuardRepr<'_, M> {
+        match &self.shards {
+            Shards::Untracked(shards) => self.get_metric_in(shards, None, id),
+            Shards::Tracked(shards, clock) => self.get_metric_in(shards, Some(clock), id),
+        }
+    }
+
+    fn get_metric_in<'a, S: AccessStamp>(
+        &'a self,
+        shards: &'a [Shard<U, M, S>],
+        clock: Option<&AccessClock>,
+        id: LabelIdInner<U>,
+    ) -> MetricLockGuardRepr<'a, M> {
+        let shard = &shards[((id.hash as usize) << 7) >> self.shift];
+        let now = clock.map_or(0, AccessClock::now);
 
         {
             let mapped = RwLockReadGuard::try_map(shard.read(), |shard| {
-                shard.find(id.hash, |(k, _v)| *k == id.id).map(|(_, v)| v)
+                let (_, v, last_access) = shard.find(id.hash, |(k, ..)| *k == id.id)?;
+                last_access.touch(now);
+                Some(v)
             });
             if let Ok(mapped) = mapped {
-                return mapped;
+                return MetricLockGuardRepr::Sparse(mapped);
+            }
+        }
+
+        // the overflow series is never inserted, so route to it without contending on the write lock.
+        if let Some(limit) = &self.limit {
+            if limit.is_overflow(&id.id) || limit.is_full() {
+                return MetricLockGuardRepr::Dense(limit.overflow());
             }
         }
 
         let shard = {
             let mut shard = shard.write();
-            let entry = shard.find_entry(id.hash, |(k, _)| *k == id.id);
+            let entry = shard.find_entry(id.hash, |(k, ..)| *k == id.id);
             match entry {
-                Ok(_) => {}
+                Ok(entry) => entry.get().2.touch(now),
                 Err(_) => {
-                    shard.insert_unique(id.hash, (id.id, M::default()), |(k, _)| {
+                    if let Some(limit) = &self.limit {
+                        if !limit.try_insert() {
+                            return MetricLockGuardRepr::Dense(limit.overflow());
+                        }
+                    }
+                    let now = clock.map_or(0, AccessClock::refresh);
+                    shard.insert_unique(id.hash, (id.id, M::default(), S::new(now)), |(k, ..)| {
                         self.hasher.hash_one(k)
                     });
+                    #[cfg(feature = "log")]
+                    if let Some(warning) = &self.warning {
+                        warning.inserted();
+                    }
                 }
             }
             RwLockWriteGuard::downgrade(shard)
         };
 
-        RwLockReadGuard::map(shard, |shard| {
-            let (_, v) = shard.find(id.hash, |(k, _v)| *k == id.id).expect(
+        MetricLockGuardRepr::Sparse(RwLockReadGuard::map(shard, |shard| {
+            let (_, v, _) = shard.find(id.hash, |(k, ..)| *k == id.id).expect(
                 "the entry was just inserted into the map without allowing any writes inbetween",
             );
             v
-        })
+        }))
+    }
+
+    /// Look up the entry without inserting it. This does not count as an access.
+    pub(super) fn find_metric(&self, id: LabelIdInner<U>) -> Option<MetricLockGuardRepr<'_, M>> {
+        fn find<'a, U: Eq, M, S>(
+            shard: &'a Shard<U, M, S>,
+            id: LabelIdInner<U>,
+        ) -> Option<SparseLockGuard<'a, M>> {
+            RwLockReadGuard::try_map(shard.read(), |shard| {
+                let (_, v, _) = shard.find(id.hash, |(k, ..)| *k == id.id)?;
+                Some(v)
+            })
+            .ok()
+        }
+
+        if let Some(limit) = &self.limit {
+            if limit.is_overflow(&id.id) {
+                return Some(MetricLockGuardRepr::Dense(&limit.overflow));
+            }
+        }
+
+        let index = ((id.hash as usize) << 7) >> self.shift;
+        let guard = match &self.shards {
+            Shards::Untracked(shards) => find(&shards[index], id),
+            Shards::Tracked(shards, _) => find(&shards[index], id),
+        }?;
+        Some(MetricLockGuardRepr::Sparse(guard))
     }
 
     pub(super) fn remove_metric(&self, id: LabelIdInner<U>) -> Option<M> {
-        let shard = &self.shards[((id.hash as usize) << 7) >> self.shift];
+        let index = ((id.hash as usize) << 7) >> self.shift;
+        let removed = match &self.shards {
+            Shards::Untracked(shards) => remove_entry(&shards[index], id),
+            Shards::Tracked(shards, _) => remove_entry(&shards[index], id),
+        }?;
 
-        let mut shard = shard.write();
-        let entry = shard.find_entry(id.hash, |(k, _)| *k == id.id);
-        match entry {
-            Ok(x) => Some(x.remove().0 .1),
-            Err(_) => None,
+        #[cfg(feature = "log")]
+        if let Some(warning) = &self.warning {
+            warning.removed(1);
+        }
+        if let Some(limit) = &self.limit {
+            limit.removed(1);
         }
+        Some(removed)
     }
 
     pub(super) fn get_metric_mut(&mut self, id: LabelIdInner<U>) -> &mut M {
-        let shard = &mut self.shards[((id.hash as usize) << 7) >> self.shift];
-
-        let entry = shard.get_mut().find_entry(id.hash, |(k, _)| *k == id.id);
-        let (_, v) = match entry {
-            Ok(o) => o.into_mut(),
-            Err(v) => v
-                .into_table()
-                .insert_unique(id.hash, (id.id, M::default()), |(k, _)| {
-                    self.hasher.hash_one(k)
-                })
-                .into_mut(),
+        let index = ((id.hash as usize) << 7) >> self.shift;
+        let ShardedMap {
+            hasher,
+            shards,
+            #[cfg(feature = "log")]
+            warning,
+            limit,
+            ..
+        } = self;
+        let inserted = || {
+            #[cfg(feature = "log")]
+            if let Some(warning) = warning {
+                warning.inserted();
+            }
+        };
+
+        match shards {
+            Shards::Untracked(shards) => {
+                get_metric_mut_in(shards[index].get_mut(), hasher, limit, inserted, 0, id)
+            }
+            Shards::Tracked(shards, clock) => {
+                let now = clock.now();
+                get_metric_mut_in(shards[index].get_mut(), hasher, limit, inserted, now, id)
+            }
+        }
+    }
+
+    /// Remove every entry, keeping the allocated capacity of the shards
+    pub(super) fn clear(&self) {
+        match &self.shards {
+            Shards::Untracked(shards) => self.retain(shards, |_, _| false),
+            Shards::Tracked(shards, _) => self.retain(shards, |_, _| false),
+        };
+    }
+
+    /// Remove every entry that was last accessed more than `age` ago. Returns the number of removed entries.
+    pub(super) fn expire_older_than(&self, age: Duration) -> usize {
+        let Shards::Tracked(shards, clock) = &self.shards else {
+            return 0;
         };
+        let now = clock.refresh();
+        let age = age.as_secs();
+        self.retain(shards, |_, last_access| {
+            u64::from(now.saturating_sub(last_access.last_access())) <= age
+        })
+    }
+
+    /// Remove every entry whose key matches the predicate. Returns the number of removed entries.
+    pub(super) fn remove_where(&self, remove: impl Fn(&U) -> bool) -> usize {
+        match &self.shards {
+            Shards::Untracked(shards) => self.retain(shards, |key, _| !remove(key)),
+            Shards::Tracked(shards, _) => self.retain(shards, |key, _| !remove(key)),
+        }
+    }
+
+    /// Keep only the entries whose key and access stamp match the predicate. Returns the number of removed entries.
+    fn retain<S>(
+        &self,
+        shards: &[Shard<U, M, S>],
+        mut keep: impl FnMut(&U, &mut S) -> bool,
+    ) -> usize {
+        let mut total = 0;
+        for shard in shards {
+            let mut shard = shard.write();
+            let len = shard.len();
+            shard.retain(|(key, _, last_access)| keep(key, last_access));
+            let removed = len - shard.len();
+            drop(shard);
+
+            #[cfg(feature = "log")]
+            if let Some(warning) = &self.warning {
+                warning.removed(removed);
+            }
+            if let Some(limit) = &self.limit {
+                limit.removed(removed);
+            }
+            total += removed;
+        }
+        total
+    }
+
+    /// Visit every entry, not including the overflow series. This does not count as an access.
+    pub(super) fn for_each_entry<E>(
+        &self,
+        f: impl FnMut(&U, &M) -> Result<(), E>,
+    ) -> Result<(), E> {
+        fn visit<U, M, S, E>(
+            shards: &[Shard<U, M, S>],
+            mut f: impl FnMut(&U, &M) -> Result<(), E>,
+        ) -> Result<(), E> {
+            for shard in shards {
+                for (k, v, _) in shard.read().iter() {
+                    f(k, v)?;
+                }
+            }
+            Ok(())
+        }
 
-        v
+        match &self.shards {
+            Shards::Untracked(shards) => visit(shards, f),
+            Shards::Tracked(shards, clock) => {
+                // collection happens regularly, so it keeps the access clock close to the real time.
+                clock.refresh();
+                visit(shards, f)
+            }
+        }
+    }
+
+    /// The overflow series and its key, if it is encoded and was accessed
+    pub(super) fn overflow_series(&self) -> Option<(&U, &M)> {
+        let limit = self.limit.as_ref()?;
+        let key = limit.overflow_key.as_ref()?;
+        let used = limit.overflow_used.load(Ordering::Relaxed);
+        used.then_some((key, &limit.overflow))
     }
 
     pub(super) fn get_cardinality(&self) -> usize {
-        self.shards
-            .iter()
-            .map(|shard| shard.read().len())
-            .sum::<usize>()
+        fn len<U, M, S>(shards: &[Shard<U, M, S>]) -> usize {
+            shards.iter().map(|shard| shard.read().len()).sum()
+        }
+
+        let len = match &self.shards {
+            Shards::Untracked(shards) => len(shards),
+            Shards::Tracked(shards, _) => len(shards),
+        };
+        len + usize::from(self.overflow_series().is_some())
     }
 }
+
+fn remove_entry<U: Eq, M, S>(shard: &Shard<U, M, S>, id: LabelIdInner<U>) -> Option<M> {
+    let mut shard = shard.write();
+    let entry = shard.find_entry(id.hash, |(k, ..)| *k == id.id).ok()?;
+    Some(entry.remove().0 .1)
+}
+
+fn get_metric_mut_in<'a, U: Hash + Eq, M: Default, S: AccessStamp>(
+    shard: &'a mut HashTable<(U, M, S)>,
+    hasher: &BuildHasherDefault<rustc_hash::FxHasher>,
+    limit: &'a mut Option<SeriesLimit<U, M>>,
+    inserted: impl FnOnce(),
+    now: u32,
+    id: LabelIdInner<U>,
+) -> &'a mut M {
+    let entry = shard.find_entry(id.hash, |(k, ..)| *k == id.id);
+    let (_, v, last_access) = match entry {
+        Ok(o) => o.into_mut(),
+        Err(v) => {
+            if let Some(limit) = limit {
+                if limit.is_overflow(&id.id) || !limit.try_insert() {
+                    *limit.overflow_used.get_mut() = true;
+                    return &mut limit.overflow;
+                }
+            }
+            inserted();
+            v.into_table()
+                .insert_unique(id.hash, (id.id, M::default(), S::new(now)), |(k, ..)| {
+                    hasher.hash_one(k)
+                })
+                .into_mut()
+        }
+    };
+
+    last_access.touch(now);
+    v
+}
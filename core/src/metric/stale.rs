@@ -0,0 +1,207 @@
+//! Gauges that stop being exposed when they are not updated. See [`StaleGauge`]
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::Gauge;
+
+use super::{
+    gauge::GaugeState, group::Encoding, name::MetricNameEncoder, reset::Reset, MetricEncoding,
+    MetricFamilyEncoding,
+};
+
+/// The value of `last_update` before the first update
+const NEVER: u64 = 0;
+
+/// A [`Gauge`] which records when it was last set, for gauges that should be updated periodically.
+///
+/// A gauge that is no longer updated keeps exposing its last value, which hides a stuck updater.
+/// With a TTL set by [`with_ttl`](Self::with_ttl), the gauge is considered stale once it has not been set
+/// for longer than the TTL, and then no sample is collected for it. Prometheus marks a series as stale
+/// when it disappears from the scrape, so the staleness can be alerted on. A gauge with a TTL
+/// that was never set is stale too.
+///
+/// Without a TTL, the gauge is always collected, and only records the time of the last update.
+///
+/// ```
+/// use std::time::Duration;
+/// use measured::MetricGroup;
+/// use measured::metric::stale::StaleGauge;
+///
+/// #[derive(MetricGroup)]
+/// struct Replication {
+///     /// replication lag in bytes, updated every 10 seconds
+///     lag_bytes: StaleGauge,
+/// }
+///
+/// let replication = Replication {
+///     lag_bytes: StaleGauge::new().with_ttl(Duration::from_secs(60)),
+/// };
+/// replication.lag_bytes.set(1024);
+/// ```
+pub struct StaleGauge {
+    gauge: Gauge,
+    start: Instant,
+    /// one more than the nanoseconds since `start`, or [`NEVER`]
+    last_update: AtomicU64,
+    ttl: Option<Duration>,
+}
+
+impl Default for StaleGauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StaleGauge {
+    /// Create a new gauge, which has never been set and has no TTL
+    pub fn new() -> Self {
+        Self {
+            gauge: Gauge::new(),
+            start: Instant::now(),
+            last_update: AtomicU64::new(NEVER),
+            ttl: None,
+        }
+    }
+
+    /// Stop collecting the gauge once it has not been set for longer than `ttl`
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    /// The underlying gauge. Updating it directly does not record an update
+    pub fn gauge(&self) -> &Gauge {
+        &self.gauge
+    }
+
+    /// Set the gauge value to `x`, and record the time of the update
+    pub fn set(&self, x: i64) {
+        self.gauge.set(x);
+        self.set_last_update(Instant::now());
+    }
+
+    /// The time the gauge was last set, if it was ever set
+    pub fn last_update(&self) -> Option<Instant> {
+        match self.last_update.load(Ordering::Relaxed) {
+            NEVER => None,
+            nanos => Some(self.start + Duration::from_nanos(nanos - 1)),
+        }
+    }
+
+    /// Whether the gauge has not been set within its TTL. A gauge without a TTL is never stale
+    pub fn is_stale(&self) -> bool {
+        self.is_stale_at(Instant::now())
+    }
+
+    fn set_last_update(&self, now: Instant) {
+        let nanos = now.saturating_duration_since(self.start).as_nanos();
+        let nanos = u64::try_from(nanos).unwrap_or(u64::MAX - 1);
+        self.last_update.fetch_max(nanos + 1, Ordering::Relaxed);
+    }
+
+    fn is_stale_at(&self, now: Instant) -> bool {
+        let Some(ttl) = self.ttl else {
+            return false;
+        };
+        match self.last_update() {
+            None => true,
+            Some(last) => now.saturating_duration_since(last) > ttl,
+        }
+    }
+
+    fn collect_family_at<Enc: Encoding>(
+        &self,
+        now: Instant,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err>
+    where
+        GaugeState: MetricEncoding<Enc>,
+    {
+        if self.is_stale_at(now) {
+            return GaugeState::write_type(&name, enc);
+        }
+        self.gauge.collect_family_into(name, enc)
+    }
+}
+
+impl<Enc: Encoding> MetricFamilyEncoding<Enc> for StaleGauge
+where
+    GaugeState: MetricEncoding<Enc>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        self.collect_family_at(Instant::now(), name, enc)
+    }
+}
+
+impl Reset for StaleGauge {
+    /// Reset the gauge to zero, and forget the last update, as if it was never set
+    fn reset(&self) {
+        self.gauge.reset();
+        self.last_update.store(NEVER, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{metric::name::MetricName, text::BufferedTextEncoder};
+
+    use super::StaleGauge;
+
+    fn collect_at(gauge: &StaleGauge, now: Instant) -> String {
+        let mut enc = BufferedTextEncoder::new();
+        gauge
+            .collect_family_at(now, MetricName::from_str("lag"), &mut enc)
+            .unwrap();
+        String::from_utf8(enc.finish().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn stale_gauge_is_skipped() {
+        let gauge = StaleGauge::new().with_ttl(Duration::from_secs(60));
+        let start = gauge.start;
+
+        // never set
+        assert_eq!(collect_at(&gauge, start), "# TYPE lag gauge\n");
+
+        gauge.gauge().set(5);
+        gauge.set_last_update(start + Duration::from_secs(10));
+        assert_eq!(gauge.last_update(), Some(start + Duration::from_secs(10)));
+
+        let fresh = collect_at(&gauge, start + Duration::from_secs(70));
+        assert_eq!(fresh, "# TYPE lag gauge\nlag 5\n");
+
+        let stale = collect_at(&gauge, start + Duration::from_secs(71));
+        assert_eq!(stale, "# TYPE lag gauge\n");
+
+        // updating makes it fresh again
+        gauge.set(7);
+        assert!(!gauge.is_stale());
+        assert!(collect_at(&gauge, Instant::now()).ends_with("lag 7\n"));
+    }
+
+    #[test]
+    fn no_ttl() {
+        let gauge = StaleGauge::new();
+        assert_eq!(gauge.last_update(), None);
+        assert!(!gauge.is_stale_at(gauge.start + Duration::from_secs(3600)));
+        assert_eq!(
+            collect_at(&gauge, gauge.start + Duration::from_secs(3600)),
+            "# TYPE lag gauge\nlag 0\n"
+        );
+
+        gauge.set(1);
+        assert!(gauge.last_update().is_some());
+    }
+}
@@ -0,0 +1,439 @@
+//! Summaries with client-side quantiles. See [`Summary`]
+
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+
+use super::{MetricLockGuard, MetricType};
+use crate::{label::LabelGroupSet, Summary, SummaryVec};
+
+/// The number of observations that are buffered before they are merged into the estimator.
+const BUFFER_LEN: usize = 128;
+
+/// The target quantiles of a [`Summary`], and the allowed error of each.
+///
+/// The quantiles are estimated with the targeted quantiles variant of the CKMS algorithm
+/// (Cormode, Korn, Muthukrishnan and Srivastava, "Effective Computation of Biased Quantiles over Data Streams").
+/// The estimate for the quantile `q` with the error `e` has a rank within `(q - e) * n..=(q + e) * n`
+/// of the `n` observations.
+///
+/// The memory used by the estimator grows with the logarithm of the number of observations, and shrinks
+/// as the allowed errors grow. Each summary also buffers up to 128 observations before merging them.
+///
+/// ```
+/// use measured::Summary;
+/// use measured::metric::summary::Quantiles;
+///
+/// let summary = Summary::with_metadata(Quantiles::new([0.5, 0.9, 0.99], 0.005));
+/// for x in 1..=1000 {
+///     summary.observe(x as f64);
+/// }
+/// let p90 = summary.quantile(0.9).unwrap();
+/// assert!((895.0..=905.0).contains(&p90));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quantiles {
+    targets: Vec<(f64, f64)>,
+}
+
+impl Quantiles {
+    /// Target each of the quantiles with the same allowed error.
+    ///
+    /// # Panics
+    /// Panics if a quantile is not within `0.0..1.0` exclusive, or if the error is not within `0.0..1.0` exclusive.
+    pub fn new(quantiles: impl IntoIterator<Item = f64>, error: f64) -> Self {
+        Self::with_targets(quantiles.into_iter().map(|q| (q, error)))
+    }
+
+    /// Target each of the `(quantile, error)` pairs. Tail quantiles usually need a smaller error than the median.
+    ///
+    /// ```
+    /// use measured::metric::summary::Quantiles;
+    ///
+    /// let quantiles = Quantiles::with_targets([(0.5, 0.05), (0.99, 0.001)]);
+    /// assert_eq!(quantiles.get(), &[(0.5, 0.05), (0.99, 0.001)]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if a quantile is not within `0.0..1.0` exclusive, or if an error is not within `0.0..1.0` exclusive.
+    pub fn with_targets(targets: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        let targets: Vec<_> = targets.into_iter().collect();
+        for &(q, e) in &targets {
+            assert!(
+                q > 0.0 && q < 1.0,
+                "summary quantile {q} is not within 0.0..1.0"
+            );
+            assert!(
+                e > 0.0 && e < 1.0,
+                "summary error {e} for quantile {q} is not within 0.0..1.0"
+            );
+        }
+        Self { targets }
+    }
+
+    /// No quantiles. The summary only records the sum and count of the observations,
+    /// and does not keep any observations in memory.
+    pub fn none() -> Self {
+        Self {
+            targets: Vec::new(),
+        }
+    }
+
+    /// View the `(quantile, error)` pairs
+    pub fn get(&self) -> &[(f64, f64)] {
+        &self.targets
+    }
+
+    /// The largest gap in rank that is allowed around rank `r` of `n` observations
+    fn invariant(&self, r: f64, n: f64) -> f64 {
+        self.targets
+            .iter()
+            .map(|&(q, e)| {
+                if q * n <= r {
+                    2.0 * e * r / q
+                } else {
+                    2.0 * e * (n - r) / (1.0 - q)
+                }
+            })
+            .fold(f64::MAX, f64::min)
+    }
+}
+
+/// The same objectives as the default of the Go prometheus client:
+/// the 0.5 quantile within 0.05, the 0.9 quantile within 0.01, and the 0.99 quantile within 0.001.
+impl Default for Quantiles {
+    fn default() -> Self {
+        Self::with_targets([(0.5, 0.05), (0.9, 0.01), (0.99, 0.001)])
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    value: f64,
+    /// The difference between the lowest rank of this sample and that of the previous sample
+    width: f64,
+    /// The difference between the highest and lowest rank of this sample
+    delta: f64,
+}
+
+/// The inner state of a summary.
+#[derive(Default)]
+pub struct SummaryStateInner {
+    /// Observations that are not yet merged into the samples
+    buffer: Vec<f64>,
+    /// The compressed samples, sorted by value
+    samples: Vec<Sample>,
+    /// The number of observations merged into the samples
+    merged: u64,
+    count: u64,
+    sum: f64,
+}
+
+impl SummaryStateInner {
+    /// Add a single observation to the [`Summary`].
+    ///
+    /// NaN observations are counted, and added to the sum, but they are not included in the quantiles.
+    pub fn observe(&mut self, quantiles: &Quantiles, x: f64) {
+        self.count += 1;
+        self.sum += x;
+        if quantiles.targets.is_empty() || x.is_nan() {
+            return;
+        }
+        self.buffer.push(x);
+        if self.buffer.len() >= BUFFER_LEN {
+            self.flush(quantiles);
+        }
+    }
+
+    /// The total number of observations
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of all observations
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// The number of values kept in memory by the estimator, including the buffered observations.
+    pub fn retained(&self) -> usize {
+        self.samples.len() + self.buffer.len()
+    }
+
+    /// Estimate the `q` quantile of the observations.
+    ///
+    /// The estimate is only within the allowed error if `q` is one of the target [`Quantiles`].
+    /// Returns `None` if there are no observations, or no target quantiles.
+    pub fn query(&mut self, quantiles: &Quantiles, q: f64) -> Option<f64> {
+        self.flush(quantiles);
+        let (first, rest) = self.samples.split_first()?;
+
+        let n = self.merged as f64;
+        let rank = (q * n).ceil();
+        let t = rank + quantiles.invariant(rank, n) / 2.0;
+
+        let mut prev = first;
+        let mut r = 0.0;
+        for sample in rest {
+            r += prev.width;
+            if r + sample.width + sample.delta > t {
+                break;
+            }
+            prev = sample;
+        }
+        Some(prev.value)
+    }
+
+    /// Merge the buffered observations into the samples, then compress the samples.
+    fn flush(&mut self, quantiles: &Quantiles) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        self.buffer.sort_unstable_by(f64::total_cmp);
+
+        let mut n = self.merged as f64;
+        let mut r = 0.0;
+        let mut merged = Vec::with_capacity(self.samples.len() + self.buffer.len());
+        let mut samples = self.samples.drain(..).peekable();
+        for &value in &self.buffer {
+            while let Some(sample) = samples.next_if(|s| s.value <= value) {
+                r += sample.width;
+                merged.push(sample);
+            }
+            // a new minimum or maximum is known exactly
+            let delta = if merged.is_empty() || samples.peek().is_none() {
+                0.0
+            } else {
+                (quantiles.invariant(r, n).floor() - 1.0).max(0.0)
+            };
+            merged.push(Sample {
+                value,
+                width: 1.0,
+                delta,
+            });
+            n += 1.0;
+            r += 1.0;
+        }
+        merged.extend(samples);
+
+        self.buffer.clear();
+        self.merged = n as u64;
+        self.samples = merged;
+        self.compress(quantiles);
+    }
+
+    /// Merge adjacent samples while the rank error stays within the allowed error.
+    fn compress(&mut self, quantiles: &Quantiles) {
+        let n = self.merged as f64;
+        let mut samples = self.samples.drain(..).rev();
+        let Some(mut x) = samples.next() else {
+            return;
+        };
+
+        let mut kept = Vec::new();
+        let mut r = n - 1.0 - x.width;
+        for sample in samples {
+            if sample.width + x.width + x.delta <= quantiles.invariant(r, n) {
+                x.width += sample.width;
+            } else {
+                kept.push(x);
+                x = sample;
+            }
+            r -= sample.width;
+        }
+        kept.push(x);
+        kept.reverse();
+        self.samples = kept;
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.buffer.clear();
+        self.samples.clear();
+        self.merged = 0;
+        self.count = 0;
+        self.sum = 0.0;
+    }
+}
+
+/// The state of a summary. See also [`SummaryStateInner`]
+#[derive(Default)]
+pub struct SummaryState {
+    /// A rwlock over the inner summary state.
+    /// The write lock is acquired for observations and sampling, as the estimator is updated in place.
+    /// The read lock is acquired to read the count and sum.
+    pub inner: RwLock<SummaryStateInner>,
+}
+
+/// Prints the current count and sum of the summary, without waiting if it is currently being updated
+impl core::fmt::Debug for SummaryState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut d = f.debug_struct("SummaryState");
+        match self.inner.try_read() {
+            Some(inner) => d.field("count", &inner.count).field("sum", &inner.sum),
+            None => d.field("inner", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+impl MetricType for SummaryState {
+    type Metadata = Quantiles;
+}
+
+/// A shared ref to an individual summary
+pub type SummaryLockGuard<'a> = MetricLockGuard<'a, SummaryState>;
+
+impl SummaryLockGuard<'_> {
+    /// Add a single observation to the [`Summary`].
+    pub fn observe(self, x: f64) {
+        self.inner.write().observe(self.metadata(), x);
+    }
+
+    /// Observe the duration in seconds
+    pub fn observe_duration(self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    /// Observe the duration in seconds since the given instant.
+    ///
+    /// An instant in the future is observed as a zero duration.
+    pub fn observe_duration_since(self, since: Instant) -> Duration {
+        let d = Instant::now().saturating_duration_since(since);
+        self.observe_duration(d);
+        d
+    }
+
+    /// Estimate the `q` quantile of the observations. See [`SummaryStateInner::query`]
+    pub fn quantile(self, q: f64) -> Option<f64> {
+        self.inner.write().query(self.metadata(), q)
+    }
+}
+
+impl Summary {
+    /// Add a single observation to the [`Summary`].
+    pub fn observe(&self, x: f64) {
+        self.get_metric().observe(x);
+    }
+
+    /// Observe the duration in seconds
+    pub fn observe_duration(&self, duration: Duration) {
+        self.get_metric().observe_duration(duration);
+    }
+
+    /// Estimate the `q` quantile of the observations. See [`SummaryStateInner::query`]
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        self.get_metric().quantile(q)
+    }
+}
+
+impl<L: LabelGroupSet> SummaryVec<L> {
+    /// Add a single observation to the [`Summary`], keyed by the label group.
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set.
+    pub fn observe(&self, label: L::Group<'_>, x: f64) {
+        self.get_metric(self.with_labels(label)).observe(x);
+    }
+
+    /// Observe the duration in seconds, keyed by the label group.
+    ///
+    /// # Panics
+    /// Panics if the label group is not contained within the label set.
+    pub fn observe_duration(&self, label: L::Group<'_>, duration: Duration) {
+        self.observe(label, duration.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
+
+    use super::Quantiles;
+    use crate::{
+        label::StaticLabelSet,
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        FixedCardinalityLabel, Summary, SummaryVec,
+    };
+
+    #[test]
+    fn quantiles_within_error() {
+        let targets = [(0.5, 0.05), (0.9, 0.01), (0.99, 0.001)];
+        let summary = Summary::with_metadata(Quantiles::with_targets(targets));
+
+        let n = 100_000;
+        let mut values: Vec<u32> = (1..=n).collect();
+        values.shuffle(&mut SmallRng::seed_from_u64(0));
+        for x in values {
+            summary.observe(x as f64);
+        }
+
+        for (q, e) in targets {
+            let estimate = summary.quantile(q).unwrap();
+            let rank = estimate / n as f64;
+            assert!(
+                (q - e..=q + e).contains(&rank),
+                "quantile {q} estimated at rank {rank}"
+            );
+        }
+
+        // the estimator keeps far fewer values than it has observed
+        let retained = summary.get_metric().inner.read().retained();
+        assert!(retained < 2_000, "retained {retained} samples");
+    }
+
+    #[test]
+    fn empty() {
+        let summary = Summary::new();
+        assert_eq!(summary.quantile(0.5), None);
+
+        let summary = Summary::with_metadata(Quantiles::none());
+        summary.observe(2.0);
+        summary.observe(3.0);
+        assert_eq!(summary.quantile(0.5), None);
+        let metric = summary.get_metric();
+        let inner = metric.inner.read();
+        assert_eq!((inner.count(), inner.sum(), inner.retained()), (2, 5.0, 0));
+    }
+
+    #[test]
+    #[should_panic = "summary quantile 1 is not within 0.0..1.0"]
+    fn invalid_quantile() {
+        Quantiles::new([0.5, 1.0], 0.01);
+    }
+
+    #[test]
+    fn text_encoding() {
+        #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "route")]
+        enum Route {
+            Home,
+            Users,
+        }
+
+        let summaries =
+            SummaryVec::<StaticLabelSet<Route>>::with_metadata(Quantiles::new([0.5, 0.9], 0.01));
+        for x in [1.0, 2.0, 3.0, 4.0] {
+            summaries.observe(Route::Home, x);
+        }
+
+        let mut enc = BufferedTextEncoder::new();
+        summaries.get_metric(summaries.with_labels(Route::Users));
+        summaries
+            .collect_family_into(MetricName::from_str("rpc_seconds"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            r#"# TYPE rpc_seconds summary
+rpc_seconds{route="home",quantile="0.5"} 2.0
+rpc_seconds{route="home",quantile="0.9"} 4.0
+rpc_seconds_sum{route="home"} 10.0
+rpc_seconds_count{route="home"} 4
+rpc_seconds{route="users",quantile="0.5"} NaN
+rpc_seconds{route="users",quantile="0.9"} NaN
+rpc_seconds_sum{route="users"} 0.0
+rpc_seconds_count{route="users"} 0
+"#
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Metrics with no type semantics. See [`Unknown`]
+
+use crate::{label::LabelGroupSet, Unknown, UnknownVec};
+
+use super::{gauge::AtomicF64, reset::ResetMetric, MetricLockGuard, MetricMut, MetricType};
+
+#[derive(Default)]
+/// The internal state that is used by [`Unknown`] and [`UnknownVec`]
+pub struct UnknownState {
+    /// The current value, which is reported as-is
+    pub value: AtomicF64,
+}
+
+impl UnknownState {
+    /// Create the state with the given value
+    pub fn new(value: f64) -> Self {
+        Self {
+            value: AtomicF64::new(value),
+        }
+    }
+}
+
+/// A reference to a specific unknown metric.
+pub type UnknownLockGuard<'a> = MetricLockGuard<'a, UnknownState>;
+
+/// A mut reference to a specific unknown metric.
+pub type UnknownMut<'a> = MetricMut<'a, UnknownState>;
+
+impl Unknown {
+    /// Set the value to `x`
+    pub fn set(&self, x: f64) {
+        self.get_metric().set(x)
+    }
+}
+
+impl UnknownLockGuard<'_> {
+    /// Set the value to `x`
+    pub fn set(self, x: f64) {
+        self.value.set(x);
+    }
+}
+
+impl UnknownMut<'_> {
+    /// Set the value to `x`
+    pub fn set(mut self, x: f64) {
+        self.value.set_mut(x);
+    }
+}
+
+impl<L: LabelGroupSet> UnknownVec<L> {
+    /// Set the value to `y`, keyed by the label group
+    pub fn set(&self, label: L::Group<'_>, y: f64) {
+        self.get_metric(self.with_labels(label)).set(y);
+    }
+}
+
+impl MetricType for UnknownState {
+    /// [`Unknown`]s require no additional metadata
+    type Metadata = ();
+}
+
+impl ResetMetric for UnknownState {
+    fn reset(&self) {
+        self.value.set(0.0);
+    }
+}
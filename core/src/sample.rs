@@ -0,0 +1,503 @@
+//! Visit metric samples with a closure, for custom exporters. See [`for_each_sample`]
+
+use std::convert::Infallible;
+
+use crate::{
+    atomic::MemoryOrdering,
+    label::{LabelGroup, LabelGroupVisitor, LabelName, LabelPair, LabelValue, LabelVisitor},
+    metric::{
+        counter::CounterState,
+        gauge::{FloatGaugeState, GaugeState},
+        group::{Encoding, MetricGroup, MetricValue},
+        histogram::{adaptive::AdaptiveHistogramState, HistogramState, Thresholds},
+        name::{Bucket, Count, MetricNameEncoder, Sum},
+        sampled::{SampleRate, SampledCounterState},
+        unknown::UnknownState,
+        MetricEncoding,
+    },
+    text::MetricType,
+};
+
+/// A single sample of a metric family
+#[derive(Clone, Copy, Debug)]
+pub struct Sample<'a> {
+    /// The name of the sample, including any suffix like `_bucket`
+    pub name: &'a str,
+    /// The type of the metric family this sample belongs to
+    pub metric_type: MetricType,
+    /// The value of the sample
+    pub value: MetricValue,
+    labels: &'a [(String, String)],
+}
+
+impl<'a> Sample<'a> {
+    /// The label pairs of this sample, as `(name, value)`
+    pub fn labels(&self) -> impl ExactSizeIterator<Item = (&'a str, &'a str)> {
+        self.labels.iter().map(|(k, v)| (&**k, &**v))
+    }
+
+    /// Find the value of the label with the given name
+    pub fn label(&self, name: &str) -> Option<&'a str> {
+        self.labels().find(|(k, _)| *k == name).map(|(_, v)| v)
+    }
+}
+
+/// Collect every sample in the group, calling `f` with each one.
+///
+/// This is a simpler alternative to implementing an encoder, for exporting metrics to a custom backend.
+/// Histograms produce a sample for every bucket, with an `le` label, followed by the `_sum` and `_count` samples,
+/// like in the prometheus text format.
+///
+/// ```
+/// use measured::{Counter, Histogram, MetricGroup};
+/// use measured::metric::histogram::Thresholds;
+/// use measured::sample::for_each_sample;
+///
+/// #[derive(MetricGroup)]
+/// struct Jobs {
+///     jobs_total: Counter,
+///     job_duration_seconds: Histogram<2>,
+/// }
+///
+/// let jobs = Jobs {
+///     jobs_total: Counter::new(),
+///     job_duration_seconds: Histogram::with_metadata(Thresholds::with_buckets([1.0, 10.0])),
+/// };
+/// jobs.jobs_total.inc();
+/// jobs.job_duration_seconds.observe(2.5);
+///
+/// let mut names = vec![];
+/// for_each_sample(&jobs, |sample| names.push(sample.name.to_owned()));
+/// assert_eq!(names, [
+///     "jobs_total",
+///     "job_duration_seconds_bucket",
+///     "job_duration_seconds_bucket",
+///     "job_duration_seconds_bucket",
+///     "job_duration_seconds_sum",
+///     "job_duration_seconds_count",
+/// ]);
+/// ```
+pub fn for_each_sample<G, F>(group: &G, f: F)
+where
+    G: MetricGroup<SampleEncoder<F>> + ?Sized,
+    F: FnMut(Sample<'_>),
+{
+    let mut enc = SampleEncoder::new(f);
+    match group.collect_group_into(&mut enc) {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+}
+
+/// An encoder which passes each [`Sample`] to a closure. See [`for_each_sample`]
+pub struct SampleEncoder<F> {
+    f: F,
+    metric_type: MetricType,
+    name: Vec<u8>,
+    labels: Vec<(String, String)>,
+}
+
+impl<F: FnMut(Sample<'_>)> SampleEncoder<F> {
+    /// Create a new sample encoder
+    pub fn new(f: F) -> Self {
+        Self {
+            f,
+            metric_type: MetricType::Untyped,
+            name: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    /// Set the metric type of the following samples
+    pub fn write_type(&mut self, metric_type: MetricType) {
+        self.metric_type = metric_type;
+    }
+
+    /// Pass a single sample to the closure
+    pub fn write_sample(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        value: MetricValue,
+    ) {
+        self.name.clear();
+        name.encode_utf8(&mut self.name)
+            .expect("writing to a vec should not fail");
+
+        self.labels.clear();
+        labels.visit_values(&mut LabelPairs(&mut self.labels));
+
+        (self.f)(Sample {
+            name: std::str::from_utf8(&self.name).expect("metric names should be valid utf8"),
+            metric_type: self.metric_type,
+            value,
+            labels: &self.labels,
+        });
+    }
+
+    fn write_histogram(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        le: &[f64],
+        buckets: &[u64],
+        inf: u64,
+        sum: f64,
+    ) {
+        const LE: &LabelName = LabelName::from_str("le");
+
+        let mut val = 0;
+        for (&le, &bucket) in le.iter().zip(buckets) {
+            val += bucket;
+            self.write_sample(
+                name.by_ref().with_suffix(Bucket),
+                labels.by_ref().compose_with(LabelPair(LE, le)),
+                MetricValue::Int(val as i64),
+            );
+        }
+        let count = val + inf;
+        self.write_sample(
+            name.by_ref().with_suffix(Bucket),
+            labels.by_ref().compose_with(LabelPair(LE, f64::INFINITY)),
+            MetricValue::Int(count as i64),
+        );
+        self.write_sample(
+            name.by_ref().with_suffix(Sum),
+            labels.by_ref(),
+            MetricValue::Float(sum),
+        );
+        self.write_sample(
+            name.by_ref().with_suffix(Count),
+            labels,
+            MetricValue::Int(count as i64),
+        );
+    }
+}
+
+impl<F> Encoding for SampleEncoder<F> {
+    type Err = Infallible;
+
+    fn write_help(&mut self, _name: impl MetricNameEncoder, _help: &str) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+pub(crate) struct LabelPairs<'a>(pub(crate) &'a mut Vec<(String, String)>);
+
+impl LabelGroupVisitor for LabelPairs<'_> {
+    type Output = ();
+
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        let mut value = String::new();
+        x.visit(LabelString(&mut value));
+        self.0.push((name.as_str().to_owned(), value));
+    }
+}
+
+pub(crate) struct LabelString<'a>(pub(crate) &'a mut String);
+
+impl LabelVisitor for LabelString<'_> {
+    type Output = ();
+
+    fn write_int(self, x: i64) {
+        self.write_str(itoa::Buffer::new().format(x));
+    }
+
+    fn write_float(self, x: f64) {
+        self.write_str(float_label(x, &mut ryu::Buffer::new()));
+    }
+
+    fn write_str(self, x: &str) {
+        self.0.push_str(x);
+    }
+}
+
+fn float_label(x: f64, buf: &mut ryu::Buffer) -> &str {
+    if x.is_infinite() {
+        if x.is_sign_positive() {
+            "+Inf"
+        } else {
+            "-Inf"
+        }
+    } else if x.is_nan() {
+        "NaN"
+    } else {
+        buf.format(x)
+    }
+}
+
+/// Writes the label names and values of a label group into byte buffers,
+/// each followed by `0xff`, which never appears in UTF-8.
+pub(crate) struct LabelBytes<'a> {
+    pub(crate) names: &'a mut Vec<u8>,
+    pub(crate) values: &'a mut Vec<u8>,
+}
+
+impl LabelGroupVisitor for LabelBytes<'_> {
+    type Output = ();
+
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        self.names.extend_from_slice(name.as_str().as_bytes());
+        self.names.push(0xff);
+        x.visit(ValueBytes(self.values));
+        self.values.push(0xff);
+    }
+}
+
+struct ValueBytes<'a>(&'a mut Vec<u8>);
+
+impl LabelVisitor for ValueBytes<'_> {
+    type Output = ();
+
+    fn write_int(self, x: i64) {
+        self.write_str(itoa::Buffer::new().format(x));
+    }
+
+    fn write_float(self, x: f64) {
+        self.write_str(float_label(x, &mut ryu::Buffer::new()));
+    }
+
+    fn write_str(self, x: &str) {
+        self.0.extend_from_slice(x.as_bytes());
+    }
+}
+
+/// Split the bytes written by [`LabelBytes`] back into strings
+pub(crate) fn split_label_bytes(mut bytes: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    while let Some(end) = memchr::memchr(0xff, bytes) {
+        strings.push(String::from_utf8_lossy(&bytes[..end]).into_owned());
+        bytes = &bytes[end + 1..];
+    }
+    strings
+}
+
+impl<F: FnMut(Sample<'_>)> MetricEncoding<SampleEncoder<F>> for CounterState {
+    fn write_type(
+        _name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_type(MetricType::Counter);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        let count = self.count.load(ordering.load());
+        enc.write_sample(name, labels, MetricValue::Int(count as i64));
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Sample<'_>)> MetricEncoding<SampleEncoder<F>> for SampledCounterState {
+    fn write_type(
+        _name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_type(MetricType::Counter);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        rate: &SampleRate,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_sample(name, labels, MetricValue::Float(self.estimate(*rate)));
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Sample<'_>)> MetricEncoding<SampleEncoder<F>> for GaugeState {
+    fn write_type(
+        _name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_type(MetricType::Gauge);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        let value = self.count.load(ordering.load());
+        enc.write_sample(name, labels, MetricValue::Int(value));
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Sample<'_>)> MetricEncoding<SampleEncoder<F>> for FloatGaugeState {
+    fn write_type(
+        _name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_type(MetricType::Gauge);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_sample(
+            name,
+            labels,
+            MetricValue::Float(self.count.load(ordering.load())),
+        );
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Sample<'_>)> MetricEncoding<SampleEncoder<F>> for UnknownState {
+    fn write_type(
+        _name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_type(MetricType::Unknown);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        _m: &(),
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_sample(name, labels, MetricValue::Float(self.value.get()));
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Sample<'_>), const N: usize> MetricEncoding<SampleEncoder<F>> for HistogramState<N> {
+    fn write_type(
+        _name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_type(MetricType::Histogram);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &Thresholds<N>,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        let (buckets, inf, sum) = self.inner.write().sample();
+        enc.write_histogram(name, labels, metadata.get(), &buckets, inf, sum);
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Sample<'_>), const N: usize> MetricEncoding<SampleEncoder<F>>
+    for AdaptiveHistogramState<N>
+{
+    fn write_type(
+        _name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        enc.write_type(MetricType::Histogram);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &<Self as crate::metric::MetricType>::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SampleEncoder<F>,
+    ) -> Result<(), Infallible> {
+        let (le, buckets, inf, sum) = self.inner.write().sample(metadata);
+        enc.write_histogram(name, labels, &le, &buckets, inf, sum);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{group::MetricValue, histogram::Thresholds},
+        text::MetricType,
+        CounterVec, FixedCardinalityLabel, FloatGauge, Histogram, MetricGroup,
+    };
+
+    use super::for_each_sample;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "queue")]
+    enum Queue {
+        High,
+        Low,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    #[metric(new())]
+    struct Worker {
+        /// jobs processed per queue
+        jobs: CounterVec<StaticLabelSet<Queue>>,
+        load: FloatGauge,
+        #[metric(metadata = Thresholds::with_buckets([0.5, 1.0]))]
+        job_seconds: Histogram<2>,
+    }
+
+    #[test]
+    fn visits_every_sample() {
+        let worker = Worker::new();
+        worker.jobs.inc_by(Queue::Low, 3);
+        worker.load.set(0.75);
+        worker.job_seconds.observe(0.7);
+
+        let mut samples = vec![];
+        for_each_sample(&worker, |sample| {
+            let labels: Vec<String> = sample.labels().map(|(k, v)| format!("{k}={v}")).collect();
+            let value = match sample.value {
+                MetricValue::Int(x) => x as f64,
+                MetricValue::Float(x) => x,
+            };
+            samples.push((sample.name.to_owned(), sample.metric_type, labels, value));
+        });
+
+        let s = |name: &str, metric_type, labels: &[&str], value| {
+            let labels = labels.iter().map(|l| l.to_string()).collect();
+            (name.to_owned(), metric_type, labels, value)
+        };
+        assert_eq!(
+            samples,
+            [
+                s("jobs", MetricType::Counter, &["queue=low"], 3.0),
+                s("load", MetricType::Gauge, &[], 0.75),
+                s(
+                    "job_seconds_bucket",
+                    MetricType::Histogram,
+                    &["le=0.5"],
+                    0.0
+                ),
+                s(
+                    "job_seconds_bucket",
+                    MetricType::Histogram,
+                    &["le=1.0"],
+                    1.0
+                ),
+                s(
+                    "job_seconds_bucket",
+                    MetricType::Histogram,
+                    &["le=+Inf"],
+                    1.0
+                ),
+                s("job_seconds_sum", MetricType::Histogram, &[], 0.7),
+                s("job_seconds_count", MetricType::Histogram, &[], 1.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,16 @@
+//! Snapshots of metric values.
+//!
+//! A snapshot can be encoded into a compact binary format with [`encode_snapshot`], to send it to another process
+//! which merges the snapshots with [`MergedSnapshots`]. With the `serde` feature, a `MetricsSnapshot` can be
+//! serialized to persist the metrics across restarts.
+
+mod binary;
+#[cfg(feature = "serde")]
+mod serialize;
+
+pub use binary::{
+    decode_snapshot, encode_families, encode_snapshot, MergeError, MergedSnapshots,
+    SnapshotDecodeError, SnapshotEncoder, SNAPSHOT_FORMAT_VERSION,
+};
+#[cfg(feature = "serde")]
+pub use serialize::MetricsSnapshot;
@@ -0,0 +1,1389 @@
+use std::{
+    convert::Infallible,
+    fmt,
+    io::{self, Write},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    atomic::MemoryOrdering,
+    label::{name::is_label_name, LabelGroup, LabelGroupVisitor, LabelName, LabelPair, LabelValue},
+    metric::{
+        counter::CounterState,
+        gauge::{FloatGaugeState, GaugeState},
+        group::{Encoding, MetricGroup, MetricValue},
+        histogram::{
+            adaptive::{AdaptiveHistogramState, AdaptiveThresholds},
+            HistogramState, Thresholds,
+        },
+        name::{Bucket, Count, MetricName, MetricNameEncoder, Sum},
+        sampled::{SampleRate, SampledCounterState},
+        summary::{Quantiles, SummaryState},
+        unknown::UnknownState,
+        MetricEncoding,
+    },
+    sample::LabelString,
+    text::{BufferedTextEncoder, MetricType, ParsedFamily, ParsedSample, TextEncoder, Unreachable},
+};
+
+const MAGIC: &[u8; 4] = b"MSNP";
+
+/// The version of the binary snapshot format written by [`encode_snapshot`].
+///
+/// Every snapshot starts with the 4 bytes `MSNP`, followed by this version as a single byte.
+/// [`decode_snapshot`] only accepts the versions it knows, and returns
+/// [`SnapshotDecodeError::UnsupportedVersion`] for any other.
+///
+/// The version is increased whenever the layout changes. A new version of this crate can still decode
+/// the previous versions, but an old version cannot decode a newer one. When the processes are upgraded
+/// one at a time, the process which decodes and merges the snapshots must be upgraded first.
+///
+/// Version 1 has the following layout, where every length, count and index is an unsigned LEB128 varint,
+/// every string is its length followed by its UTF-8 bytes, and every fixed size number is little-endian:
+///
+/// * the number of strings in the string table, and each string. The sample names, label names and
+///   label values are only stored once, in this table, and are referred to by their index.
+/// * the number of families, and for each family:
+///     * the family name
+///     * the type: `0` for none, then counter, histogram, gauge, summary, untyped and unknown
+///       from `1` to `6`
+///     * `0` without help text, or `1` followed by the help text
+///     * the number of samples, and for each sample:
+///         * `0` followed by the index of the suffix after the family name, or `1` followed by the
+///           index of the full sample name
+///         * the number of labels, and the index of the name and value of each label
+///         * the value: `0` followed by an unsigned varint, `1` followed by a zigzag encoded signed varint,
+///           or `2` followed by the 8 bytes of an `f64`
+///         * `0` without a timestamp, or `1` followed by the 8 bytes of an `i64` timestamp
+pub const SNAPSHOT_FORMAT_VERSION: u8 = 1;
+
+/// Encode every metric family in the group into the compact binary snapshot format.
+///
+/// This is meant for multi-process architectures, where child processes record metrics and send
+/// snapshots to a parent process, which merges them with [`MergedSnapshots`] and exposes the result.
+/// Unlike the text format, the snapshot is cheap to decode and match up with the snapshots of other processes,
+/// and counts are kept exactly. See [`SNAPSHOT_FORMAT_VERSION`] for the layout.
+///
+/// ```
+/// use measured::{Counter, MetricGroup};
+/// use measured::snapshot::{decode_snapshot, encode_snapshot, MergedSnapshots};
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Worker {
+///     /// total jobs processed
+///     jobs_total: Counter,
+/// }
+///
+/// // in each child process
+/// let worker = Worker::default();
+/// worker.jobs_total.inc_by(3);
+/// let bytes = encode_snapshot(&worker);
+///
+/// // in the parent process
+/// let mut merged = MergedSnapshots::new();
+/// merged.merge(decode_snapshot(&bytes).unwrap()).unwrap();
+/// merged.merge(decode_snapshot(&bytes).unwrap()).unwrap();
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// merged.collect_group_into(&mut enc).unwrap();
+/// assert_eq!(
+///     enc.finish(),
+///     "# HELP jobs_total total jobs processed\n# TYPE jobs_total counter\njobs_total 6\n",
+/// );
+/// ```
+pub fn encode_snapshot<G: MetricGroup<SnapshotEncoder> + ?Sized>(group: &G) -> Vec<u8> {
+    let mut enc = SnapshotEncoder::new();
+    match group.collect_group_into(&mut enc) {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+    enc.finish()
+}
+
+/// Encode the families into the compact binary snapshot format. See [`encode_snapshot`]
+pub fn encode_families(families: &[ParsedFamily]) -> Vec<u8> {
+    let mut enc = SnapshotEncoder::new();
+    for family in families {
+        enc.begin_family(family.name.clone(), family.metric_type, family.help.clone());
+        for sample in &family.samples {
+            let value = match sample.int_value {
+                Some(x) => Value::Uint(x),
+                None => Value::Float(sample.value),
+            };
+            let labels = sample.labels.iter().map(|(k, v)| (&**k, &**v));
+            enc.push_sample(&sample.name, labels, value, sample.timestamp);
+        }
+    }
+    enc.finish()
+}
+
+/// An encoder which writes the binary snapshot format. See [`encode_snapshot`]
+#[derive(Default)]
+pub struct SnapshotEncoder {
+    strings: StringTable,
+    /// The encoded families before the current one
+    families: Vec<u8>,
+    family_count: usize,
+    family: Option<PendingFamily>,
+    /// The help text for the next family
+    help: Option<String>,
+    name: Vec<u8>,
+    label: String,
+    labels: Vec<(usize, usize)>,
+}
+
+struct PendingFamily {
+    name: String,
+    metric_type: Option<MetricType>,
+    help: Option<String>,
+    samples: Vec<u8>,
+    sample_count: usize,
+}
+
+/// The value of a sample in the snapshot
+#[derive(Clone, Copy)]
+enum Value {
+    Uint(u64),
+    Int(i64),
+    Float(f64),
+}
+
+impl SnapshotEncoder {
+    /// Create a new snapshot encoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a new metric family of the given type
+    pub fn write_type(&mut self, name: impl MetricNameEncoder, metric_type: MetricType) {
+        self.name.clear();
+        name.encode_utf8(&mut self.name)
+            .expect("writing to a vec should not fail");
+        let name = String::from_utf8(self.name.clone()).expect("metric names should be valid utf8");
+        let help = self.help.take();
+        self.begin_family(name, Some(metric_type), help);
+    }
+
+    /// Write a single sample of the current family
+    pub fn write_sample(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        value: MetricValue,
+    ) {
+        let value = match value {
+            MetricValue::Int(x) => Value::Int(x),
+            MetricValue::Float(x) => Value::Float(x),
+        };
+        self.write_value(name, labels, value);
+    }
+
+    /// Write a single sample of the current family which counts something, like a counter or
+    /// the `_count` of a histogram. Unlike [`MetricValue`], the count is kept exactly.
+    pub fn write_count(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        count: u64,
+    ) {
+        self.write_value(name, labels, Value::Uint(count));
+    }
+
+    /// Finish the snapshot, returning the encoded bytes
+    pub fn finish(mut self) -> Vec<u8> {
+        self.end_family();
+
+        let mut buf = Vec::with_capacity(self.families.len() + 16 * self.strings.index.len());
+        buf.extend_from_slice(MAGIC);
+        buf.push(SNAPSHOT_FORMAT_VERSION);
+
+        let mut strings = vec![""; self.strings.index.len()];
+        for (s, &i) in &self.strings.index {
+            strings[i] = s;
+        }
+        write_len(&mut buf, strings.len());
+        for s in strings {
+            write_str(&mut buf, s);
+        }
+
+        write_len(&mut buf, self.family_count);
+        buf.extend_from_slice(&self.families);
+        buf
+    }
+
+    fn write_value(&mut self, name: impl MetricNameEncoder, labels: impl LabelGroup, value: Value) {
+        let mut name_buf = std::mem::take(&mut self.name);
+        name_buf.clear();
+        name.encode_utf8(&mut name_buf)
+            .expect("writing to a vec should not fail");
+
+        self.labels.clear();
+        labels.visit_values(&mut InternLabels {
+            strings: &mut self.strings,
+            value: &mut self.label,
+            labels: &mut self.labels,
+        });
+
+        let sample_name =
+            std::str::from_utf8(&name_buf).expect("metric names should be valid utf8");
+        self.push_encoded_sample(sample_name, value, None);
+        self.name = name_buf;
+    }
+
+    fn write_histogram(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        le: &[f64],
+        buckets: &[u64],
+        inf: u64,
+        sum: f64,
+    ) {
+        const LE: &LabelName = LabelName::from_str("le");
+
+        let mut val = 0;
+        for (&le, &bucket) in le.iter().zip(buckets) {
+            val += bucket;
+            self.write_count(
+                name.by_ref().with_suffix(Bucket),
+                labels.by_ref().compose_with(LabelPair(LE, le)),
+                val,
+            );
+        }
+        let count = val + inf;
+        self.write_count(
+            name.by_ref().with_suffix(Bucket),
+            labels.by_ref().compose_with(LabelPair(LE, f64::INFINITY)),
+            count,
+        );
+        self.write_value(
+            name.by_ref().with_suffix(Sum),
+            labels.by_ref(),
+            Value::Float(sum),
+        );
+        self.write_count(name.by_ref().with_suffix(Count), labels, count);
+    }
+
+    fn begin_family(
+        &mut self,
+        name: String,
+        metric_type: Option<MetricType>,
+        help: Option<String>,
+    ) {
+        self.end_family();
+        self.family = Some(PendingFamily {
+            name,
+            metric_type,
+            help,
+            samples: Vec::new(),
+            sample_count: 0,
+        });
+    }
+
+    fn end_family(&mut self) {
+        let Some(family) = self.family.take() else {
+            return;
+        };
+        let buf = &mut self.families;
+        write_str(buf, &family.name);
+        buf.push(family.metric_type.map_or(0, type_to_byte));
+        match &family.help {
+            None => buf.push(0),
+            Some(help) => {
+                buf.push(1);
+                write_str(buf, help);
+            }
+        }
+        write_len(buf, family.sample_count);
+        buf.extend_from_slice(&family.samples);
+        self.family_count += 1;
+    }
+
+    /// Write a sample with the label pairs given as strings
+    fn push_sample<'a>(
+        &mut self,
+        name: &str,
+        labels: impl Iterator<Item = (&'a str, &'a str)>,
+        value: Value,
+        timestamp: Option<i64>,
+    ) {
+        self.labels.clear();
+        for (k, v) in labels {
+            let pair = (self.strings.intern(k), self.strings.intern(v));
+            self.labels.push(pair);
+        }
+        self.push_encoded_sample(name, value, timestamp);
+    }
+
+    /// Write a sample with the label pairs already interned into `self.labels`
+    fn push_encoded_sample(&mut self, name: &str, value: Value, timestamp: Option<i64>) {
+        // a sample without a family is written as its own untyped family, like the text format would
+        if self.family.is_none() {
+            let help = self.help.take();
+            self.begin_family(name.to_owned(), None, help);
+        }
+        let family = self.family.as_mut().expect("a family was just started");
+
+        let buf = &mut family.samples;
+        match name.strip_prefix(&*family.name) {
+            Some(suffix) => {
+                buf.push(0);
+                write_len(buf, self.strings.intern(suffix));
+            }
+            None => {
+                buf.push(1);
+                write_len(buf, self.strings.intern(name));
+            }
+        }
+        write_len(buf, self.labels.len());
+        for &(k, v) in &self.labels {
+            write_len(buf, k);
+            write_len(buf, v);
+        }
+        match value {
+            Value::Uint(x) => {
+                buf.push(0);
+                write_varint(buf, x);
+            }
+            Value::Int(x) => {
+                buf.push(1);
+                write_varint(buf, ((x << 1) ^ (x >> 63)) as u64);
+            }
+            Value::Float(x) => {
+                buf.push(2);
+                buf.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        match timestamp {
+            None => buf.push(0),
+            Some(ts) => {
+                buf.push(1);
+                buf.extend_from_slice(&ts.to_le_bytes());
+            }
+        }
+        family.sample_count += 1;
+    }
+}
+
+impl Encoding for SnapshotEncoder {
+    type Err = Infallible;
+
+    fn write_help(&mut self, _name: impl MetricNameEncoder, help: &str) -> Result<(), Infallible> {
+        self.help = Some(help.to_owned());
+        Ok(())
+    }
+}
+
+/// The index of every string in the snapshot
+#[derive(Default)]
+struct StringTable {
+    index: FxHashMap<Box<str>, usize>,
+}
+
+impl StringTable {
+    fn intern(&mut self, s: &str) -> usize {
+        if let Some(&i) = self.index.get(s) {
+            return i;
+        }
+        let i = self.index.len();
+        self.index.insert(s.into(), i);
+        i
+    }
+}
+
+struct InternLabels<'a> {
+    strings: &'a mut StringTable,
+    value: &'a mut String,
+    labels: &'a mut Vec<(usize, usize)>,
+}
+
+impl LabelGroupVisitor for InternLabels<'_> {
+    type Output = ();
+
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        self.value.clear();
+        x.visit(LabelString(self.value));
+        let name = self.strings.intern(name.as_str());
+        let value = self.strings.intern(self.value);
+        self.labels.push((name, value));
+    }
+}
+
+impl MetricEncoding<SnapshotEncoder> for CounterState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Counter);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_count(name, labels, self.count.load(ordering.load()));
+        Ok(())
+    }
+}
+
+impl MetricEncoding<SnapshotEncoder> for SampledCounterState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Counter);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        rate: &SampleRate,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_sample(name, labels, MetricValue::Float(self.estimate(*rate)));
+        Ok(())
+    }
+}
+
+impl MetricEncoding<SnapshotEncoder> for GaugeState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Gauge);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        let value = self.count.load(ordering.load());
+        enc.write_sample(name, labels, MetricValue::Int(value));
+        Ok(())
+    }
+}
+
+impl MetricEncoding<SnapshotEncoder> for FloatGaugeState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Gauge);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_sample(
+            name,
+            labels,
+            MetricValue::Float(self.count.load(ordering.load())),
+        );
+        Ok(())
+    }
+}
+
+impl MetricEncoding<SnapshotEncoder> for UnknownState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Unknown);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        _m: &(),
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_sample(name, labels, MetricValue::Float(self.value.get()));
+        Ok(())
+    }
+}
+
+impl<const N: usize> MetricEncoding<SnapshotEncoder> for HistogramState<N> {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Histogram);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &Thresholds<N>,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        let (buckets, inf, sum) = self.inner.write().sample();
+        enc.write_histogram(name, labels, metadata.get(), &buckets, inf, sum);
+        Ok(())
+    }
+}
+
+impl<const N: usize> MetricEncoding<SnapshotEncoder> for AdaptiveHistogramState<N> {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Histogram);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &AdaptiveThresholds<N>,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        let (le, buckets, inf, sum) = self.inner.write().sample(metadata);
+        enc.write_histogram(name, labels, &le, &buckets, inf, sum);
+        Ok(())
+    }
+}
+
+impl MetricEncoding<SnapshotEncoder> for SummaryState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_type(name, MetricType::Summary);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &Quantiles,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SnapshotEncoder,
+    ) -> Result<(), Infallible> {
+        const QUANTILE: &LabelName = LabelName::from_str("quantile");
+
+        let mut inner = self.inner.write();
+        for &(q, _) in metadata.get() {
+            enc.write_sample(
+                name.by_ref(),
+                labels.by_ref().compose_with(LabelPair(QUANTILE, q)),
+                MetricValue::Float(inner.query(metadata, q).unwrap_or(f64::NAN)),
+            );
+        }
+        enc.write_sample(
+            name.by_ref().with_suffix(Sum),
+            labels.by_ref(),
+            MetricValue::Float(inner.sum()),
+        );
+        enc.write_count(name.by_ref().with_suffix(Count), labels, inner.count());
+        Ok(())
+    }
+}
+
+/// Decode the families of a snapshot written by [`encode_snapshot`].
+///
+/// # Errors
+/// Returns an error if the snapshot is truncated or corrupted, if it has a newer format version,
+/// or if it contains an invalid metric or label name.
+pub fn decode_snapshot(bytes: &[u8]) -> Result<Vec<ParsedFamily>, SnapshotDecodeError> {
+    let mut r = Reader(bytes);
+    if r.bytes(MAGIC.len())? != MAGIC {
+        return Err(SnapshotDecodeError::InvalidHeader);
+    }
+    match r.byte()? {
+        SNAPSHOT_FORMAT_VERSION => {}
+        version => return Err(SnapshotDecodeError::UnsupportedVersion(version)),
+    }
+
+    let n = r.len()?;
+    let mut strings = Vec::with_capacity(n.min(r.0.len()));
+    for _ in 0..n {
+        strings.push(r.str()?);
+    }
+    let strings = &*strings;
+
+    let n = r.len()?;
+    let mut families = Vec::with_capacity(n.min(r.0.len()));
+    for _ in 0..n {
+        let name = r.metric_name()?;
+        let metric_type = match r.byte()? {
+            0 => None,
+            b => Some(byte_to_type(b).ok_or(SnapshotDecodeError::InvalidMetricType(b))?),
+        };
+        let help = match r.flag()? {
+            false => None,
+            true => Some(r.str()?.to_owned()),
+        };
+
+        let n = r.len()?;
+        let mut samples = Vec::with_capacity(n.min(r.0.len()));
+        for _ in 0..n {
+            let sample_name = match r.flag()? {
+                false => format!("{name}{}", r.string(strings)?),
+                true => r.string(strings)?.to_owned(),
+            };
+            if MetricName::try_from_str(&sample_name).is_err() {
+                return Err(SnapshotDecodeError::InvalidName(sample_name));
+            }
+
+            let n = r.len()?;
+            let mut labels = Vec::with_capacity(n.min(r.0.len()));
+            for _ in 0..n {
+                let label = r.string(strings)?;
+                if !is_label_name(label) {
+                    return Err(SnapshotDecodeError::InvalidName(label.to_owned()));
+                }
+                labels.push((label.to_owned(), r.string(strings)?.to_owned()));
+            }
+
+            let (value, int_value) = match r.byte()? {
+                0 => {
+                    let x = r.varint()?;
+                    (x as f64, Some(x))
+                }
+                1 => {
+                    let x = r.varint()?;
+                    let x = (x >> 1) as i64 ^ -((x & 1) as i64);
+                    (x as f64, u64::try_from(x).ok())
+                }
+                2 => float_value(f64::from_le_bytes(r.array()?)),
+                b => return Err(SnapshotDecodeError::InvalidValueKind(b)),
+            };
+            let timestamp = match r.flag()? {
+                false => None,
+                true => Some(i64::from_le_bytes(r.array()?)),
+            };
+            samples.push(ParsedSample {
+                name: sample_name,
+                labels,
+                value,
+                int_value,
+                timestamp,
+            });
+        }
+
+        families.push(ParsedFamily {
+            name,
+            help,
+            metric_type,
+            samples,
+        });
+    }
+
+    if !r.0.is_empty() {
+        return Err(SnapshotDecodeError::TrailingData);
+    }
+    Ok(families)
+}
+
+/// Like the text parser, integers are kept exactly
+fn float_value(value: f64) -> (f64, Option<u64>) {
+    let int_value =
+        (value >= 0.0 && value.fract() == 0.0 && value < u64::MAX as f64).then_some(value as u64);
+    (value, int_value)
+}
+
+/// The error returned by [`decode_snapshot`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotDecodeError {
+    /// The bytes do not start with the snapshot header
+    InvalidHeader,
+    /// The snapshot was written with a format version this crate does not know
+    UnsupportedVersion(u8),
+    /// The snapshot ended in the middle of a value
+    UnexpectedEnd,
+    /// There were more bytes after the last family
+    TrailingData,
+    /// A string in the snapshot is not valid utf8
+    InvalidUtf8,
+    /// A flag byte in the snapshot is neither `0` nor `1`
+    InvalidFlag(u8),
+    /// A family has a type this format version does not know
+    InvalidMetricType(u8),
+    /// A metric or label name in the snapshot is not valid
+    InvalidName(String),
+    /// A string index is outside of the string table
+    InvalidStringIndex(usize),
+    /// A sample value has a kind this format version does not know
+    InvalidValueKind(u8),
+}
+
+impl fmt::Display for SnapshotDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => f.write_str("not a metrics snapshot"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported snapshot format version {v}"),
+            Self::UnexpectedEnd => f.write_str("snapshot ended unexpectedly"),
+            Self::TrailingData => f.write_str("unexpected data at end of snapshot"),
+            Self::InvalidUtf8 => f.write_str("snapshot string is not valid utf8"),
+            Self::InvalidFlag(b) => write!(f, "invalid flag {b} in snapshot"),
+            Self::InvalidMetricType(b) => write!(f, "unknown metric type {b} in snapshot"),
+            Self::InvalidName(name) => write!(f, "invalid name {name:?} in snapshot"),
+            Self::InvalidStringIndex(i) => write!(f, "invalid string index {i} in snapshot"),
+            Self::InvalidValueKind(b) => write!(f, "unknown value kind {b} in snapshot"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotDecodeError {}
+
+/// The metric families of several snapshots, merged into one.
+///
+/// Families are matched by name, and the samples within them by name and labels, regardless of the
+/// order of the labels. When a series is in more than one snapshot:
+/// * counter and histogram samples are summed, including the `_sum`, `_count` and `_bucket` samples.
+///   Integer samples, like counts, are summed exactly. A histogram series must have the same buckets in
+///   every snapshot.
+/// * the `_sum` and `_count` samples of a summary are summed. Its quantiles cannot be merged, so
+///   the last one wins, like gauges and untyped samples.
+/// * the latest timestamp is kept.
+///
+/// The first help text of a family is kept. A series that is only in some of the snapshots is
+/// added as is. The merged families are encoded with [`MetricGroup`], so they can be exposed
+/// next to the metrics of the parent process. See [`encode_snapshot`].
+#[derive(Clone, Debug, Default)]
+pub struct MergedSnapshots {
+    families: Vec<ParsedFamily>,
+    /// the index of every family in `families` by name
+    index: FxHashMap<String, usize>,
+    /// the series of every family in `families`
+    series: Vec<FamilySeries>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct FamilySeries {
+    /// the index of every sample by name and sorted labels
+    samples: FxHashMap<SeriesKey, usize>,
+    /// the `le` bounds of every histogram series, by its sorted labels without `le`
+    buckets: BucketLayout,
+}
+
+type BucketLayout = FxHashMap<Vec<(String, String)>, Vec<String>>;
+
+impl MergedSnapshots {
+    /// Create an empty set of merged families
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The merged families, in the order they were first seen
+    pub fn families(&self) -> &[ParsedFamily] {
+        &self.families
+    }
+
+    /// Remove all the merged families, to start merging a new round of snapshots
+    pub fn clear(&mut self) {
+        self.families.clear();
+        self.index.clear();
+        self.series.clear();
+    }
+
+    /// Merge the families of a snapshot, from [`decode_snapshot`], into these families.
+    ///
+    /// # Errors
+    /// Returns an error if a family has a different type than the family of the same name that
+    /// was merged before, if a histogram series has different buckets than the series it would be
+    /// merged with, or if a family contains an invalid name. Nothing is merged in that case.
+    pub fn merge(&mut self, families: Vec<ParsedFamily>) -> Result<(), MergeError> {
+        let mut layouts = Vec::with_capacity(families.len());
+        for family in &families {
+            validate_names(family)?;
+            let existing = self.index.get(&family.name).copied();
+            let metric_type = match existing {
+                Some(i) => {
+                    let expected = self.families[i].metric_type;
+                    if let (Some(expected), Some(found)) = (expected, family.metric_type) {
+                        if expected != found {
+                            return Err(MergeError::TypeMismatch {
+                                family: family.name.clone(),
+                                expected,
+                                found,
+                            });
+                        }
+                    }
+                    expected.or(family.metric_type)
+                }
+                None => family.metric_type,
+            };
+
+            let layout = match metric_type {
+                Some(MetricType::Histogram) => bucket_layout(family),
+                _ => BucketLayout::default(),
+            };
+            if let Some(i) = existing {
+                for (labels, le) in &layout {
+                    match self.series[i].buckets.get(labels) {
+                        Some(existing) if existing != le => {
+                            return Err(MergeError::BucketMismatch {
+                                family: family.name.clone(),
+                                labels: labels.clone(),
+                            })
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            layouts.push(layout);
+        }
+
+        for (family, layout) in families.into_iter().zip(layouts) {
+            let i = match self.index.get(&family.name) {
+                Some(&i) => i,
+                None => {
+                    let i = self.families.len();
+                    self.index.insert(family.name.clone(), i);
+                    self.families.push(ParsedFamily {
+                        name: family.name.clone(),
+                        help: None,
+                        metric_type: None,
+                        samples: Vec::with_capacity(family.samples.len()),
+                    });
+                    self.series.push(FamilySeries::default());
+                    i
+                }
+            };
+            merge_family(&mut self.families[i], &mut self.series[i], family);
+            for (labels, le) in layout {
+                self.series[i].buckets.entry(labels).or_insert(le);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `le` bounds of every histogram series in the family, by its sorted labels without `le`
+fn bucket_layout(family: &ParsedFamily) -> BucketLayout {
+    let mut layout = BucketLayout::default();
+    for sample in &family.samples {
+        let is_bucket = sample
+            .name
+            .strip_prefix(&*family.name)
+            .is_some_and(|suffix| suffix == "_bucket");
+        let Some(le) = sample.label("le").filter(|_| is_bucket) else {
+            continue;
+        };
+        let mut labels: Vec<_> = sample
+            .labels
+            .iter()
+            .filter(|(k, _)| k != "le")
+            .cloned()
+            .collect();
+        labels.sort_unstable();
+        layout.entry(labels).or_default().push(le.to_owned());
+    }
+    layout
+}
+
+/// The error returned by [`MergedSnapshots::merge`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeError {
+    /// The family was merged before with a different type
+    TypeMismatch {
+        /// The name of the family
+        family: String,
+        /// The type of the family that was merged before
+        expected: MetricType,
+        /// The type of the family in the new snapshot
+        found: MetricType,
+    },
+    /// A histogram series was merged before with different buckets
+    BucketMismatch {
+        /// The name of the family
+        family: String,
+        /// The sorted labels of the series, without `le`
+        labels: Vec<(String, String)>,
+    },
+    /// A metric or label name is not valid
+    InvalidName(String),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TypeMismatch {
+                family,
+                expected,
+                found,
+            } => write!(
+                f,
+                "metric family {family} has type {found:?}, but it was merged with type {expected:?}"
+            ),
+            Self::BucketMismatch { family, labels } => write!(
+                f,
+                "histogram {family} with labels {labels:?} has different buckets than it was merged with"
+            ),
+            Self::InvalidName(name) => write!(f, "invalid name {name:?}"),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+fn validate_names(family: &ParsedFamily) -> Result<(), MergeError> {
+    let names = core::iter::once(&family.name).chain(family.samples.iter().map(|s| &s.name));
+    for name in names {
+        if MetricName::try_from_str(name).is_err() {
+            return Err(MergeError::InvalidName(name.clone()));
+        }
+    }
+    for (label, _) in family.samples.iter().flat_map(|s| &s.labels) {
+        if !is_label_name(label) {
+            return Err(MergeError::InvalidName(label.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// The name and sorted labels of a sample
+type SeriesKey = (String, Vec<(String, String)>);
+
+fn series_key(sample: &ParsedSample) -> SeriesKey {
+    let mut labels = sample.labels.clone();
+    labels.sort_unstable();
+    (sample.name.clone(), labels)
+}
+
+fn merge_family(existing: &mut ParsedFamily, series: &mut FamilySeries, family: ParsedFamily) {
+    existing.help = existing.help.take().or(family.help);
+    existing.metric_type = existing.metric_type.or(family.metric_type);
+
+    for sample in family.samples {
+        let key = series_key(&sample);
+        let Some(&i) = series.samples.get(&key) else {
+            series.samples.insert(key, existing.samples.len());
+            existing.samples.push(sample);
+            continue;
+        };
+
+        let summed = match existing.metric_type {
+            Some(MetricType::Counter | MetricType::Histogram) => true,
+            Some(MetricType::Summary) => sample
+                .name
+                .strip_prefix(&*existing.name)
+                .is_some_and(|suffix| suffix == "_sum" || suffix == "_count"),
+            _ => false,
+        };
+        let s = &mut existing.samples[i];
+        if summed {
+            s.int_value = s
+                .int_value
+                .zip(sample.int_value)
+                .and_then(|(a, b)| a.checked_add(b));
+            s.value = match s.int_value {
+                Some(x) => x as f64,
+                None => s.value + sample.value,
+            };
+        } else {
+            s.value = sample.value;
+            s.int_value = sample.int_value;
+        }
+        s.timestamp = s.timestamp.max(sample.timestamp);
+    }
+}
+
+impl<W: Write> MetricGroup<TextEncoder<W>> for MergedSnapshots {
+    fn collect_group_into(&self, enc: &mut TextEncoder<W>) -> io::Result<()> {
+        for family in &self.families {
+            let name =
+                MetricName::try_from_str(&family.name).expect("names are validated on merge");
+            if let Some(help) = &family.help {
+                enc.write_help(name, help)?;
+            }
+            // without a type line, the samples would be encoded as part of the previous family
+            enc.write_type(&name, family.metric_type.unwrap_or(MetricType::Untyped))?;
+
+            for sample in &family.samples {
+                let sample_name =
+                    MetricName::try_from_str(&sample.name).expect("names are validated on merge");
+                let value = sample.value;
+                // integer values, like counters, are written the same as they were collected
+                let value = match sample.int_value.map(i64::try_from) {
+                    Some(Ok(x)) => MetricValue::Int(x),
+                    _ if value.fract() == 0.0 && value.abs() < i64::MAX as f64 => {
+                        MetricValue::Int(value as i64)
+                    }
+                    _ => MetricValue::Float(value),
+                };
+                enc.write_metric_value(sample_name, SampleLabels(&sample.labels), value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MetricGroup<BufferedTextEncoder> for MergedSnapshots {
+    fn collect_group_into(
+        &self,
+        enc: &mut BufferedTextEncoder,
+    ) -> Result<(), <BufferedTextEncoder as Encoding>::Err> {
+        self.collect_group_into(&mut enc.inner).unreachable()
+    }
+}
+
+struct SampleLabels<'a>(&'a [(String, String)]);
+
+impl LabelGroup for SampleLabels<'_> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        for (name, value) in self.0 {
+            v.write_value(LabelName::from_str(name), value);
+        }
+    }
+}
+
+fn type_to_byte(typ: MetricType) -> u8 {
+    match typ {
+        MetricType::Counter => 1,
+        MetricType::Histogram => 2,
+        MetricType::Gauge => 3,
+        MetricType::Summary => 4,
+        MetricType::Untyped => 5,
+        MetricType::Unknown => 6,
+    }
+}
+
+fn byte_to_type(b: u8) -> Option<MetricType> {
+    match b {
+        1 => Some(MetricType::Counter),
+        2 => Some(MetricType::Histogram),
+        3 => Some(MetricType::Gauge),
+        4 => Some(MetricType::Summary),
+        5 => Some(MetricType::Untyped),
+        6 => Some(MetricType::Unknown),
+        _ => None,
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn write_len(buf: &mut Vec<u8>, len: usize) {
+    write_varint(buf, len as u64);
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    write_len(buf, s.len());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], SnapshotDecodeError> {
+        if self.0.len() < n {
+            return Err(SnapshotDecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotDecodeError> {
+        Ok(self.bytes(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotDecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn flag(&mut self) -> Result<bool, SnapshotDecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(SnapshotDecodeError::InvalidFlag(b)),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, SnapshotDecodeError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            n |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(SnapshotDecodeError::UnexpectedEnd)
+    }
+
+    fn len(&mut self) -> Result<usize, SnapshotDecodeError> {
+        usize::try_from(self.varint()?).map_err(|_| SnapshotDecodeError::UnexpectedEnd)
+    }
+
+    fn str(&mut self) -> Result<&'a str, SnapshotDecodeError> {
+        let len = self.len()?;
+        core::str::from_utf8(self.bytes(len)?).map_err(|_| SnapshotDecodeError::InvalidUtf8)
+    }
+
+    /// Read the index of a string in the string table
+    fn string(&mut self, table: &[&'a str]) -> Result<&'a str, SnapshotDecodeError> {
+        let i = self.len()?;
+        table
+            .get(i)
+            .copied()
+            .ok_or(SnapshotDecodeError::InvalidStringIndex(i))
+    }
+
+    fn metric_name(&mut self) -> Result<String, SnapshotDecodeError> {
+        let name = self.str()?;
+        match MetricName::try_from_str(name) {
+            Ok(_) => Ok(name.to_owned()),
+            Err(_) => Err(SnapshotDecodeError::InvalidName(name.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::histogram::Thresholds,
+        text::{parse_text_exposition, BufferedTextEncoder, MetricType},
+        Counter, CounterVec, FixedCardinalityLabel, Gauge, Histogram, HistogramVec, MetricGroup,
+    };
+
+    use super::{
+        decode_snapshot, encode_snapshot, MergeError, MergedSnapshots, SnapshotDecodeError,
+    };
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "kind")]
+    enum Kind {
+        Read,
+        Write,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Worker {
+        /// operations processed
+        ops: CounterVec<StaticLabelSet<Kind>>,
+        /// in flight operations
+        in_flight: Gauge,
+        latency: Histogram<2>,
+    }
+
+    impl Worker {
+        fn new() -> Self {
+            Self {
+                ops: CounterVec::new(),
+                in_flight: Gauge::new(),
+                latency: Histogram::with_metadata(Thresholds::with_buckets([0.1, 1.0])),
+            }
+        }
+    }
+
+    fn collect(merged: &MergedSnapshots) -> String {
+        let mut enc = BufferedTextEncoder::new();
+        merged.collect_group_into(&mut enc).unwrap();
+        String::from_utf8(enc.finish().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let worker = Worker::new();
+        worker.ops.inc(Kind::Read);
+        worker.in_flight.set(-3);
+        worker.latency.observe(0.5);
+
+        let families = parse_text_exposition(&{
+            let mut enc = BufferedTextEncoder::new();
+            worker.collect_group_into(&mut enc).unwrap();
+            enc.finish()
+        })
+        .unwrap();
+        assert_eq!(
+            decode_snapshot(&encode_snapshot(&worker)).unwrap(),
+            families
+        );
+    }
+
+    #[test]
+    fn merge_processes() {
+        let a = Worker::new();
+        a.ops.inc_by(Kind::Read, 2);
+        a.in_flight.set(4);
+        a.latency.observe(0.05);
+
+        let b = Worker::new();
+        b.ops.inc(Kind::Read);
+        b.ops.inc(Kind::Write);
+        b.in_flight.set(1);
+        b.latency.observe(0.5);
+        b.latency.observe(5.0);
+
+        let mut merged = MergedSnapshots::new();
+        for worker in [&a, &b] {
+            let families = decode_snapshot(&encode_snapshot(worker)).unwrap();
+            merged.merge(families).unwrap();
+        }
+
+        assert_eq!(
+            collect(&merged),
+            r#"# HELP ops operations processed
+# TYPE ops counter
+ops{kind="read"} 3
+ops{kind="write"} 1
+
+# HELP in_flight in flight operations
+# TYPE in_flight gauge
+in_flight 1
+
+# TYPE latency histogram
+latency_bucket{le="0.1"} 1
+latency_bucket{le="1.0"} 2
+latency_bucket{le="+Inf"} 3
+latency_sum 5.55
+latency_count 3
+"#
+        );
+    }
+
+    #[test]
+    fn merge_type_mismatch() {
+        let counter = {
+            #[derive(MetricGroup)]
+            #[metric(crate = crate)]
+            struct G {
+                up: Counter,
+            }
+            encode_snapshot(&G { up: Counter::new() })
+        };
+        let gauge = {
+            #[derive(MetricGroup)]
+            #[metric(crate = crate)]
+            struct G {
+                up: Gauge,
+                other: Gauge,
+            }
+            encode_snapshot(&G {
+                up: Gauge::new(),
+                other: Gauge::new(),
+            })
+        };
+
+        let mut merged = MergedSnapshots::new();
+        merged.merge(decode_snapshot(&counter).unwrap()).unwrap();
+        assert_eq!(
+            merged.merge(decode_snapshot(&gauge).unwrap()),
+            Err(MergeError::TypeMismatch {
+                family: "up".to_owned(),
+                expected: MetricType::Counter,
+                found: MetricType::Gauge,
+            })
+        );
+        // nothing was merged
+        assert_eq!(merged.families().len(), 1);
+    }
+
+    #[test]
+    fn exact_counts() {
+        #[derive(MetricGroup)]
+        #[metric(crate = crate)]
+        struct G {
+            big_total: Counter,
+        }
+        let g = G {
+            big_total: Counter::new(),
+        };
+        // not exactly representable as an f64
+        g.big_total.inc_by((1 << 53) + 1);
+
+        let mut merged = MergedSnapshots::new();
+        for _ in 0..2 {
+            merged
+                .merge(decode_snapshot(&encode_snapshot(&g)).unwrap())
+                .unwrap();
+        }
+        assert_eq!(
+            collect(&merged),
+            "# TYPE big_total counter\nbig_total 18014398509481986\n"
+        );
+    }
+
+    #[test]
+    fn labels_are_stored_once() {
+        #[derive(MetricGroup)]
+        #[metric(crate = crate)]
+        struct G {
+            latency: HistogramVec<StaticLabelSet<Kind>, 2>,
+        }
+        let g = G {
+            latency: HistogramVec::with_metadata(Thresholds::with_buckets([0.1, 1.0])),
+        };
+        g.latency.observe(Kind::Read, 0.5);
+        g.latency.observe(Kind::Write, 0.5);
+
+        let bytes = encode_snapshot(&g);
+        let count = |s: &[u8]| bytes.windows(s.len()).filter(|w| *w == s).count();
+        assert_eq!(count(b"kind"), 1);
+        assert_eq!(count(b"_bucket"), 1);
+    }
+
+    #[test]
+    fn merge_bucket_mismatch() {
+        let a = Worker::new();
+        let b = Worker {
+            latency: Histogram::with_metadata(Thresholds::with_buckets([0.5, 1.0])),
+            ..Worker::new()
+        };
+
+        let mut merged = MergedSnapshots::new();
+        merged
+            .merge(decode_snapshot(&encode_snapshot(&a)).unwrap())
+            .unwrap();
+        assert_eq!(
+            merged.merge(decode_snapshot(&encode_snapshot(&b)).unwrap()),
+            Err(MergeError::BucketMismatch {
+                family: "latency".to_owned(),
+                labels: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        let worker = Worker::new();
+        let bytes = encode_snapshot(&worker);
+
+        assert_eq!(
+            decode_snapshot(b"not a snapshot"),
+            Err(SnapshotDecodeError::InvalidHeader)
+        );
+
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(
+            decode_snapshot(&newer),
+            Err(SnapshotDecodeError::UnsupportedVersion(2))
+        );
+
+        for len in 0..bytes.len() {
+            assert!(decode_snapshot(&bytes[..len]).is_err());
+        }
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert_eq!(
+            decode_snapshot(&trailing),
+            Err(SnapshotDecodeError::TrailingData)
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    label::LabelGroupSet,
+    metric::group::MetricGroup,
+    text::{parse_text_exposition, BufferedTextEncoder, ParsedFamily, ParsedSample},
+    Counter, CounterVec,
+};
+
+/// A point-in-time copy of every metric family in a [`MetricGroup`].
+///
+/// The snapshot uses the same representation as [`parse_text_exposition`], so each family records its name,
+/// help text, type, and the value of every sample along with its labels.
+///
+/// This can be serialized to persist metrics across restarts, and counters can be re-seeded from
+/// a snapshot with [`Counter::restore`] and [`CounterVec::restore`].
+///
+/// ```
+/// use measured::{Counter, MetricGroup};
+/// use measured::snapshot::MetricsSnapshot;
+///
+/// #[derive(MetricGroup)]
+/// struct Billing {
+///     /// total bytes billed
+///     bytes_total: Counter,
+/// }
+///
+/// let billing = Billing { bytes_total: Counter::new() };
+/// billing.bytes_total.inc_by(1024);
+///
+/// let snapshot = MetricsSnapshot::collect(&billing);
+/// let json = serde_json::to_string(&snapshot).unwrap();
+///
+/// // after a restart
+/// let billing = Billing { bytes_total: Counter::new() };
+/// let snapshot: MetricsSnapshot = serde_json::from_str(&json).unwrap();
+/// billing.bytes_total.restore(snapshot.family("bytes_total").unwrap());
+/// assert_eq!(MetricsSnapshot::collect(&billing), snapshot);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// All metric families in the snapshot, in the order they were collected
+    pub families: Vec<ParsedFamily>,
+}
+
+impl MetricsSnapshot {
+    /// Take a snapshot of all the metrics in the group
+    pub fn collect<G: MetricGroup<BufferedTextEncoder> + ?Sized>(group: &G) -> Self {
+        let mut enc = BufferedTextEncoder::new();
+        group
+            .collect_group_into(&mut enc)
+            .expect("the text encoder should not fail");
+        let families = parse_text_exposition(&enc.finish())
+            .expect("the text encoder should produce a valid exposition");
+        Self { families }
+    }
+
+    /// Find the family with the given name
+    pub fn family(&self, name: &str) -> Option<&ParsedFamily> {
+        self.families.iter().find(|f| f.name == name)
+    }
+}
+
+impl Counter {
+    /// Set the counter to the value persisted in the family snapshot.
+    ///
+    /// This overwrites the current value, so it should be called on startup before the counter is used.
+    /// Nothing is changed if the family has no sample with the same name as the family.
+    pub fn restore(&self, family: &ParsedFamily) {
+        if let Some(sample) = family.samples.iter().find(|s| s.name == family.name) {
+            self.get_metric()
+                .count
+                .store(counter_value(sample), Ordering::Relaxed);
+        }
+    }
+}
+
+impl<L: LabelGroupSet> CounterVec<L> {
+    /// Set each counter to the value persisted in the family snapshot.
+    ///
+    /// `labels` converts the labels of each sample back into a label group. Samples where it returns `None`
+    /// are skipped, as are samples whose label group is not in this vec's label set.
+    ///
+    /// This overwrites the current values, so it should be called on startup before the counters are used.
+    pub fn restore(
+        &self,
+        family: &ParsedFamily,
+        mut labels: impl FnMut(&ParsedSample) -> Option<L::Group<'_>>,
+    ) {
+        for sample in family.samples.iter().filter(|s| s.name == family.name) {
+            let Some(id) = labels(sample).and_then(|l| self.try_with_labels(l)) else {
+                continue;
+            };
+            self.get_metric(id)
+                .count
+                .store(counter_value(sample), Ordering::Relaxed);
+        }
+    }
+}
+
+/// Counters are written as integers, which are kept exactly even if they do not fit in an `f64`
+fn counter_value(sample: &ParsedSample) -> u64 {
+    sample.int_value.unwrap_or(sample.value as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet, text::MetricType, Counter, CounterVec, FixedCardinalityLabel,
+        MetricGroup,
+    };
+
+    use super::MetricsSnapshot;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "tier")]
+    enum Tier {
+        Free,
+        Paid,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Accounting {
+        /// requests billed per tier
+        requests_total: CounterVec<StaticLabelSet<Tier>>,
+        /// total bytes billed
+        bytes_total: Counter,
+    }
+
+    impl Accounting {
+        fn new() -> Self {
+            Self {
+                requests_total: CounterVec::new(),
+                bytes_total: Counter::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let metrics = Accounting::new();
+        metrics.requests_total.inc_by(Tier::Free, 3);
+        metrics.requests_total.inc_by(Tier::Paid, 1 << 40);
+        metrics.bytes_total.inc_by(512);
+
+        let snapshot = MetricsSnapshot::collect(&metrics);
+        let requests = snapshot.family("requests_total").unwrap();
+        assert_eq!(requests.metric_type, Some(MetricType::Counter));
+        assert_eq!(requests.help.as_deref(), Some("requests billed per tier"));
+        assert_eq!(requests.samples.len(), 2);
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let snapshot: MetricsSnapshot = serde_json::from_str(&json).unwrap();
+
+        let restored = Accounting::new();
+        // counters are overwritten, not incremented
+        restored.bytes_total.inc_by(100);
+        restored
+            .bytes_total
+            .restore(snapshot.family("bytes_total").unwrap());
+        restored
+            .requests_total
+            .restore(snapshot.family("requests_total").unwrap(), |s| {
+                match s.label("tier")? {
+                    "free" => Some(Tier::Free),
+                    "paid" => Some(Tier::Paid),
+                    _ => None,
+                }
+            });
+
+        assert_eq!(MetricsSnapshot::collect(&restored), snapshot);
+    }
+
+    #[test]
+    fn restore_large_counters() {
+        let metrics = Accounting::new();
+        let large = (1 << 53) + 1;
+        metrics.bytes_total.inc_by(large);
+        metrics.requests_total.inc_by(Tier::Free, (1 << 62) + 1);
+
+        let json = serde_json::to_string(&MetricsSnapshot::collect(&metrics)).unwrap();
+        let snapshot: MetricsSnapshot = serde_json::from_str(&json).unwrap();
+
+        let restored = Accounting::new();
+        restored
+            .bytes_total
+            .restore(snapshot.family("bytes_total").unwrap());
+        restored
+            .requests_total
+            .restore(snapshot.family("requests_total").unwrap(), |s| {
+                (s.label("tier")? == "free").then_some(Tier::Free)
+            });
+        assert_eq!(restored.bytes_total.get(), large);
+        assert_eq!(restored.requests_total.get(Tier::Free), (1 << 62) + 1);
+    }
+
+    #[test]
+    fn restore_skips_unknown_labels() {
+        let metrics = Accounting::new();
+        metrics.requests_total.inc_by(Tier::Paid, 7);
+        let snapshot = MetricsSnapshot::collect(&metrics);
+
+        let restored = Accounting::new();
+        restored
+            .requests_total
+            .restore(snapshot.family("requests_total").unwrap(), |_| None);
+        let requests = MetricsSnapshot::collect(&restored);
+        assert!(requests
+            .family("requests_total")
+            .unwrap()
+            .samples
+            .iter()
+            .all(|s| s.value == 0.0));
+    }
+}
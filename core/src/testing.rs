@@ -0,0 +1,82 @@
+//! Utilities for asserting that metrics stay allocation-free.
+//!
+//! Install the [`CountingAllocator`] as the global allocator in a test binary, then use
+//! [`count_allocations`] to count how many allocations a closure performs.
+//!
+//! ```
+//! use measured::testing::{count_allocations, CountingAllocator};
+//!
+//! #[global_allocator]
+//! static ALLOC: CountingAllocator = CountingAllocator::system();
+//!
+//! let counter = measured::Counter::new();
+//! let ((), allocs) = count_allocations(|| counter.inc());
+//! assert_eq!(allocs, 0);
+//! ```
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A global allocator that counts the number of allocations made on each thread.
+///
+/// Both allocations and re-allocations are counted. Deallocations are not.
+pub struct CountingAllocator<A = System> {
+    inner: A,
+}
+
+impl CountingAllocator {
+    /// Count the allocations made by the [`System`] allocator
+    pub const fn system() -> Self {
+        Self::new(System)
+    }
+}
+
+impl<A> CountingAllocator<A> {
+    /// Count the allocations made by the given allocator
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+fn record() {
+    // the thread local might already be destroyed while the thread is shutting down.
+    let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+}
+
+// SAFETY: all methods defer to the inner allocator
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record();
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record();
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record();
+        self.inner.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+}
+
+/// Run `f`, returning its output and the number of allocations it made on the current thread.
+///
+/// This only counts allocations if a [`CountingAllocator`] is installed as the `#[global_allocator]`.
+pub fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let output = f();
+    let after = ALLOCATIONS.with(Cell::get);
+    (output, after - before)
+}
@@ -3,38 +3,201 @@
 use std::{
     convert::Infallible,
     io::{self, Write},
+    ops::Range,
 };
 
 use bytes::{BufMut, Bytes, BytesMut};
-use memchr::memchr3_iter;
+use memchr::{memchr2_iter, memchr3_iter};
 
 use crate::{
-    label::{LabelGroup, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor},
+    atomic::MemoryOrdering,
+    label::{LabelGroup, LabelGroupVisitor, LabelName, LabelPair, LabelValue, LabelVisitor},
     metric::{
         counter::CounterState,
         gauge::{FloatGaugeState, GaugeState},
-        group::{Encoding, MetricValue},
-        histogram::{HistogramState, Thresholds},
-        name::{Bucket, Count, MetricNameEncoder, Sum},
-        MetricEncoding,
+        group::{Encoding, MetricGroup, MetricValue},
+        histogram::{
+            adaptive::{AdaptiveHistogramState, AdaptiveThresholds},
+            HistogramState, Thresholds,
+        },
+        name::{Bucket, Count, MetricNameEncoder, Sum, Total},
+        sampled::{SampleRate, SampledCounterState},
+        summary::{Quantiles, SummaryState},
+        unknown::UnknownState,
+        MetricEncoding, MetricFamilyEncoding,
     },
 };
 
+mod merge;
+mod parse;
+#[cfg(feature = "http")]
+mod response;
+
+pub use merge::{concat_expositions, ConcatError};
+pub use parse::{parse_text_exposition, ParseError, ParsedFamily, ParsedSample};
+
+/// The content type of the prometheus text format, as written by [`TextEncoder`] by default.
+pub const CONTENT_TYPE_TEXT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The content type of the OpenMetrics text format, as written by [`TextEncoder`] with [`TextFormat::OpenMetrics`].
+pub const CONTENT_TYPE_OPENMETRICS: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 /// The prometheus text encoder helper
 pub struct TextEncoder<W> {
     state: State,
+    sorted: Option<SortedSamples>,
+    header: Option<PendingHeader>,
+    skip_trailing_empty_buckets: bool,
+    line_ending: LineEnding,
+    format: TextFormat,
+    /// The type of the current metric family
+    family: Option<MetricType>,
+    /// The name and help text of the next family, which is written after its type line in OpenMetrics
+    help: PendingLine,
+    /// The name and unit of the next family, which is written after its type line in OpenMetrics
+    unit: PendingLine,
+    /// A reused buffer for the name of the current family or sample in OpenMetrics
+    name: Vec<u8>,
     /// The inner writer for this text encoder.
     pub writer: W,
 }
 
+/// The text exposition formats written by [`TextEncoder`]. See [`TextEncoder::with_format`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextFormat {
+    /// The prometheus text format, version 0.0.4
+    #[default]
+    Prometheus,
+    /// The OpenMetrics text format, version 1.0.0
+    ///
+    /// The families are not separated by empty lines, the exposition ends with `# EOF`, and counter families
+    /// are named without their `_total` suffix, while their samples always have it. The units written with
+    /// [`Encoding::write_unit`] get a `# UNIT` line.
+    OpenMetrics,
+}
+
+impl TextFormat {
+    /// Pick the format to respond with, given the `Accept` header of a scrape request.
+    ///
+    /// OpenMetrics is picked if the header accepts `application/openmetrics-text` with a quality at least as
+    /// high as `text/plain`. Otherwise, or if there is no header, the prometheus text format is picked.
+    ///
+    /// ```
+    /// use measured::text::TextFormat;
+    ///
+    /// // sent by prometheus 2.x
+    /// let accept = "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,\
+    ///     text/plain;version=0.0.4;q=0.5,*/*;q=0.1";
+    /// assert_eq!(TextFormat::from_accept(Some(accept)), TextFormat::OpenMetrics);
+    /// assert_eq!(TextFormat::from_accept(Some("text/plain")), TextFormat::Prometheus);
+    /// assert_eq!(TextFormat::from_accept(None), TextFormat::Prometheus);
+    /// ```
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let mut openmetrics = 0.0;
+        let mut text = 0.0;
+        for range in accept.unwrap_or_default().split(',') {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if media_type.eq_ignore_ascii_case("application/openmetrics-text") {
+                openmetrics = f32::max(openmetrics, q);
+            } else if media_type.eq_ignore_ascii_case("text/plain") {
+                text = f32::max(text, q);
+            }
+        }
+
+        if openmetrics > 0.0 && openmetrics >= text {
+            TextFormat::OpenMetrics
+        } else {
+            TextFormat::Prometheus
+        }
+    }
+
+    /// The content type of this format, for the `Content-Type` header of the response
+    pub fn content_type(self) -> &'static str {
+        match self {
+            TextFormat::Prometheus => CONTENT_TYPE_TEXT,
+            TextFormat::OpenMetrics => CONTENT_TYPE_OPENMETRICS,
+        }
+    }
+}
+
+/// A metric name that has already been encoded
+struct EncodedName<'a>(&'a [u8]);
+
+impl MetricNameEncoder for EncodedName<'_> {
+    fn encode_utf8(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(self.0)
+    }
+    fn encode_len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Encode the name into the reused buffer
+fn encode_name_into(name: impl MetricNameEncoder, buf: &mut Vec<u8>) {
+    buf.clear();
+    name.encode_utf8(&mut *buf)
+        .expect("writing to a vec should not fail");
+}
+
+/// A help or unit line that is written once the name of the family is known. The buffers are reused.
+#[derive(Default)]
+struct PendingLine {
+    name: Vec<u8>,
+    text: String,
+    pending: bool,
+}
+
+impl PendingLine {
+    fn set(&mut self, name: impl MetricNameEncoder, text: &str) {
+        encode_name_into(name, &mut self.name);
+        self.text.clear();
+        self.text.push_str(text);
+        self.pending = true;
+    }
+
+    /// Take the text of the line, if it was set for the given name
+    fn take_for(&mut self, name: &[u8]) -> Option<&str> {
+        let pending = std::mem::replace(&mut self.pending, false);
+        (pending && self.name == name).then_some(&*self.text)
+    }
+}
+
+/// The terminator written after every line of the exposition. See [`TextEncoder::with_line_ending`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`, as required by the Prometheus text format
+    #[default]
+    Lf,
+    /// `\r\n`, for consumers that expect Windows style line endings
+    CrLf,
+}
+
+impl LineEnding {
+    /// The bytes of the line terminator
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum State {
     Info,
     Metrics,
 }
 
-/// Prometheus only supports these 5 types of metrics
-#[derive(Clone, Copy, Debug)]
+/// The types of metrics supported by the prometheus text format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MetricType {
     /// Corresponds to [`Counter`](crate::Counter)
     Counter,
@@ -44,8 +207,14 @@ pub enum MetricType {
     Gauge,
     /// Not currently supported
     Summary,
-    /// Not currently supported
+    /// A metric without a type, from the `untyped` type of the Prometheus text format.
+    ///
+    /// Written as `unknown` in OpenMetrics, which has no `untyped` type.
     Untyped,
+    /// Corresponds to [`Unknown`](crate::Unknown), from the `unknown` type of OpenMetrics.
+    ///
+    /// Written as `untyped` in the Prometheus text format, which has no `unknown` type.
+    Unknown,
 }
 
 impl<W: Write> Encoding for TextEncoder<W> {
@@ -57,16 +226,37 @@ impl<W: Write> Encoding for TextEncoder<W> {
         name: impl MetricNameEncoder,
         help: &str,
     ) -> Result<(), std::io::Error> {
-        if self.state == State::Metrics {
-            self.write_line()?;
+        self.flush_sorted()?;
+        if self.format == TextFormat::OpenMetrics {
+            // the family name is only known once the type is written
+            self.help.set(name, help);
+            return Ok(());
         }
-        self.state = State::Info;
 
-        self.writer.write_all(b"# HELP ")?;
-        name.encode_utf8(&mut self.writer)?;
-        self.writer.write_all(b" ")?;
-        self.writer.write_all(help.as_bytes())?;
-        self.writer.write_all(b"\n")?;
+        if let Some(header) = &mut self.header {
+            // any pending header belongs to a previous family which had no samples.
+            header.buf.clear();
+            header.has_type = false;
+        }
+        self.write_header(|w, line_ending| {
+            write_help_line(w, name, help, TextFormat::Prometheus, line_ending)
+        })
+    }
+
+    /// Write the unit line for a metric, in the OpenMetrics format.
+    ///
+    /// The Prometheus text format has no units, so nothing is written in that format. OpenMetrics requires the
+    /// name of the family to end in `_` followed by the unit, like `request_duration_seconds` for `seconds`.
+    fn write_unit(
+        &mut self,
+        name: impl MetricNameEncoder,
+        unit: &str,
+    ) -> Result<(), std::io::Error> {
+        self.flush_sorted()?;
+        if self.format == TextFormat::OpenMetrics {
+            // the family name is only known once the type is written
+            self.unit.set(name, unit);
+        }
         Ok(())
     }
 }
@@ -78,18 +268,133 @@ impl<W: Write> TextEncoder<W> {
     pub fn new(w: W) -> Self {
         Self {
             state: State::Info,
+            sorted: None,
+            header: None,
+            skip_trailing_empty_buckets: false,
+            line_ending: LineEnding::Lf,
+            format: TextFormat::Prometheus,
+            family: None,
+            help: PendingLine::default(),
+            unit: PendingLine::default(),
+            name: Vec::new(),
             writer: w,
         }
     }
 
+    /// Write the exposition in the given format. The default is [`TextFormat::Prometheus`].
+    ///
+    /// The format should be reported in the `Content-Type` of the response, see [`TextFormat::content_type`].
+    pub fn with_format(mut self, format: TextFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Change the format of the following expositions. See [`TextEncoder::with_format`]
+    pub fn set_format(&mut self, format: TextFormat) {
+        self.format = format;
+    }
+
+    /// The format of the exposition
+    pub fn format(&self) -> TextFormat {
+        self.format
+    }
+
+    /// Sort the samples of each metric family by their label values before writing them.
+    ///
+    /// Sparse metric vecs are backed by a hashmap, so the order of their samples can change between collections.
+    /// Sorting makes the output deterministic, which is useful for diffing, caching, and snapshot tests.
+    /// This costs some extra memory and CPU, as all the samples of a family are buffered before being written,
+    /// so it is disabled by default.
+    pub fn with_sorted_samples(mut self) -> Self {
+        self.sorted = Some(SortedSamples::default());
+        self
+    }
+
+    /// Do not write the `# HELP` and `# TYPE` lines of metric families that have no samples.
+    ///
+    /// By default, the type of every metric family is written, even if it has no samples yet,
+    /// like a sparse metric vec before any events have been recorded. Some stricter parsers reject a
+    /// family with a `# TYPE` line but no samples. With this set, the header lines are buffered until the
+    /// first sample of the family is written, and dropped if there are none.
+    pub fn skip_empty_families(mut self) -> Self {
+        self.header = Some(PendingHeader::default());
+        self
+    }
+
+    /// Do not write the finite histogram buckets after the last bucket that has any observations.
+    ///
+    /// Histograms with many buckets, where most observations land in the first few, can spend most of the
+    /// exposition on empty buckets. With this set, a histogram writes its finite buckets up to and including
+    /// the last non-empty one, followed by the `+Inf` bucket, sum and count as usual. The buckets stay cumulative,
+    /// but the upper bucket boundaries are no longer listed in the exposition until they have observations,
+    /// and a series can gain new bucket lines over time.
+    ///
+    /// This changes the result of `histogram_quantile` when the quantile falls into the `+Inf` bucket.
+    /// It then returns the upper bound of the highest finite bucket that is listed, which is now the last non-empty
+    /// bucket instead of the largest configured bound. Series that list different buckets also no longer line up
+    /// when they are aggregated with `sum by (le)`.
+    pub fn skip_trailing_empty_buckets(mut self) -> Self {
+        self.skip_trailing_empty_buckets = true;
+        self
+    }
+
+    /// Terminate every line with the given line ending, instead of `\n`.
+    ///
+    /// The Prometheus text format requires `\n`, which is the default. This is only intended for
+    /// consumers that do not follow the format and expect `\r\n`.
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
     /// Finish the text encoding and extract the bytes to send in a HTTP response.
     pub fn flush(&mut self) -> std::io::Result<()> {
+        self.flush_sorted()?;
+        if let Some(header) = &mut self.header {
+            header.buf.clear();
+            header.has_type = false;
+        }
         self.state = State::Info;
+        self.family = None;
+        self.help.pending = false;
+        self.unit.pending = false;
+        if self.format == TextFormat::OpenMetrics {
+            self.writer.write_all(b"# EOF")?;
+            self.write_line()?;
+        }
         self.writer.flush()
     }
 
+    /// Finish the text encoding and return the inner writer. See [`TextEncoder::flush`]
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+
     fn write_line(&mut self) -> std::io::Result<()> {
-        self.writer.write_all(b"\n")
+        self.writer.write_all(self.line_ending.as_bytes())
+    }
+
+    /// Families are separated by an empty line in the prometheus format, which OpenMetrics does not allow
+    fn separate_family(&mut self) -> std::io::Result<()> {
+        if self.state == State::Metrics && self.format == TextFormat::Prometheus {
+            self.write_line()?;
+        }
+        Ok(())
+    }
+
+    /// Write header lines of a family, or buffer them if the family might be empty
+    fn write_header(
+        &mut self,
+        f: impl FnOnce(&mut dyn Write, LineEnding) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        if let Some(header) = &mut self.header {
+            return f(&mut header.buf, self.line_ending);
+        }
+
+        self.separate_family()?;
+        self.state = State::Info;
+        f(&mut self.writer, self.line_ending)
     }
 
     /// Write the type line for a metric
@@ -98,105 +403,421 @@ impl<W: Write> TextEncoder<W> {
         name: &impl MetricNameEncoder,
         typ: MetricType,
     ) -> Result<(), std::io::Error> {
-        if self.state == State::Metrics {
-            self.write_line()?;
+        self.flush_sorted()?;
+        self.family = Some(typ);
+        if let Some(header) = &mut self.header {
+            if header.has_type {
+                // the previous family had no samples.
+                header.buf.clear();
+            }
+            header.has_type = true;
         }
-        self.state = State::Info;
 
-        self.writer.write_all(b"# TYPE ")?;
-        name.encode_utf8(&mut self.writer)?;
-        match typ {
-            MetricType::Counter => self.writer.write_all(b" counter\n"),
-            MetricType::Histogram => self.writer.write_all(b" histogram\n"),
-            MetricType::Gauge => self.writer.write_all(b" gauge\n"),
-            MetricType::Summary => self.writer.write_all(b" summary\n"),
-            MetricType::Untyped => self.writer.write_all(b" untyped\n"),
+        if self.format == TextFormat::Prometheus {
+            return self.write_header(|w, line_ending| {
+                write_type_line(w, name, typ, TextFormat::Prometheus, line_ending)
+            });
+        }
+
+        // the buffers are moved out while writing, and moved back to be reused
+        let mut name_buf = std::mem::take(&mut self.name);
+        let mut help_line = std::mem::take(&mut self.help);
+        let mut unit_line = std::mem::take(&mut self.unit);
+        encode_name_into(name, &mut name_buf);
+
+        let name = &*name_buf;
+        let family = match typ {
+            MetricType::Counter => name.strip_suffix(b"_total").unwrap_or(name),
+            _ => name,
+        };
+        let help = help_line.take_for(name);
+        let unit = unit_line.take_for(name);
+        if let Some(unit) = unit {
+            debug_assert!(
+                family
+                    .strip_suffix(unit.as_bytes())
+                    .is_some_and(|f| f.ends_with(b"_")),
+                "the metric family name must end with its unit `_{unit}` in OpenMetrics",
+            );
+        }
+        let res = self.write_header(|w, line_ending| {
+            write_type_line(
+                w,
+                &EncodedName(family),
+                typ,
+                TextFormat::OpenMetrics,
+                line_ending,
+            )?;
+            if let Some(unit) = unit {
+                write_unit_line(w, EncodedName(family), unit, line_ending)?;
+            }
+            match help {
+                Some(help) => write_help_line(
+                    w,
+                    EncodedName(family),
+                    help,
+                    TextFormat::OpenMetrics,
+                    line_ending,
+                ),
+                None => Ok(()),
+            }
+        });
+
+        self.name = name_buf;
+        self.help = help_line;
+        self.unit = unit_line;
+        res
+    }
+
+    /// Write the bytes into the exposition verbatim, as if they were a metric family of their own.
+    ///
+    /// This is an escape hatch for content which this crate does not model, like a metric type that is not supported
+    /// yet, or a comment that some tooling expects. The bytes are not validated, so they can easily corrupt the
+    /// exposition: they must be complete lines in the format of the exposition, ending in the line ending of the
+    /// encoder. Debug builds panic if the bytes are not UTF-8, do not end in the line ending, or contain an
+    /// OpenMetrics `# EOF` line. Families are separated like usual, so in the Prometheus format, the raw bytes get
+    /// an empty line before and after them.
+    ///
+    /// ```
+    /// use measured::text::BufferedTextEncoder;
+    ///
+    /// let mut enc = BufferedTextEncoder::new();
+    /// enc.write_raw(b"# TYPE rpc_duration_seconds summary\nrpc_duration_seconds{quantile=\"0.5\"} 0.05\n")
+    ///     .unwrap();
+    /// ```
+    pub fn write_raw(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        debug_assert!(
+            std::str::from_utf8(bytes).is_ok(),
+            "raw exposition content must be utf-8"
+        );
+        debug_assert!(
+            bytes.ends_with(self.line_ending.as_bytes()),
+            "raw exposition content must end with a line ending"
+        );
+        debug_assert!(
+            !bytes
+                .split(|&b| b == b'\n')
+                .any(|line| line.strip_suffix(b"\r").unwrap_or(line) == b"# EOF"),
+            "raw exposition content must not contain an EOF line"
+        );
+
+        self.flush_sorted()?;
+        if let Some(header) = &mut self.header {
+            // any pending header belongs to a previous family which had no samples.
+            header.buf.clear();
+            header.has_type = false;
         }
+        self.separate_family()?;
+        self.family = None;
+        self.help.pending = false;
+        self.unit.pending = false;
+        self.writer.write_all(bytes)?;
+        self.state = State::Metrics;
+        Ok(())
     }
 
-    /// Write the metric data
-    fn write_metric_value(
+    /// Write a single sample line for a metric.
+    ///
+    /// A metric can write any number of samples in its [`MetricEncoding::collect_into`], like the
+    /// `_bucket`, `_sum` and `_count` samples of a histogram, using [`MetricNameEncoder::with_suffix`]
+    /// and [`LabelGroup::compose_with`] to extend the name and labels of the series.
+    /// Use [`TextEncoder::write_series`] to keep those samples together.
+    pub fn write_metric_value(
         &mut self,
         name: impl MetricNameEncoder,
         labels: impl LabelGroup,
         value: MetricValue,
     ) -> Result<(), std::io::Error> {
-        struct Visitor<'a, W> {
-            writer: &'a mut W,
+        self.write_pending_header()?;
+        self.state = State::Metrics;
+        if self.format == TextFormat::OpenMetrics && self.family == Some(MetricType::Counter) {
+            // counter samples always end in `_total`
+            let mut name_buf = std::mem::take(&mut self.name);
+            encode_name_into(name, &mut name_buf);
+            let res = if name_buf.ends_with(b"_total") {
+                self.write_sample(EncodedName(&name_buf), labels, value)
+            } else {
+                self.write_sample(EncodedName(&name_buf).with_suffix(Total), labels, value)
+            };
+            self.name = name_buf;
+            return res;
         }
-        impl<W: Write> LabelVisitor for Visitor<'_, W> {
-            type Output = Result<(), std::io::Error>;
-            fn write_int(self, x: i64) -> Result<(), std::io::Error> {
-                self.write_str(itoa::Buffer::new().format(x))
-            }
+        self.write_sample(name, labels, value)
+    }
 
-            fn write_float(self, x: f64) -> Result<(), std::io::Error> {
-                if x.is_infinite() {
-                    if x.is_sign_positive() {
-                        self.write_str("+Inf")
-                    } else {
-                        self.write_str("-Inf")
-                    }
-                } else if x.is_nan() {
-                    self.write_str("NaN")
-                } else {
-                    self.write_str(ryu::Buffer::new().format(x))
+    fn write_sample(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        value: MetricValue,
+    ) -> Result<(), std::io::Error> {
+        match &mut self.sorted {
+            None => write_sample(&mut self.writer, name, labels, value, self.line_ending),
+            Some(sorted) => {
+                if !sorted.grouping {
+                    sorted.begin_series(labels.by_ref());
                 }
+                sorted.write_sample(name, labels, value, self.line_ending);
+                Ok(())
             }
+        }
+    }
 
-            fn write_str(self, x: &str) -> Result<(), std::io::Error> {
-                self.writer.write_all(b"=\"")?;
-                write_label_str_value(x, &mut *self.writer)?;
-                self.writer.write_all(b"\"")?;
-                Ok(())
+    /// Write several samples that all belong to a single series with the given `labels`.
+    ///
+    /// The samples are always written together and in order, even if the samples are being sorted by
+    /// their labels (see [`TextEncoder::with_sorted_samples`]). Metrics which write multiple samples per
+    /// series, like histograms and summaries, should write them in `f`.
+    pub fn write_series<T>(
+        &mut self,
+        labels: impl LabelGroup,
+        f: impl FnOnce(&mut Self) -> Result<T, std::io::Error>,
+    ) -> Result<T, std::io::Error> {
+        let grouping = match &mut self.sorted {
+            // already in a series, so this series will be kept together with the outer series.
+            Some(sorted) if sorted.grouping => false,
+            Some(sorted) => {
+                sorted.begin_series(labels);
+                sorted.grouping = true;
+                true
             }
+            None => false,
+        };
+
+        let res = f(self);
+
+        if let (true, Some(sorted)) = (grouping, &mut self.sorted) {
+            sorted.grouping = false;
         }
+        res
+    }
 
-        struct GroupVisitor<'a, W> {
-            first: bool,
-            writer: &'a mut W,
+    /// Write out the buffered header lines before the first sample of a family, see [`TextEncoder::skip_empty_families`]
+    fn write_pending_header(&mut self) -> Result<(), std::io::Error> {
+        let Some(header) = &mut self.header else {
+            return Ok(());
+        };
+        if header.buf.is_empty() {
+            return Ok(());
         }
-        impl<W: Write> LabelGroupVisitor for GroupVisitor<'_, W> {
-            type Output = Result<(), std::io::Error>;
-            fn write_value(
-                &mut self,
-                name: &LabelName,
-                x: &impl LabelValue,
-            ) -> Result<(), std::io::Error> {
-                if self.first {
-                    self.first = false;
-                    self.writer.write_all(b"{")?;
+
+        if self.state == State::Metrics && self.format == TextFormat::Prometheus {
+            self.writer.write_all(self.line_ending.as_bytes())?;
+        }
+        self.writer.write_all(&header.buf)?;
+        header.buf.clear();
+        header.has_type = false;
+        Ok(())
+    }
+
+    /// Write out any buffered samples, see [`TextEncoder::with_sorted_samples`]
+    fn flush_sorted(&mut self) -> Result<(), std::io::Error> {
+        match &mut self.sorted {
+            Some(sorted) => sorted.flush(&mut self.writer),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_help_line(
+    writer: &mut (impl Write + ?Sized),
+    name: impl MetricNameEncoder,
+    help: &str,
+    format: TextFormat,
+    line_ending: LineEnding,
+) -> Result<(), std::io::Error> {
+    writer.write_all(b"# HELP ")?;
+    name.encode_utf8(&mut &mut *writer)?;
+    writer.write_all(b" ")?;
+    match format {
+        TextFormat::Prometheus => write_help_str_value(help, &mut &mut *writer)?,
+        // OpenMetrics escapes the help text like a label value
+        TextFormat::OpenMetrics => write_label_str_value(help, &mut &mut *writer)?,
+    }
+    writer.write_all(line_ending.as_bytes())
+}
+
+fn write_unit_line(
+    writer: &mut (impl Write + ?Sized),
+    name: impl MetricNameEncoder,
+    unit: &str,
+    line_ending: LineEnding,
+) -> Result<(), std::io::Error> {
+    writer.write_all(b"# UNIT ")?;
+    name.encode_utf8(&mut &mut *writer)?;
+    writer.write_all(b" ")?;
+    writer.write_all(unit.as_bytes())?;
+    writer.write_all(line_ending.as_bytes())
+}
+
+fn write_type_line(
+    writer: &mut (impl Write + ?Sized),
+    name: &impl MetricNameEncoder,
+    typ: MetricType,
+    format: TextFormat,
+    line_ending: LineEnding,
+) -> Result<(), std::io::Error> {
+    writer.write_all(b"# TYPE ")?;
+    name.encode_utf8(&mut &mut *writer)?;
+    match typ {
+        MetricType::Counter => writer.write_all(b" counter")?,
+        MetricType::Histogram => writer.write_all(b" histogram")?,
+        MetricType::Gauge => writer.write_all(b" gauge")?,
+        MetricType::Summary => writer.write_all(b" summary")?,
+        MetricType::Untyped | MetricType::Unknown => match format {
+            TextFormat::Prometheus => writer.write_all(b" untyped")?,
+            TextFormat::OpenMetrics => writer.write_all(b" unknown")?,
+        },
+    }
+    writer.write_all(line_ending.as_bytes())
+}
+
+fn write_label_group(
+    writer: &mut impl Write,
+    labels: impl LabelGroup,
+) -> Result<(), std::io::Error> {
+    struct Visitor<'a, W> {
+        writer: &'a mut W,
+    }
+    impl<W: Write> LabelVisitor for Visitor<'_, W> {
+        type Output = Result<(), std::io::Error>;
+        fn write_int(self, x: i64) -> Result<(), std::io::Error> {
+            self.write_str(itoa::Buffer::new().format(x))
+        }
+
+        fn write_float(self, x: f64) -> Result<(), std::io::Error> {
+            if x.is_infinite() {
+                if x.is_sign_positive() {
+                    self.write_str("+Inf")
                 } else {
-                    self.writer.write_all(b",")?;
+                    self.write_str("-Inf")
                 }
-                self.writer.write_all(name.as_str().as_bytes())?;
-                x.visit(Visitor {
-                    writer: self.writer,
-                })
+            } else if x.is_nan() {
+                self.write_str("NaN")
+            } else if (1e16..i64::MAX as f64).contains(&x.abs()) {
+                // ryu writes these with an exponent, like `1e16`. They are all whole numbers, and they are written
+                // in full instead, which keeps large `le` bounds, like byte sizes, readable
+                self.writer.write_all(b"=\"")?;
+                self.writer
+                    .write_all(itoa::Buffer::new().format(x as i64).as_bytes())?;
+                self.writer.write_all(b".0\"")
+            } else {
+                self.write_str(ryu::Buffer::new().format(x))
             }
         }
 
-        self.state = State::Metrics;
-        name.encode_utf8(&mut self.writer)?;
+        fn write_str(self, x: &str) -> Result<(), std::io::Error> {
+            self.writer.write_all(b"=\"")?;
+            write_label_str_value(x, &mut *self.writer)?;
+            self.writer.write_all(b"\"")?;
+            Ok(())
+        }
+    }
 
-        let mut visitor = GroupVisitor {
-            first: true,
-            writer: &mut self.writer,
-        };
-        labels.visit_values(&mut visitor);
-        if !visitor.first {
-            self.writer.write_all(b"}")?;
-        }
-        self.writer.write_all(b" ")?;
-        match value {
-            MetricValue::Int(x) => self
-                .writer
-                .write_all(itoa::Buffer::new().format(x).as_bytes())?,
-            MetricValue::Float(x) => self
-                .writer
-                .write_all(ryu::Buffer::new().format(x).as_bytes())?,
-        }
-        self.writer.write_all(b"\n")?;
+    struct GroupVisitor<'a, W> {
+        first: bool,
+        writer: &'a mut W,
+    }
+    impl<W: Write> LabelGroupVisitor for GroupVisitor<'_, W> {
+        type Output = Result<(), std::io::Error>;
+        fn write_value(
+            &mut self,
+            name: &LabelName,
+            x: &impl LabelValue,
+        ) -> Result<(), std::io::Error> {
+            if self.first {
+                self.first = false;
+                self.writer.write_all(b"{")?;
+            } else {
+                self.writer.write_all(b",")?;
+            }
+            self.writer.write_all(name.as_str().as_bytes())?;
+            x.visit(Visitor {
+                writer: self.writer,
+            })
+        }
+    }
+
+    let mut visitor = GroupVisitor {
+        first: true,
+        writer,
+    };
+    labels.visit_values(&mut visitor);
+    if !visitor.first {
+        visitor.writer.write_all(b"}")?;
+    }
+    Ok(())
+}
+
+fn write_sample(
+    writer: &mut impl Write,
+    name: impl MetricNameEncoder,
+    labels: impl LabelGroup,
+    value: MetricValue,
+    line_ending: LineEnding,
+) -> Result<(), std::io::Error> {
+    name.encode_utf8(&mut *writer)?;
+    write_label_group(&mut *writer, labels)?;
+    writer.write_all(b" ")?;
+    match value {
+        MetricValue::Int(x) => writer.write_all(itoa::Buffer::new().format(x).as_bytes())?,
+        MetricValue::Float(x) => writer.write_all(ryu::Buffer::new().format(x).as_bytes())?,
+    }
+    writer.write_all(line_ending.as_bytes())?;
+    Ok(())
+}
+
+/// Buffers the header lines of a metric family until its first sample is written.
+#[derive(Default)]
+struct PendingHeader {
+    buf: Vec<u8>,
+    /// Whether the buffer already contains a `# TYPE` line
+    has_type: bool,
+}
+
+/// Buffers the samples of a metric family so they can be sorted by their labels.
+#[derive(Default)]
+struct SortedSamples {
+    buf: Vec<u8>,
+    /// The byte ranges of the labels and the sample lines of each series
+    series: Vec<(Range<usize>, Range<usize>)>,
+    /// Whether the following samples belong to the current series, eg the lines of a histogram
+    grouping: bool,
+}
+
+impl SortedSamples {
+    fn begin_series(&mut self, labels: impl LabelGroup) {
+        let start = self.buf.len();
+        write_label_group(&mut self.buf, labels).expect("writing to a vec should not fail");
+        let end = self.buf.len();
+        self.series.push((start..end, end..end));
+    }
+
+    fn write_sample(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        value: MetricValue,
+        line_ending: LineEnding,
+    ) {
+        write_sample(&mut self.buf, name, labels, value, line_ending)
+            .expect("writing to a vec should not fail");
+        let (_, lines) = self
+            .series
+            .last_mut()
+            .expect("a series should have been started");
+        lines.end = self.buf.len();
+    }
+
+    fn flush(&mut self, writer: &mut impl Write) -> Result<(), std::io::Error> {
+        let buf = &self.buf;
+        // the sort is stable, so series with equal labels keep their relative order
+        self.series
+            .sort_by(|(a, _), (b, _)| buf[a.clone()].cmp(&buf[b.clone()]));
+        for (_, lines) in self.series.drain(..) {
+            writer.write_all(&buf[lines])?;
+        }
+        self.buf.clear();
         Ok(())
     }
 }
@@ -215,69 +836,175 @@ impl<W: Write, const N: usize> MetricEncoding<TextEncoder<W>> for HistogramState
         name: impl MetricNameEncoder,
         enc: &mut TextEncoder<W>,
     ) -> Result<(), std::io::Error> {
-        struct F64(f64);
-        impl LabelValue for F64 {
-            fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
-                v.write_float(self.0)
-            }
-        }
+        let (buckets, inf, sum) = self.inner.write().sample();
+        enc.write_histogram(name, labels, metadata.get(), &buckets, inf, sum)
+    }
+}
 
-        struct HistogramLabelLe {
-            le: f64,
-        }
+impl<W: Write, const N: usize> MetricEncoding<TextEncoder<W>> for AdaptiveHistogramState<N> {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.write_type(&name, MetricType::Histogram)
+    }
+    fn collect_into(
+        &self,
+        metadata: &AdaptiveThresholds<N>,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        let (le, buckets, inf, sum) = self.inner.write().sample(metadata);
+        enc.write_histogram(name, labels, &le, &buckets, inf, sum)
+    }
+}
+
+impl<W: Write> MetricEncoding<TextEncoder<W>> for SummaryState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.write_type(&name, MetricType::Summary)
+    }
+    fn collect_into(
+        &self,
+        metadata: &Quantiles,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        const QUANTILE: &LabelName = LabelName::from_str("quantile");
 
-        impl LabelGroup for HistogramLabelLe {
-            fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
-                const LE: &LabelName = LabelName::from_str("le");
-                v.write_value(LE, &F64(self.le));
+        let mut inner = self.inner.write();
+        // keep all the lines of this summary together when sorting
+        enc.write_series(labels.by_ref(), |enc| {
+            for &(q, _) in metadata.get() {
+                enc.write_metric_value(
+                    name.by_ref(),
+                    labels.by_ref().compose_with(LabelPair(QUANTILE, q)),
+                    MetricValue::Float(inner.query(metadata, q).unwrap_or(f64::NAN)),
+                )?;
             }
-        }
+            enc.write_metric_value(
+                name.by_ref().with_suffix(Sum),
+                labels.by_ref(),
+                MetricValue::Float(inner.sum()),
+            )?;
+            enc.write_metric_value(
+                name.by_ref().with_suffix(Count),
+                labels.by_ref(),
+                MetricValue::Int(inner.count() as i64),
+            )
+        })
+    }
+}
 
-        let (buckets, inf, sum) = self.inner.write().sample();
-        let mut val = 0;
+impl<W: Write> TextEncoder<W> {
+    /// Write the samples for a histogram, given the non-cumulative bucket counts.
+    fn write_histogram(
+        &mut self,
+        name: impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        le: &[f64],
+        buckets: &[u64],
+        inf: u64,
+        sum: f64,
+    ) -> Result<(), std::io::Error> {
+        const LE: &LabelName = LabelName::from_str("le");
 
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..N {
-            let le = metadata.get()[i];
-            val += buckets[i];
+        let buckets = if self.skip_trailing_empty_buckets {
+            let len = buckets.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            &buckets[..len]
+        } else {
+            buckets
+        };
+        let count = buckets.iter().sum::<u64>() + inf;
+
+        // keep all the lines of this histogram together when sorting
+        self.write_series(labels.by_ref(), |enc| {
+            let mut val = 0;
+            for (&le, &bucket) in le.iter().zip(buckets) {
+                val += bucket;
+                enc.write_metric_value(
+                    name.by_ref().with_suffix(Bucket),
+                    labels.by_ref().compose_with(LabelPair(LE, le)),
+                    MetricValue::Int(val as i64),
+                )?;
+            }
             enc.write_metric_value(
                 name.by_ref().with_suffix(Bucket),
-                labels.by_ref().compose_with(HistogramLabelLe { le }),
-                MetricValue::Int(val as i64),
+                labels.by_ref().compose_with(LabelPair(LE, f64::INFINITY)),
+                MetricValue::Int(count as i64),
             )?;
-        }
-        let count = val + inf;
-        enc.write_metric_value(
-            name.by_ref().with_suffix(Bucket),
-            labels
-                .by_ref()
-                .compose_with(HistogramLabelLe { le: f64::INFINITY }),
-            MetricValue::Int(count as i64),
-        )?;
+            enc.write_metric_value(
+                name.by_ref().with_suffix(Sum),
+                labels.by_ref(),
+                MetricValue::Float(sum),
+            )?;
+            enc.write_metric_value(
+                name.by_ref().with_suffix(Count),
+                labels.by_ref(),
+                MetricValue::Int(count as i64),
+            )
+        })
+    }
+}
+
+impl<W: Write> MetricEncoding<TextEncoder<W>> for CounterState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.write_type(&name, MetricType::Counter)
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
         enc.write_metric_value(
-            name.by_ref().with_suffix(Sum),
-            labels.by_ref(),
-            MetricValue::Float(sum),
-        )?;
+            &name,
+            labels,
+            MetricValue::Int(self.count.load(ordering.load()) as i64),
+        )
+    }
+}
+
+impl<W: Write> MetricEncoding<TextEncoder<W>> for GaugeState {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.write_type(&name, MetricType::Gauge)
+    }
+    fn collect_into(
+        &self,
+        ordering: &MemoryOrdering,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
         enc.write_metric_value(
-            name.by_ref().with_suffix(Count),
+            &name,
             labels,
-            MetricValue::Int(count as i64),
-        )?;
-        Ok(())
+            MetricValue::Int(self.count.load(ordering.load())),
+        )
     }
 }
 
-impl<W: Write> MetricEncoding<TextEncoder<W>> for CounterState {
+impl<W: Write> MetricEncoding<TextEncoder<W>> for FloatGaugeState {
     fn write_type(
         name: impl MetricNameEncoder,
         enc: &mut TextEncoder<W>,
     ) -> Result<(), std::io::Error> {
-        enc.write_type(&name, MetricType::Counter)
+        enc.write_type(&name, MetricType::Gauge)
     }
     fn collect_into(
         &self,
-        _m: &(),
+        ordering: &MemoryOrdering,
         labels: impl LabelGroup,
         name: impl MetricNameEncoder,
         enc: &mut TextEncoder<W>,
@@ -285,17 +1012,17 @@ impl<W: Write> MetricEncoding<TextEncoder<W>> for CounterState {
         enc.write_metric_value(
             &name,
             labels,
-            MetricValue::Int(self.count.load(core::sync::atomic::Ordering::Relaxed) as i64),
+            MetricValue::Float(self.count.load(ordering.load())),
         )
     }
 }
 
-impl<W: Write> MetricEncoding<TextEncoder<W>> for GaugeState {
+impl<W: Write> MetricEncoding<TextEncoder<W>> for UnknownState {
     fn write_type(
         name: impl MetricNameEncoder,
         enc: &mut TextEncoder<W>,
     ) -> Result<(), std::io::Error> {
-        enc.write_type(&name, MetricType::Gauge)
+        enc.write_type(&name, MetricType::Unknown)
     }
     fn collect_into(
         &self,
@@ -304,35 +1031,32 @@ impl<W: Write> MetricEncoding<TextEncoder<W>> for GaugeState {
         name: impl MetricNameEncoder,
         enc: &mut TextEncoder<W>,
     ) -> Result<(), std::io::Error> {
-        enc.write_metric_value(
-            &name,
-            labels,
-            MetricValue::Int(self.count.load(core::sync::atomic::Ordering::Relaxed)),
-        )
+        enc.write_metric_value(&name, labels, MetricValue::Float(self.value.get()))
     }
 }
 
-impl<W: Write> MetricEncoding<TextEncoder<W>> for FloatGaugeState {
+impl<W: Write> MetricEncoding<TextEncoder<W>> for SampledCounterState {
     fn write_type(
         name: impl MetricNameEncoder,
         enc: &mut TextEncoder<W>,
     ) -> Result<(), std::io::Error> {
-        enc.write_type(&name, MetricType::Gauge)
+        enc.write_type(&name, MetricType::Counter)
     }
     fn collect_into(
         &self,
-        _m: &(),
+        rate: &SampleRate,
         labels: impl LabelGroup,
         name: impl MetricNameEncoder,
         enc: &mut TextEncoder<W>,
     ) -> Result<(), std::io::Error> {
-        enc.write_metric_value(&name, labels, MetricValue::Float(self.count.get()))
+        enc.write_metric_value(&name, labels, MetricValue::Float(self.estimate(*rate)))
     }
 }
 
 /// The prometheus text encoder helper
 pub struct BufferedTextEncoder {
-    inner: TextEncoder<BytesWriter>,
+    pub(crate) inner: TextEncoder<BytesWriter>,
+    capacity: usize,
 }
 
 impl Default for BufferedTextEncoder {
@@ -341,7 +1065,7 @@ impl Default for BufferedTextEncoder {
     }
 }
 
-trait Unreachable<T> {
+pub(crate) trait Unreachable<T> {
     fn unreachable(self) -> Result<T, Infallible>;
 }
 
@@ -361,6 +1085,11 @@ impl Encoding for BufferedTextEncoder {
     fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Infallible> {
         self.inner.write_help(name, help).unreachable()
     }
+
+    /// Write the unit line for a metric, in the OpenMetrics format
+    fn write_unit(&mut self, name: impl MetricNameEncoder, unit: &str) -> Result<(), Infallible> {
+        self.inner.write_unit(name, unit).unreachable()
+    }
 }
 
 impl BufferedTextEncoder {
@@ -368,20 +1097,180 @@ impl BufferedTextEncoder {
     ///
     /// This should ideally be cached and re-used between collections to reduce re-allocating
     pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Create a new text encoder, with a buffer that has space for at least `capacity` bytes.
+    ///
+    /// The buffer is reserved up-front, and again after every [`finish`](Self::finish), so that encoding
+    /// an exposition of up to `capacity` bytes does not need to re-allocate halfway through.
+    /// If an exposition is larger than `capacity`, the next encode will reserve enough space for that size instead.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
             inner: TextEncoder::new(BytesWriter {
-                buf: BytesMut::new(),
+                buf: BytesMut::with_capacity(capacity),
             }),
+            capacity,
+        }
+    }
+
+    /// Sort the samples of each metric family by their label values. See [`TextEncoder::with_sorted_samples`]
+    pub fn with_sorted_samples(self) -> Self {
+        Self {
+            inner: self.inner.with_sorted_samples(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Do not write the header lines of empty metric families. See [`TextEncoder::skip_empty_families`]
+    pub fn skip_empty_families(self) -> Self {
+        Self {
+            inner: self.inner.skip_empty_families(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Do not write the empty histogram buckets at the end. See [`TextEncoder::skip_trailing_empty_buckets`]
+    pub fn skip_trailing_empty_buckets(self) -> Self {
+        Self {
+            inner: self.inner.skip_trailing_empty_buckets(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Terminate every line with the given line ending. See [`TextEncoder::with_line_ending`]
+    pub fn with_line_ending(self, line_ending: LineEnding) -> Self {
+        Self {
+            inner: self.inner.with_line_ending(line_ending),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Write the exposition in the given format. See [`TextEncoder::with_format`]
+    pub fn with_format(self, format: TextFormat) -> Self {
+        Self {
+            inner: self.inner.with_format(format),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Encode the metric group in the format picked from the `Accept` header of a scrape request,
+    /// returning the body and the content type of the response. See [`TextFormat::from_accept`]
+    ///
+    /// ```
+    /// use measured::{Counter, MetricGroup};
+    /// use measured::text::{BufferedTextEncoder, CONTENT_TYPE_OPENMETRICS};
+    ///
+    /// #[derive(MetricGroup, Default)]
+    /// struct Metrics {
+    ///     events_total: Counter,
+    /// }
+    ///
+    /// let mut enc = BufferedTextEncoder::new();
+    /// let accept = Some("application/openmetrics-text; version=1.0.0");
+    /// let (body, content_type) = enc.encode_for_accept(&Metrics::default(), accept);
+    /// assert_eq!(content_type, CONTENT_TYPE_OPENMETRICS);
+    /// assert_eq!(body, "# TYPE events counter\nevents_total 0\n# EOF\n");
+    /// ```
+    pub fn encode_for_accept<G>(&mut self, group: &G, accept: Option<&str>) -> (Bytes, &'static str)
+    where
+        G: MetricGroup<Self> + ?Sized,
+    {
+        let format = TextFormat::from_accept(accept);
+        self.inner.set_format(format);
+        match group.collect_group_into(self) {
+            Ok(()) => {}
+            Err(never) => match never {},
         }
+        (self.finish(), format.content_type())
+    }
+
+    /// Write the bytes into the exposition verbatim. See [`TextEncoder::write_raw`]
+    pub fn write_raw(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        self.inner.write_raw(bytes)
+    }
+
+    /// Collect any number of metric families and groups into this encoder with the closure, then
+    /// [`finish`](Self::finish) the exposition.
+    ///
+    /// ```
+    /// use measured::{Counter, Gauge};
+    /// use measured::metric::name::MetricName;
+    /// use measured::metric::MetricFamilyEncoding;
+    /// use measured::text::BufferedTextEncoder;
+    ///
+    /// let requests = Counter::new();
+    /// let connections = Gauge::new();
+    /// requests.inc();
+    ///
+    /// let mut enc = BufferedTextEncoder::new();
+    /// let body = enc.encode_with(|enc| {
+    ///     requests.collect_family_into(MetricName::from_str("requests_total"), enc).unwrap();
+    ///     connections.collect_family_into(MetricName::from_str("connections"), enc).unwrap();
+    /// });
+    /// assert_eq!(
+    ///     body,
+    ///     "# TYPE requests_total counter\nrequests_total 1\n\n# TYPE connections gauge\nconnections 0\n",
+    /// );
+    /// ```
+    pub fn encode_with(&mut self, f: impl FnOnce(&mut Self)) -> Bytes {
+        f(self);
+        self.finish()
     }
 
     /// Finish the text encoding and extract the bytes to send in a HTTP response.
     pub fn finish(&mut self) -> Bytes {
         self.inner.flush().unreachable().unwrap();
-        self.inner.writer.buf.split().freeze()
+        let bytes = self.inner.writer.buf.split().freeze();
+
+        if self.capacity > 0 {
+            self.capacity = self.capacity.max(bytes.len());
+            // the returned bytes still share the current allocation, so this cannot reclaim it. Unless the
+            // space left over by the split is large enough, this allocates a fresh buffer, and the old one is
+            // freed once the caller drops the returned bytes.
+            self.inner.writer.buf.reserve(self.capacity);
+        }
+        bytes
     }
 }
 
+/// Encode a single metric family in the prometheus text format.
+///
+/// This allocates a new buffer for every call. Re-use a [`BufferedTextEncoder`] to encode regularly.
+///
+/// ```
+/// use measured::Counter;
+/// use measured::metric::name::MetricName;
+///
+/// let requests = Counter::new();
+/// requests.inc();
+///
+/// let body = measured::text::encode_to_vec(&requests, MetricName::from_str("requests_total"));
+/// assert_eq!(body, b"# TYPE requests_total counter\nrequests_total 1\n");
+/// ```
+pub fn encode_to_vec<M>(metric: &M, name: impl MetricNameEncoder) -> Vec<u8>
+where
+    M: MetricFamilyEncoding<TextEncoder<Vec<u8>>> + ?Sized,
+{
+    let mut enc = TextEncoder::new(Vec::new());
+    metric
+        .collect_family_into(name, &mut enc)
+        .expect("writing to a vec should not fail");
+    enc.finish().expect("writing to a vec should not fail")
+}
+
+/// Encode a single metric family in the prometheus text format, as a string. See [`encode_to_vec`]
+///
+/// # Panics
+/// Panics if the exposition is not valid UTF-8. Names and label values are always UTF-8,
+/// so this can only be caused by a metric which writes invalid bytes, eg through [`TextEncoder::write_raw`].
+pub fn encode_to_string<M>(metric: &M, name: impl MetricNameEncoder) -> String
+where
+    M: MetricFamilyEncoding<TextEncoder<Vec<u8>>> + ?Sized,
+{
+    String::from_utf8(encode_to_vec(metric, name)).expect("the text exposition should be UTF-8")
+}
+
 impl<T: MetricEncoding<TextEncoder<BytesWriter>>> MetricEncoding<BufferedTextEncoder> for T {
     fn write_type(
         name: impl MetricNameEncoder,
@@ -401,6 +1290,21 @@ impl<T: MetricEncoding<TextEncoder<BytesWriter>>> MetricEncoding<BufferedTextEnc
     }
 }
 
+/// Escape the help text in the prometheus format, where only backslashes and newlines are escaped
+pub(crate) fn write_help_str_value(s: &str, b: &mut impl Write) -> io::Result<()> {
+    let mut i = 0;
+    for j in memchr2_iter(b'\\', b'\n', s.as_bytes()) {
+        b.write_all(&s.as_bytes()[i..j])?;
+        match s.as_bytes()[j] {
+            b'\\' => b.write_all(b"\\\\")?,
+            b'\n' => b.write_all(b"\\n")?,
+            _ => unreachable!(),
+        }
+        i = j + 1;
+    }
+    b.write_all(&s.as_bytes()[i..])
+}
+
 pub(crate) fn write_label_str_value(s: &str, b: &mut impl Write) -> io::Result<()> {
     let mut i = 0;
     for j in memchr3_iter(b'\\', b'"', b'\n', s.as_bytes()) {
@@ -416,7 +1320,7 @@ pub(crate) fn write_label_str_value(s: &str, b: &mut impl Write) -> io::Result<(
     b.write_all(&s.as_bytes()[i..])
 }
 
-struct BytesWriter {
+pub(crate) struct BytesWriter {
     buf: BytesMut,
 }
 
@@ -441,7 +1345,7 @@ impl Write for BytesWriter {
 
 #[cfg(test)]
 mod tests {
-    use bytes::{BufMut, BytesMut};
+    use bytes::{BufMut, Bytes, BytesMut};
 
     use crate::{
         label::StaticLabelSet,
@@ -451,10 +1355,10 @@ mod tests {
             name::{MetricName, Total},
             MetricFamilyEncoding,
         },
-        CounterVec, Histogram,
+        CounterVec, Gauge, Histogram, HistogramVec,
     };
 
-    use super::{write_label_str_value, BufferedTextEncoder};
+    use super::{write_label_str_value, BufferedTextEncoder, LineEnding, TextFormat};
 
     #[test]
     fn write_encoded_str() {
@@ -472,6 +1376,49 @@ This is on a new line"#,
         );
     }
 
+    #[test]
+    fn escaped_label_values() {
+        use crate::{
+            label::{LabelName, LabelPair},
+            metric::group::MetricValue,
+        };
+
+        use super::{parse_text_exposition, MetricType, TextEncoder};
+
+        const PATH: &LabelName = LabelName::from_str("path");
+        let values = ["a\"b", "a\\b", "a\nb", "\\\"\n"];
+
+        for format in [TextFormat::Prometheus, TextFormat::OpenMetrics] {
+            let name = MetricName::from_str("hits");
+            let mut enc = TextEncoder::new(Vec::new()).with_format(format);
+            enc.write_type(&name, MetricType::Gauge).unwrap();
+            for value in values {
+                enc.write_metric_value(name, LabelPair(PATH, value), MetricValue::Int(1))
+                    .unwrap();
+            }
+            let output = enc.finish().unwrap();
+
+            let expected = [
+                r#"hits{path="a\"b"} 1"#,
+                r#"hits{path="a\\b"} 1"#,
+                r#"hits{path="a\nb"} 1"#,
+                r#"hits{path="\\\"\n"} 1"#,
+            ];
+            let text = std::str::from_utf8(&output).unwrap();
+            let lines: Vec<_> = text.lines().skip(1).take(values.len()).collect();
+            assert_eq!(lines, expected, "{format:?}");
+
+            // the escaped values are read back as the original values
+            let families = parse_text_exposition(&output).unwrap();
+            let parsed: Vec<_> = families[0]
+                .samples
+                .iter()
+                .map(|s| s.label("path").unwrap())
+                .collect();
+            assert_eq!(parsed, values, "{format:?}");
+        }
+    }
+
     #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
     #[label(crate = crate, set = RequestLabelSet)]
     struct RequestLabels {
@@ -568,4 +1515,593 @@ http_request_duration_seconds_count 4
 "#
         );
     }
+
+    #[test]
+    fn with_capacity_reserves_between_passes() {
+        let histogram = Histogram::with_metadata(Thresholds::<8>::exponential_buckets(0.1, 2.0));
+        let name = MetricName::from_str("http_request_duration_seconds");
+
+        let mut encoder = BufferedTextEncoder::with_capacity(4096);
+        for _ in 0..3 {
+            let ptr = encoder.inner.writer.buf.as_ptr();
+            assert!(encoder.inner.writer.buf.capacity() >= 4096);
+
+            histogram.collect_family_into(name, &mut encoder).unwrap();
+            // no re-allocation happened during the encode pass
+            assert_eq!(encoder.inner.writer.buf.as_ptr(), ptr);
+
+            let bytes = encoder.finish();
+            assert!(!bytes.is_empty());
+        }
+    }
+
+    #[test]
+    fn skip_trailing_empty_buckets() {
+        let thresholds = Thresholds::<6>::with_buckets([0.1, 0.2, 0.5, 1.0, 2.0, 5.0]);
+        let histogram = Histogram::with_metadata(thresholds);
+        let name = MetricName::from_str("request_duration_seconds");
+
+        let mut encoder = BufferedTextEncoder::new().skip_trailing_empty_buckets();
+        histogram.collect_family_into(name, &mut encoder).unwrap();
+        assert_eq!(
+            encoder.finish(),
+            "# TYPE request_duration_seconds histogram\n\
+            request_duration_seconds_bucket{le=\"+Inf\"} 0\n\
+            request_duration_seconds_sum 0.0\n\
+            request_duration_seconds_count 0\n"
+        );
+
+        histogram.get_metric().observe(0.05);
+        histogram.get_metric().observe(0.4);
+        histogram.get_metric().observe(10.0);
+        histogram.collect_family_into(name, &mut encoder).unwrap();
+        assert_eq!(
+            encoder.finish(),
+            "# TYPE request_duration_seconds histogram\n\
+            request_duration_seconds_bucket{le=\"0.1\"} 1\n\
+            request_duration_seconds_bucket{le=\"0.2\"} 1\n\
+            request_duration_seconds_bucket{le=\"0.5\"} 2\n\
+            request_duration_seconds_bucket{le=\"+Inf\"} 3\n\
+            request_duration_seconds_sum 10.45\n\
+            request_duration_seconds_count 3\n"
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "operation")]
+        enum Operation {
+            Read,
+            Write,
+        }
+
+        let requests = CounterVec::<StaticLabelSet<Operation>>::new();
+        requests.inc(Operation::Read);
+        let up = Gauge::new();
+        let name = MetricName::from_str("requests");
+
+        for mut encoder in [
+            BufferedTextEncoder::new().with_line_ending(LineEnding::CrLf),
+            BufferedTextEncoder::new()
+                .with_line_ending(LineEnding::CrLf)
+                .with_sorted_samples()
+                .skip_empty_families(),
+        ] {
+            encoder.write_help(name, "requests").unwrap();
+            requests.collect_family_into(name, &mut encoder).unwrap();
+            up.collect_family_into(MetricName::from_str("up"), &mut encoder)
+                .unwrap();
+            assert_eq!(
+                encoder.finish(),
+                "# HELP requests requests\r\n\
+                # TYPE requests counter\r\n\
+                requests{operation=\"read\"} 1\r\n\
+                \r\n\
+                # TYPE up gauge\r\n\
+                up 0\r\n"
+            );
+        }
+    }
+
+    #[test]
+    fn encode_helpers() {
+        use super::{encode_to_string, TextEncoder};
+
+        let gauge = Gauge::new();
+        gauge.set(-3);
+        let name = MetricName::from_str("temperature");
+        assert_eq!(
+            encode_to_string(&gauge, name),
+            "# TYPE temperature gauge\ntemperature -3\n"
+        );
+
+        // finishing an OpenMetrics exposition writes the EOF marker before returning the writer
+        let mut enc = TextEncoder::new(Vec::new()).with_format(TextFormat::OpenMetrics);
+        gauge.collect_family_into(name, &mut enc).unwrap();
+        assert_eq!(
+            enc.finish().unwrap(),
+            b"# TYPE temperature gauge\ntemperature -3\n# EOF\n"
+        );
+
+        let mut enc = BufferedTextEncoder::new();
+        let body = enc.encode_with(|enc| gauge.collect_family_into(name, enc).unwrap());
+        assert_eq!(body, "# TYPE temperature gauge\ntemperature -3\n");
+        // the encoder is ready for the next exposition
+        assert_eq!(enc.encode_with(|_| {}), "");
+    }
+
+    #[test]
+    fn openmetrics_units() {
+        use crate::{metric::group::MetricGroup, Counter};
+
+        #[derive(crate::MetricGroup)]
+        #[metric(crate = crate)]
+        struct Metrics {
+            /// bytes sent
+            #[metric(unit = "bytes")]
+            sent_bytes_total: Counter,
+            #[metric(unit = "seconds")]
+            latency_seconds: Histogram<1>,
+        }
+
+        let metrics = Metrics {
+            sent_bytes_total: Counter::new(),
+            latency_seconds: Histogram::with_metadata(Thresholds::with_buckets([1.0])),
+        };
+        metrics.sent_bytes_total.inc_by(1024);
+
+        let mut encoder = BufferedTextEncoder::new().with_format(TextFormat::OpenMetrics);
+        metrics.collect_group_into(&mut encoder).unwrap();
+        assert_eq!(
+            encoder.finish(),
+            "# TYPE sent_bytes counter\n\
+            # UNIT sent_bytes bytes\n\
+            # HELP sent_bytes bytes sent\n\
+            sent_bytes_total 1024\n\
+            # TYPE latency_seconds histogram\n\
+            # UNIT latency_seconds seconds\n\
+            latency_seconds_bucket{le=\"1.0\"} 0\n\
+            latency_seconds_bucket{le=\"+Inf\"} 0\n\
+            latency_seconds_sum 0.0\n\
+            latency_seconds_count 0\n\
+            # EOF\n"
+        );
+
+        // the prometheus format has no units
+        let mut encoder = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut encoder).unwrap();
+        assert!(!String::from_utf8_lossy(&encoder.finish()).contains("UNIT"));
+    }
+
+    #[test]
+    fn openmetrics() {
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "operation")]
+        enum Operation {
+            Read,
+            Write,
+        }
+
+        let requests = CounterVec::<StaticLabelSet<Operation>>::new();
+        requests.inc(Operation::Read);
+        let errors = CounterVec::<StaticLabelSet<Operation>>::new();
+        let latency = Histogram::with_metadata(Thresholds::<1>::with_buckets([1.0]));
+        latency.observe(0.5);
+        let up = Gauge::new();
+
+        let collect = |encoder: &mut BufferedTextEncoder| {
+            let name = MetricName::from_str("requests_total");
+            encoder.write_help(name, "total requests").unwrap();
+            requests.collect_family_into(name, &mut *encoder).unwrap();
+            let name = MetricName::from_str("errors");
+            encoder.write_help(name, "errors").unwrap();
+            errors.collect_family_into(name, &mut *encoder).unwrap();
+            latency
+                .collect_family_into(MetricName::from_str("latency"), &mut *encoder)
+                .unwrap();
+            up.collect_family_into(MetricName::from_str("up"), &mut *encoder)
+                .unwrap();
+            encoder.finish()
+        };
+
+        let mut encoder = BufferedTextEncoder::new().with_format(TextFormat::OpenMetrics);
+        assert_eq!(
+            collect(&mut encoder),
+            "# TYPE requests counter\n\
+            # HELP requests total requests\n\
+            requests_total{operation=\"read\"} 1\n\
+            # TYPE errors counter\n\
+            # HELP errors errors\n\
+            # TYPE latency histogram\n\
+            latency_bucket{le=\"1.0\"} 1\n\
+            latency_bucket{le=\"+Inf\"} 1\n\
+            latency_sum 0.5\n\
+            latency_count 1\n\
+            # TYPE up gauge\n\
+            up 0\n\
+            # EOF\n"
+        );
+
+        // the header of an empty family is dropped, along with its help
+        let mut encoder = BufferedTextEncoder::new()
+            .with_format(TextFormat::OpenMetrics)
+            .skip_empty_families()
+            .with_sorted_samples();
+        let name = MetricName::from_str("errors");
+        encoder.write_help(name, "errors").unwrap();
+        errors.collect_family_into(name, &mut encoder).unwrap();
+        up.collect_family_into(MetricName::from_str("up"), &mut encoder)
+            .unwrap();
+        assert_eq!(encoder.finish(), "# TYPE up gauge\nup 0\n# EOF\n");
+    }
+
+    #[test]
+    fn untyped_keyword() {
+        let upstream = crate::Unknown::new();
+        upstream.set(1.5);
+        let name = MetricName::from_str("upstream");
+
+        let mut encoder = BufferedTextEncoder::new();
+        upstream.collect_family_into(name, &mut encoder).unwrap();
+        assert_eq!(encoder.finish(), "# TYPE upstream untyped\nupstream 1.5\n");
+
+        let mut encoder = BufferedTextEncoder::new().with_format(TextFormat::OpenMetrics);
+        upstream.collect_family_into(name, &mut encoder).unwrap();
+        assert_eq!(
+            encoder.finish(),
+            "# TYPE upstream unknown\nupstream 1.5\n# EOF\n"
+        );
+    }
+
+    #[test]
+    fn accept_header() {
+        assert_eq!(TextFormat::from_accept(None), TextFormat::Prometheus);
+        assert_eq!(
+            TextFormat::from_accept(Some("application/openmetrics-text; version=1.0.0")),
+            TextFormat::OpenMetrics
+        );
+        assert_eq!(
+            TextFormat::from_accept(Some("text/plain;q=0.9, application/openmetrics-text;q=0.5")),
+            TextFormat::Prometheus
+        );
+        assert_eq!(
+            TextFormat::from_accept(Some("application/openmetrics-text;q=0, text/plain;q=0.1")),
+            TextFormat::Prometheus
+        );
+        assert_eq!(TextFormat::from_accept(Some("*/*")), TextFormat::Prometheus);
+        assert_eq!(
+            TextFormat::OpenMetrics.content_type(),
+            "application/openmetrics-text; version=1.0.0; charset=utf-8"
+        );
+        assert_eq!(
+            TextFormat::Prometheus.content_type(),
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn text_histogram_negative() {
+        let thresholds = Thresholds::<3>::with_buckets([-1.0, 0.0, 1.0]);
+        let histogram = Histogram::with_metadata(thresholds);
+
+        histogram.get_metric().observe(-5.0);
+        histogram.get_metric().observe(-0.5);
+        histogram.get_metric().observe(2.5);
+
+        let mut encoder = BufferedTextEncoder::default();
+        let name = MetricName::from_str("queue_growth");
+        histogram.collect_family_into(name, &mut encoder).unwrap();
+
+        let s = String::from_utf8(encoder.finish().to_vec()).unwrap();
+        assert_eq!(
+            s,
+            r#"# TYPE queue_growth histogram
+queue_growth_bucket{le="-1.0"} 1
+queue_growth_bucket{le="0.0"} 2
+queue_growth_bucket{le="1.0"} 2
+queue_growth_bucket{le="+Inf"} 3
+queue_growth_sum -3.0
+queue_growth_count 3
+"#
+        );
+    }
+
+    #[test]
+    fn sorted_samples() {
+        #[derive(Clone, Copy, PartialEq, Debug, crate::LabelGroup)]
+        #[label(crate = crate, set = RequestSet)]
+        struct Request {
+            method: Method,
+            shard: Shard,
+        }
+
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case")]
+        enum Method {
+            Get,
+            Post,
+        }
+
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case")]
+        enum Shard {
+            A,
+            B,
+            C,
+            D,
+            E,
+            F,
+            G,
+            H,
+        }
+
+        fn collect(requests_in: &[Request]) -> Bytes {
+            let requests = CounterVec::<RequestSet>::sparse();
+            let latency =
+                HistogramVec::<RequestSet, 2>::sparse_with_metadata(Thresholds::with_buckets([
+                    0.5, 1.0,
+                ]));
+            for &request in requests_in {
+                requests.inc(request);
+                latency.observe(request, 0.7);
+            }
+
+            let mut encoder = BufferedTextEncoder::new().with_sorted_samples();
+            requests
+                .collect_family_into(MetricName::from_str("requests"), &mut encoder)
+                .unwrap();
+            latency
+                .collect_family_into(MetricName::from_str("latency"), &mut encoder)
+                .unwrap();
+            encoder.finish()
+        }
+
+        let mut all = vec![];
+        for method in [Method::Get, Method::Post] {
+            for shard in [
+                Shard::A,
+                Shard::B,
+                Shard::C,
+                Shard::D,
+                Shard::E,
+                Shard::F,
+                Shard::G,
+                Shard::H,
+            ] {
+                all.push(Request { method, shard });
+            }
+        }
+
+        let a = collect(&all);
+        all.reverse();
+        let b = collect(&all);
+        assert_eq!(a, b);
+        assert_eq!(a, collect(&all));
+
+        let s = std::str::from_utf8(&a).unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines[0], "# TYPE requests counter");
+        assert_eq!(lines[1], r#"requests{method="get",shard="a"} 1"#);
+        assert_eq!(lines[2], r#"requests{method="get",shard="b"} 1"#);
+        assert_eq!(lines[16], r#"requests{method="post",shard="h"} 1"#);
+        assert_eq!(lines[18], "# TYPE latency histogram");
+        // the lines of each histogram are kept together
+        assert_eq!(
+            lines[19..24],
+            [
+                r#"latency_bucket{method="get",shard="a",le="0.5"} 0"#,
+                r#"latency_bucket{method="get",shard="a",le="1.0"} 1"#,
+                r#"latency_bucket{method="get",shard="a",le="+Inf"} 1"#,
+                r#"latency_sum{method="get",shard="a"} 0.7"#,
+                r#"latency_count{method="get",shard="a"} 1"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_empty_families() {
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "kind")]
+        enum Kind {
+            Read,
+            Write,
+        }
+
+        let empty = CounterVec::<StaticLabelSet<Kind>>::sparse();
+        let requests = CounterVec::<StaticLabelSet<Kind>>::sparse();
+        requests.inc(Kind::Write);
+        let up = Gauge::new();
+
+        let collect = |mut enc: BufferedTextEncoder| {
+            enc.write_help(MetricName::from_str("empty_help"), "no samples")
+                .unwrap();
+            empty
+                .collect_family_into(MetricName::from_str("empty_help"), &mut enc)
+                .unwrap();
+            enc.write_help(MetricName::from_str("requests"), "requests")
+                .unwrap();
+            requests
+                .collect_family_into(MetricName::from_str("requests"), &mut enc)
+                .unwrap();
+            empty
+                .collect_family_into(MetricName::from_str("empty"), &mut enc)
+                .unwrap();
+            up.collect_family_into(MetricName::from_str("up"), &mut enc)
+                .unwrap();
+            empty
+                .collect_family_into(MetricName::from_str("empty_trailing"), &mut enc)
+                .unwrap();
+            enc.finish()
+        };
+
+        // the type of empty families is written by default
+        assert_eq!(
+            collect(BufferedTextEncoder::new()),
+            "# HELP empty_help no samples\n# TYPE empty_help counter\n\
+            # HELP requests requests\n# TYPE requests counter\nrequests{kind=\"write\"} 1\n\n\
+            # TYPE empty counter\n\
+            # TYPE up gauge\nup 0\n\n\
+            # TYPE empty_trailing counter\n"
+        );
+
+        let expected =
+            "# HELP requests requests\n# TYPE requests counter\nrequests{kind=\"write\"} 1\n\n\
+            # TYPE up gauge\nup 0\n";
+        assert_eq!(
+            collect(BufferedTextEncoder::new().skip_empty_families()),
+            expected
+        );
+        assert_eq!(
+            collect(
+                BufferedTextEncoder::new()
+                    .skip_empty_families()
+                    .with_sorted_samples()
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn multiple_samples_per_series() {
+        use std::io::Write;
+
+        use crate::{
+            label::{LabelGroup, LabelName, LabelPair},
+            metric::{
+                group::MetricValue,
+                name::{Count, MetricNameEncoder, Sum},
+                MetricEncoding, MetricType,
+            },
+            text::{MetricType as TextMetricType, TextEncoder},
+            MetricVec,
+        };
+
+        /// A fixed summary, to check that metrics can write several samples per series
+        #[derive(Default)]
+        struct SummaryState;
+
+        impl MetricType for SummaryState {
+            type Metadata = ();
+        }
+
+        impl<W: Write> MetricEncoding<TextEncoder<W>> for SummaryState {
+            fn write_type(
+                name: impl MetricNameEncoder,
+                enc: &mut TextEncoder<W>,
+            ) -> Result<(), std::io::Error> {
+                enc.write_type(&name, TextMetricType::Summary)
+            }
+
+            fn collect_into(
+                &self,
+                _: &(),
+                labels: impl LabelGroup,
+                name: impl MetricNameEncoder,
+                enc: &mut TextEncoder<W>,
+            ) -> Result<(), std::io::Error> {
+                const QUANTILE: &LabelName = LabelName::from_str("quantile");
+                enc.write_series(labels.by_ref(), |enc| {
+                    for (q, v) in [(0.5, 1.0), (0.99, 4.0)] {
+                        enc.write_metric_value(
+                            name.by_ref(),
+                            labels.by_ref().compose_with(LabelPair(QUANTILE, q)),
+                            MetricValue::Float(v),
+                        )?;
+                    }
+                    enc.write_metric_value(
+                        name.by_ref().with_suffix(Sum),
+                        labels.by_ref(),
+                        MetricValue::Float(10.0),
+                    )?;
+                    enc.write_metric_value(
+                        name.by_ref().with_suffix(Count),
+                        labels.by_ref(),
+                        MetricValue::Int(5),
+                    )
+                })
+            }
+        }
+
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, rename_all = "snake_case", singleton = "route")]
+        enum Route {
+            Users,
+            Home,
+        }
+
+        let summaries = MetricVec::<SummaryState, StaticLabelSet<Route>>::sparse();
+        summaries.get_metric(summaries.with_labels(Route::Users));
+        summaries.get_metric(summaries.with_labels(Route::Home));
+
+        let mut enc = BufferedTextEncoder::new().with_sorted_samples();
+        summaries
+            .collect_family_into(MetricName::from_str("rpc_seconds"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            r#"# TYPE rpc_seconds summary
+rpc_seconds{route="home",quantile="0.5"} 1.0
+rpc_seconds{route="home",quantile="0.99"} 4.0
+rpc_seconds_sum{route="home"} 10.0
+rpc_seconds_count{route="home"} 5
+rpc_seconds{route="users",quantile="0.5"} 1.0
+rpc_seconds{route="users",quantile="0.99"} 4.0
+rpc_seconds_sum{route="users"} 10.0
+rpc_seconds_count{route="users"} 5
+"#
+        );
+    }
+
+    #[test]
+    fn write_raw() {
+        const RAW: &[u8] = b"# TYPE rpc_duration_seconds summary\n\
+            rpc_duration_seconds{quantile=\"0.5\"} 0.05\n\
+            rpc_duration_seconds_sum 1.5\n\
+            rpc_duration_seconds_count 30\n";
+
+        let requests = Gauge::new();
+        requests.set(3);
+
+        let mut enc = BufferedTextEncoder::new();
+        requests
+            .collect_family_into(MetricName::from_str("requests"), &mut enc)
+            .unwrap();
+        enc.write_raw(RAW).unwrap();
+        requests
+            .collect_family_into(MetricName::from_str("inflight"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            [
+                &b"# TYPE requests gauge\nrequests 3\n\n"[..],
+                RAW,
+                b"\n# TYPE inflight gauge\ninflight 3\n",
+            ]
+            .concat()
+        );
+
+        #[derive(Clone, Copy, PartialEq, Debug, crate::FixedCardinalityLabel)]
+        #[label(crate = crate, singleton = "kind")]
+        enum Kind {
+            Timeout,
+        }
+
+        // the header of an empty family before the raw content is dropped
+        let mut enc = BufferedTextEncoder::new().skip_empty_families();
+        CounterVec::<StaticLabelSet<Kind>>::new()
+            .collect_family_into(MetricName::from_str("errors"), &mut enc)
+            .unwrap();
+        enc.write_raw(RAW).unwrap();
+        assert_eq!(enc.finish(), RAW);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "raw exposition content must end with a line ending"]
+    fn write_raw_without_newline() {
+        BufferedTextEncoder::new()
+            .write_raw(b"# some comment")
+            .unwrap();
+    }
 }
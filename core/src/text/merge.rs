@@ -0,0 +1,225 @@
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::{
+    parse_text_exposition, write_help_str_value, write_label_str_value, MetricType, ParseError,
+    ParsedFamily,
+};
+
+/// Concatenate multiple text expositions into a single valid exposition.
+///
+/// This is useful when migrating from another metrics library, like the `prometheus` crate,
+/// where both libraries need to be served from the same `/metrics` endpoint for a while.
+///
+/// Prometheus rejects an exposition that declares the same metric family twice, so families
+/// with the same name are merged:
+/// * The first `# HELP` line for a family wins. Later help text is dropped.
+/// * The `# TYPE` lines must agree. A family declared with two different types is an error.
+/// * The samples are concatenated in order. If the exact same series (name and labels) appears
+///   more than once, only the first sample is kept.
+///
+/// ```
+/// use measured::text::concat_expositions;
+///
+/// let a = b"# HELP requests total requests\n# TYPE requests counter\nrequests{app=\"new\"} 3\n";
+/// let b = b"# HELP requests Total requests.\n# TYPE requests counter\nrequests{app=\"old\"} 5\n";
+///
+/// let merged = concat_expositions(&[a, b]).unwrap();
+/// assert_eq!(
+///     std::str::from_utf8(&merged).unwrap(),
+///     "# HELP requests total requests\n# TYPE requests counter\nrequests{app=\"new\"} 3\nrequests{app=\"old\"} 5\n",
+/// );
+/// ```
+pub fn concat_expositions(expositions: &[&[u8]]) -> Result<Vec<u8>, ConcatError> {
+    let mut families = Vec::<ParsedFamily>::new();
+    // the index of every family in `families` by name
+    let mut index = FxHashMap::<String, usize>::default();
+    // the series already in each family, by sample name and labels
+    let mut series = Vec::<FxHashSet<(String, Vec<(String, String)>)>>::new();
+
+    for exposition in expositions {
+        for family in parse_text_exposition(exposition).map_err(ConcatError::Parse)? {
+            let Some(&i) = index.get(&family.name) else {
+                index.insert(family.name.clone(), families.len());
+                series.push(
+                    family
+                        .samples
+                        .iter()
+                        .map(|s| (s.name.clone(), s.labels.clone()))
+                        .collect(),
+                );
+                families.push(family);
+                continue;
+            };
+
+            let existing = &mut families[i];
+            match (existing.metric_type, family.metric_type) {
+                (Some(expected), Some(found)) if expected != found => {
+                    return Err(ConcatError::TypeMismatch {
+                        family: family.name,
+                        expected,
+                        found,
+                    });
+                }
+                _ => {}
+            }
+            existing.help = existing.help.take().or(family.help);
+            existing.metric_type = existing.metric_type.or(family.metric_type);
+            for sample in family.samples {
+                if series[i].insert((sample.name.clone(), sample.labels.clone())) {
+                    existing.samples.push(sample);
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for (i, family) in families.iter().enumerate() {
+        if i > 0 {
+            out.push(b'\n');
+        }
+        write_family(family, &mut out).expect("writing to a vec should not fail");
+    }
+    Ok(out)
+}
+
+/// The error returned by [`concat_expositions`]
+#[derive(Debug, PartialEq)]
+pub enum ConcatError {
+    /// One of the expositions could not be parsed
+    Parse(ParseError),
+    /// The family was declared before with a different type
+    TypeMismatch {
+        /// The name of the family
+        family: String,
+        /// The type of the family in an earlier exposition
+        expected: MetricType,
+        /// The type of the family in a later exposition
+        found: MetricType,
+    },
+}
+
+impl fmt::Display for ConcatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => e.fmt(f),
+            Self::TypeMismatch {
+                family,
+                expected,
+                found,
+            } => write!(
+                f,
+                "metric family {family} has type {found:?}, but it was declared before with type {expected:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConcatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse(e) => Some(e),
+            Self::TypeMismatch { .. } => None,
+        }
+    }
+}
+
+fn write_family(family: &ParsedFamily, w: &mut impl Write) -> io::Result<()> {
+    if let Some(help) = &family.help {
+        write!(w, "# HELP {} ", family.name)?;
+        write_help_str_value(help, w)?;
+        w.write_all(b"\n")?;
+    }
+    if let Some(typ) = family.metric_type {
+        let typ = match typ {
+            MetricType::Counter => "counter",
+            MetricType::Histogram => "histogram",
+            MetricType::Gauge => "gauge",
+            MetricType::Summary => "summary",
+            // `unknown` is only valid in OpenMetrics
+            MetricType::Untyped | MetricType::Unknown => "untyped",
+        };
+        writeln!(w, "# TYPE {} {typ}", family.name)?;
+    }
+
+    for sample in &family.samples {
+        w.write_all(sample.name.as_bytes())?;
+        for (i, (name, value)) in sample.labels.iter().enumerate() {
+            w.write_all(if i == 0 { b"{" } else { b"," })?;
+            write!(w, "{name}=\"")?;
+            write_label_str_value(value, w)?;
+            w.write_all(b"\"")?;
+        }
+        if !sample.labels.is_empty() {
+            w.write_all(b"}")?;
+        }
+
+        let value = sample.value;
+        if value.is_nan() {
+            w.write_all(b" NaN")?;
+        } else if value.is_infinite() {
+            w.write_all(if value > 0.0 { b" +Inf" } else { b" -Inf" })?;
+        } else {
+            write!(w, " {value}")?;
+        }
+
+        if let Some(ts) = sample.timestamp {
+            write!(w, " {ts}")?;
+        }
+        w.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text::{parse_text_exposition, MetricType};
+
+    use super::{concat_expositions, ConcatError};
+
+    #[test]
+    fn merge_conflicts() {
+        let a = b"# HELP up is up\n# TYPE up gauge\nup 1\n\n# TYPE a_total counter\na_total{x=\"1\"} 2\n";
+        let b =
+            b"# HELP up Is up.\nup 0\nup{job=\"old\"} 1\n\nb{y=\"a\\\\b\\n\"} 1.5 1700000000000\n";
+
+        let merged = concat_expositions(&[a, b]).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&merged).unwrap(),
+            "# HELP up is up\n# TYPE up gauge\nup 1\nup{job=\"old\"} 1\n\n\
+            # TYPE a_total counter\na_total{x=\"1\"} 2\n\n\
+            b{y=\"a\\\\b\\n\"} 1.5 1700000000000\n"
+        );
+
+        let families = parse_text_exposition(&merged).unwrap();
+        assert_eq!(families.len(), 3);
+        assert_eq!(families[0].metric_type, Some(MetricType::Gauge));
+        assert_eq!(families[2].samples[0].label("y"), Some("a\\b\n"));
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let a = b"# TYPE up gauge\nup 1\n";
+        let b = b"# TYPE up untyped\nup 0\n";
+
+        assert_eq!(
+            concat_expositions(&[a, b]),
+            Err(ConcatError::TypeMismatch {
+                family: "up".to_owned(),
+                expected: MetricType::Gauge,
+                found: MetricType::Untyped,
+            })
+        );
+    }
+
+    #[test]
+    fn help_escapes() {
+        let a = b"# HELP foo line one\\nline two \\\\ done\nfoo +Inf\n";
+        let merged = concat_expositions(&[a, b""]).unwrap();
+        assert_eq!(&*merged, a);
+    }
+}
@@ -0,0 +1,442 @@
+use std::fmt;
+
+use super::MetricType;
+
+/// A metric family parsed from the prometheus text exposition format
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParsedFamily {
+    /// The name of the metric family
+    pub name: String,
+    /// The help text from the `# HELP` line, if present
+    pub help: Option<String>,
+    /// The type from the `# TYPE` line, if present
+    pub metric_type: Option<MetricType>,
+    /// All samples that belong to this family.
+    ///
+    /// For histograms and summaries, this includes the `_bucket`, `_sum` and `_count` samples.
+    pub samples: Vec<ParsedSample>,
+}
+
+/// A single sample line parsed from the prometheus text exposition format
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParsedSample {
+    /// The full name of this sample, including any suffix like `_bucket`
+    pub name: String,
+    /// The label pairs of this sample, in the order they were written
+    pub labels: Vec<(String, String)>,
+    /// The sample value
+    pub value: f64,
+    /// The exact sample value, if it was written as an integer that fits in a `u64`.
+    ///
+    /// Counters are written as integers, and can be larger than `value` can represent exactly.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub int_value: Option<u64>,
+    /// The optional timestamp in milliseconds
+    pub timestamp: Option<i64>,
+}
+
+impl ParsedSample {
+    /// Find the value of the label with the given name
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| &**v)
+    }
+}
+
+/// The error returned by [`parse_text_exposition`]
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    line: usize,
+    kind: ParseErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseErrorKind {
+    InvalidUtf8,
+    InvalidMetricName,
+    InvalidLabel,
+    InvalidValue,
+    InvalidTimestamp,
+    UnknownType,
+    TrailingData,
+}
+
+impl ParseError {
+    /// The 1-indexed line number that the error occurred on
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self.kind {
+            ParseErrorKind::InvalidUtf8 => "exposition is not valid utf8",
+            ParseErrorKind::InvalidMetricName => "invalid metric name",
+            ParseErrorKind::InvalidLabel => "invalid label pair",
+            ParseErrorKind::InvalidValue => "invalid sample value",
+            ParseErrorKind::InvalidTimestamp => "invalid sample timestamp",
+            ParseErrorKind::UnknownType => "unknown metric type",
+            ParseErrorKind::TrailingData => "unexpected data at end of line",
+        };
+        write!(f, "{msg} on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse the prometheus text exposition format into structured families.
+///
+/// This accepts the format produced by [`TextEncoder`](super::TextEncoder), which makes it useful for
+/// round-trip tests, and for federation where an upstream exposition needs to be ingested.
+///
+/// Samples are grouped into the family declared by the preceding `# HELP` or `# TYPE` lines.
+/// Samples that do not belong to the current family start a new untyped family.
+///
+/// ```
+/// use measured::text::{parse_text_exposition, MetricType};
+///
+/// let families = parse_text_exposition(b"# TYPE requests counter\nrequests{method=\"get\"} 3\n").unwrap();
+/// assert_eq!(families[0].name, "requests");
+/// assert_eq!(families[0].metric_type, Some(MetricType::Counter));
+/// assert_eq!(families[0].samples[0].label("method"), Some("get"));
+/// assert_eq!(families[0].samples[0].value, 3.0);
+/// ```
+pub fn parse_text_exposition(input: &[u8]) -> Result<Vec<ParsedFamily>, ParseError> {
+    let input = core::str::from_utf8(input).map_err(|_| ParseError {
+        line: 1,
+        kind: ParseErrorKind::InvalidUtf8,
+    })?;
+
+    let mut families = Vec::<ParsedFamily>::new();
+    for (i, line) in input.lines().enumerate() {
+        let err = |kind| ParseError { line: i + 1, kind };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim_start();
+            let (keyword, rest) = comment.split_once(' ').unwrap_or((comment, ""));
+            match keyword {
+                "HELP" => {
+                    let (name, help) = rest.split_once(' ').unwrap_or((rest, ""));
+                    check_name(name).ok_or_else(|| err(ParseErrorKind::InvalidMetricName))?;
+                    family_for_header(&mut families, name, |f| f.help.is_none()).help =
+                        Some(unescape_help(help));
+                }
+                "TYPE" => {
+                    let (name, typ) = rest.split_once(' ').unwrap_or((rest, ""));
+                    check_name(name).ok_or_else(|| err(ParseErrorKind::InvalidMetricName))?;
+                    let typ =
+                        parse_type(typ.trim()).ok_or_else(|| err(ParseErrorKind::UnknownType))?;
+                    family_for_header(&mut families, name, |f| f.metric_type.is_none())
+                        .metric_type = Some(typ);
+                }
+                // other comments are ignored
+                _ => {}
+            }
+            continue;
+        }
+
+        let sample = parse_sample(line).map_err(err)?;
+        match families.last_mut() {
+            Some(family) if belongs_to(family, &sample.name) => family.samples.push(sample),
+            _ => families.push(ParsedFamily {
+                name: sample.name.clone(),
+                help: None,
+                metric_type: None,
+                samples: vec![sample],
+            }),
+        }
+    }
+
+    Ok(families)
+}
+
+/// Find the family that a `# HELP` or `# TYPE` header should attach to, creating a new one if needed.
+fn family_for_header<'a>(
+    families: &'a mut Vec<ParsedFamily>,
+    name: &str,
+    missing: impl Fn(&ParsedFamily) -> bool,
+) -> &'a mut ParsedFamily {
+    let reuse =
+        matches!(families.last(), Some(f) if f.name == name && f.samples.is_empty() && missing(f));
+    if !reuse {
+        families.push(ParsedFamily {
+            name: name.to_owned(),
+            help: None,
+            metric_type: None,
+            samples: vec![],
+        });
+    }
+    families.last_mut().unwrap()
+}
+
+fn belongs_to(family: &ParsedFamily, sample: &str) -> bool {
+    let Some(suffix) = sample.strip_prefix(&*family.name) else {
+        return false;
+    };
+    match family.metric_type {
+        _ if suffix.is_empty() => true,
+        Some(MetricType::Histogram) => matches!(suffix, "_bucket" | "_sum" | "_count"),
+        Some(MetricType::Summary) => matches!(suffix, "_sum" | "_count"),
+        _ => false,
+    }
+}
+
+fn parse_type(s: &str) -> Option<MetricType> {
+    match s {
+        "counter" => Some(MetricType::Counter),
+        "histogram" => Some(MetricType::Histogram),
+        "gauge" => Some(MetricType::Gauge),
+        "summary" => Some(MetricType::Summary),
+        "untyped" => Some(MetricType::Untyped),
+        "unknown" => Some(MetricType::Unknown),
+        _ => None,
+    }
+}
+
+fn is_name_char(c: char, first: bool, colons: bool) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || (colons && c == ':') || (!first && c.is_ascii_digit())
+}
+
+fn check_name(name: &str) -> Option<()> {
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    (is_name_char(first, true, true) && chars.all(|c| is_name_char(c, false, true))).then_some(())
+}
+
+/// Split off the leading name from the string
+fn take_name(s: &str, colons: bool) -> (&str, &str) {
+    let end = s
+        .char_indices()
+        .find(|&(i, c)| !is_name_char(c, i == 0, colons))
+        .map_or(s.len(), |(i, _)| i);
+    s.split_at(end)
+}
+
+fn parse_sample(line: &str) -> Result<ParsedSample, ParseErrorKind> {
+    let (name, mut rest) = take_name(line, true);
+    if name.is_empty() {
+        return Err(ParseErrorKind::InvalidMetricName);
+    }
+
+    let mut labels = vec![];
+    if let Some(r) = rest.strip_prefix('{') {
+        rest = r.trim_start();
+        loop {
+            if let Some(r) = rest.strip_prefix('}') {
+                rest = r;
+                break;
+            }
+
+            let (label, r) = take_name(rest, false);
+            if label.is_empty() {
+                return Err(ParseErrorKind::InvalidLabel);
+            }
+            let r = r
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or(ParseErrorKind::InvalidLabel)?;
+            let r = r
+                .trim_start()
+                .strip_prefix('"')
+                .ok_or(ParseErrorKind::InvalidLabel)?;
+            let (value, r) = take_label_value(r).ok_or(ParseErrorKind::InvalidLabel)?;
+            labels.push((label.to_owned(), value));
+
+            rest = r.trim_start();
+            if let Some(r) = rest.strip_prefix(',') {
+                rest = r.trim_start();
+            } else if !rest.starts_with('}') {
+                return Err(ParseErrorKind::InvalidLabel);
+            }
+        }
+    }
+
+    let mut parts = rest.split_ascii_whitespace();
+    let raw_value = parts.next().ok_or(ParseErrorKind::InvalidValue)?;
+    let value = raw_value
+        .parse::<f64>()
+        .map_err(|_| ParseErrorKind::InvalidValue)?;
+    let int_value = raw_value.parse::<u64>().ok();
+    let timestamp = parts
+        .next()
+        .map(|t| {
+            t.parse::<i64>()
+                .map_err(|_| ParseErrorKind::InvalidTimestamp)
+        })
+        .transpose()?;
+    if parts.next().is_some() {
+        return Err(ParseErrorKind::TrailingData);
+    }
+
+    Ok(ParsedSample {
+        name: name.to_owned(),
+        labels,
+        value,
+        int_value,
+        timestamp,
+    })
+}
+
+/// Parse an escaped label value, up to and including the closing quote
+fn take_label_value(s: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                c @ ('\\' | '"') => value.push(c),
+                _ => return None,
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+fn unescape_help(s: &str) -> String {
+    let mut help = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                help.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                help.push('\\');
+            }
+            (c, _) => help.push(c),
+        }
+    }
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        label::StaticLabelSet,
+        metric::{group::Encoding, histogram::Thresholds, name::MetricName, MetricFamilyEncoding},
+        text::{BufferedTextEncoder, MetricType},
+        CounterVec, Histogram,
+    };
+
+    use super::{parse_text_exposition, ParseError, ParseErrorKind};
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "method")]
+    enum Method {
+        Post,
+        Get,
+    }
+
+    #[test]
+    fn round_trip() {
+        let requests = CounterVec::<StaticLabelSet<Method>>::new();
+        requests.inc_by(Method::Post, 3);
+        requests.inc_by(Method::Get, 5);
+
+        let histogram = Histogram::with_metadata(Thresholds::<2>::with_buckets([0.5, 1.0]));
+        histogram.get_metric().observe(0.7);
+
+        let mut enc = BufferedTextEncoder::new();
+        let name = MetricName::from_str("requests");
+        enc.write_help(name, "number of \"requests\" per method")
+            .unwrap();
+        requests.collect_family_into(name, &mut enc).unwrap();
+        histogram
+            .collect_family_into(MetricName::from_str("latency"), &mut enc)
+            .unwrap();
+
+        let families = parse_text_exposition(&enc.finish()).unwrap();
+        assert_eq!(families.len(), 2);
+
+        assert_eq!(families[0].name, "requests");
+        assert_eq!(families[0].metric_type, Some(MetricType::Counter));
+        assert_eq!(
+            families[0].help.as_deref(),
+            Some("number of \"requests\" per method")
+        );
+        assert_eq!(families[0].samples.len(), 2);
+        assert_eq!(families[0].samples[0].label("method"), Some("post"));
+        assert_eq!(families[0].samples[0].value, 3.0);
+        assert_eq!(families[0].samples[1].label("method"), Some("get"));
+        assert_eq!(families[0].samples[1].value, 5.0);
+
+        assert_eq!(families[1].name, "latency");
+        assert_eq!(families[1].metric_type, Some(MetricType::Histogram));
+        assert_eq!(families[1].help, None);
+        let samples = &families[1].samples;
+        assert_eq!(samples.len(), 5);
+        assert_eq!(samples[0].name, "latency_bucket");
+        assert_eq!(samples[0].label("le"), Some("0.5"));
+        assert_eq!(samples[0].value, 0.0);
+        assert_eq!(samples[2].label("le"), Some("+Inf"));
+        assert_eq!(samples[2].value, 1.0);
+        assert_eq!(samples[3].name, "latency_sum");
+        assert_eq!(samples[3].value, 0.7);
+        assert_eq!(samples[4].name, "latency_count");
+    }
+
+    #[test]
+    fn untyped_and_escapes() {
+        let families = parse_text_exposition(
+            b"# some comment\nfoo{a=\"x\\\"y\\\\z\\nw\",b=\"\",} +Inf 1700000000000\nbar NaN\n",
+        )
+        .unwrap();
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].metric_type, None);
+        let sample = &families[0].samples[0];
+        assert_eq!(sample.label("a"), Some("x\"y\\z\nw"));
+        assert_eq!(sample.label("b"), Some(""));
+        assert_eq!(sample.value, f64::INFINITY);
+        assert_eq!(sample.timestamp, Some(1700000000000));
+        assert!(families[1].samples[0].value.is_nan());
+    }
+
+    #[test]
+    fn unknown_type() {
+        let families = parse_text_exposition(b"# TYPE upstream unknown\nupstream 1.5\n").unwrap();
+        assert_eq!(families[0].metric_type, Some(MetricType::Unknown));
+        assert_eq!(families[0].samples[0].value, 1.5);
+
+        let families = parse_text_exposition(b"# TYPE upstream untyped\nupstream 1.5\n").unwrap();
+        assert_eq!(families[0].metric_type, Some(MetricType::Untyped));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |line, kind| Err(ParseError { line, kind });
+        assert_eq!(
+            parse_text_exposition(b"foo 1\nfoo{a=b} 1\n"),
+            err(2, ParseErrorKind::InvalidLabel)
+        );
+        assert_eq!(
+            parse_text_exposition(b"foo bar\n"),
+            err(1, ParseErrorKind::InvalidValue)
+        );
+        assert_eq!(
+            parse_text_exposition(b"# TYPE foo thing\n"),
+            err(1, ParseErrorKind::UnknownType)
+        );
+        assert_eq!(
+            parse_text_exposition(b"foo 1 2 3\n"),
+            err(1, ParseErrorKind::TrailingData)
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use bytes::Bytes;
+use http::{header, HeaderValue, Response, StatusCode};
+
+use crate::metric::group::MetricGroup;
+
+use super::BufferedTextEncoder;
+
+impl BufferedTextEncoder {
+    /// Encode the metric group into a complete HTTP response for a scrape request, given the value of its
+    /// `Accept` header.
+    ///
+    /// The format is negotiated like [`encode_for_accept`](Self::encode_for_accept), and the response has the
+    /// matching `Content-Type` header. This works with any framework built on the `http` crate.
+    ///
+    /// ```
+    /// use measured::{Counter, MetricGroup};
+    /// use measured::text::{BufferedTextEncoder, CONTENT_TYPE_TEXT};
+    ///
+    /// #[derive(MetricGroup, Default)]
+    /// struct Metrics {
+    ///     events_total: Counter,
+    /// }
+    ///
+    /// let mut enc = BufferedTextEncoder::new();
+    /// let response = enc.encode_response(&Metrics::default(), None);
+    /// assert_eq!(response.headers()["content-type"], CONTENT_TYPE_TEXT);
+    /// assert_eq!(response.body(), "# TYPE events_total counter\nevents_total 0\n");
+    /// ```
+    pub fn encode_response<G>(&mut self, group: &G, accept: Option<&HeaderValue>) -> Response<Bytes>
+    where
+        G: MetricGroup<Self> + ?Sized,
+    {
+        // a header which is not valid utf-8 can't be parsed, so it is treated like a missing header
+        let accept = accept.and_then(|accept| accept.to_str().ok());
+        let (body, content_type) = self.encode_for_accept(group, accept);
+
+        let mut response = Response::new(body);
+        *response.status_mut() = StatusCode::OK;
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header, HeaderValue, StatusCode};
+    use measured_derive::MetricGroup;
+
+    use crate::{
+        text::{BufferedTextEncoder, CONTENT_TYPE_OPENMETRICS, CONTENT_TYPE_TEXT},
+        Counter,
+    };
+
+    #[derive(MetricGroup, Default)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        events_total: Counter,
+    }
+
+    #[test]
+    fn negotiates_format() {
+        let metrics = Metrics::default();
+        let mut enc = BufferedTextEncoder::new();
+
+        let accept = HeaderValue::from_static("application/openmetrics-text; version=1.0.0");
+        let response = enc.encode_response(&metrics, Some(&accept));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            CONTENT_TYPE_OPENMETRICS
+        );
+        assert_eq!(
+            response.body(),
+            "# TYPE events counter\nevents_total 0\n# EOF\n"
+        );
+
+        // not utf-8
+        let accept = HeaderValue::from_bytes(b"application/\xff").unwrap();
+        let response = enc.encode_response(&metrics, Some(&accept));
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE_TEXT);
+        assert_eq!(
+            response.body(),
+            "# TYPE events_total counter\nevents_total 0\n"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! Record the duration of [`tracing`](https://docs.rs/tracing) spans into histograms. See [`TimingLayer`]
+
+use std::{
+    cell::Cell,
+    fmt::{self, Write},
+    marker::PhantomData,
+    ops::Range,
+    sync::Arc,
+    time::Instant,
+};
+
+use tracing_core::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Metadata, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{label::LabelGroupSet, metric::LabelId, HistogramVec};
+
+/// The field names and formatted values of a span
+type FieldBuffers = (String, Vec<(&'static str, Range<usize>)>);
+
+thread_local! {
+    /// The buffers of the last [`SpanFields`] on this thread, so that new spans do not allocate
+    static FIELD_BUFFERS: Cell<FieldBuffers> = Cell::default();
+}
+
+/// The name and fields of a span, captured when the span was created.
+///
+/// This is passed to the label function of a [`TimingLayer`] to choose the labels for the span.
+pub struct SpanFields {
+    metadata: &'static Metadata<'static>,
+    /// The values of every field, one after the other
+    values: String,
+    fields: Vec<(&'static str, Range<usize>)>,
+}
+
+impl SpanFields {
+    /// Capture the fields of the span, and pass them to `f`. The buffers are reused by the next span on this thread.
+    fn with<R>(attrs: &Attributes<'_>, f: impl FnOnce(&SpanFields) -> R) -> R {
+        let (values, fields) = FIELD_BUFFERS.try_with(Cell::take).unwrap_or_default();
+        let mut span = SpanFields {
+            metadata: attrs.metadata(),
+            values,
+            fields,
+        };
+        attrs.record(&mut span);
+
+        let result = f(&span);
+
+        let SpanFields {
+            mut values,
+            mut fields,
+            ..
+        } = span;
+        values.clear();
+        fields.clear();
+        let _ = FIELD_BUFFERS.try_with(|buffers| buffers.set((values, fields)));
+        result
+    }
+
+    /// The name of the span
+    pub fn name(&self) -> &'static str {
+        self.metadata.name()
+    }
+
+    /// The static metadata of the span, like the target and level
+    pub fn metadata(&self) -> &'static Metadata<'static> {
+        self.metadata
+    }
+
+    /// Find the value of a field on the span.
+    ///
+    /// String fields are returned as is. Every other field is formatted with its [`Debug`](fmt::Debug) impl.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, value)| &self.values[value.clone()])
+    }
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let start = self.values.len();
+        self.values.push_str(value);
+        self.fields.push((field.name(), start..self.values.len()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let start = self.values.len();
+        let _ = write!(self.values, "{value:?}");
+        self.fields.push((field.name(), start..self.values.len()));
+    }
+}
+
+/// A [`Layer`] that observes the duration of every span into a [`HistogramVec`].
+///
+/// The duration is measured from when the span is created until it is closed, and it is observed in seconds.
+/// The histograms are shared with an [`Arc`], so they can also be part of a metric group that is exposed elsewhere.
+/// The start of every timed span is stored in the span's extensions, so the subscriber must be a [`LookupSpan`],
+/// like the [`Registry`](tracing_subscriber::Registry).
+///
+/// The label function maps the [`SpanFields`] of each new span to a label group. Spans where it returns `None`,
+/// or where the label group is not in the label set, are not timed. The labels are chosen when the span is created,
+/// so field values that are recorded later are not seen.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use measured::{FixedCardinalityLabel, HistogramVec};
+/// use measured::label::StaticLabelSet;
+/// use measured::metric::histogram::Thresholds;
+/// use measured::tracing::TimingLayer;
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// #[label(singleton = "operation")]
+/// enum Operation { Query, Commit }
+///
+/// let durations: Arc<HistogramVec<StaticLabelSet<Operation>, 8>> = Arc::new(
+///     HistogramVec::with_metadata(Thresholds::exponential_buckets(0.001, 2.0)),
+/// );
+///
+/// let layer = TimingLayer::new(durations.clone(), |span| match span.get("op")? {
+///     "query" => Some(Operation::Query),
+///     "commit" => Some(Operation::Commit),
+///     _ => None,
+/// });
+///
+/// let subscriber = tracing_subscriber::registry().with(layer);
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!("db", op = "query").entered();
+/// });
+/// ```
+pub struct TimingLayer<L: LabelGroupSet, const N: usize, F> {
+    histograms: Arc<HistogramVec<L, N>>,
+    labels: F,
+}
+
+impl<L, const N: usize, F> TimingLayer<L, N, F>
+where
+    L: LabelGroupSet,
+    F: for<'a> Fn(&'a SpanFields) -> Option<L::Group<'a>>,
+{
+    /// Observe the span durations into `histograms`, keyed by the label group that `labels` chooses for each span.
+    pub fn new(histograms: Arc<HistogramVec<L, N>>, labels: F) -> Self {
+        Self { histograms, labels }
+    }
+
+    /// The histograms the span durations are observed into
+    pub fn histograms(&self) -> &Arc<HistogramVec<L, N>> {
+        &self.histograms
+    }
+}
+
+/// The start and labels of a timed span, stored in its extensions.
+///
+/// It is keyed by the layer type, so that several timing layers can time the same span.
+struct SpanTiming<I, Layer> {
+    start: Instant,
+    label: I,
+    layer: PhantomData<fn() -> Layer>,
+}
+
+impl<S, L, const N: usize, F> Layer<S> for TimingLayer<L, N, F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    L: LabelGroupSet + 'static,
+    L::Unique: Send + Sync,
+    F: for<'a> Fn(&'a SpanFields) -> Option<L::Group<'a>> + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let start = Instant::now();
+        let label = SpanFields::with(attrs, |fields| {
+            (self.labels)(fields).and_then(|l| self.histograms.try_with_labels(l))
+        });
+        let (Some(label), Some(span)) = (label, ctx.span(id)) else {
+            return;
+        };
+        span.extensions_mut().replace(SpanTiming::<_, Self> {
+            start,
+            label,
+            layer: PhantomData,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let timing = span
+            .extensions_mut()
+            .remove::<SpanTiming<LabelId<L>, Self>>();
+        if let Some(timing) = timing {
+            self.histograms
+                .get_metric(timing.label)
+                .observe_duration(timing.start.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::Ordering, Arc};
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::{
+        label::StaticLabelSet, metric::histogram::Thresholds, FixedCardinalityLabel, HistogramVec,
+    };
+
+    use super::TimingLayer;
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case", singleton = "span")]
+    enum SpanName {
+        Outer,
+        Inner,
+    }
+
+    #[test]
+    fn times_closed_spans() {
+        let durations: Arc<HistogramVec<StaticLabelSet<SpanName>, 4>> = Arc::new(
+            HistogramVec::with_metadata(Thresholds::exponential_buckets(1.0, 10.0)),
+        );
+
+        let layer = TimingLayer::new(durations.clone(), |span| match span.name() {
+            "outer" => Some(SpanName::Outer),
+            "inner" if span.get("timed") == Some("true") => Some(SpanName::Inner),
+            _ => None,
+        });
+        // another layer timing the same spans
+        let all = TimingLayer::new(durations.clone(), |_| Some(SpanName::Outer));
+
+        let count = |name| {
+            let m = durations.get_metric(durations.with_labels(name));
+            let count = m.inner.read().load_count(Ordering::Relaxed);
+            count
+        };
+
+        let subscriber = tracing_subscriber::registry().with(layer).with(all);
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("outer");
+            let clone = outer.clone();
+            {
+                let _inner = tracing::info_span!("inner", timed = true).entered();
+                let _untimed = tracing::info_span!("inner", timed = false).entered();
+                let _ignored = tracing::info_span!("other").entered();
+            }
+            assert_eq!(count(SpanName::Inner), 1);
+            assert_eq!(count(SpanName::Outer), 3);
+
+            // the span is only closed once every handle is dropped
+            drop(outer);
+            assert_eq!(count(SpanName::Outer), 3);
+            drop(clone);
+            assert_eq!(count(SpanName::Outer), 5);
+        });
+
+        assert_eq!(count(SpanName::Inner), 1);
+    }
+}
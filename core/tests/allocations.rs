@@ -0,0 +1,194 @@
+//! measured promises that the hot paths do not allocate.
+//! These tests count the allocations to make sure that this stays true.
+
+use measured::{
+    metric::{
+        group::{Encoding, MetricGroup},
+        histogram::Thresholds,
+        name::MetricName,
+        request::RequestMetrics,
+        MetricFamilyEncoding,
+    },
+    testing::{count_allocations, CountingAllocator},
+    text::{BufferedTextEncoder, TextFormat},
+    Counter, CounterVec, FixedCardinalityLabel, Histogram, LabelGroup,
+};
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator::system();
+
+#[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+#[label(set = ErrorsSet)]
+struct Error {
+    kind: ErrorKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+enum ErrorKind {
+    User,
+    Internal,
+    Network,
+}
+
+#[test]
+fn counter() {
+    let (counter, allocs) = count_allocations(Counter::new);
+    assert_eq!(allocs, 0);
+
+    let ((), allocs) = count_allocations(|| counter.inc());
+    assert_eq!(allocs, 0);
+}
+
+#[test]
+fn histogram() {
+    let (histogram, allocs) = count_allocations(|| {
+        Histogram::with_metadata(Thresholds::<8>::exponential_buckets(0.1, 2.0))
+    });
+    assert_eq!(allocs, 0);
+
+    let ((), allocs) = count_allocations(|| histogram.observe(1.5));
+    assert_eq!(allocs, 0);
+}
+
+#[test]
+fn dense_vec() {
+    // the only allocation is for the dense storage
+    let (errors, allocs) = count_allocations(CounterVec::<ErrorsSet>::dense);
+    assert_eq!(allocs, 1);
+
+    let ((), allocs) = count_allocations(|| {
+        errors.inc(Error {
+            kind: ErrorKind::Network,
+        })
+    });
+    assert_eq!(allocs, 0);
+}
+
+#[test]
+fn sparse_vec() {
+    // warm up the lazily computed shard count
+    drop(CounterVec::<ErrorsSet>::sparse());
+
+    // the only allocation is for the shards
+    let (errors, allocs) = count_allocations(CounterVec::<ErrorsSet>::sparse);
+    assert_eq!(allocs, 1);
+
+    // the first insert into a shard allocates the table for that shard
+    let ((), allocs) = count_allocations(|| {
+        errors.inc(Error {
+            kind: ErrorKind::User,
+        })
+    });
+    assert!(allocs <= 1);
+
+    // existing series do not allocate
+    let ((), allocs) = count_allocations(|| {
+        errors.inc(Error {
+            kind: ErrorKind::User,
+        })
+    });
+    assert_eq!(allocs, 0);
+}
+
+#[test]
+fn collect() {
+    let errors = CounterVec::<ErrorsSet>::dense();
+    errors.inc(Error {
+        kind: ErrorKind::User,
+    });
+    errors.inc(Error {
+        kind: ErrorKind::Internal,
+    });
+    let histogram = Histogram::with_metadata(Thresholds::<8>::exponential_buckets(0.1, 2.0));
+    histogram.observe(1.5);
+
+    let mut encoder = BufferedTextEncoder::with_capacity(4096);
+    for _ in 0..3 {
+        let ((), allocs) = count_allocations(|| {
+            errors
+                .collect_family_into(MetricName::from_str("errors_total"), &mut encoder)
+                .unwrap();
+            histogram
+                .collect_family_into(MetricName::from_str("latency_seconds"), &mut encoder)
+                .unwrap();
+        });
+        assert_eq!(allocs, 0);
+
+        drop(encoder.finish());
+    }
+}
+
+#[test]
+fn collect_openmetrics() {
+    let errors = CounterVec::<ErrorsSet>::dense();
+    errors.inc(Error {
+        kind: ErrorKind::User,
+    });
+    let requests = Counter::new();
+    requests.inc();
+    let histogram = Histogram::with_metadata(Thresholds::<8>::exponential_buckets(0.1, 2.0));
+    histogram.observe(1.5);
+
+    let mut encoder = BufferedTextEncoder::with_capacity(4096).with_format(TextFormat::OpenMetrics);
+    // the first collection sizes the reused name buffers
+    for i in 0..3 {
+        let ((), allocs) = count_allocations(|| {
+            encoder
+                .write_help(MetricName::from_str("errors_total"), "errors by kind")
+                .unwrap();
+            errors
+                .collect_family_into(MetricName::from_str("errors_total"), &mut encoder)
+                .unwrap();
+            // `_total` is appended to the samples
+            requests
+                .collect_family_into(MetricName::from_str("requests"), &mut encoder)
+                .unwrap();
+            encoder
+                .write_unit(MetricName::from_str("latency_seconds"), "seconds")
+                .unwrap();
+            histogram
+                .collect_family_into(MetricName::from_str("latency_seconds"), &mut encoder)
+                .unwrap();
+        });
+        if i > 0 {
+            assert_eq!(allocs, 0);
+        }
+
+        drop(encoder.finish());
+    }
+}
+
+#[test]
+fn collect_request_metrics() {
+    let http = RequestMetrics::<ErrorsSet, 8>::new(
+        MetricName::from_str("http_requests_total"),
+        MetricName::from_str("http_request_duration_seconds"),
+        ErrorsSet::default(),
+        Thresholds::exponential_buckets(0.1, 2.0),
+    );
+    http.record(
+        Error {
+            kind: ErrorKind::User,
+        },
+        0.2,
+    );
+    http.record(
+        Error {
+            kind: ErrorKind::Internal,
+        },
+        1.5,
+    );
+
+    let mut encoder = BufferedTextEncoder::with_capacity(4096);
+    // the first collection sizes the reused samples
+    for i in 0..3 {
+        let ((), allocs) = count_allocations(|| {
+            http.collect_group_into(&mut encoder).unwrap();
+        });
+        if i > 0 {
+            assert_eq!(allocs, 0);
+        }
+
+        drop(encoder.finish());
+    }
+}
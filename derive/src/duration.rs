@@ -0,0 +1,91 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Lit, Token,
+};
+
+use crate::Krate;
+
+/// The input to `duration_buckets!`, a comma separated list of duration literals like `5ms`
+pub struct DurationBuckets {
+    krate: Krate,
+    nanos: Vec<u64>,
+}
+
+impl Parse for DurationBuckets {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lits = Punctuated::<Lit, Token![,]>::parse_terminated(input)?;
+
+        let mut nanos = Vec::with_capacity(lits.len());
+        for lit in &lits {
+            let n = parse_duration(lit)?;
+            if let Some(&prev) = nanos.last() {
+                if n <= prev {
+                    return Err(syn::Error::new_spanned(
+                        lit,
+                        "consecutive histogram buckets must not decrease or be equal",
+                    ));
+                }
+            }
+            nanos.push(n);
+        }
+
+        Ok(Self {
+            krate: Krate::default(),
+            nanos,
+        })
+    }
+}
+
+fn parse_duration(lit: &Lit) -> syn::Result<u64> {
+    let (value, suffix) = match lit {
+        Lit::Int(i) => (i.base10_digits().parse::<f64>(), i.suffix()),
+        Lit::Float(f) => (f.base10_digits().parse::<f64>(), f.suffix()),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                lit,
+                "expected a duration like `5ms`",
+            ))
+        }
+    };
+    let value = value.map_err(|e| syn::Error::new_spanned(lit, e))?;
+
+    let scale = match suffix {
+        "ns" => 1.0,
+        "us" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "" => {
+            return Err(syn::Error::new_spanned(
+                lit,
+                "missing duration unit, expected one of `ns`, `us`, `ms`, or `s`",
+            ))
+        }
+        _ => {
+            return Err(syn::Error::new_spanned(
+                lit,
+                "unknown duration unit, expected one of `ns`, `us`, `ms`, or `s`",
+            ))
+        }
+    };
+
+    let nanos = (value * scale).round();
+    if nanos > u64::MAX as f64 {
+        return Err(syn::Error::new_spanned(lit, "duration is too large"));
+    }
+    Ok(nanos as u64)
+}
+
+impl ToTokens for DurationBuckets {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let krate = &self.krate.0;
+        let nanos = &self.nanos;
+        tokens.extend(quote! {
+            #krate::metric::histogram::Thresholds::from_durations([
+                #(::core::time::Duration::from_nanos(#nanos)),*
+            ])
+        });
+    }
+}
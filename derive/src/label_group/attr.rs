@@ -1,5 +1,5 @@
 use proc_macro2::Ident;
-use syn::{Attribute, LitStr, Path};
+use syn::{Attribute, Expr, LitStr, Path};
 
 use crate::Krate;
 
@@ -10,6 +10,8 @@ pub struct ContainerAttrs {
     /// Optional `crate = $:path` arg
     pub krate: Option<Krate>,
     pub set: Option<Ident>,
+    /// Optional `const_cardinality` flag
+    pub const_cardinality: bool,
 }
 
 impl ContainerAttrs {
@@ -29,6 +31,11 @@ impl ContainerAttrs {
                                 return Err(meta.error("duplicate `label(set)` arg"));
                             }
                         }
+                        () if meta.path.is_ident("const_cardinality") => {
+                            if std::mem::replace(&mut args.const_cardinality, true) {
+                                return Err(meta.error("duplicate `label(const_cardinality)` arg"));
+                            }
+                        }
                         () => return Err(meta.error("unknown argument found")),
                     }
 
@@ -45,6 +52,8 @@ pub struct LabelGroupFieldAttrs {
     pub kind: LabelGroupFieldAttrsKind,
     pub default: bool,
     pub rename: Option<LitStr>,
+    /// Optional `fallback = $:expr` arg, encoded when the value is not in the set
+    pub fallback: Option<Expr>,
 }
 
 #[derive(Clone)]
@@ -75,6 +84,7 @@ impl LabelGroupFieldAttrs {
         let mut kind = None;
         let mut default = None;
         let mut rename = None;
+        let mut fallback = None;
 
         for attr in attrs {
             if attr.path().is_ident(LABEL_ATTR) {
@@ -95,7 +105,9 @@ impl LabelGroupFieldAttrs {
                                 return Err(meta.error("duplicate `label(fixed_with)` arg"));
                             }
                         }
-                        () if meta.path.is_ident("dynamic_with") => {
+                        () if meta.path.is_ident("dynamic_with")
+                            || meta.path.is_ident("dynamic") =>
+                        {
                             if kind
                                 .replace(LabelGroupFieldAttrsKind::DynamicWith(
                                     meta.value()?.parse()?,
@@ -115,6 +127,11 @@ impl LabelGroupFieldAttrs {
                                 return Err(meta.error("duplicate `label(rename)` arg"));
                             }
                         }
+                        () if meta.path.is_ident("fallback") => {
+                            if fallback.replace(meta.value()?.parse()?).is_some() {
+                                return Err(meta.error("duplicate `label(fallback)` arg"));
+                            }
+                        }
                         () => return Err(meta.error("unknown argument found")),
                     }
 
@@ -124,7 +141,7 @@ impl LabelGroupFieldAttrs {
         }
 
         let kind = kind.unwrap_or(LabelGroupFieldAttrsKind::Fixed);
-        let default = default.map_or(false, |()| true);
+        let default = default.is_some();
 
         // fixed implies default
         let default = default || matches!(kind, LabelGroupFieldAttrsKind::Fixed);
@@ -132,6 +149,7 @@ impl LabelGroupFieldAttrs {
             kind,
             default,
             rename,
+            fallback,
         })
     }
 }
@@ -14,6 +14,7 @@ pub struct LabelGroup {
     ident: Ident,
     fields: Vec<LabelGroupField>,
     generics: Generics,
+    const_cardinality: bool,
 }
 
 #[derive(Clone)]
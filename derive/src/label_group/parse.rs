@@ -2,7 +2,7 @@ use syn::{spanned::Spanned, Data, DeriveInput, Field, Fields};
 
 use crate::Krate;
 
-use super::attr::{ContainerAttrs, LabelGroupFieldAttrs};
+use super::attr::{ContainerAttrs, LabelGroupFieldAttrs, LabelGroupFieldAttrsKind};
 use super::{LabelGroup, LabelGroupField};
 
 impl TryFrom<Field> for LabelGroupField {
@@ -54,6 +54,18 @@ impl TryFrom<DeriveInput> for LabelGroup {
             },
         };
 
+        if args.const_cardinality {
+            if let Some(field) = fields
+                .iter()
+                .find(|x| !matches!(x.attrs.kind, LabelGroupFieldAttrsKind::Fixed))
+            {
+                return Err(syn::Error::new(
+                    field.span,
+                    "`const_cardinality` requires every label to be `fixed`",
+                ));
+            }
+        }
+
         Ok(Self {
             vis,
             krate,
@@ -61,6 +73,7 @@ impl TryFrom<DeriveInput> for LabelGroup {
             ident,
             fields,
             generics,
+            const_cardinality: args.const_cardinality,
         })
     }
 }
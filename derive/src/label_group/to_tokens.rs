@@ -53,6 +53,7 @@ impl ToTokens for Set<'_> {
             generics,
             fields,
             set_ident,
+            const_cardinality,
         } = self.0;
 
         let mut sorted_fields = fields.clone();
@@ -188,6 +189,17 @@ impl ToTokens for Set<'_> {
             )
         };
 
+        let const_cardinality = const_cardinality.then(|| {
+            let tys = fixed.iter().map(|x| &x.ty);
+            quote! {
+                #[automatically_derived]
+                impl #krate::label::ConstCardinality for #set_ident {
+                    const CARDINALITY: usize = 1usize
+                        #( .saturating_mul(<#tys as #krate::label::ConstCardinality>::CARDINALITY) )*;
+                }
+            }
+        });
+
         let encode_fn = SetEncode {
             group: self.0,
             fixed,
@@ -202,6 +214,13 @@ impl ToTokens for Set<'_> {
             cardinalities: &cardinalities,
         };
 
+        let take_reassigned_fn = SetTakeReassigned {
+            group: self.0,
+            fixed,
+            dynamics,
+            cardinalities: &cardinalities,
+        };
+
         tokens.extend(quote! {
             #vis struct #set_ident {
                 #(#set_fields)*
@@ -219,7 +238,11 @@ impl ToTokens for Set<'_> {
                 #encode_fn
 
                 #decode_fn
+
+                #take_reassigned_fn
             }
+
+            #const_cardinality
         });
     }
 }
@@ -243,36 +266,30 @@ impl ToTokens for SetEncode<'_> {
         let fixed_encodes: Vec<TokenStream> = fixed
             .iter()
             .map(|x| {
-                let LabelGroupField {
-                    name, attrs, ty, ..
-                } = x;
+                let LabelGroupField { attrs, ty, .. } = x;
 
-                match &attrs.kind {
+                let set_ty = match &attrs.kind {
                     LabelGroupFieldAttrsKind::Fixed => {
-                        quote_spanned!(x.span => <#krate::label::StaticLabelSet<#ty> as #krate::label::LabelSet>::encode(&self.#name, value.#name)?)
-                    }
-                    LabelGroupFieldAttrsKind::FixedWith(ty) => {
-                        quote_spanned!(x.span => <#ty as #krate::label::LabelSet>::encode(&self.#name, value.#name)?)
+                        quote_spanned!(x.span => #krate::label::StaticLabelSet<#ty>)
                     }
+                    LabelGroupFieldAttrsKind::FixedWith(ty) => ty.to_token_stream(),
                     LabelGroupFieldAttrsKind::DynamicWith(_) => unreachable!(),
-                }
+                };
+                encode_field(krate, x, &set_ty)
             })
             .collect();
 
         let dynamic_encodes: Vec<TokenStream> = dynamics
             .iter()
-            .map(|x| {
-                let LabelGroupField { name, attrs, .. } = x;
-
-                match &attrs.kind {
-                    LabelGroupFieldAttrsKind::DynamicWith(ty) => {
-                        quote_spanned!(x.span => {
-                            <#ty as #krate::label::DynamicLabelSet>::__private_check_dynamic();
-                            <#ty as #krate::label::LabelSet>::encode(&self.#name, value.#name)?
-                        })
-                    }
-                    _ => unreachable!(),
+            .map(|x| match &x.attrs.kind {
+                LabelGroupFieldAttrsKind::DynamicWith(ty) => {
+                    let encode = encode_field(krate, x, &ty.to_token_stream());
+                    quote_spanned!(x.span => {
+                        <#ty as #krate::label::DynamicLabelSet>::__private_check_dynamic();
+                        #encode
+                    })
                 }
+                _ => unreachable!(),
             })
             .collect();
 
@@ -295,6 +312,21 @@ impl ToTokens for SetEncode<'_> {
     }
 }
 
+/// Encode a single field of the label group with its label set, trying the fallback value if the field's value is not in the set
+fn encode_field(krate: &syn::Path, field: &LabelGroupField, set_ty: &TokenStream) -> TokenStream {
+    let LabelGroupField { name, attrs, .. } = field;
+    match &attrs.fallback {
+        None => {
+            quote_spanned!(field.span => <#set_ty as #krate::label::LabelSet>::encode(&self.#name, value.#name)?)
+        }
+        Some(fallback) => quote_spanned!(field.span => {
+            let set = &self.#name;
+            <#set_ty as #krate::label::LabelSet>::encode(set, value.#name)
+                .or_else(|| <#set_ty as #krate::label::LabelSet>::encode(set, #fallback))?
+        }),
+    }
+}
+
 struct SetDecode<'a> {
     group: &'a LabelGroup,
     fixed: &'a [LabelGroupField],
@@ -373,3 +405,82 @@ impl ToTokens for SetDecode<'_> {
         });
     }
 }
+
+struct SetTakeReassigned<'a> {
+    group: &'a LabelGroup,
+    fixed: &'a [LabelGroupField],
+    dynamics: &'a [LabelGroupField],
+    cardinalities: &'a [TokenStream],
+}
+
+impl ToTokens for SetTakeReassigned<'_> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Self {
+            group: LabelGroup { krate, .. },
+            fixed,
+            dynamics,
+            cardinalities,
+        } = *self;
+
+        if fixed.is_empty() && dynamics.is_empty() {
+            return;
+        }
+
+        let take = |x: &LabelGroupField| {
+            let LabelGroupField {
+                name, attrs, ty, ..
+            } = x;
+            match &attrs.kind {
+                LabelGroupFieldAttrsKind::Fixed => {
+                    quote_spanned!(x.span => <#krate::label::StaticLabelSet<#ty> as #krate::label::LabelSet>::take_reassigned(&self.#name))
+                }
+                LabelGroupFieldAttrsKind::FixedWith(ty)
+                | LabelGroupFieldAttrsKind::DynamicWith(ty) => {
+                    quote_spanned!(x.span => <#ty as #krate::label::LabelSet>::take_reassigned(&self.#name))
+                }
+            }
+        };
+        let fixed_takes = fixed.iter().map(take);
+        let dynamic_takes = dynamics.iter().map(take);
+
+        let fixed_reassigned = (0..fixed.len())
+            .map(|i| format_ident!("fixed_reassigned{i}"))
+            .collect::<Vec<_>>();
+        let dynamic_reassigned = (0..dynamics.len())
+            .map(|i| format_ident!("dynamic_reassigned{i}"))
+            .collect::<Vec<_>>();
+        let dynamic_indices = (0..dynamics.len())
+            .map(|i| format_ident!("dynamic_index{i}"))
+            .collect::<Vec<_>>();
+        let all_reassigned = fixed_reassigned.iter().chain(&dynamic_reassigned);
+
+        tokens.extend(quote! {
+            fn take_reassigned(&self) -> Option<#krate::label::ReassignedGroups<'_, Self::Unique>> {
+                #( let #fixed_reassigned: Vec<usize> = #fixed_takes; )*
+                #( let #dynamic_reassigned: Vec<usize> = #dynamic_takes; )*
+
+                if #( #all_reassigned.is_empty() )&&* {
+                    return None;
+                }
+
+                Some(Box::new(move |value: &Self::Unique| {
+                    let (index #(, #dynamic_indices)*) = *value;
+                    let mut reassigned = false;
+
+                    #(
+                        let card = #cardinalities;
+                        let (index, index1) = (index / card, index % card);
+                        reassigned |= #fixed_reassigned.contains(&index1);
+                    )*
+                    let _ = index;
+
+                    #(
+                        reassigned |= #dynamic_reassigned.contains(&#dynamic_indices);
+                    )*
+
+                    reassigned
+                }))
+            }
+        });
+    }
+}
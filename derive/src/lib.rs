@@ -1,6 +1,7 @@
 use quote::ToTokens;
 use syn::{parse_macro_input, DeriveInput};
 
+mod duration;
 mod label_group;
 mod metric_group;
 mod value;
@@ -32,6 +33,13 @@ pub fn derive_metric_group(input: proc_macro::TokenStream) -> proc_macro::TokenS
     .into()
 }
 
+#[proc_macro]
+pub fn duration_buckets(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    parse_macro_input!(input as duration::DurationBuckets)
+        .to_token_stream()
+        .into()
+}
+
 const CRATE: &str = "measured";
 struct Krate(pub syn::Path);
 
@@ -51,6 +51,7 @@ impl ContainerAttrs {
 pub struct MetricGroupFieldAttrs {
     pub kind: MetricGroupFieldAttrsKind,
     pub docs: Option<String>,
+    pub unit: Option<LitStr>,
     pub init: Option<MetricGroupFieldAttrsInit>,
 }
 
@@ -73,6 +74,7 @@ impl MetricGroupFieldAttrs {
     pub fn parse_attrs(attrs: &[Attribute]) -> syn::Result<Self> {
         let mut args = None;
         let mut docs = None;
+        let mut unit: Option<LitStr> = None;
         let mut init = None;
 
         for attr in attrs {
@@ -87,6 +89,11 @@ impl MetricGroupFieldAttrs {
                                 return Err(meta.error("duplicate `metric(rename)` attr"));
                             }
                         }
+                        () if meta.path.is_ident("unit") => {
+                            if unit.replace(meta.value()?.parse()?).is_some() {
+                                return Err(meta.error("duplicate `metric(unit)` attr"));
+                            }
+                        }
                         () if meta.path.is_ident("namespace") => {
                             let arg = MetricGroupFieldAttrsKind::Group {
                                 namespace: Some(meta.value()?.parse()?),
@@ -171,9 +178,17 @@ impl MetricGroupFieldAttrs {
                 docs.get_or_insert_with(String::new).push_str(&s.value());
             }
         }
+        let kind = args.unwrap_or(MetricGroupFieldAttrsKind::Metric { rename: None });
+        if let (Some(unit), MetricGroupFieldAttrsKind::Group { .. }) = (&unit, &kind) {
+            return Err(syn::Error::new(
+                unit.span(),
+                "`metric(unit)` is only supported on metrics, not nested groups",
+            ));
+        }
         Ok(Self {
-            kind: args.unwrap_or(MetricGroupFieldAttrsKind::Metric { rename: None }),
+            kind,
             docs,
+            unit,
             init,
         })
     }
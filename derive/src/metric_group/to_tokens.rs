@@ -62,9 +62,16 @@ impl ToTokens for MetricGroup {
                         })
                     });
 
+                    let unit = attrs.unit.as_ref().map(|unit| {
+                        quote_spanned!(x.span => {
+                            <#enc as #krate::metric::group::Encoding>::write_unit(enc, #ident, #unit)?;
+                        })
+                    });
+
                     quote_spanned! { x.span =>
                         const #ident: &#krate::metric::name::MetricName = #krate::metric::name::MetricName::from_str(#name_string);
                         #help
+                        #unit
                         <#ty as #krate::metric::MetricFamilyEncoding<#enc>>::collect_family_into(&self.#name, #ident, enc)?;
                     }
                 },
@@ -94,6 +101,33 @@ impl ToTokens for MetricGroup {
             }
         });
 
+        // every field must be reset for the group to be reset. the bounds are higher-ranked, so that a field which
+        // cannot be reset does not fail the derive, the group just doesn't implement `Reset` either
+        let mut reset_generics = generics.clone();
+        let wc = reset_generics.make_where_clause();
+        for field in fields {
+            let MetricGroupField { ty, .. } = field;
+            wc.predicates.push(parse_quote_spanned!(field.span =>
+                for<'__reset_tmp_lt> #ty: #krate::metric::reset::Reset
+            ));
+        }
+        let (_, _, reset_where_clause) = reset_generics.split_for_impl();
+        let resets = fields.iter().map(|x| {
+            let MetricGroupField { name, ty, .. } = x;
+            quote_spanned! { x.span =>
+                <#ty as #krate::metric::reset::Reset>::reset(&self.#name);
+            }
+        });
+
+        tokens.extend(quote! {
+            #[automatically_derived]
+            impl #impl_generics #krate::metric::reset::Reset for #ident #ty_generics #reset_where_clause {
+                fn reset(&self) {
+                    #(#resets)*
+                }
+            }
+        });
+
         if let Some(inputs) = inputs {
             let inits = fields.iter().map(|x| {
                 let MetricGroupField { name,ty, attrs, .. } = x;
@@ -114,6 +148,7 @@ impl ToTokens for MetricGroup {
 
             tokens.extend(quote! {
                 impl #impl_generics #ident #ty_generics #where_clause {
+                    #[allow(clippy::unit_arg)]
                     pub fn new(#inputs) -> Self {
                         Self {
                             #(#inits)*
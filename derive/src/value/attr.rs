@@ -72,11 +72,16 @@ impl ContainerAttrs {
 #[derive(Clone)]
 pub struct VariantAttrs {
     pub rename: Option<LitStr>,
+    /// `other` flag, for the catch-all variant
+    pub other: bool,
 }
 
 impl VariantAttrs {
     pub fn parse_attrs(attrs: &[Attribute]) -> syn::Result<Self> {
-        let mut args = VariantAttrs { rename: None };
+        let mut args = VariantAttrs {
+            rename: None,
+            other: false,
+        };
         for attr in attrs {
             if attr.path().is_ident(LABEL_ATTR) {
                 attr.meta.require_list()?.parse_nested_meta(|meta| {
@@ -86,6 +91,11 @@ impl VariantAttrs {
                                 return Err(meta.error("duplicate `label(rename)` arg"));
                             }
                         }
+                        () if meta.path.is_ident("other") => {
+                            if std::mem::replace(&mut args.other, true) {
+                                return Err(meta.error("duplicate `label(other)` arg"));
+                            }
+                        }
                         () => return Err(meta.error("unknown argument found")),
                     }
 
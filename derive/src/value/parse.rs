@@ -63,6 +63,13 @@ impl TryFrom<DeriveInput> for FixedCardinalityLabel {
             Data::Struct(_) => return Err(syn::Error::new(span, "structs not supported")),
         };
 
+        if let Some(var) = variants.iter().filter(|var| var.attrs.other).nth(1) {
+            return Err(syn::Error::new(
+                var.span,
+                "only one variant can be `label(other)`",
+            ));
+        }
+
         Ok(Self {
             krate,
             rename_all,
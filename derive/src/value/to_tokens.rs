@@ -1,7 +1,7 @@
 use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned, ToTokens};
 
-use super::FixedCardinalityLabel;
+use super::{attr::RenameAll, FixedCardinalityLabel, FixedCardinalityLabelVariant};
 
 impl ToTokens for FixedCardinalityLabel {
     fn to_tokens(&self, tokens: &mut TokenStream) {
@@ -24,15 +24,45 @@ impl ToTokens for FixedCardinalityLabel {
             let write = if let Some(int) = &var.value {
                 quote_spanned!(int.span() => v.write_int(#int))
             } else {
-                let name = var.attrs.rename.as_ref().map_or_else(
-                    || rename_all.apply(&var.ident.to_string()),
-                    syn::LitStr::value,
-                );
+                let name = var.name(rename_all);
                 quote_spanned!(var.span => v.write_str(#name))
             };
             quote_spanned!(var.span => #ident :: #var_ident => #write,)
         });
 
+        let other = variants.iter().find(|var| var.attrs.other);
+
+        let decode_other = match other {
+            Some(other) => {
+                let other = &other.ident;
+                quote!(_ => #ident :: #other,)
+            }
+            None => quote!(_ => panic!("invalid value"),),
+        };
+
+        let from_str = other.map(|other| {
+            let other = &other.ident;
+            let arms = variants.iter().filter(|var| !var.attrs.other).map(|var| {
+                let var_ident = &var.ident;
+                let value = match &var.value {
+                    Some(int) => int.base10_digits().to_owned(),
+                    None => var.name(rename_all),
+                };
+                quote_spanned!(var.span => #value => #ident :: #var_ident,)
+            });
+            quote! {
+                #[automatically_derived]
+                impl ::core::convert::From<&str> for #ident {
+                    fn from(value: &str) -> Self {
+                        match value {
+                            #(#arms)*
+                            _ => #ident :: #other,
+                        }
+                    }
+                }
+            }
+        });
+
         let singleton = singleton.as_ref().map(|s| {
             quote!{
                 impl #krate::label::LabelGroup for #ident {
@@ -45,6 +75,11 @@ impl ToTokens for FixedCardinalityLabel {
         });
 
         tokens.extend(quote! {
+            #[automatically_derived]
+            impl #krate::label::ConstCardinality for #ident {
+                const CARDINALITY: usize = #cardinality;
+            }
+
             #[automatically_derived]
             impl #krate::label::FixedCardinalityLabel for #ident {
                 fn cardinality() -> usize {
@@ -60,7 +95,7 @@ impl ToTokens for FixedCardinalityLabel {
                 fn decode(value: usize) -> Self {
                     match value {
                         #(#count2 => #ident :: #var_idents2,)*
-                        _ => panic!("invalid value"),
+                        #decode_other
                     }
                 }
             }
@@ -75,6 +110,18 @@ impl ToTokens for FixedCardinalityLabel {
             }
 
             #singleton
+
+            #from_str
         });
     }
 }
+
+impl FixedCardinalityLabelVariant {
+    /// The label value of a variant without an integer value
+    fn name(&self, rename_all: &RenameAll) -> String {
+        self.attrs.rename.as_ref().map_or_else(
+            || rename_all.apply(&self.ident.to_string()),
+            syn::LitStr::value,
+        )
+    }
+}
@@ -11,6 +11,8 @@ use metrics::{AppMetrics, AppMetricsEncoder};
 use tokio::net::TcpListener;
 
 mod metrics;
+#[cfg(feature = "unix")]
+mod unix;
 
 #[derive(Clone)]
 struct AppState {
@@ -31,6 +33,14 @@ async fn main() {
         )))),
     };
 
+    // optionally expose the metrics over a unix socket
+    #[cfg(feature = "unix")]
+    if let Some(path) = std::env::var_os("METRICS_SOCKET") {
+        let listener = unix::bind_unix(path).unwrap();
+        let metrics = state.metrics.clone();
+        tokio::spawn(unix::serve_unix(listener, metrics));
+    }
+
     let app = Router::new()
         // add our API routes
         .nest("/api/v1", api_v1())
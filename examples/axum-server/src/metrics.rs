@@ -1,7 +1,9 @@
 use std::sync::Arc;
 
 use axum::{
+    body::Body,
     extract::{MatchedPath, Request, State},
+    http::{header::ACCEPT, HeaderMap},
     middleware::Next,
     response::Response,
     RequestExt,
@@ -94,12 +96,16 @@ pub async fn middleware(
 }
 
 /// sample and export the metrics
-pub async fn handler(s: State<Arc<AppMetricsEncoder>>) -> Response {
+pub async fn handler(s: State<Arc<AppMetricsEncoder>>, headers: HeaderMap) -> Response {
     let AppMetricsEncoder { encoder, metrics } = &*s.0;
 
-    let mut encoder = encoder.lock().await;
-    metrics.collect_group_into(&mut *encoder).unwrap();
-    Response::new(encoder.finish().into())
+    // respond in OpenMetrics if the scraper asks for it
+    let accept = headers.get(ACCEPT);
+    encoder
+        .lock()
+        .await
+        .encode_response(metrics, accept)
+        .map(Body::from)
 }
 
 #[derive(LabelGroup)]
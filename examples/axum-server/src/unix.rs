@@ -0,0 +1,58 @@
+use std::{io, os::unix::fs::FileTypeExt, path::Path, sync::Arc, time::Duration};
+
+use axum::{extract::Request, routing::get, Router};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+};
+use tokio::net::UnixListener;
+use tower::ServiceExt;
+
+use crate::metrics::{self, AppMetricsEncoder};
+
+/// Bind a unix domain socket at `path`, to serve the metrics on with [`serve_unix`].
+///
+/// A socket left behind by a previous run is removed first, but never any other kind of file.
+pub fn bind_unix(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) | Err(_) => {}
+    }
+    UnixListener::bind(path)
+}
+
+/// Serve the metrics on a unix domain socket, for sidecars and local agents
+/// that should not have to scrape over TCP.
+///
+/// This serves the same handler as the `/metrics` route of the main server.
+/// Errors accepting a connection, like running out of file descriptors, are logged and do not stop the server.
+pub async fn serve_unix(listener: UnixListener, metrics: Arc<AppMetricsEncoder>) {
+    let app = Router::new()
+        .route("/metrics", get(metrics::handler))
+        .with_state(metrics);
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                eprintln!("failed to accept a metrics connection: {err}");
+                // the error is usually transient, eg too many open files, so back off before retrying
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let app = app.clone();
+        tokio::spawn(async move {
+            let service =
+                hyper::service::service_fn(move |req: Request<Incoming>| app.clone().oneshot(req));
+
+            // a failed connection only affects that scraper
+            let _ = Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
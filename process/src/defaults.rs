@@ -0,0 +1,168 @@
+use measured::{
+    label::{LabelGroupVisitor, LabelName},
+    metric::{
+        gauge::{write_gauge, GaugeState},
+        group::Encoding,
+        name::MetricNameEncoder,
+        reset::Reset,
+        MetricEncoding, MetricFamilyEncoding,
+    },
+    LabelGroup, MetricGroup,
+};
+
+use crate::ProcessCollector;
+
+/// The standard baseline metrics for a service, ready to be added to a [`MetricGroup`].
+///
+/// This exposes the following series:
+///
+/// * `process_open_fds`, `process_max_fds`, `process_virtual_memory_bytes`, `process_resident_memory_bytes`,
+///   `process_cpu_seconds_total`, `process_threads` and `process_start_time_seconds`,
+///   from the [`ProcessCollector`] of the current process. These are only written on Linux.
+/// * `tokio_threads`, `tokio_active_tasks`, `tokio_queued_tasks`, `tokio_scheduled_tasks_total` and the other
+///   series of [`measured_tokio::RuntimeCollector`], if the `tokio` feature is enabled and the collectors were
+///   created inside of a tokio runtime.
+/// * `build_info`, a gauge which is always 1, labelled with the [`BuildInfo`].
+///
+/// ```
+/// use measured::MetricGroup;
+/// use measured_process::{BuildInfo, DefaultCollectors};
+///
+/// #[derive(MetricGroup)]
+/// struct MyAppMetrics {
+///     #[metric(flatten)]
+///     defaults: DefaultCollectors,
+///
+///     // other metrics
+/// }
+///
+/// let metrics = MyAppMetrics {
+///     defaults: DefaultCollectors::new(BuildInfo::new(env!("CARGO_PKG_VERSION"))),
+/// };
+/// # drop(metrics);
+/// ```
+#[derive(MetricGroup)]
+pub struct DefaultCollectors {
+    #[metric(namespace = "process")]
+    process: ProcessCollector,
+
+    #[cfg(feature = "tokio")]
+    #[metric(namespace = "tokio")]
+    tokio: Option<measured_tokio::RuntimeCollector>,
+
+    /// Build information about the running binary
+    build_info: BuildInfo,
+}
+
+impl DefaultCollectors {
+    /// Create the default collectors for the current process.
+    ///
+    /// With the `tokio` feature, this also collects the tokio runtime that this is called from, if any.
+    pub fn new(build_info: BuildInfo) -> Self {
+        Self {
+            process: ProcessCollector::for_self(),
+            #[cfg(feature = "tokio")]
+            tokio: tokio::runtime::Handle::try_current()
+                .ok()
+                .map(|handle| measured_tokio::RuntimeCollector::new(handle.metrics())),
+            build_info,
+        }
+    }
+}
+
+/// Static information about the running binary, exposed as a gauge with a value of 1 and the info as labels.
+///
+/// ```
+/// use measured_process::BuildInfo;
+///
+/// // encoded as `build_info{version="1.2.3",revision="abc123"} 1`
+/// let info = BuildInfo::new("1.2.3").with_revision("abc123");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct BuildInfo {
+    version: &'static str,
+    revision: Option<&'static str>,
+}
+
+impl BuildInfo {
+    /// Build info with the given version, usually `env!("CARGO_PKG_VERSION")`
+    pub const fn new(version: &'static str) -> Self {
+        Self {
+            version,
+            revision: None,
+        }
+    }
+
+    /// Add the source control revision that the binary was built from
+    pub const fn with_revision(mut self, revision: &'static str) -> Self {
+        self.revision = Some(revision);
+        self
+    }
+}
+
+impl LabelGroup for BuildInfo {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        const VERSION: &LabelName = LabelName::from_str("version");
+        const REVISION: &LabelName = LabelName::from_str("revision");
+        v.write_value(VERSION, &self.version);
+        if let Some(revision) = self.revision {
+            v.write_value(REVISION, &revision);
+        }
+    }
+}
+
+impl<Enc: Encoding> MetricFamilyEncoding<Enc> for BuildInfo
+where
+    GaugeState: MetricEncoding<Enc>,
+{
+    fn collect_family_into(
+        &self,
+        name: impl MetricNameEncoder,
+        enc: &mut Enc,
+    ) -> Result<(), Enc::Err> {
+        GaugeState::write_type(&name, enc)?;
+        write_gauge(enc, name, self, 1)
+    }
+}
+
+impl Reset for BuildInfo {
+    /// The build info is constant, so there is nothing to reset
+    fn reset(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use measured::{
+        metric::{name::MetricName, MetricFamilyEncoding},
+        text::BufferedTextEncoder,
+        MetricGroup,
+    };
+
+    use super::{BuildInfo, DefaultCollectors};
+
+    #[test]
+    fn build_info() {
+        let mut enc = BufferedTextEncoder::new();
+        BuildInfo::new("1.2.3")
+            .with_revision("abc123")
+            .collect_family_into(MetricName::from_str("build_info"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            "# TYPE build_info gauge\nbuild_info{version=\"1.2.3\",revision=\"abc123\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn default_collectors() {
+        let defaults = DefaultCollectors::new(BuildInfo::new("1.2.3"));
+        let mut enc = BufferedTextEncoder::new();
+        defaults.collect_group_into(&mut enc).unwrap();
+        let output = String::from_utf8(enc.finish().to_vec()).unwrap();
+
+        assert!(output.contains("# HELP build_info Build information about the running binary\n"));
+        assert!(output.contains("build_info{version=\"1.2.3\"} 1\n"));
+        #[cfg(target_os = "linux")]
+        assert!(output.contains("\nprocess_threads "));
+    }
+}
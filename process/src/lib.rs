@@ -34,11 +34,15 @@ use measured::{
         gauge::{write_gauge, GaugeState},
         group::Encoding,
         name::MetricName,
+        reset::Reset,
         MetricEncoding,
     },
     MetricGroup,
 };
 
+mod defaults;
+pub use defaults::{BuildInfo, DefaultCollectors};
+
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 // https://github.com/tikv/rust-prometheus/blob/f49c724df0e123520554664436da68e555593af0/src/process_collector.rs
 // With modifications by Conrad Ludgate for the transition to measured.
@@ -139,6 +143,11 @@ where
     }
 }
 
+impl Reset for ProcessCollector {
+    /// The process metrics are read from the OS on every collection, so there is nothing to reset
+    fn reset(&self) {}
+}
+
 fn clk_tck() -> i64 {
     static CLK_TCK: OnceLock<i64> = OnceLock::new();
     *CLK_TCK.get_or_init(|| unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as i64)
@@ -75,6 +75,22 @@ pub mod int32 {
     }
 }
 
+pub mod uint64 {
+    use crate::encoding::*;
+    pub fn encode<B>(tag: u32, value: &u64, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encode_key(tag, WireType::Varint, buf);
+        encode_varint(*value, buf);
+    }
+
+    #[inline]
+    pub fn encoded_len(tag: u32, value: &u64) -> usize {
+        key_len(tag) + encoded_len_varint(*value)
+    }
+}
+
 pub mod double {
     use crate::encoding::*;
     pub fn encode<B>(tag: u32, value: &f64, buf: &mut B)
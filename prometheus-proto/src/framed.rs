@@ -0,0 +1,56 @@
+use std::io;
+
+/// How each `MetricFamily` message is delimited in the output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Framing {
+    /// A varint length prefix, as expected by prometheus
+    Varint,
+    /// A big-endian `u32` length prefix
+    U32,
+}
+
+/// Reads the frames written by [`ProtoEncoder::framed`](crate::ProtoEncoder::framed).
+///
+/// Each item is the bytes of a single protobuf encoded `MetricFamily`.
+/// If the input ends in the middle of a frame, an [`io::ErrorKind::UnexpectedEof`] error is returned.
+pub struct FrameReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> FrameReader<'a> {
+    /// Read the frames from the given bytes
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for FrameReader<'a> {
+    type Item = io::Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let frame = self
+            .buf
+            .split_first_chunk::<4>()
+            .map(|(len, rest)| (u32::from_be_bytes(*len) as usize, rest))
+            .filter(|(len, rest)| *len <= rest.len());
+
+        match frame {
+            Some((len, rest)) => {
+                let (frame, rest) = rest.split_at(len);
+                self.buf = rest;
+                Some(Ok(frame))
+            }
+            None => {
+                self.buf = &[];
+                Some(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "incomplete metric family frame",
+                )))
+            }
+        }
+    }
+}
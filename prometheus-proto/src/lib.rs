@@ -2,11 +2,13 @@ use std::io::Write;
 
 use encoding::{encode_key, encode_varint, encoded_len_varint, key_len, WireType::LengthDelimited};
 use measured::{
+    atomic::MemoryOrdering,
     label::{LabelGroupVisitor, LabelName, LabelValue, LabelVisitor},
     metric::{
         counter::CounterState,
         gauge::{FloatGaugeState, GaugeState},
-        group::Encoding,
+        group::{Encoding, MetricGroup},
+        histogram::{HistogramState, Thresholds},
         name::MetricNameEncoder,
         MetricEncoding,
     },
@@ -14,10 +16,35 @@ use measured::{
 };
 
 mod encoding;
+mod framed;
+
+pub use framed::FrameReader;
+use framed::Framing;
+
+/// The content type of the delimited protobuf exposition format, as written by [`ProtoEncoder::new`]
+/// and [`collect_delimited_into`].
+pub const CONTENT_TYPE: &str =
+    "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited";
+
+/// Encode all the metric families in the group into `writer`, in the delimited protobuf exposition format that
+/// prometheus requests when scraping with the [`CONTENT_TYPE`].
+///
+/// Each `MetricFamily` message is prefixed by its length as a varint. The writer is returned after it is flushed.
+pub fn collect_delimited_into<G, W>(group: &G, writer: W) -> std::io::Result<W>
+where
+    G: MetricGroup<ProtoEncoder<W>> + ?Sized,
+    W: Write,
+{
+    let mut enc = ProtoEncoder::new(writer);
+    group.collect_group_into(&mut enc)?;
+    enc.flush()?;
+    Ok(enc.writer)
+}
 
 /// The prometheus text encoder helper
 pub struct ProtoEncoder<W> {
     state: State,
+    framing: Framing,
     pub writer: W,
     buf: Vec<u8>,
 }
@@ -29,11 +56,24 @@ impl<W: Write> ProtoEncoder<W> {
     pub fn new(w: W) -> Self {
         Self {
             state: State::Init,
+            framing: Framing::Varint,
             writer: w,
             buf: Vec::new(),
         }
     }
 
+    /// Create a new encoder where each `MetricFamily` is prefixed by its length as a big-endian `u32`,
+    /// instead of the varint length prefix that prometheus expects.
+    ///
+    /// This is useful for streaming families incrementally over a custom binary transport.
+    /// Use [`FrameReader`] to split the frames apart again.
+    pub fn framed(w: W) -> Self {
+        Self {
+            framing: Framing::U32,
+            ..Self::new(w)
+        }
+    }
+
     /// Finish the text encoding and extract the bytes to send in a HTTP response.
     pub fn flush(&mut self) -> std::io::Result<()> {
         self.flush_buf()?;
@@ -45,9 +85,23 @@ impl<W: Write> ProtoEncoder<W> {
             self.state = State::Init;
 
             let len = self.buf.len() - 10;
-            let varint_len = encoded_len_varint(len as u64);
-            let offset = 10 - varint_len;
-            encode_varint(len as u64, &mut &mut self.buf[offset..]);
+            let offset = match self.framing {
+                Framing::Varint => {
+                    let offset = 10 - encoded_len_varint(len as u64);
+                    encode_varint(len as u64, &mut &mut self.buf[offset..]);
+                    offset
+                }
+                Framing::U32 => {
+                    let len = u32::try_from(len).map_err(|_| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "metric family is too large for a u32 frame",
+                        )
+                    })?;
+                    self.buf[6..10].copy_from_slice(&len.to_be_bytes());
+                    6
+                }
+            };
             self.writer.write_all(&self.buf[offset..])?;
 
             self.buf.resize(10, 0);
@@ -239,7 +293,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for CounterState {
 
     fn collect_into(
         &self,
-        _m: &(),
+        ordering: &MemoryOrdering,
         labels: impl LabelGroup,
         _name: impl MetricNameEncoder,
         enc: &mut ProtoEncoder<W>,
@@ -252,7 +306,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for CounterState {
         labels.visit_values(&mut label_pairs_len);
         metric_len += label_pairs_len.len;
 
-        let count = self.count.load(std::sync::atomic::Ordering::Relaxed) as f64;
+        let count = self.count.load(ordering.load()) as f64;
         let count_len = encoding::double::encoded_len(1, &count);
         metric_len += message_len(3, count_len);
 
@@ -294,7 +348,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for GaugeState {
 
     fn collect_into(
         &self,
-        _m: &(),
+        ordering: &MemoryOrdering,
         labels: impl LabelGroup,
         _name: impl MetricNameEncoder,
         enc: &mut ProtoEncoder<W>,
@@ -307,7 +361,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for GaugeState {
         labels.visit_values(&mut label_pairs_len);
         metric_len += label_pairs_len.len;
 
-        let gauge = self.count.load(std::sync::atomic::Ordering::Relaxed) as f64;
+        let gauge = self.count.load(ordering.load()) as f64;
         let gauge_len = encoding::double::encoded_len(1, &gauge);
         metric_len += message_len(3, gauge_len);
 
@@ -349,7 +403,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for FloatGaugeState {
 
     fn collect_into(
         &self,
-        _m: &(),
+        ordering: &MemoryOrdering,
         labels: impl LabelGroup,
         _name: impl MetricNameEncoder,
         enc: &mut ProtoEncoder<W>,
@@ -362,7 +416,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for FloatGaugeState {
         labels.visit_values(&mut label_pairs_len);
         metric_len += label_pairs_len.len;
 
-        let gauge = self.count.get();
+        let gauge = self.count.load(ordering.load());
         let gauge_len = encoding::double::encoded_len(1, &gauge);
         metric_len += message_len(3, gauge_len);
 
@@ -381,6 +435,102 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for FloatGaugeState {
     }
 }
 
+impl<W: Write, const N: usize> MetricEncoding<ProtoEncoder<W>> for HistogramState<N> {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut ProtoEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.flush_buf()?;
+
+        if enc.state == State::Init {
+            // optional string     name   = 1;
+            encode_key(1, LengthDelimited, &mut enc.buf);
+            encode_varint(name.encode_len() as u64, &mut enc.buf);
+            name.encode_utf8(&mut enc.buf)?;
+        }
+
+        // optional MetricType type   = 3;
+        // HISTOGRAM = 4;
+        encoding::int32::encode(3, &4, &mut enc.buf);
+
+        Ok(())
+    }
+
+    fn collect_into(
+        &self,
+        thresholds: &Thresholds<N>,
+        labels: impl LabelGroup,
+        _name: impl MetricNameEncoder,
+        enc: &mut ProtoEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.state = State::Metrics;
+
+        // hold the write lock so that the buckets, count and sum are consistent
+        let (buckets, inf, sum) = {
+            let inner = self.inner.write();
+            let ordering = std::sync::atomic::Ordering::Relaxed;
+            (
+                inner.load_buckets(ordering),
+                inner.load_inf(ordering),
+                inner.load_sum(ordering),
+            )
+        };
+
+        let mut cumulative = [0; N];
+        let mut count = 0;
+        for (c, b) in cumulative.iter_mut().zip(buckets) {
+            count += b;
+            *c = count;
+        }
+        count += inf;
+
+        let bucket_len = |cumulative_count: &u64, upper_bound: &f64| {
+            encoding::uint64::encoded_len(1, cumulative_count)
+                + encoding::double::encoded_len(2, upper_bound)
+        };
+
+        let mut histogram_len = 0;
+        histogram_len += encoding::uint64::encoded_len(1, &count);
+        histogram_len += encoding::double::encoded_len(2, &sum);
+        for (c, le) in cumulative.iter().zip(thresholds.get()) {
+            histogram_len += message_len(3, bucket_len(c, le));
+        }
+
+        let mut metric_len = 0;
+
+        let mut label_pairs_len = GroupLenVisitor { len: 0 };
+        labels.visit_values(&mut label_pairs_len);
+        metric_len += label_pairs_len.len;
+        metric_len += message_len(7, histogram_len);
+
+        // repeated Metric     metric = 4;
+        encode_message(4, metric_len, &mut enc.buf, |buf| {
+            labels.visit_values(&mut GroupVisitor { buf });
+
+            // optional Histogram histogram    = 7;
+            encode_message(7, histogram_len, buf, |buf| {
+                // optional uint64 sample_count = 1;
+                encoding::uint64::encode(1, &count, buf);
+                // optional double sample_sum   = 2;
+                encoding::double::encode(2, &sum, buf);
+
+                // the +Inf bucket is implied by the sample count
+                for (c, le) in cumulative.iter().zip(thresholds.get()) {
+                    // repeated Bucket bucket       = 3;
+                    encode_message(3, bucket_len(c, le), buf, |buf| {
+                        // optional uint64 cumulative_count = 1;
+                        encoding::uint64::encode(1, c, buf);
+                        // optional double upper_bound = 2;
+                        encoding::double::encode(2, le, buf);
+                    });
+                }
+            });
+        });
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod generated;
 
@@ -392,16 +542,20 @@ mod tests {
     use measured::{
         metric::{
             group::Encoding,
+            histogram::Thresholds,
             name::{MetricName, Total},
             MetricFamilyEncoding,
         },
-        CounterVec, GaugeVec,
+        CounterVec, GaugeVec, HistogramVec,
     };
     use prost::Message;
 
     use crate::{
-        generated::{Counter, Gauge, LabelPair, Metric, MetricFamily, MetricType},
-        ProtoEncoder,
+        collect_delimited_into,
+        generated::{
+            Bucket, Counter, Gauge, Histogram, LabelPair, Metric, MetricFamily, MetricType,
+        },
+        FrameReader, ProtoEncoder,
     };
 
     #[derive(Clone, Copy, PartialEq, Debug, measured::LabelGroup)]
@@ -589,4 +743,161 @@ mod tests {
         let actual = MetricFamily::decode_length_delimited(actual_msg).unwrap();
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn framed() {
+        let requests = CounterVec::<RequestLabelSet>::new();
+        requests.inc_by(
+            RequestLabels {
+                method: Method::Post,
+                code: StatusCode::Ok,
+            },
+            1027,
+        );
+        let errors = GaugeVec::<RequestLabelSet>::new();
+        errors.inc_by(
+            RequestLabels {
+                method: Method::Get,
+                code: StatusCode::BadRequest,
+            },
+            3,
+        );
+
+        let mut enc = ProtoEncoder::framed(BytesMut::new().writer());
+        requests
+            .collect_family_into(MetricName::from_str("requests"), &mut enc)
+            .unwrap();
+        errors
+            .collect_family_into(MetricName::from_str("errors"), &mut enc)
+            .unwrap();
+        enc.flush().unwrap();
+        let msg = enc.writer.into_inner().freeze();
+
+        let families = FrameReader::new(&msg)
+            .map(|frame| MetricFamily::decode(frame.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].name.as_deref(), Some("requests"));
+        assert_eq!(
+            families[0].metric[0].counter.as_ref().unwrap().value,
+            Some(1027.0)
+        );
+        assert_eq!(families[1].name.as_deref(), Some("errors"));
+        assert_eq!(
+            families[1].metric[0].gauge.as_ref().unwrap().value,
+            Some(3.0)
+        );
+
+        // the first frame is prefixed by its big-endian length
+        let len = u32::from_be_bytes(msg[..4].try_into().unwrap()) as usize;
+        assert_eq!(MetricFamily::decode(&msg[4..4 + len]).unwrap(), families[0]);
+
+        // truncated frames are an error
+        let mut frames = FrameReader::new(&msg[..msg.len() - 1]);
+        assert!(frames.next().unwrap().is_ok());
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+    }
+
+    #[test]
+    fn delimited() {
+        #[derive(measured::MetricGroup)]
+        struct Metrics {
+            /// total requests
+            requests: CounterVec<RequestLabelSet>,
+            /// requests in flight
+            in_flight: GaugeVec<RequestLabelSet>,
+        }
+
+        let metrics = Metrics {
+            requests: CounterVec::new(),
+            in_flight: GaugeVec::new(),
+        };
+        let labels = RequestLabels {
+            method: Method::Get,
+            code: StatusCode::Ok,
+        };
+        metrics.requests.inc_by(labels, 5);
+        metrics.in_flight.inc(labels);
+
+        let msg = collect_delimited_into(&metrics, BytesMut::new().writer())
+            .unwrap()
+            .into_inner();
+
+        let mut buf = &msg[..];
+        let mut families = vec![];
+        while !buf.is_empty() {
+            families.push(MetricFamily::decode_length_delimited(&mut buf).unwrap());
+        }
+
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].name.as_deref(), Some("requests"));
+        assert_eq!(families[0].help.as_deref(), Some("total requests"));
+        assert_eq!(families[0].r#type, Some(MetricType::Counter as i32));
+        assert_eq!(
+            families[0].metric[0].counter.as_ref().unwrap().value,
+            Some(5.0)
+        );
+        assert_eq!(families[1].name.as_deref(), Some("in_flight"));
+        assert_eq!(families[1].r#type, Some(MetricType::Gauge as i32));
+        assert_eq!(
+            families[1].metric[0].gauge.as_ref().unwrap().value,
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn histogram() {
+        let latency =
+            HistogramVec::<RequestLabelSet, 2>::with_metadata(Thresholds::with_buckets([0.1, 1.0]));
+        let labels = RequestLabels {
+            method: Method::Get,
+            code: StatusCode::Ok,
+        };
+        for x in [0.05, 0.5, 0.5, 5.0] {
+            latency.observe(labels, x);
+        }
+
+        let mut enc = ProtoEncoder::new(BytesMut::new().writer());
+        latency
+            .collect_family_into(MetricName::from_str("latency"), &mut enc)
+            .unwrap();
+        enc.flush().unwrap();
+        let actual_msg = enc.writer.into_inner();
+
+        let bucket = |cumulative_count, upper_bound| Bucket {
+            cumulative_count: Some(cumulative_count),
+            upper_bound: Some(upper_bound),
+            ..Bucket::default()
+        };
+        let expected = MetricFamily {
+            name: Some("latency".to_string()),
+            help: None,
+            r#type: Some(MetricType::Histogram as i32),
+            metric: vec![Metric {
+                label: vec![
+                    LabelPair {
+                        name: Some("method".to_owned()),
+                        value: Some("get".to_owned()),
+                    },
+                    LabelPair {
+                        name: Some("code".to_owned()),
+                        value: Some("200".to_owned()),
+                    },
+                ],
+                histogram: Some(Histogram {
+                    sample_count: Some(4),
+                    sample_sum: Some(6.05),
+                    bucket: vec![bucket(1, 0.1), bucket(3, 1.0)],
+                    ..Histogram::default()
+                }),
+                ..Metric::default()
+            }],
+            unit: None,
+        };
+        let mut expected_msg = BytesMut::new();
+        expected.encode_length_delimited(&mut expected_msg).unwrap();
+
+        assert_eq!(actual_msg, expected_msg);
+    }
 }
@@ -14,7 +14,7 @@
 //!     tokio: measured_tokio::RuntimeCollector,
 //!
 //!     // other metrics
-//! }}
+//! }
 //!
 //! #[tokio::main]
 //! async fn main() {
@@ -35,12 +35,59 @@ use measured::{
         gauge::{write_float_gauge, write_gauge, FloatGaugeState, GaugeState},
         group::Encoding,
         name::{Bucket, Count, MetricName, Sum},
+        reset::Reset,
         MetricEncoding,
     },
     FixedCardinalityLabel, LabelGroup, MetricGroup,
 };
 use tokio::runtime::RuntimeMetrics;
 
+/// Collect the metric groups into the encoder, yielding back to the tokio runtime between each group.
+///
+/// A large set of metrics can take a while to encode. Collecting them synchronously
+/// inside of a task can then block the worker thread and starve other tasks.
+/// The groups are still collected in order, so the output is identical to the synchronous
+/// [`MetricGroup::collect_group_into`].
+///
+/// ```
+/// use measured::{text::BufferedTextEncoder, Counter, MetricGroup};
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Http {
+///     requests: Counter,
+/// }
+///
+/// #[derive(MetricGroup, Default)]
+/// struct Db {
+///     queries: Counter,
+/// }
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let http = Http::default();
+/// let db = Db::default();
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// measured_tokio::collect_groups_into(&[&http, &db], &mut enc).await.unwrap();
+///
+/// assert_eq!(
+///     enc.finish(),
+///     "# TYPE requests counter\nrequests 0\n\n# TYPE queries counter\nqueries 0\n",
+/// );
+/// # });
+/// ```
+pub async fn collect_groups_into<Enc: Encoding>(
+    groups: &[&(dyn MetricGroup<Enc> + Sync)],
+    enc: &mut Enc,
+) -> Result<(), Enc::Err> {
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            tokio::task::yield_now().await;
+        }
+        group.collect_group_into(enc)?;
+    }
+    Ok(())
+}
+
 /// A collector which contains multiple named tokio runtimes
 pub struct NamedRuntimesCollector {
     runtimes: RwLock<Vec<RuntimeCollector>>,
@@ -95,6 +142,11 @@ where
     }
 }
 
+impl Reset for NamedRuntimesCollector {
+    /// The runtime metrics are read from tokio on every collection, so there is nothing to reset
+    fn reset(&self) {}
+}
+
 /// A collector which exports the current state of tokio metrics
 pub struct RuntimeCollector {
     runtime: RuntimeMetrics,
@@ -355,6 +407,11 @@ where
     }
 }
 
+impl Reset for RuntimeCollector {
+    /// The runtime metrics are read from tokio on every collection, so there is nothing to reset
+    fn reset(&self) {}
+}
+
 struct I64(i64);
 impl LabelValue for I64 {
     fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {